@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::AnomalyConfig;
+
+const MINUTE: i64 = 60_000;
+
+/// Minutes without matches learned at most at once, after a long pause.
+const MAX_CATCH_UP: i64 = 7 * 24 * 60;
+
+/// The matches per minute a rule normally sees, learned as an
+/// exponentially weighted mean and variance of the count of each minute.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Baseline {
+    pub mean: f64,
+    pub variance: f64,
+    /// Minutes learned so far.
+    pub learned: u64,
+    /// The minute being counted, since the epoch, and its matches.
+    #[serde(skip)]
+    minute: i64,
+    #[serde(skip)]
+    current: usize,
+    /// Whether counting started within the current minute, which is then
+    /// not learned.
+    #[serde(skip)]
+    partial: bool,
+    /// Whether the current minute raised an alarm already.
+    #[serde(skip)]
+    reported: bool,
+}
+
+impl Baseline {
+    /// Counts a match at `now` (milliseconds).
+    pub fn push(&mut self, config: &AnomalyConfig, now: i64) {
+        self.roll(config, now);
+        self.current += 1;
+    }
+
+    /// Matches in the current minute.
+    pub fn rate(&self) -> usize {
+        self.current
+    }
+
+    /// The rate from which on the current minute is anomalous.
+    pub fn limit(&self, config: &AnomalyConfig) -> f64 {
+        self.mean + config.factor * self.variance.sqrt()
+    }
+
+    /// Whether the matches of the current minute are well above those
+    /// learned, once enough minutes were.
+    pub fn exceeded(&mut self, config: &AnomalyConfig, now: i64) -> bool {
+        self.roll(config, now);
+        !self.reported
+            && self.learned >= config.warmup_minutes
+            && self.current >= config.min_matches
+            && self.current as f64 > self.limit(config)
+    }
+
+    /// Keeps the current minute from raising another alarm.
+    pub fn report(&mut self) {
+        self.reported = true;
+    }
+
+    /// Learns the minutes that ended before `now`.
+    fn roll(&mut self, config: &AnomalyConfig, now: i64) {
+        let minute = now.div_euclid(MINUTE);
+        if self.minute == 0 {
+            self.minute = minute;
+            self.partial = true;
+        }
+        if minute <= self.minute {
+            return;
+        }
+        let alpha = 2.0 / (config.learn_hours * 60.0 + 1.0);
+        let mut count = Some(self.current as f64).filter(|_| !self.partial);
+        for _ in 0..(minute - self.minute).min(MAX_CATCH_UP) {
+            if let Some(count) = count {
+                let difference = count - self.mean;
+                let increment = alpha * difference;
+                self.mean += increment;
+                self.variance = (1.0 - alpha) * (self.variance + difference * increment);
+                self.learned += 1;
+            }
+            count = Some(0.0);
+        }
+        self.minute = minute;
+        self.current = 0;
+        self.partial = false;
+        self.reported = false;
+    }
+}
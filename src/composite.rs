@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+
+use regex::RegexSet;
+
+/// Named patterns and an expression over them, e.g. `refused and
+/// exhausted`, telling which of them must have matched within the window
+/// of a rule.
+///
+/// Expressions combine the names with `and`, `or` and `not` (or `&&`, `||`
+/// and `!`), in that order of precedence, and parentheses. Without one any
+/// of the patterns will do.
+#[derive(Clone)]
+pub struct Composite {
+    text: String,
+    names: Vec<String>,
+    patterns: RegexSet,
+    expr: Expr,
+}
+
+#[derive(Clone)]
+enum Expr {
+    Term(usize),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, present: &dyn Fn(usize) -> bool) -> bool {
+        match self {
+            Expr::Term(index) => present(*index),
+            Expr::Not(expr) => !expr.eval(present),
+            Expr::And(left, right) => left.eval(present) && right.eval(present),
+            Expr::Or(left, right) => left.eval(present) || right.eval(present),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum Token {
+    Name(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let (token, length) = match c {
+            '(' => (Token::Open, 1),
+            ')' => (Token::Close, 1),
+            '!' => (Token::Not, 1),
+            '&' if rest.starts_with("&&") => (Token::And, 2),
+            '|' if rest.starts_with("||") => (Token::Or, 2),
+            c if c.is_alphanumeric() || c == '_' => {
+                let length = rest.find(|c: char| !(c.is_alphanumeric() || "_-.".contains(c))).unwrap_or(rest.len());
+                let token = match &rest[..length] {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    name => Token::Name(name.to_string()),
+                };
+                (token, length)
+            },
+            c => return Err(format!("unexpected {} in {}", c, text)),
+        };
+        tokens.push(token);
+        rest = rest[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Parses tokens by recursive descent, one function per precedence level.
+struct Parser<'a> {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+    names: &'a [String],
+    text: &'a str,
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.tokens.next_if_eq(&Token::And).is_some() {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        match self.tokens.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.not()?))),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err(format!("missing ) in {}", self.text)),
+                }
+            },
+            Some(Token::Name(name)) => match self.names.iter().position(|known| *known == name) {
+                Some(index) => Ok(Expr::Term(index)),
+                None => Err(format!("{} is not one of the terms", name)),
+            },
+            _ => Err(format!("{} is not a complete expression", self.text)),
+        }
+    }
+}
+
+impl Composite {
+    /// Compiles `terms`, regexes by name, and the expression `when` over
+    /// their names.
+    pub fn new(terms: &BTreeMap<String, String>, when: Option<&str>) -> Result<Composite, String> {
+        if terms.is_empty() {
+            return Err("needs terms to combine".to_string());
+        }
+        let names: Vec<String> = terms.keys().cloned().collect();
+        let patterns = RegexSet::new(terms.values()).map_err(|e| e.to_string())?;
+        let text = match when {
+            Some(when) => when.trim().to_string(),
+            None => names.join(" or "),
+        };
+        let mut parser = Parser { tokens: tokenize(&text)?.into_iter().peekable(), names: &names, text: &text };
+        let expr = parser.or()?;
+        if parser.tokens.next().is_some() {
+            return Err(format!("{} is not a complete expression", text));
+        }
+        Ok(Composite { text, names, patterns, expr })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// The names of the terms, in the order [`Composite::matching`]
+    /// indexes them.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// The terms `line` matches.
+    pub fn matching(&self, line: &str) -> Vec<usize> {
+        self.patterns.matches(line).into_iter().collect()
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        self.patterns.is_match(line)
+    }
+
+    /// Whether the expression holds, given which terms are `present`.
+    pub fn holds(&self, present: &dyn Fn(usize) -> bool) -> bool {
+        self.expr.eval(present)
+    }
+}
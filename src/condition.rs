@@ -0,0 +1,136 @@
+use std::cmp::Ordering;
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+/// A test on a field of a JSON log line, e.g. `status >= 500`.
+///
+/// The field is named by its path, with `.` between the keys of nested
+/// objects. Without an operator the field only has to be set.
+#[derive(Clone)]
+pub struct Condition {
+    text: String,
+    path: Vec<String>,
+    test: Test,
+}
+
+#[derive(Clone)]
+enum Test {
+    Exists,
+    Compare(Operator, Value),
+    Matches(Regex),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// Longest operators first, so `>=` is not taken for `>`.
+const OPERATORS: [(&str, Option<Operator>); 7] = [
+    ("==", Some(Operator::Equal)),
+    ("!=", Some(Operator::NotEqual)),
+    ("<=", Some(Operator::LessOrEqual)),
+    (">=", Some(Operator::GreaterOrEqual)),
+    ("=~", None),
+    ("<", Some(Operator::Less)),
+    (">", Some(Operator::Greater)),
+];
+
+impl Condition {
+    /// Parses `<field> <operator> <value>`, where the value is JSON, e.g. a
+    /// quoted string or a number, or else taken as a string. `=~` matches
+    /// the field against a regex.
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let text = text.trim();
+        let end = text.find(|c: char| c.is_whitespace() || "=!<>~".contains(c)).unwrap_or(text.len());
+        let (field, rest) = text.split_at(end);
+        if field.is_empty() {
+            return Err(format!("condition {} names no field", text));
+        }
+        let path = field.split('.').map(String::from).collect();
+        let rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(Condition { text: text.to_string(), path, test: Test::Exists });
+        }
+        let (operator, value) = OPERATORS.iter()
+            .find_map(|(symbol, operator)| rest.strip_prefix(symbol).map(|value| (*operator, value.trim())))
+            .ok_or_else(|| format!("condition {} has no operator", text))?;
+        let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+        let test = match operator {
+            Some(operator) => Test::Compare(operator, value),
+            None => {
+                let pattern = value.as_str().ok_or_else(|| format!("condition {} needs a regex", text))?;
+                Test::Matches(Regex::new(pattern).map_err(|e| e.to_string())?)
+            },
+        };
+        Ok(Condition { text: text.to_string(), path, test })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_match(&self, fields: &Map<String, Value>) -> bool {
+        let field = match lookup(fields, &self.path) {
+            Some(Value::Null) | None => return false,
+            Some(field) => field,
+        };
+        match &self.test {
+            Test::Exists => true,
+            Test::Matches(regex) => regex.is_match(&text(field)),
+            Test::Compare(operator, value) => {
+                let ordering = compare(field, value);
+                match operator {
+                    Operator::Equal => ordering == Some(Ordering::Equal),
+                    Operator::NotEqual => ordering != Some(Ordering::Equal),
+                    Operator::Less => ordering == Some(Ordering::Less),
+                    Operator::LessOrEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                    Operator::Greater => ordering == Some(Ordering::Greater),
+                    Operator::GreaterOrEqual => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+                }
+            },
+        }
+    }
+}
+
+/// The field at `path`, through nested objects and arrays.
+pub(crate) fn lookup<'a>(fields: &'a Map<String, Value>, path: &[String]) -> Option<&'a Value> {
+    let mut path = path.iter();
+    let first = path.next().and_then(|key| fields.get(key));
+    let field = path.try_fold(first, |value, key| match value {
+        Some(Value::Object(object)) => Some(object.get(key)),
+        Some(Value::Array(items)) => Some(key.parse::<usize>().ok().and_then(|index| items.get(index))),
+        _ => None,
+    });
+    field.flatten()
+}
+
+/// Numbers compare as numbers, also when the field holds one as a string,
+/// and strings compare as strings. Other values can only be equal.
+fn compare(field: &Value, value: &Value) -> Option<Ordering> {
+    let number = |value: &Value| match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.parse().ok(),
+        _ => None,
+    };
+    match (field, value) {
+        (Value::String(field), Value::String(value)) => Some(field.cmp(value)),
+        (_, Value::Number(_)) => number(field)?.partial_cmp(&number(value)?),
+        _ if field == value => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+/// The field as text, without the quotes of strings.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
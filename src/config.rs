@@ -0,0 +1,1870 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use encoding_rs::{Encoding, UTF_8};
+use serde::de::{self, MapAccess, SeqAccess};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::error::Error;
+use crate::grok;
+use crate::interpolate;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    pub log: LogConfigs,
+    #[serde(default)]
+    pub email: Option<EmailConfig>,
+    #[serde(default)]
+    pub notifiers: Vec<NotifierEntry>,
+    /// File keeping alert states across restarts.
+    #[serde(default)]
+    pub state_file: Option<String>,
+    /// Print alerts instead of sending them.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// SQLite database recording every fired alert.
+    #[serde(default)]
+    pub history_db: Option<String>,
+    /// Serves monitoring endpoints such as `/metrics` when set.
+    #[serde(default)]
+    pub http: Option<HttpConfig>,
+    /// Retries failed notifications when set.
+    #[serde(default)]
+    pub retry: Option<RetryConfig>,
+    /// How notifications wait to be sent.
+    #[serde(default)]
+    pub notify_queue: NotifyQueueConfig,
+    /// Periods during which alerts are held back.
+    #[serde(default)]
+    pub schedule: Option<ScheduleConfig>,
+    /// How changes to the log files are detected.
+    #[serde(default)]
+    pub watch_backend: WatchBackend,
+    /// Bytes read from inotify at once.
+    #[serde(default = "default_watch_buffer_size")]
+    pub watch_buffer_size: usize,
+    /// Where matched lines are shipped to, besides raising alerts.
+    #[serde(default)]
+    pub outputs: Vec<OutputEntry>,
+    /// Named patterns regexes can refer to as `%{NAME}`, besides the
+    /// library.
+    #[serde(default)]
+    pub grok: Option<GrokConfig>,
+    /// Added to every alert, e.g. `environment: prod`, so alerts of many
+    /// machines can be told apart beside their hostname and IP.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchBackend {
+    /// Linux inotify, the default there.
+    Inotify,
+    /// The native API of each platform through the `notify` crate, the
+    /// default elsewhere.
+    Notify,
+}
+
+impl Default for WatchBackend {
+    fn default() -> WatchBackend {
+        if cfg!(target_os = "linux") {
+            WatchBackend::Inotify
+        } else {
+            WatchBackend::Notify
+        }
+    }
+}
+
+pub(crate) fn default_watch_buffer_size() -> usize {
+    40960
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ScheduleConfig {
+    /// IANA time zone the times are given in, e.g. `Europe/Berlin`.
+    /// Defaults to the local time zone.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub quiet_hours: Vec<QuietHours>,
+    #[serde(default)]
+    pub maintenance: Vec<MaintenanceWindow>,
+}
+
+/// A daily time range, e.g. `22:00` to `06:00`. A range ending before it
+/// starts runs over midnight; one ending when it starts lasts all day.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuietHours {
+    /// Weekdays the range starts on, e.g. `[sat, sun]`. Every day when empty.
+    #[serde(default)]
+    pub days: Vec<String>,
+    pub from: String,
+    pub to: String,
+    #[serde(flatten)]
+    pub scope: QuietScope,
+}
+
+/// Thresholds that apply during the minutes a cron expression covers, e.g.
+/// `* 9-17 * * mon-fri`. Those left out stay as they are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimedThresholds {
+    pub when: String,
+    /// IANA time zone of the expression, by default that of the schedule.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub count_threshold: Option<i32>,
+    #[serde(default)]
+    pub time_threshold: Option<i64>,
+}
+
+/// A one-off period given as `YYYY-MM-DD HH:MM` or RFC 3339.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaintenanceWindow {
+    pub start: String,
+    pub end: String,
+    #[serde(flatten)]
+    pub scope: QuietScope,
+}
+
+/// What a quiet period applies to and what happens to alerts meanwhile.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct QuietScope {
+    /// Log ids affected, all logs when empty.
+    #[serde(default)]
+    pub logs: Vec<String>,
+    /// Rule names affected, all rules when empty.
+    #[serde(default)]
+    pub rules: Vec<String>,
+    #[serde(default)]
+    pub action: QuietAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuietAction {
+    /// Drop the alerts.
+    #[default]
+    Suppress,
+    /// Send one combined alert per rule once the period is over.
+    Digest,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpConfig {
+    /// Address to listen on, e.g. `127.0.0.1:9898`.
+    pub bind: String,
+    /// Required by `POST /ack` when set, as a bearer token.
+    #[serde(default)]
+    pub ack_token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// Attempts per channel, including the first one.
+    #[serde(default = "default_retry_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after every failure.
+    #[serde(default = "default_retry_delay")]
+    pub initial_delay_seconds: u64,
+    #[serde(default = "default_retry_max_delay")]
+    pub max_delay_seconds: u64,
+    /// Keeps pending retries across restarts.
+    #[serde(default)]
+    pub queue_file: Option<String>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> RetryConfig {
+        RetryConfig {
+            max_attempts: default_retry_attempts(),
+            initial_delay_seconds: default_retry_delay(),
+            max_delay_seconds: default_retry_max_delay(),
+            queue_file: None,
+        }
+    }
+}
+
+/// Notifications wait in a queue for a few senders, so a slow channel
+/// holds up neither the logs nor, with its timeouts, the whole queue.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotifyQueueConfig {
+    /// Notifications waiting at most, beyond those being sent.
+    #[serde(default = "default_queue_capacity")]
+    pub capacity: usize,
+    /// Notifications sent at once.
+    #[serde(default = "default_queue_senders")]
+    pub senders: usize,
+    #[serde(default)]
+    pub when_full: QueueOverflow,
+}
+
+impl Default for NotifyQueueConfig {
+    fn default() -> NotifyQueueConfig {
+        NotifyQueueConfig {
+            capacity: default_queue_capacity(),
+            senders: default_queue_senders(),
+            when_full: QueueOverflow::default(),
+        }
+    }
+}
+
+fn default_queue_capacity() -> usize {
+    1000
+}
+
+fn default_queue_senders() -> usize {
+    4
+}
+
+/// What becomes of notifications while the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueOverflow {
+    /// Hand them to the retry queue, or drop them without a `retry` section.
+    #[default]
+    Retry,
+    Drop,
+}
+
+fn default_retry_attempts() -> u32 {
+    5
+}
+
+fn default_retry_delay() -> u64 {
+    5
+}
+
+fn default_retry_max_delay() -> u64 {
+    300
+}
+
+impl Config {
+    /// Moves settings of older configurations to where they are read now.
+    pub fn migrate(&mut self) {
+        if let Some(email) = self.email.as_mut() {
+            if let Some(host) = email.stmp.take() {
+                if email.smtp.is_empty() {
+                    email.smtp = vec![host];
+                }
+            }
+        }
+    }
+
+    /// Returns the (count, window) thresholds of `log`, falling back to the
+    /// values in the email section for older configurations.
+    pub fn thresholds(&self, log: &LogConfig) -> (usize, i64) {
+        let email = self.email.as_ref();
+        let count = log.count_threshold
+            .or_else(|| email.and_then(|e| e.count_threshold))
+            .unwrap_or(DEFAULT_COUNT_THRESHOLD);
+        let time = log.time_threshold
+            .or_else(|| email.and_then(|e| e.time_threshold))
+            .unwrap_or(DEFAULT_TIME_THRESHOLD);
+        (count.max(1) as usize, time)
+    }
+
+    /// Names of the configured notifiers, as used by rules.
+    pub fn notifier_names(&self) -> Vec<&str> {
+        let email = self.email.as_ref().map(|_| "email");
+        email.into_iter().chain(self.notifiers.iter().map(NotifierEntry::name)).collect()
+    }
+
+    /// The severities each notifier subscribed to, by name.
+    pub fn subscriptions(&self) -> HashMap<String, Vec<Severity>> {
+        let email = self.email.as_ref().map(|email| ("email".to_string(), email.severities.clone()));
+        let entries = self.notifiers.iter().map(|entry| (entry.name().to_string(), entry.severities.clone()));
+        email.into_iter().chain(entries).collect()
+    }
+
+    /// The notifier each notifier falls back to, by name.
+    pub fn fallbacks(&self) -> HashMap<String, String> {
+        let email = self.email.as_ref().map(|email| ("email", email.fallback.clone()));
+        let entries = self.notifiers.iter().map(|entry| (entry.name(), entry.fallback.clone()));
+        email.into_iter().chain(entries)
+            .filter_map(|(name, fallback)| Some((name.to_string(), fallback?)))
+            .collect()
+    }
+
+    /// The rate limits of notifiers, by name.
+    pub fn rate_limits(&self) -> HashMap<String, RateLimitConfig> {
+        let email = self.email.as_ref().map(|email| ("email", email.rate_limit.clone()));
+        let entries = self.notifiers.iter().map(|entry| (entry.name(), entry.rate_limit.clone()));
+        email.into_iter().chain(entries)
+            .filter_map(|(name, limit)| Some((name.to_string(), limit?)))
+            .collect()
+    }
+
+    /// Milliseconds between the summaries of notifiers sending digests, by
+    /// name.
+    pub fn digests(&self) -> HashMap<String, i64> {
+        let email = self.email.as_ref().map(|email| ("email", email.digest_seconds));
+        let entries = self.notifiers.iter().map(|entry| (entry.name(), entry.digest_seconds));
+        email.into_iter().chain(entries)
+            .filter_map(|(name, seconds)| Some((name.to_string(), seconds.filter(|s| *s > 0)? as i64 * 1000)))
+            .collect()
+    }
+}
+
+/// Patterns added to the grok library, or replacing those in it.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct GrokConfig {
+    /// Pattern files as for Logstash, a name and a regex per line.
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// More patterns by name, taking precedence over the files.
+    #[serde(default)]
+    pub patterns: BTreeMap<String, String>,
+}
+
+const DEFAULT_COUNT_THRESHOLD: i32 = 20;
+const DEFAULT_TIME_THRESHOLD: i64 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum LogConfigs {
+    Single(Box<LogConfig>),
+    Multiple(Vec<LogConfig>),
+}
+
+impl LogConfigs {
+    pub fn as_slice(&self) -> &[LogConfig] {
+        match self {
+            LogConfigs::Single(log) => std::slice::from_ref(&**log),
+            LogConfigs::Multiple(logs) => logs.as_slice(),
+        }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [LogConfig] {
+        match self {
+            LogConfigs::Single(log) => std::slice::from_mut(&mut **log),
+            LogConfigs::Multiple(logs) => logs.as_mut_slice(),
+        }
+    }
+}
+
+/// Tells the entry from the list by the YAML itself, so that errors in an
+/// entry are reported where they are instead of as no variant matching.
+impl<'de> Deserialize<'de> for LogConfigs {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<LogConfigs, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = LogConfigs;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a log entry or a list of them")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<LogConfigs, A::Error> {
+                let log = LogConfig::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(LogConfigs::Single(Box::new(log)))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<LogConfigs, A::Error> {
+                Vec::deserialize(de::value::SeqAccessDeserializer::new(seq)).map(LogConfigs::Multiple)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogConfig {
+    pub id: String,
+    /// File, directory or glob to watch. Not needed with another source.
+    #[serde(default)]
+    pub path: String,
+    /// `path` may not exist yet, so validation does not warn about it.
+    #[serde(default)]
+    pub allow_missing: bool,
+    /// Read the systemd journal instead of files.
+    #[serde(default)]
+    pub journal: Option<JournalConfig>,
+    /// Read the output of Docker containers instead of files, each as a
+    /// log of its own.
+    #[serde(default)]
+    pub docker: Option<DockerConfig>,
+    /// Read the output of Kubernetes pods instead of files, each as a log
+    /// of its own.
+    #[serde(default)]
+    pub kubernetes: Option<KubernetesConfig>,
+    /// Receive syslog messages over the network instead of reading files.
+    #[serde(default)]
+    pub syslog: Option<SyslogConfig>,
+    /// Read standard input instead of files. Once it ends and no other
+    /// log is watched, the watcher shuts down.
+    #[serde(default)]
+    pub stdin: bool,
+    #[serde(default)]
+    pub mode: WatchMode,
+    /// When `path` is a directory or glob, also watch its subdirectories.
+    #[serde(default)]
+    pub recursive: bool,
+    /// Check the files every this many milliseconds instead of waiting for
+    /// file system events, e.g. on NFS or CIFS mounts.
+    #[serde(default)]
+    pub poll_interval: Option<i64>,
+    /// In `tail` mode, read a changed file only this many milliseconds
+    /// later, so whatever was written in the meantime is read at once.
+    #[serde(default)]
+    pub debounce: Option<i64>,
+    /// In `tail` mode, join lines into entries, e.g. stack traces, which
+    /// are then matched and counted as one.
+    #[serde(default)]
+    pub multiline: Option<MultilineConfig>,
+    /// How lines are parsed, so rules can test their fields.
+    #[serde(default)]
+    pub format: LineFormat,
+    /// Character encoding of files and standard input, e.g. `gbk` or
+    /// `latin1`. UTF-8 by default.
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// In `tail` mode, only lines whose fields pass all of these tests, e.g.
+    /// `status >= 500`, count as errors.
+    #[serde(default)]
+    pub fields: Vec<String>,
+    /// In `tail` mode, only lines containing one of these count as errors.
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// In `tail` mode, only lines matching one of these regexes count as errors.
+    /// With no keywords nor patterns every appended line counts.
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// Lines matching one of these regexes never count, for any rule of
+    /// the entry.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Number of events within the time window needed to raise an alarm.
+    #[serde(default)]
+    pub count_threshold: Option<i32>,
+    /// Length of the sliding time window in milliseconds.
+    #[serde(default)]
+    pub time_threshold: Option<i64>,
+    /// Number of recent matching lines included in alerts.
+    #[serde(default = "default_sample_lines")]
+    pub sample_lines: usize,
+    /// Lines longer than this many characters are cut in alerts.
+    #[serde(default = "default_max_line_length")]
+    pub max_line_length: usize,
+    /// Seconds after an alarm during which further alarms are suppressed.
+    /// Matches seen meanwhile are reported with the next alarm.
+    #[serde(default)]
+    pub cooldown_seconds: u64,
+    /// Milliseconds without any match after which a fired alarm is resolved.
+    /// Resolve notifications are only sent when this is set.
+    #[serde(default)]
+    pub resolve_after: Option<i64>,
+    /// Resolve once fewer than this many events are within the time window
+    /// for `resolve_after` milliseconds, instead of once none are seen.
+    #[serde(default)]
+    pub clear_threshold: Option<usize>,
+    /// Severity of the alerts of the entry's rules.
+    #[serde(default)]
+    pub severity: Severity,
+    /// Adds the load and free memory of the machine, and the free space of
+    /// the filesystem the log is on, to the entry's alerts.
+    #[serde(default)]
+    pub system_snapshot: bool,
+    /// Hold back alerts about an error that already raised one recently.
+    #[serde(default)]
+    pub dedup: Option<DedupConfig>,
+    /// Remind of alarms that neither resolve nor get acknowledged.
+    #[serde(default)]
+    pub escalation: Option<EscalationConfig>,
+    /// Alert at once on many matches within a short time, besides the
+    /// thresholds.
+    #[serde(default)]
+    pub burst: Option<BurstConfig>,
+    /// Alert when matches are well above the rate learned for a rule,
+    /// instead of at the thresholds.
+    #[serde(default)]
+    pub anomaly: Option<AnomalyConfig>,
+    /// Alert on many different errors within the window, instead of many
+    /// matches.
+    #[serde(default)]
+    pub distinct: Option<DistinctConfig>,
+    /// Values carried from the last matching line into alerts.
+    #[serde(default)]
+    pub extract: Option<ExtractConfig>,
+    /// Other thresholds at certain times, e.g. overnight. The first that
+    /// applies wins.
+    #[serde(default)]
+    pub thresholds: Vec<TimedThresholds>,
+    /// Named conditions with their own thresholds and channels. The
+    /// keywords and patterns above, if any, form one more rule.
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+}
+
+/// A named condition on a log. Unset thresholds fall back to the log's.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleConfig {
+    pub name: String,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    #[serde(default)]
+    pub fields: Vec<String>,
+    /// Regexes by name, which `when` combines. Lines matching any of them
+    /// count.
+    #[serde(default)]
+    pub terms: BTreeMap<String, String>,
+    /// Which `terms` must have matched within the window for an alert,
+    /// e.g. `refused and exhausted`. Any of them by default.
+    #[serde(default)]
+    pub when: Option<String>,
+    /// Rhai script whose `check(line, fields)` decides about each line the
+    /// rule matched, and may set the severity and fields of the alert.
+    #[serde(default)]
+    pub script: Option<String>,
+    /// A number in the lines, such as a latency, that is held to limits.
+    #[serde(default)]
+    pub value: Option<ValueConfig>,
+    /// Lines matching one of these regexes do not count, in addition to
+    /// those excluded by the log entry.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub count_threshold: Option<i32>,
+    #[serde(default)]
+    pub time_threshold: Option<i64>,
+    #[serde(default)]
+    pub cooldown_seconds: Option<u64>,
+    #[serde(default)]
+    pub resolve_after: Option<i64>,
+    #[serde(default)]
+    pub clear_threshold: Option<usize>,
+    /// Turns the rule around: alert when nothing matched for this many
+    /// milliseconds, and resolve once something matches again.
+    #[serde(default)]
+    pub absent_after: Option<i64>,
+    /// Names of the notifiers alerts are sent to, all of them when empty.
+    #[serde(default)]
+    pub notify: Vec<String>,
+    /// Defaults to the severity of the log entry.
+    #[serde(default)]
+    pub severity: Option<Severity>,
+    /// Defaults to the deduplication of the log entry.
+    #[serde(default)]
+    pub dedup: Option<DedupConfig>,
+    /// Defaults to the escalation of the log entry.
+    #[serde(default)]
+    pub escalation: Option<EscalationConfig>,
+    /// Defaults to the burst of the log entry.
+    #[serde(default)]
+    pub burst: Option<BurstConfig>,
+    /// Defaults to the anomaly detection of the log entry.
+    #[serde(default)]
+    pub anomaly: Option<AnomalyConfig>,
+    /// Defaults to the distinct error count of the log entry.
+    #[serde(default)]
+    pub distinct: Option<DistinctConfig>,
+    /// Defaults to the extraction of the log entry.
+    #[serde(default)]
+    pub extract: Option<ExtractConfig>,
+    /// Defaults to the timed thresholds of the log entry.
+    #[serde(default)]
+    pub thresholds: Vec<TimedThresholds>,
+}
+
+/// A number taken from the lines of a rule, and the limits it is held to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValueConfig {
+    /// Regex whose group named `value`, or else first group, is the number.
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// Or the field of JSON lines holding it, e.g. `timing.total_ms`.
+    #[serde(default)]
+    pub field: Option<String>,
+    /// Only values beyond these limits count.
+    #[serde(default)]
+    pub above: Option<f64>,
+    #[serde(default)]
+    pub below: Option<f64>,
+    /// Hold the average of the values within the window to the limits
+    /// instead of each value.
+    #[serde(default)]
+    pub average: bool,
+}
+
+/// How alerts about the same error are recognized, by the fingerprint of
+/// the line that raised them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DedupConfig {
+    /// Milliseconds an error is not alerted again after an alert about it.
+    pub window: i64,
+    /// Regex whose first capture group, or match, is the fingerprint. By
+    /// default it is the line with numbers and ids left out.
+    #[serde(default)]
+    pub pattern: Option<String>,
+}
+
+/// A spike of matches, such as 5 fatal errors within 10 seconds, alerted
+/// on its own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BurstConfig {
+    /// Matches needed within the window.
+    pub count: usize,
+    /// Length of the window in milliseconds.
+    pub window: i64,
+    /// Seconds no further burst is alerted after one.
+    #[serde(default)]
+    pub cooldown_seconds: u64,
+}
+
+/// How unusual a rate of matches must be, against the rate learned, to
+/// raise an alarm.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnomalyConfig {
+    /// Standard deviations above the mean matches per minute.
+    #[serde(default = "default_anomaly_factor")]
+    pub factor: f64,
+    /// Hours the learned rate mostly reflects.
+    #[serde(default = "default_learn_hours")]
+    pub learn_hours: f64,
+    /// Minutes learned before any alarm.
+    #[serde(default = "default_warmup_minutes")]
+    pub warmup_minutes: u64,
+    /// Matches within a minute needed at least, so a rule that is almost
+    /// always quiet does not alarm on a single match.
+    #[serde(default = "default_min_matches")]
+    pub min_matches: usize,
+}
+
+/// How many different errors, told apart by fingerprint, raise an alarm.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DistinctConfig {
+    /// Different errors needed within the window.
+    pub count: usize,
+    /// Regex whose first capture group, or match, is the fingerprint, as
+    /// for deduplication.
+    #[serde(default)]
+    pub pattern: Option<String>,
+}
+
+/// Values taken from matching lines, which alerts carry as `extracted`
+/// and Alertmanager as labels.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtractConfig {
+    /// Regexes whose named groups are values, e.g. `user=(?P<user>\w+)`.
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// Fields of JSON or logfmt lines by the name of their value, e.g.
+    /// `status: http.status`.
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Reminders sent while an alarm neither resolved nor was acknowledged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EscalationConfig {
+    /// Seconds after the alert, and between reminders, before a reminder.
+    pub after_seconds: u64,
+    /// Reminders sent at most.
+    #[serde(default = "default_escalation_times")]
+    pub times: u32,
+    /// Names of notifiers that get the reminders besides the rule's own.
+    #[serde(default)]
+    pub notify: Vec<String>,
+}
+
+fn default_escalation_times() -> u32 {
+    1
+}
+
+fn default_anomaly_factor() -> f64 {
+    3.0
+}
+
+fn default_learn_hours() -> f64 {
+    24.0
+}
+
+fn default_warmup_minutes() -> u64 {
+    60
+}
+
+fn default_min_matches() -> usize {
+    3
+}
+
+/// How urgent the alerts of a rule are, from the least urgent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+impl LogConfig {
+    /// The encoding lines are read in. Malformed bytes become U+FFFD.
+    pub fn text_encoding(&self) -> &'static Encoding {
+        self.encoding.as_deref().and_then(|label| Encoding::for_label(label.as_bytes())).unwrap_or(UTF_8)
+    }
+
+    /// What the entry reads, for messages.
+    pub fn location(&self) -> String {
+        if let Some(journal) = &self.journal {
+            if journal.units.is_empty() {
+                return "the journal".to_string();
+            }
+            return format!("the journal of {}", journal.units.join(", "));
+        }
+        if self.docker.is_some() {
+            return "Docker containers".to_string();
+        }
+        if self.kubernetes.is_some() {
+            return "Kubernetes pods".to_string();
+        }
+        if let Some(syslog) = &self.syslog {
+            return format!("syslog on {}", syslog.bind);
+        }
+        if self.stdin {
+            return "standard input".to_string();
+        }
+        self.path.clone()
+    }
+
+    /// Whether both entries read the same thing the same way, so a running
+    /// one can be kept on reload.
+    pub fn same_source(&self, other: &LogConfig) -> bool {
+        self.id == other.id && self.path == other.path && self.mode == other.mode
+            && self.recursive == other.recursive && self.poll_interval == other.poll_interval
+            && self.journal == other.journal && self.docker == other.docker
+            && self.kubernetes == other.kubernetes && self.syslog == other.syslog && self.stdin == other.stdin
+    }
+}
+
+/// Which journal entries are read.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct JournalConfig {
+    /// Systemd units, e.g. `nginx.service`. Empty for all of them.
+    #[serde(default, deserialize_with = "string_or_list")]
+    pub units: Vec<String>,
+    /// Syslog identifiers, e.g. `sshd`.
+    #[serde(default, deserialize_with = "string_or_list")]
+    pub identifiers: Vec<String>,
+    /// Only entries of this priority or more important: `emerg`, `alert`,
+    /// `crit`, `err`, `warning`, `notice`, `info` or `debug`.
+    #[serde(default)]
+    pub priority: Option<String>,
+}
+
+/// Which Docker containers are read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DockerConfig {
+    /// Unix socket of the Docker API.
+    #[serde(default = "default_docker_socket")]
+    pub socket: String,
+    /// Container names, as regular expressions. Empty for all containers.
+    #[serde(default, deserialize_with = "string_or_list")]
+    pub names: Vec<String>,
+    /// Labels the containers must have, as `key` or `key=value`.
+    #[serde(default, deserialize_with = "string_or_list")]
+    pub labels: Vec<String>,
+}
+
+fn default_docker_socket() -> String {
+    "/var/run/docker.sock".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineFormat {
+    #[default]
+    Plain,
+    /// Each line is a JSON object. Lines that are not stay plain text.
+    Json,
+    /// Each line is `key=value` pairs, as many Go services write them.
+    Logfmt,
+}
+
+/// How lines are joined into entries. Exactly one of `start` and
+/// `continuation` is set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MultilineConfig {
+    /// Lines matching this regex start an entry, the others continue it.
+    #[serde(default)]
+    pub start: Option<String>,
+    /// Lines matching this regex continue the entry, the others start one.
+    #[serde(default)]
+    pub continuation: Option<String>,
+    /// Longest entry, in lines.
+    #[serde(default = "default_multiline_max_lines")]
+    pub max_lines: usize,
+    /// Milliseconds after which the last entry is complete when no line
+    /// follows it.
+    #[serde(default = "default_multiline_timeout")]
+    pub timeout: i64,
+}
+
+fn default_multiline_max_lines() -> usize {
+    500
+}
+
+fn default_multiline_timeout() -> i64 {
+    1000
+}
+
+/// Which pods to read, and how to reach the cluster.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KubernetesConfig {
+    /// By default the namespace of the service account or of the kubeconfig
+    /// context, else `default`.
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// Label selector of the pods, e.g. `app=web,tier!=cache`.
+    #[serde(default)]
+    pub selector: Option<String>,
+    /// Only read containers of this name.
+    #[serde(default)]
+    pub container: Option<String>,
+    /// Used outside of a cluster; `$KUBECONFIG` or `~/.kube/config` by
+    /// default.
+    #[serde(default)]
+    pub kubeconfig: Option<String>,
+}
+
+/// Where syslog messages are received.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyslogConfig {
+    /// Address to listen on, e.g. `0.0.0.0:514`.
+    pub bind: String,
+    #[serde(default)]
+    pub protocol: SyslogProtocol,
+    /// Only messages of this severity or more important, named like the
+    /// journal's priorities.
+    #[serde(default)]
+    pub severity: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogProtocol {
+    #[default]
+    Udp,
+    Tcp,
+    /// UDP and TCP on the same port.
+    Both,
+}
+
+fn default_sample_lines() -> usize {
+    5
+}
+
+fn default_max_line_length() -> usize {
+    500
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchMode {
+    /// Count raw file modification events.
+    #[default]
+    Event,
+    /// Read appended lines and count those matching the configured condition.
+    Tail,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmailConfig {
+    /// Severities of the alerts sent by mail, all of them when empty.
+    #[serde(default)]
+    pub severities: Vec<Severity>,
+    /// Collects alerts for this long and sends them in one summary mail.
+    #[serde(default)]
+    pub digest_seconds: Option<u64>,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Name of the notifier mails that could not be sent go to instead.
+    #[serde(default)]
+    pub fallback: Option<String>,
+    /// How mails leave this host.
+    #[serde(default)]
+    pub transport: EmailTransport,
+    /// Program of the `sendmail` transport.
+    #[serde(default = "default_sendmail_command")]
+    pub sendmail_command: String,
+    /// Only needed by the `smtp` transport.
+    #[serde(default)]
+    pub username: String,
+    /// Not needed when `oauth2` is set.
+    #[serde(default)]
+    pub password: String,
+    /// Authenticates with XOAUTH2 instead of the password.
+    #[serde(default)]
+    pub oauth2: Option<OAuth2Config>,
+    /// Host names of the SMTP servers, each tried in turn until one takes
+    /// the mail; `localhost` by default for a relay.
+    #[serde(default, deserialize_with = "string_or_list")]
+    pub smtp: Vec<String>,
+    /// Misspelling of `smtp` kept for older configurations, moved there
+    /// when the configuration is read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stmp: Option<String>,
+    /// Single recipient, kept for older configurations; see `to`.
+    #[serde(default)]
+    pub target: Option<String>,
+    #[serde(default, deserialize_with = "string_or_list")]
+    pub to: Vec<String>,
+    #[serde(default, deserialize_with = "string_or_list")]
+    pub cc: Vec<String>,
+    #[serde(default, deserialize_with = "string_or_list")]
+    pub bcc: Vec<String>,
+    /// Defaults to `username`.
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub reply_to: Option<String>,
+    /// Handlebars templates replacing the default subject and body.
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Sent alongside the text body when set.
+    #[serde(default)]
+    pub html_body: Option<String>,
+    /// Defaults to the usual port of the TLS mode.
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: TlsMode,
+    /// PEM file of further CAs to trust, e.g. the private one of the server.
+    #[serde(default)]
+    pub ca_file: Option<String>,
+    /// SHA-256 fingerprint of the certificate of the server, in hex, which
+    /// is then trusted whatever it says, e.g. a self-signed one.
+    #[serde(default)]
+    pub certificate_fingerprint: Option<String>,
+    /// Connection and command timeout.
+    #[serde(default)]
+    pub timeout_seconds: Option<u64>,
+    /// How long the connection to the server is kept open for further
+    /// mails, 0 to close it after each one.
+    #[serde(default = "default_idle_timeout_seconds")]
+    pub idle_timeout_seconds: u64,
+    #[serde(default)]
+    pub count_threshold: Option<i32>,
+    #[serde(default)]
+    pub time_threshold: Option<i64>,
+}
+
+impl EmailConfig {
+    /// The `To` addresses, including the legacy `target`.
+    pub fn recipients(&self) -> impl Iterator<Item = &String> {
+        self.target.iter().chain(self.to.iter())
+    }
+
+    pub fn port(&self) -> u16 {
+        if self.transport == EmailTransport::Relay {
+            return self.port.unwrap_or(25);
+        }
+        self.port.unwrap_or(match self.tls {
+            TlsMode::None => 25,
+            TlsMode::Starttls => 587,
+            TlsMode::Implicit => 465,
+        })
+    }
+}
+
+fn default_idle_timeout_seconds() -> u64 {
+    60
+}
+
+fn default_sendmail_command() -> String {
+    "/usr/sbin/sendmail".to_string()
+}
+
+/// How mails are handed on, for hosts that cannot log in to a server.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransport {
+    /// An SMTP server, logged in to with a password or OAuth2.
+    #[default]
+    Smtp,
+    /// An SMTP relay taking mails without a login, in plaintext, such as the
+    /// MTA on port 25 of localhost.
+    Relay,
+    /// The local MTA, through its `sendmail` program.
+    Sendmail,
+}
+
+/// Where XOAUTH2 access tokens come from: either a shell command printing one,
+/// or a refresh token exchanged at `token_url`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OAuth2Config {
+    #[serde(default)]
+    pub token_command: Option<String>,
+    #[serde(default = "default_token_url")]
+    pub token_url: String,
+    #[serde(default)]
+    pub client_id: String,
+    #[serde(default)]
+    pub client_secret: String,
+    #[serde(default)]
+    pub refresh_token: String,
+}
+
+fn default_token_url() -> String {
+    "https://oauth2.googleapis.com/token".to_string()
+}
+
+/// How the SMTP connection is secured.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsMode {
+    /// Plaintext, for trusted internal relays.
+    None,
+    /// Upgrade a plaintext connection with STARTTLS.
+    Starttls,
+    /// TLS from the first byte (SMTPS).
+    #[default]
+    Implicit,
+}
+
+/// At most `count` alerts per `per_seconds` through a notifier.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    pub count: u32,
+    pub per_seconds: u64,
+    /// Limits the alerts of each rule on its own.
+    #[serde(default)]
+    pub per_rule: bool,
+}
+
+/// A notifier under the name rules refer to it by.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotifierEntry {
+    /// Defaults to the notifier type.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Severities of the alerts it gets, all of them when empty.
+    #[serde(default)]
+    pub severities: Vec<Severity>,
+    /// Collects alerts for this long and sends them in one summary.
+    #[serde(default)]
+    pub digest_seconds: Option<u64>,
+    /// Drops alerts beyond a rate, so that a noisy log cannot flood it.
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Name of the notifier alerts that could not be sent go to instead.
+    #[serde(default)]
+    pub fallback: Option<String>,
+    #[serde(flatten)]
+    pub notifier: NotifierConfig,
+}
+
+/// A store matched lines are sent to in batches.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputEntry {
+    /// Defaults to the output type.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Ids of the logs whose lines are sent, all of them when empty.
+    #[serde(default)]
+    pub logs: Vec<String>,
+    /// Lines sent at most in one request.
+    #[serde(default = "default_output_batch")]
+    pub batch_size: usize,
+    /// Lines are sent at latest this long after they matched.
+    #[serde(default = "default_output_flush")]
+    pub flush_seconds: u64,
+    /// Lines kept while the store cannot be reached; the oldest ones are
+    /// dropped beyond.
+    #[serde(default = "default_output_buffer")]
+    pub max_buffer: usize,
+    #[serde(flatten)]
+    pub output: OutputConfig,
+}
+
+impl OutputEntry {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or_else(|| self.output.kind())
+    }
+}
+
+fn default_output_batch() -> usize {
+    500
+}
+
+fn default_output_flush() -> u64 {
+    5
+}
+
+fn default_output_buffer() -> usize {
+    10000
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OutputConfig {
+    Loki(LokiConfig),
+    Elasticsearch(ElasticsearchConfig),
+    Kafka(KafkaConfig),
+    Gelf(GelfConfig),
+}
+
+impl OutputConfig {
+    /// The `type` of the output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            OutputConfig::Loki(_) => "loki",
+            OutputConfig::Elasticsearch(_) => "elasticsearch",
+            OutputConfig::Kafka(_) => "kafka",
+            OutputConfig::Gelf(_) => "gelf",
+        }
+    }
+}
+
+/// Grafana Loki, pushed to with its HTTP API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LokiConfig {
+    /// e.g. `http://loki:3100`.
+    pub url: String,
+    /// Further labels of every stream, besides `log_id`, `rule` and `host`.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Sent as `X-Scope-OrgID` to multi-tenant Loki.
+    #[serde(default)]
+    pub tenant: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Elasticsearch or OpenSearch, written to with the bulk API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ElasticsearchConfig {
+    /// e.g. `http://elasticsearch:9200`.
+    pub url: String,
+    /// strftime escapes are replaced with the time of the line.
+    #[serde(default = "default_elasticsearch_index")]
+    pub index: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+fn default_elasticsearch_index() -> String {
+    "log-alarmer-%Y.%m.%d".to_string()
+}
+
+impl NotifierEntry {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or_else(|| self.notifier.kind())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotifierConfig {
+    Slack(SlackConfig),
+    Telegram(TelegramConfig),
+    Webhook(WebhookConfig),
+    PagerDuty(PagerDutyConfig),
+    Discord(DiscordConfig),
+    Teams(TeamsConfig),
+    DingTalk(DingTalkConfig),
+    WeCom(WeComConfig),
+    Feishu(FeishuConfig),
+    Twilio(TwilioConfig),
+    Ntfy(NtfyConfig),
+    Gotify(GotifyConfig),
+    Pushover(PushoverConfig),
+    Mqtt(MqttConfig),
+    Exec(ExecConfig),
+    Desktop(DesktopConfig),
+    Sns(SnsConfig),
+    Matrix(MatrixConfig),
+    Alertmanager(AlertmanagerConfig),
+    Sentry(SentryConfig),
+    Kafka(KafkaConfig),
+    Gelf(GelfConfig),
+    Zabbix(ZabbixConfig),
+    Snmp(SnmpConfig),
+    Grafana(GrafanaConfig),
+    Opsgenie(OpsgenieConfig),
+}
+
+impl NotifierConfig {
+    /// The `type` of the notifier.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NotifierConfig::Slack(_) => "slack",
+            NotifierConfig::Telegram(_) => "telegram",
+            NotifierConfig::Webhook(_) => "webhook",
+            NotifierConfig::PagerDuty(_) => "pagerduty",
+            NotifierConfig::Discord(_) => "discord",
+            NotifierConfig::Teams(_) => "teams",
+            NotifierConfig::DingTalk(_) => "dingtalk",
+            NotifierConfig::WeCom(_) => "wecom",
+            NotifierConfig::Feishu(_) => "feishu",
+            NotifierConfig::Twilio(_) => "twilio",
+            NotifierConfig::Ntfy(_) => "ntfy",
+            NotifierConfig::Gotify(_) => "gotify",
+            NotifierConfig::Pushover(_) => "pushover",
+            NotifierConfig::Mqtt(_) => "mqtt",
+            NotifierConfig::Exec(_) => "exec",
+            NotifierConfig::Desktop(_) => "desktop",
+            NotifierConfig::Sns(_) => "sns",
+            NotifierConfig::Matrix(_) => "matrix",
+            NotifierConfig::Alertmanager(_) => "alertmanager",
+            NotifierConfig::Sentry(_) => "sentry",
+            NotifierConfig::Kafka(_) => "kafka",
+            NotifierConfig::Gelf(_) => "gelf",
+            NotifierConfig::Zabbix(_) => "zabbix",
+            NotifierConfig::Snmp(_) => "snmp",
+            NotifierConfig::Grafana(_) => "grafana",
+            NotifierConfig::Opsgenie(_) => "opsgenie",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlackConfig {
+    pub webhook_url: String,
+    #[serde(default)]
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    /// Numeric chat id or `@channelusername`.
+    #[serde(deserialize_with = "string_or_number")]
+    pub chat_id: String,
+    #[serde(default = "default_telegram_api_url")]
+    pub api_url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default = "default_webhook_method")]
+    pub method: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// JSON body with `{{log_id}}`, `{{count}}`, `{{timestamp}}` and
+    /// `{{lines}}` placeholders. Defaults to an object with all of them.
+    #[serde(default)]
+    pub body: Option<serde_json::Value>,
+}
+
+fn default_webhook_method() -> String {
+    "POST".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PagerDutyConfig {
+    pub routing_key: String,
+    /// One of `critical`, `error`, `warning` or `info`.
+    #[serde(default = "default_pagerduty_severity")]
+    pub severity: String,
+    #[serde(default = "default_pagerduty_url")]
+    pub url: String,
+}
+
+fn default_pagerduty_severity() -> String {
+    "error".to_string()
+}
+
+fn default_pagerduty_url() -> String {
+    "https://events.pagerduty.com/v2/enqueue".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscordConfig {
+    pub webhook_url: String,
+    /// Replaces the name the webhook posts under.
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Sends embeds colored by severity, or else plain messages.
+    #[serde(default = "default_discord_embed")]
+    pub embed: bool,
+}
+
+fn default_discord_embed() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeamsConfig {
+    /// URL of an incoming webhook or of a Workflows webhook trigger.
+    pub webhook_url: String,
+}
+
+/// A DingTalk custom robot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DingTalkConfig {
+    /// `https://oapi.dingtalk.com/robot/send?access_token=...`
+    pub webhook_url: String,
+    /// Signs the requests, for robots secured with `加签`.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Mobile numbers of the members to mention.
+    #[serde(default)]
+    pub at_mobiles: Vec<String>,
+    /// Mentions everyone in the group.
+    #[serde(default)]
+    pub at_all: bool,
+}
+
+/// A WeChat Work (企业微信) group bot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeComConfig {
+    /// `https://qyapi.weixin.qq.com/cgi-bin/webhook/send?key=...`
+    pub webhook_url: String,
+    /// User ids of the members to mention.
+    #[serde(default)]
+    pub mentioned_users: Vec<String>,
+}
+
+/// A Feishu (Lark) custom bot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeishuConfig {
+    /// `https://open.feishu.cn/open-apis/bot/v2/hook/...`, or the same on
+    /// `open.larksuite.com`.
+    pub webhook_url: String,
+    /// Signs the requests, for bots with signature verification.
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+/// Text messages sent through Twilio.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TwilioConfig {
+    pub account_sid: String,
+    pub auth_token: String,
+    /// Twilio number the messages come from, e.g. `+15005550006`.
+    pub from: String,
+    /// Numbers to text, each in E.164 format.
+    #[serde(deserialize_with = "string_or_list")]
+    pub to: Vec<String>,
+    /// Also text when an alarm clears.
+    #[serde(default)]
+    pub send_resolved: bool,
+    #[serde(default = "default_twilio_api_url")]
+    pub api_url: String,
+}
+
+fn default_twilio_api_url() -> String {
+    "https://api.twilio.com".to_string()
+}
+
+/// An ntfy topic, on ntfy.sh or a server of its own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NtfyConfig {
+    /// URL of the topic, e.g. `https://ntfy.sh/my-alerts`.
+    pub url: String,
+    /// From 1 (min) to 5 (max). By default taken from the severity.
+    #[serde(default)]
+    pub priority: Option<u8>,
+    /// Access token of a protected topic.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// User of a protected topic, instead of a token.
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// An application on a Gotify server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GotifyConfig {
+    /// URL of the server, e.g. `https://gotify.example.com`.
+    pub url: String,
+    /// Token of the application.
+    pub token: String,
+    /// From 0 to 10. By default taken from the severity.
+    #[serde(default)]
+    pub priority: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PushoverConfig {
+    /// Key of the user or group notified.
+    pub user_key: String,
+    /// API token of the application sending.
+    pub app_token: String,
+    /// Pushover priority (-2 to 2) of each severity, instead of -1 for
+    /// `info`, 0 for `warning` and 1 for `critical`.
+    #[serde(default)]
+    pub priorities: BTreeMap<Severity, i8>,
+    /// How often emergency messages (priority 2) repeat until acknowledged.
+    #[serde(default = "default_pushover_retry")]
+    pub retry_seconds: u32,
+    /// How long emergency messages keep repeating.
+    #[serde(default = "default_pushover_expire")]
+    pub expire_seconds: u32,
+    /// Only notify this device of the user.
+    #[serde(default)]
+    pub device: Option<String>,
+    #[serde(default)]
+    pub sound: Option<String>,
+    #[serde(default = "default_pushover_api_url")]
+    pub api_url: String,
+}
+
+fn default_pushover_retry() -> u32 {
+    60
+}
+
+fn default_pushover_expire() -> u32 {
+    3600
+}
+
+fn default_pushover_api_url() -> String {
+    "https://api.pushover.net".to_string()
+}
+
+/// A topic of an MQTT broker alerts are published to as JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MqttConfig {
+    pub host: String,
+    /// Defaults to 1883, or 8883 with `tls`.
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// May hold placeholders, e.g. `alerts/{{log_id}}`.
+    pub topic: String,
+    /// 0 (at most once), 1 (at least once) or 2 (exactly once).
+    #[serde(default)]
+    pub qos: u8,
+    /// The broker keeps the last alert for new subscribers.
+    #[serde(default)]
+    pub retain: bool,
+    #[serde(default)]
+    pub tls: bool,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Defaults to `log-alarmer-<hostname>-<random number>`. Alerts are
+    /// published one at a time with a configured one.
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default = "default_mqtt_timeout")]
+    pub timeout_seconds: u64,
+}
+
+fn default_mqtt_timeout() -> u64 {
+    10
+}
+
+/// A command run for each alert, with the alert in its environment as
+/// `ALERT_*` variables and, as JSON, on its standard input.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecConfig {
+    /// Run by `sh -c`, or `cmd /C` on Windows.
+    pub command: String,
+    /// Further environment variables of the command.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    /// Writes the alert as JSON to the standard input of the command.
+    #[serde(default = "default_exec_stdin")]
+    pub stdin: bool,
+    /// The command is killed when it runs longer.
+    #[serde(default = "default_exec_timeout")]
+    pub timeout_seconds: u64,
+    /// Also runs the command when an alarm clears.
+    #[serde(default = "default_exec_send_resolved")]
+    pub send_resolved: bool,
+}
+
+fn default_exec_stdin() -> bool {
+    true
+}
+
+fn default_exec_timeout() -> u64 {
+    30
+}
+
+fn default_exec_send_resolved() -> bool {
+    true
+}
+
+/// Popups on the desktop the alarmer runs on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DesktopConfig {
+    /// Seconds the popup stays, 0 until it is closed. Left to the desktop
+    /// by default.
+    #[serde(default)]
+    pub timeout_seconds: Option<u32>,
+    /// An icon name or the path of an image, instead of one by severity.
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+/// A topic of AWS SNS. Credentials are taken from the environment, the
+/// shared credentials file or the role of the machine unless given here.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnsConfig {
+    /// e.g. `arn:aws:sns:eu-west-1:123456789012:alerts`.
+    pub topic_arn: String,
+    /// Defaults to the region of the topic.
+    #[serde(default)]
+    pub region: Option<String>,
+    /// A profile of the shared credentials file.
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+    #[serde(default)]
+    pub secret_access_key: Option<String>,
+    /// Instead of the regional endpoint, e.g. of LocalStack.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+}
+
+impl SnsConfig {
+    pub fn region(&self) -> Option<String> {
+        self.region.clone().or_else(|| self.topic_arn.split(':').nth(3).map(str::to_string))
+    }
+}
+
+/// A room on a Matrix homeserver, posted to as the user of the token.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatrixConfig {
+    /// e.g. `https://matrix.example.org`.
+    pub homeserver: String,
+    pub access_token: String,
+    /// e.g. `!AbCdEf:example.org`, or an alias like `#ops:example.org`.
+    pub room_id: String,
+    /// Sends notices, which clients show less prominently and bots ignore.
+    #[serde(default = "default_matrix_notice")]
+    pub notice: bool,
+}
+
+fn default_matrix_notice() -> bool {
+    true
+}
+
+/// Prometheus Alertmanager, or the instances of an Alertmanager cluster.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertmanagerConfig {
+    /// e.g. `http://alertmanager:9093`, or a list of them.
+    #[serde(deserialize_with = "string_or_list")]
+    pub url: Vec<String>,
+    /// Further labels of every alert, e.g. `team: shop`.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Alerts end this long after they fired unless sent again. Left to
+    /// the `resolve_timeout` of Alertmanager by default.
+    #[serde(default)]
+    pub ends_after_seconds: Option<u64>,
+}
+
+/// A Sentry project, which receives alerts as events.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SentryConfig {
+    /// e.g. `https://key@o0.ingest.sentry.io/123`.
+    pub dsn: String,
+    #[serde(default)]
+    pub environment: Option<String>,
+    #[serde(default)]
+    pub release: Option<String>,
+}
+
+/// A Kafka topic alerts, or matched lines as an output, are produced to
+/// as JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KafkaConfig {
+    /// `host:port` of the brokers to find the others from.
+    #[serde(deserialize_with = "string_or_list")]
+    pub brokers: Vec<String>,
+    pub topic: String,
+    /// -1 (all in-sync replicas), 1 (the leader) or 0 (no acknowledgement).
+    #[serde(default = "default_kafka_acks")]
+    pub acks: i16,
+    #[serde(default)]
+    pub tls: bool,
+    /// Attempts after the first for messages the brokers did not take.
+    #[serde(default = "default_kafka_retries")]
+    pub retries: u32,
+    #[serde(default = "default_kafka_timeout")]
+    pub timeout_seconds: u64,
+    /// Defaults to `log-alarmer`.
+    #[serde(default)]
+    pub client_id: Option<String>,
+}
+
+fn default_kafka_acks() -> i16 {
+    -1
+}
+
+fn default_kafka_retries() -> u32 {
+    3
+}
+
+fn default_kafka_timeout() -> u64 {
+    10
+}
+
+/// A GELF input of Graylog, sent alerts, or matched lines as an output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GelfConfig {
+    /// `host:port` of the input, e.g. `graylog:12201`.
+    pub address: String,
+    #[serde(default)]
+    pub protocol: GelfProtocol,
+    /// Only over TCP.
+    #[serde(default)]
+    pub tls: bool,
+    /// Further fields of every message, named without the leading `_`.
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+    #[serde(default = "default_gelf_timeout")]
+    pub timeout_seconds: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GelfProtocol {
+    #[default]
+    Udp,
+    Tcp,
+}
+
+fn default_gelf_timeout() -> u64 {
+    10
+}
+
+/// A trapper item of Zabbix, submitted values like `zabbix_sender` does.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ZabbixConfig {
+    /// The server or proxy, `host[:port]` with port 10051 by default.
+    pub server: String,
+    /// The host of the item as named in Zabbix, by default the name of
+    /// this machine.
+    #[serde(default)]
+    pub host: Option<String>,
+    /// May hold placeholders, e.g. `log.alarm[{{log_id}}]`.
+    #[serde(default = "default_zabbix_key")]
+    pub key: String,
+    /// Submitted for an alert, defaults to its summary. May hold
+    /// placeholders, e.g. `{{count}}` for a numeric item.
+    #[serde(default)]
+    pub value: Option<String>,
+    /// Submitted when an alarm clears; nothing is by default.
+    #[serde(default)]
+    pub resolved_value: Option<String>,
+    #[serde(default = "default_zabbix_timeout")]
+    pub timeout_seconds: u64,
+}
+
+fn default_zabbix_key() -> String {
+    "log.alarmer".to_string()
+}
+
+fn default_zabbix_timeout() -> u64 {
+    10
+}
+
+/// An SNMP manager sent alerts as traps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnmpConfig {
+    /// `host[:port]`, with port 162 by default.
+    pub target: String,
+    /// `2c` or `3`.
+    #[serde(default = "default_snmp_version", deserialize_with = "string_or_number")]
+    pub version: String,
+    /// Of version 2c.
+    #[serde(default = "default_snmp_community")]
+    pub community: String,
+    /// The user of version 3, known to the manager with the engine id.
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub auth_protocol: Option<SnmpAuth>,
+    #[serde(default)]
+    pub auth_password: Option<String>,
+    /// Encrypts traps with AES-128, along with authentication.
+    #[serde(default)]
+    pub priv_password: Option<String>,
+    /// In hex. Defaults to one made of the name of this machine.
+    #[serde(default)]
+    pub engine_id: Option<String>,
+    /// Prefix of the OIDs of the traps and their objects.
+    #[serde(default = "default_snmp_enterprise_oid")]
+    pub enterprise_oid: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnmpAuth {
+    Md5,
+    /// SHA-1.
+    Sha,
+    Sha256,
+}
+
+fn default_snmp_version() -> String {
+    "2c".to_string()
+}
+
+fn default_snmp_community() -> String {
+    "public".to_string()
+}
+
+fn default_snmp_enterprise_oid() -> String {
+    // netSnmpPlaypen, for experiments and local MIBs.
+    "1.3.6.1.4.1.8072.9999.9999.1".to_string()
+}
+
+/// Grafana, whose HTTP API annotates dashboards with alerts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GrafanaConfig {
+    /// e.g. `https://grafana.example.com`.
+    pub url: String,
+    /// Of a service account that may write annotations.
+    pub token: String,
+    /// Annotations show on this dashboard only, or on all of them that
+    /// query annotations by tag when unset.
+    #[serde(default)]
+    pub dashboard_uid: Option<String>,
+    /// Of the dashboard, to show on one panel only.
+    #[serde(default)]
+    pub panel_id: Option<u64>,
+    /// Further tags, besides the log id, rule and severity.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Opsgenie, whose Alerts API creates alerts and closes them on resolution.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpsgenieConfig {
+    /// Of an API integration.
+    pub api_key: String,
+    /// `https://api.eu.opsgenie.com` for accounts in the EU.
+    #[serde(default = "default_opsgenie_url")]
+    pub url: String,
+    /// `P1` to `P5` by severity, P1 for critical, P3 for warning and P5 for
+    /// info alerts unless set.
+    #[serde(default)]
+    pub priorities: BTreeMap<Severity, String>,
+    /// Further tags, besides the log id, rule and severity.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The team the alerts are routed to, if not by the integration.
+    #[serde(default)]
+    pub team: Option<String>,
+}
+
+fn default_opsgenie_url() -> String {
+    "https://api.opsgenie.com".to_string()
+}
+
+/// Accepts ids written in YAML either as numbers or as strings.
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        Str(String),
+        Int(i64),
+    }
+    Ok(match Id::deserialize(deserializer)? {
+        Id::Str(s) => s,
+        Id::Int(i) => i.to_string(),
+    })
+}
+
+/// Accepts either a single string or a list of strings.
+fn string_or_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrList {
+        String(String),
+        List(Vec<String>),
+    }
+    Ok(match StringOrList::deserialize(deserializer)? {
+        StringOrList::String(value) => vec![value],
+        StringOrList::List(values) => values,
+    })
+}
+
+fn default_telegram_api_url() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+/// The languages a configuration file can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl Format {
+    /// The format of the file at `path`, by its extension: TOML for `.toml`,
+    /// JSON for `.json` and YAML for anything else.
+    pub fn of(path: &str) -> Format {
+        match Path::new(path).extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("toml") => Format::Toml,
+            Some(extension) if extension.eq_ignore_ascii_case("json") => Format::Json,
+            _ => Format::Yaml,
+        }
+    }
+
+    /// Reads a configuration written in this format, failing with the
+    /// message and line of the first syntax or type error.
+    pub(crate) fn parse(self, text: &str) -> Result<Config, (String, Option<usize>)> {
+        match self {
+            Format::Yaml => serde_yaml::from_str(text)
+                .map_err(|e| (e.to_string(), e.location().map(|location| location.line()))),
+            Format::Toml => toml::from_str(text).map_err(|e| {
+                let line = e.span().map(|span| line_at(text, span.start));
+                let message = match line {
+                    Some(line) => format!("{} at line {}", e.message().trim_end(), line),
+                    None => e.message().trim_end().to_string(),
+                };
+                (message, line)
+            }),
+            Format::Json => serde_json::from_str(text)
+                .map_err(|e| (e.to_string(), Some(e.line()).filter(|line| *line > 0))),
+        }
+    }
+}
+
+/// The line, counted from 1, of the byte at `offset` in `text`.
+pub(crate) fn line_at(text: &str, offset: usize) -> usize {
+    text.as_bytes()[..offset.min(text.len())].iter().filter(|byte| **byte == b'\n').count() + 1
+}
+
+/// Reads the configuration file at `path`, in the format given by its
+/// extension, filling it in from the environment.
+pub fn read_configuration(path: &str) -> Result<Config, Error> {
+    let text = std::fs::read_to_string(path).map_err(|source| Error::Read { path: path.to_string(), source })?;
+    let d = Format::of(path).parse(&text)
+        .map_err(|(message, line)| Error::Parse { path: path.to_string(), message, line })?;
+    let mut d = interpolate::apply(d)?;
+    d.migrate();
+    if let Some((setting, message)) = grok::expand(&mut d).into_iter().next() {
+        return Err(Error::Config(format!("{}: {}", setting, message)));
+    }
+    Ok(d)
+}
@@ -0,0 +1,100 @@
+use chrono::{Datelike, NaiveDateTime, Timelike};
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const DAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// A cron expression of minute, hour, day of month, month and day of week,
+/// e.g. `* 9-17 * * mon-fri`, telling which minutes it covers.
+///
+/// Fields are `*`, numbers, ranges such as `9-17` and lists of them, each
+/// with an optional step such as `*/15`. Months and days of the week may be
+/// named; Sunday is 0 or 7. As in cron, a minute matches either restricted
+/// day field when both are.
+#[derive(Debug, Clone)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    pub fn parse(text: &str) -> Result<Cron, String> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("{} has {} fields, expected 5: minute hour day month weekday", text, fields.len()));
+        }
+        let mut weekdays = field(fields[4], 0, 7, &DAYS)?;
+        // Sunday is both 0 and 7.
+        if weekdays & 1 << 7 != 0 {
+            weekdays |= 1;
+        }
+        Ok(Cron {
+            minutes: field(fields[0], 0, 59, &[])?,
+            hours: field(fields[1], 0, 23, &[])?,
+            days: field(fields[2], 1, 31, &[])?,
+            months: field(fields[3], 1, 12, &MONTHS)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    /// Whether the minute of `at` is covered.
+    pub fn contains(&self, at: NaiveDateTime) -> bool {
+        let has = |set: u64, value: u32| set & 1 << value != 0;
+        let day = has(self.days, at.day());
+        let weekday = has(self.weekdays, at.weekday().num_days_from_sunday());
+        let day = if self.any_day || self.any_weekday { day && weekday } else { day || weekday };
+        day && has(self.minutes, at.minute()) && has(self.hours, at.hour()) && has(self.months, at.month())
+    }
+}
+
+/// The values of one field as bits, from `min` to `max`. `names` are the
+/// names of the values from `min` on.
+fn field(text: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let mut set = 0;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("invalid step {} in {}", step, text))?;
+                if step == 0 {
+                    return Err(format!("step 0 in {}", text));
+                }
+                (range, step)
+            },
+            None => (part, 1),
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (value(from, min, max, names)?, value(to, min, max, names)?),
+            // `5/10` runs from 5 to the end.
+            None if step > 1 => (value(range, min, max, names)?, max),
+            None => {
+                let value = value(range, min, max, names)?;
+                (value, value)
+            },
+        };
+        if from > to {
+            return Err(format!("range {} runs backwards", range));
+        }
+        for value in (from..=to).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+fn value(text: &str, min: u32, max: u32, names: &[&str]) -> Result<u32, String> {
+    let lower = text.to_lowercase();
+    if let Some(index) = names.iter().position(|name| *name == lower) {
+        return Ok(min + index as u32);
+    }
+    match text.parse() {
+        Ok(value) if (min..=max).contains(&value) => Ok(value),
+        _ => Err(format!("{} is not between {} and {}", text, min, max)),
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use chrono::Local;
+use tracing::info;
+
+use crate::config::Severity;
+use crate::metrics;
+use crate::notify::digest::Digest;
+use crate::notify::{Alert, Notifier};
+use crate::ratelimit::RateLimiter;
+
+/// A configured notifier, by the name rules refer to it.
+struct Channel {
+    name: String,
+    /// Severities of the alerts it gets, all of them when empty.
+    severities: Vec<Severity>,
+    notifier: Arc<dyn Notifier>,
+    /// Collects the alerts routed to the notifier, when it sends digests.
+    digest: Option<Arc<Digest>>,
+    limit: Option<Arc<RateLimiter>>,
+}
+
+/// Decides which notifiers alerts are sent through.
+pub(crate) struct Dispatcher {
+    configured: Vec<Channel>,
+    /// The notifier each notifier falls back to, by name.
+    fallbacks: HashMap<String, String>,
+    /// Labels added to every alert.
+    labels: BTreeMap<String, String>,
+    /// Notifiers registered by the embedding program get every alert.
+    custom: Vec<Arc<dyn Notifier>>,
+}
+
+impl Dispatcher {
+    /// `configured` are named notifiers, subscribed to the severities in
+    /// `subscriptions`, sending the `digests` and limited by the `limits`
+    /// under their name.
+    pub fn new(
+        configured: Vec<(String, Arc<dyn Notifier>)>,
+        subscriptions: &HashMap<String, Vec<Severity>>,
+        digests: &HashMap<String, Arc<Digest>>,
+        limits: &HashMap<String, Arc<RateLimiter>>,
+        fallbacks: &HashMap<String, String>,
+        labels: &BTreeMap<String, String>,
+        custom: Vec<Arc<dyn Notifier>>,
+    ) -> Dispatcher {
+        let configured = configured.into_iter()
+            .map(|(name, notifier)| Channel {
+                severities: subscriptions.get(&name).cloned().unwrap_or_default(),
+                digest: digests.get(&name).filter(|digest| !digest.retired()).cloned(),
+                limit: limits.get(&name).cloned(),
+                name,
+                notifier,
+            })
+            .collect();
+        Dispatcher { configured, fallbacks: fallbacks.clone(), labels: labels.clone(), custom }
+    }
+
+    /// Adds the configured labels to `alert`.
+    pub fn label(&self, alert: &mut Alert) {
+        alert.labels.extend(self.labels.iter().map(|(name, value)| (name.clone(), value.clone())));
+    }
+
+    /// Every notifier, sending at once.
+    pub fn all(&self) -> Vec<Arc<dyn Notifier>> {
+        self.configured.iter()
+            .map(|channel| channel.notifier.clone())
+            .chain(self.custom.iter().cloned())
+            .collect()
+    }
+
+    /// The configured notifier called `name`, sending at once.
+    pub fn channel(&self, name: &str) -> Option<Arc<dyn Notifier>> {
+        self.configured.iter()
+            .find(|channel| channel.name == name)
+            .map(|channel| channel.notifier.clone())
+    }
+
+    /// The notifier that takes over when the one called `name` fails.
+    pub fn fallback(&self, name: &str) -> Option<Arc<dyn Notifier>> {
+        self.channel(self.fallbacks.get(name)?)
+    }
+
+    /// The notifiers `alert` goes to: those named in `targets`, or all of
+    /// them, that subscribed to its severity and are within their rate
+    /// limit. Alerts for notifiers sending digests go to their digest
+    /// instead.
+    pub fn route(&self, targets: &[String], alert: &Alert) -> Vec<Arc<dyn Notifier>> {
+        self.select(alert, |channel| routed(channel, targets, alert))
+    }
+
+    /// The notifiers a reminder goes to: those of [`Dispatcher::route`] and
+    /// the ones in `escalation`, whatever they subscribed to.
+    pub fn escalate(&self, targets: &[String], escalation: &[String], alert: &Alert) -> Vec<Arc<dyn Notifier>> {
+        self.select(alert, |channel| routed(channel, targets, alert) || escalation.contains(&channel.name))
+    }
+
+    fn select(&self, alert: &Alert, selected: impl Fn(&Channel) -> bool) -> Vec<Arc<dyn Notifier>> {
+        self.configured.iter()
+            .filter(|channel| selected(channel) && self.allow(channel, alert))
+            .map(|channel| match &channel.digest {
+                Some(digest) => digest.clone() as Arc<dyn Notifier>,
+                None => channel.notifier.clone(),
+            })
+            .chain(self.custom.iter().cloned())
+            .collect()
+    }
+
+    /// Takes a token from the rate limit of `channel`, if it has one.
+    /// Resolutions are never held back.
+    fn allow(&self, channel: &Channel, alert: &Alert) -> bool {
+        let limit = match &channel.limit {
+            Some(limit) if !alert.resolved => limit,
+            _ => return true,
+        };
+        if limit.allow(&format!("{}/{}", alert.log_id, alert.rule), Local::now().timestamp_millis()) {
+            return true;
+        }
+        info!("Alert for {} not sent via {}, over its rate limit.", alert.log_id, channel.name);
+        metrics::rate_limited(&channel.name);
+        false
+    }
+}
+
+fn routed(channel: &Channel, targets: &[String], alert: &Alert) -> bool {
+    (targets.is_empty() || targets.contains(&channel.name))
+        && (channel.severities.is_empty() || channel.severities.contains(&alert.severity))
+}
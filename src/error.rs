@@ -0,0 +1,42 @@
+use std::io;
+
+use thiserror::Error;
+
+/// Why the configuration could not be used or the logs could not be
+/// watched.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to read configuration {path}: {source}")]
+    Read { path: String, source: io::Error },
+    /// The configuration file is malformed, at `line` if known.
+    #[error("failed to parse configuration {path}: {message}")]
+    Parse { path: String, message: String, line: Option<usize> },
+    /// The configuration was read but is not valid.
+    #[error("{0}")]
+    Config(String),
+    /// A setting taken from the environment, by the variable or the
+    /// setting it went into.
+    #[error("{setting}: {message}")]
+    Environment { setting: String, message: String },
+    #[error("invalid regex: {0}")]
+    Regex(#[from] regex::Error),
+    #[error("failed to serve HTTP on {bind}: {source}")]
+    Http { bind: String, source: io::Error },
+    /// The file events kept failing.
+    #[error("file watch failed: {0}")]
+    Watch(io::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::Config(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Error {
+        Error::Config(message.to_string())
+    }
+}
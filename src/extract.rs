@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::condition::lookup;
+use crate::config::ExtractConfig;
+
+/// Takes values out of matching lines, such as the user or the order id,
+/// so alerts tell what went wrong.
+#[derive(Clone)]
+pub struct Extractor {
+    patterns: Vec<Regex>,
+    fields: Vec<(String, Vec<String>)>,
+}
+
+impl Extractor {
+    pub fn new(config: &ExtractConfig) -> Result<Extractor, String> {
+        let mut patterns = Vec::new();
+        for pattern in &config.patterns {
+            let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+            if regex.capture_names().flatten().next().is_none() {
+                return Err(format!("pattern {} has no named group, e.g. (?P<user>\\w+)", pattern));
+            }
+            if let Some(name) = regex.capture_names().flatten().find(|name| !is_name(name)) {
+                return Err(format!("group {} is not a name of letters, digits and _", name));
+            }
+            patterns.push(regex);
+        }
+        for name in config.fields.keys() {
+            if !is_name(name) {
+                return Err(format!("{} is not a name of letters, digits and _", name));
+            }
+        }
+        let fields = config.fields.iter()
+            .map(|(name, path)| (name.clone(), path.split('.').map(String::from).collect()))
+            .collect();
+        Ok(Extractor { patterns, fields })
+    }
+
+    /// The values found in `line` and its `fields`, by name. A later
+    /// pattern or field wins over an earlier one of the same name.
+    pub fn extract(&self, line: &str, fields: Option<&Map<String, Value>>) -> BTreeMap<String, String> {
+        let mut values = BTreeMap::new();
+        for regex in &self.patterns {
+            if let Some(captures) = regex.captures(line) {
+                for name in regex.capture_names().flatten() {
+                    if let Some(value) = captures.name(name) {
+                        values.insert(name.to_string(), value.as_str().to_string());
+                    }
+                }
+            }
+        }
+        for (name, path) in &self.fields {
+            match fields.and_then(|fields| lookup(fields, path)) {
+                Some(Value::String(value)) => values.insert(name.clone(), value.clone()),
+                Some(Value::Null) | None => continue,
+                Some(value) => values.insert(name.clone(), value.to_string()),
+            };
+        }
+        values
+    }
+}
+
+/// Names of values are valid as template variables and Alertmanager
+/// labels alike.
+pub(crate) fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
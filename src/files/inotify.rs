@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+use futures_util::{FutureExt, StreamExt};
+use inotify::{EventMask, EventOwned, EventStream, Inotify, WatchDescriptor, WatchMask, Watches};
+
+use super::{Backend, Event, Events, WatchId};
+
+/// Watches paths with Linux inotify.
+pub struct InotifyBackend {
+    events: EventStream<Vec<u8>>,
+    watches: Watches,
+    descriptors: HashMap<WatchId, WatchDescriptor>,
+}
+
+impl InotifyBackend {
+    pub fn new(buffer_size: usize) -> io::Result<InotifyBackend> {
+        let events = Inotify::init()?.into_event_stream(vec![0u8; buffer_size])?;
+        Ok(InotifyBackend { watches: events.watches(), events, descriptors: HashMap::new() })
+    }
+
+    fn add(&mut self, path: &Path, mask: WatchMask) -> io::Result<WatchId> {
+        let wd = self.watches.add(path, mask)?;
+        let id = wd.get_watch_descriptor_id() as WatchId;
+        self.descriptors.insert(id, wd);
+        Ok(id)
+    }
+
+    fn translate(&mut self, event: EventOwned) -> Option<(WatchId, Event)> {
+        let EventOwned { wd, mask, name, .. } = event;
+        let id = wd.get_watch_descriptor_id() as WatchId;
+        let event = if mask.contains(EventMask::Q_OVERFLOW) {
+            Event::Overflow
+        } else if mask.contains(EventMask::MODIFY) {
+            Event::Modified
+        } else if mask.contains(EventMask::ATTRIB) {
+            Event::Attrib
+        } else if mask.intersects(EventMask::DELETE_SELF | EventMask::MOVE_SELF) {
+            Event::Removed
+        } else if mask.contains(EventMask::IGNORED) {
+            self.descriptors.remove(&id);
+            Event::Ignored
+        } else if mask.intersects(EventMask::CREATE | EventMask::MOVED_TO) {
+            Event::Created(name?)
+        } else {
+            return None;
+        };
+        Some((id, event))
+    }
+}
+
+impl Backend for InotifyBackend {
+    fn watch_file(&mut self, path: &Path) -> io::Result<WatchId> {
+        self.add(path, WatchMask::MODIFY | WatchMask::ATTRIB | WatchMask::DELETE_SELF | WatchMask::MOVE_SELF)
+    }
+
+    fn watch_dir(&mut self, path: &Path) -> io::Result<WatchId> {
+        self.add(path, WatchMask::CREATE | WatchMask::MOVED_TO)
+    }
+
+    #[allow(unused_must_use)]
+    fn remove(&mut self, watch: WatchId) {
+        if let Some(wd) = self.descriptors.remove(&watch) {
+            self.watches.remove(wd);
+        }
+    }
+
+    fn next(&mut self) -> Events<'_> {
+        Box::pin(async move {
+            let first = self.events.next().await
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "inotify stream closed"))?;
+            let mut batch = vec![first];
+            while let Some(Some(event)) = self.events.next().now_or_never() {
+                batch.push(event);
+            }
+            let mut events = Vec::new();
+            for event in batch {
+                events.extend(self.translate(event?));
+            }
+            // A burst of changes, e.g. `chmod` and `chown` by a rotation tool,
+            // is handled once.
+            let mut seen = Vec::new();
+            events.retain(|event| {
+                let new = !seen.contains(event);
+                if new {
+                    seen.push(event.clone());
+                }
+                new
+            });
+            Ok(events)
+        })
+    }
+}
@@ -0,0 +1,548 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::time::{Duration, SystemTime};
+
+use tracing::{debug, info, warn};
+
+use crate::config::WatchBackend;
+
+#[cfg(target_os = "linux")]
+mod inotify;
+mod notify;
+
+/// What happened to a watched file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    Modified,
+    Attrib,
+    /// The file was renamed away or deleted, e.g. by log rotation.
+    Removed,
+    /// A file appeared again at the watched path.
+    Created,
+}
+
+impl Change {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Change::Modified => "modify",
+            Change::Attrib => "attrib",
+            Change::Removed => "removed",
+            Change::Created => "created",
+        }
+    }
+}
+
+/// Identifies a watch of a [`Backend`].
+pub type WatchId = u64;
+
+/// What a [`Backend`] reports about a watch.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Modified,
+    Attrib,
+    /// The watched file was renamed away or deleted.
+    Removed,
+    /// An entry with this name appeared in the watched directory.
+    Created(OsString),
+    /// The watch is gone, e.g. because the directory was deleted.
+    Ignored,
+    /// Events of all watches were lost, as the queue of the system
+    /// overflowed. Its watch id means nothing.
+    Overflow,
+}
+
+/// The events of the next batch, per watch.
+pub type Events<'a> = Pin<Box<dyn Future<Output = io::Result<Vec<(WatchId, Event)>>> + Send + 'a>>;
+
+/// Platform specific source of file change events.
+///
+/// Watching a path that is already watched returns the same id.
+pub trait Backend: Send {
+    /// Watches a file for changes to its content and for being removed.
+    fn watch_file(&mut self, path: &Path) -> io::Result<WatchId>;
+
+    /// Watches a directory for new entries.
+    fn watch_dir(&mut self, path: &Path) -> io::Result<WatchId>;
+
+    fn remove(&mut self, watch: WatchId);
+
+    /// Waits for the next events.
+    fn next(&mut self) -> Events<'_>;
+}
+
+/// How often watched files are checked for changes that no event reported.
+const SILENCE_CHECK: i64 = 10_000;
+
+/// Polling interval of paths whose events stopped, in milliseconds.
+const FALLBACK_POLL: i64 = 1000;
+
+/// What polling compares to tell whether a path changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Stat {
+    len: u64,
+    modified: Option<SystemTime>,
+    inode: u64,
+}
+
+impl Stat {
+    fn of(path: &Path) -> Option<Stat> {
+        let metadata = std::fs::metadata(path).ok()?;
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(&metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+        Some(Stat { len: metadata.len(), modified: metadata.modified().ok(), inode })
+    }
+
+    /// Whether the last change is old enough that its event must have
+    /// arrived by now.
+    fn settled(&self) -> bool {
+        let recent = SystemTime::now() - Duration::from_secs(1);
+        self.modified.is_none_or(|modified| modified < recent)
+    }
+}
+
+/// A path checked with `stat` instead of being watched.
+struct Polled {
+    interval: i64,
+    due: i64,
+    stat: Option<Stat>,
+    dir: bool,
+}
+
+/// Tells whether a watched path received events since the last check.
+struct Activity {
+    stat: Option<Stat>,
+    active: bool,
+}
+
+/// Watches a set of file paths, following them across rotation.
+///
+/// While a path is missing its parent directory is watched instead, so the
+/// file is picked up again as soon as it is recreated. If the directory is
+/// missing too, or watching it again failed, [`FileWatcher::retry_missing`]
+/// tries again. Directories
+/// added with [`FileWatcher::add_dir`] report `Created` whenever an entry
+/// appears, and `Removed` when they are deleted.
+///
+/// Paths added with [`FileWatcher::add_polled`] are compared to their last
+/// `stat` instead, as are watched files that change without any event
+/// arriving, e.g. on network file systems.
+pub struct FileWatcher {
+    backend: Box<dyn Backend>,
+    paths: HashMap<usize, PathBuf>,
+    next_index: usize,
+    files: HashMap<WatchId, Vec<usize>>,
+    /// The inode each file watch is on.
+    inodes: HashMap<WatchId, u64>,
+    dirs: HashMap<WatchId, Vec<usize>>,
+    /// Directories whose new entries are reported.
+    scans: HashMap<WatchId, Vec<usize>>,
+    /// Paths whose directory is missing as well.
+    missing: Vec<usize>,
+    /// Files still in place that could not be watched again, e.g. after
+    /// their attributes changed.
+    failed: Vec<usize>,
+    polled: HashMap<usize, Polled>,
+    activity: HashMap<usize, Activity>,
+    next_check: i64,
+}
+
+fn parent_of(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+impl FileWatcher {
+    /// Creates the watcher, reading inotify events into a buffer of
+    /// `buffer_size` bytes. Must be called within a tokio runtime.
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    pub fn new(backend: WatchBackend, buffer_size: usize) -> io::Result<FileWatcher> {
+        let backend: Box<dyn Backend> = match backend {
+            #[cfg(target_os = "linux")]
+            WatchBackend::Inotify => Box::new(inotify::InotifyBackend::new(buffer_size)?),
+            #[cfg(not(target_os = "linux"))]
+            WatchBackend::Inotify => {
+                return Err(io::Error::new(io::ErrorKind::Unsupported, "inotify is only available on Linux"));
+            },
+            WatchBackend::Notify => Box::new(notify::NotifyBackend::new()?),
+        };
+        Ok(FileWatcher {
+            backend,
+            paths: HashMap::new(),
+            next_index: 0,
+            files: HashMap::new(),
+            inodes: HashMap::new(),
+            dirs: HashMap::new(),
+            scans: HashMap::new(),
+            missing: Vec::new(),
+            failed: Vec::new(),
+            polled: HashMap::new(),
+            activity: HashMap::new(),
+            next_check: 0,
+        })
+    }
+
+    /// Starts watching `path` and returns the index used to report its changes.
+    pub fn add(&mut self, path: &str) -> io::Result<usize> {
+        let index = self.next_index;
+        self.next_index += 1;
+        self.paths.insert(index, PathBuf::from(path));
+        if let Err(e) = self.watch_file(index) {
+            self.paths.remove(&index);
+            return Err(e);
+        }
+        Ok(index)
+    }
+
+    /// Starts watching the directory `path` for new entries and returns the
+    /// index used to report them.
+    pub fn add_dir(&mut self, path: &Path) -> io::Result<usize> {
+        let wd = self.backend.watch_dir(path)?;
+        let index = self.next_index;
+        self.next_index += 1;
+        self.paths.insert(index, path.to_path_buf());
+        self.scans.entry(wd).or_default().push(index);
+        Ok(index)
+    }
+
+    /// Starts checking `path` every `interval` milliseconds instead of
+    /// watching it and returns the index used to report its changes. A
+    /// directory reports `Created` whenever its entries change.
+    pub fn add_polled(&mut self, path: &Path, interval: i64, dir: bool) -> usize {
+        let index = self.next_index;
+        self.next_index += 1;
+        self.paths.insert(index, path.to_path_buf());
+        self.polled.insert(index, Polled { interval, due: 0, stat: Stat::of(path), dir });
+        index
+    }
+
+    /// Stops watching the path registered under `index`.
+    pub fn remove(&mut self, index: usize) {
+        self.paths.remove(&index);
+        self.missing.retain(|i| *i != index);
+        self.failed.retain(|i| *i != index);
+        self.polled.remove(&index);
+        self.activity.remove(&index);
+        self.unwatch(index);
+    }
+
+    /// Drops the watches of `index`.
+    fn unwatch(&mut self, index: usize) {
+        let file = self.files.iter().find(|(_, indexes)| indexes.contains(&index)).map(|(wd, _)| *wd);
+        if let Some(wd) = file {
+            let indexes = self.files.get_mut(&wd).unwrap();
+            indexes.retain(|i| *i != index);
+            if indexes.is_empty() {
+                self.files.remove(&wd);
+                self.inodes.remove(&wd);
+                self.backend.remove(wd);
+            }
+        }
+        let dir = self.dirs.iter().find(|(_, indexes)| indexes.contains(&index)).map(|(wd, _)| *wd);
+        if let Some(wd) = dir {
+            self.unwatch_dir(&wd, index);
+        }
+        let scan = self.scans.iter().find(|(_, indexes)| indexes.contains(&index)).map(|(wd, _)| *wd);
+        if let Some(wd) = scan {
+            let indexes = self.scans.get_mut(&wd).unwrap();
+            indexes.retain(|i| *i != index);
+            if indexes.is_empty() {
+                self.scans.remove(&wd);
+                if !self.dirs.contains_key(&wd) {
+                    self.backend.remove(wd);
+                }
+            }
+        }
+    }
+
+    /// Watches the file itself, or its parent directory if it is missing.
+    /// Returns true if the file is being watched.
+    fn watch_file(&mut self, index: usize) -> io::Result<bool> {
+        let path = match self.paths.get(&index) {
+            Some(path) => path.clone(),
+            None => return Ok(false),
+        };
+        match self.backend.watch_file(&path) {
+            Ok(wd) => {
+                if let Some(stat) = Stat::of(&path) {
+                    self.inodes.insert(wd, stat.inode);
+                }
+                self.files.entry(wd).or_default().push(index);
+                return Ok(true);
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+        let wd = match self.backend.watch_dir(parent_of(&path)) {
+            Ok(wd) => wd,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                if !self.missing.contains(&index) {
+                    self.missing.push(index);
+                }
+                return Ok(false);
+            },
+            Err(e) => return Err(e),
+        };
+        self.dirs.entry(wd).or_default().push(index);
+        // The file may have been created before the directory watch was in place.
+        if path.exists() {
+            self.unwatch_dir(&wd, index);
+            return self.watch_file(index);
+        }
+        Ok(false)
+    }
+
+    fn unwatch_dir(&mut self, wd: &WatchId, index: usize) {
+        if let Some(waiting) = self.dirs.get_mut(wd) {
+            waiting.retain(|i| *i != index);
+            if waiting.is_empty() {
+                self.dirs.remove(wd);
+                if !self.scans.contains_key(wd) {
+                    self.backend.remove(*wd);
+                }
+            }
+        }
+    }
+
+    fn unwatch_file(&mut self, wd: &WatchId) -> Vec<usize> {
+        let indexes = self.files.remove(wd).unwrap_or_default();
+        self.inodes.remove(wd);
+        self.backend.remove(*wd);
+        indexes
+    }
+
+    /// Tries again to watch paths whose directory was missing, reporting
+    /// `Created` for files that appeared meanwhile, and files that could
+    /// not be watched again, reporting what they missed.
+    pub fn retry_missing(&mut self) -> Vec<(usize, Change)> {
+        let mut changes = Vec::new();
+        for index in std::mem::take(&mut self.missing) {
+            match self.watch_file(index) {
+                Ok(true) => changes.push((index, Change::Created)),
+                Ok(false) => (),
+                Err(e) => {
+                    debug!("Still failing to watch {}: {}", self.paths[&index].display(), e);
+                    self.missing.push(index);
+                },
+            }
+        }
+        for index in std::mem::take(&mut self.failed) {
+            match self.watch_file(index) {
+                Ok(true) => changes.push((index, Change::Modified)),
+                Ok(false) => changes.push((index, Change::Removed)),
+                Err(e) => {
+                    debug!("Still failing to watch {}: {}", self.paths[&index].display(), e);
+                    self.failed.push(index);
+                },
+            }
+        }
+        changes
+    }
+
+    /// Watches a file again after an event, returning true if it is being
+    /// watched. If that fails it is left to [`FileWatcher::retry_missing`],
+    /// as missing or, if it is still `present`, as failed.
+    fn rewatch(&mut self, index: usize, present: bool) -> bool {
+        match self.watch_file(index) {
+            Ok(watched) => watched,
+            Err(e) => {
+                warn!("Failed to watch {} again, retrying: {}", self.paths[&index].display(), e);
+                if present {
+                    self.failed.push(index);
+                } else if !self.missing.contains(&index) {
+                    self.missing.push(index);
+                }
+                false
+            },
+        }
+    }
+
+    /// Checks the polled paths that are due at `now` (milliseconds), and
+    /// from time to time whether watched paths changed without events,
+    /// polling them from then on if so.
+    pub fn poll(&mut self, now: i64) -> Vec<(usize, Change)> {
+        let mut changes = Vec::new();
+        for (index, polled) in self.polled.iter_mut() {
+            if polled.due > now {
+                continue;
+            }
+            polled.due = now + polled.interval;
+            let current = Stat::of(&self.paths[index]);
+            let previous = std::mem::replace(&mut polled.stat, current);
+            if current == previous {
+                continue;
+            }
+            if polled.dir {
+                changes.push((*index, Change::Created));
+                continue;
+            }
+            match (previous, current) {
+                (None, _) => changes.push((*index, Change::Created)),
+                (_, None) => changes.push((*index, Change::Removed)),
+                (Some(previous), Some(current)) if previous.inode != current.inode => {
+                    changes.push((*index, Change::Removed));
+                    changes.push((*index, Change::Created));
+                },
+                _ => changes.push((*index, Change::Modified)),
+            }
+        }
+        if now >= self.next_check {
+            self.next_check = now + SILENCE_CHECK;
+            changes.extend(self.check_silent(now));
+        }
+        changes
+    }
+
+    fn check_silent(&mut self, now: i64) -> Vec<(usize, Change)> {
+        let watched = self.files.values().flatten().map(|index| (*index, false))
+            .chain(self.scans.values().flatten().map(|index| (*index, true)))
+            .collect::<Vec<_>>();
+        let mut changes = Vec::new();
+        for (index, dir) in watched {
+            let current = Stat::of(&self.paths[&index]);
+            let activity = self.activity.entry(index).or_insert(Activity { stat: current, active: true });
+            let silent = !activity.active && activity.stat != current && current.is_some_and(|stat| stat.settled());
+            activity.stat = current;
+            activity.active = false;
+            if !silent {
+                continue;
+            }
+            info!("No file events for {}, polling it instead", self.paths[&index].display());
+            self.unwatch(index);
+            self.activity.remove(&index);
+            self.polled.insert(index, Polled { interval: FALLBACK_POLL, due: now + FALLBACK_POLL, stat: current, dir });
+            changes.push((index, if dir { Change::Created } else { Change::Modified }));
+        }
+        changes
+    }
+
+    /// Watches the file at `index` again if it is no longer the one of the
+    /// `watched` inode, reporting it as replaced or removed. Returns true
+    /// if it is still the same file.
+    fn follow(&mut self, index: usize, watched: Option<u64>, changes: &mut Vec<(usize, Change)>) -> bool {
+        let current = Stat::of(&self.paths[&index]).map(|stat| stat.inode);
+        if current.is_some() && current == watched {
+            return true;
+        }
+        self.unwatch(index);
+        if self.rewatch(index, current.is_some()) {
+            changes.push((index, Change::Removed));
+            changes.push((index, Change::Created));
+        } else if current.is_none() {
+            changes.push((index, Change::Removed));
+        }
+        false
+    }
+
+    /// Finds what lost events would have told: watched files are read or
+    /// followed to their replacement, files waited for are picked up when
+    /// present and watched directories are scanned.
+    fn resync(&mut self) -> Vec<(usize, Change)> {
+        let mut changes = Vec::new();
+        for (wd, indexes) in self.files.clone() {
+            let watched = self.inodes.get(&wd).copied();
+            for index in indexes {
+                if self.follow(index, watched, &mut changes) {
+                    changes.push((index, Change::Modified));
+                }
+            }
+        }
+        for (wd, indexes) in self.dirs.clone() {
+            for index in indexes {
+                if !self.paths[&index].exists() {
+                    continue;
+                }
+                self.unwatch_dir(&wd, index);
+                if self.rewatch(index, false) {
+                    changes.push((index, Change::Created));
+                }
+            }
+        }
+        changes.extend(self.scans.values().flatten().map(|index| (*index, Change::Created)));
+        changes
+    }
+
+    /// Waits for the next events and returns the resulting changes per watched path.
+    pub async fn next_changes(&mut self) -> io::Result<Vec<(usize, Change)>> {
+        let mut changes = Vec::new();
+        for (wd, event) in self.backend.next().await? {
+            changes.extend(self.handle(wd, event));
+        }
+        for (index, _) in &changes {
+            if let Some(activity) = self.activity.get_mut(index) {
+                activity.active = true;
+            }
+        }
+        Ok(changes)
+    }
+
+    fn handle(&mut self, wd: WatchId, event: Event) -> Vec<(usize, Change)> {
+        let mut changes = Vec::new();
+        if event == Event::Overflow {
+            warn!("File events were lost, as too many arrived at once; checking every watched path");
+            return self.resync();
+        }
+        if let Some(indexes) = self.files.get(&wd).cloned() {
+            match event {
+                Event::Modified => changes.extend(indexes.iter().map(|i| (*i, Change::Modified))),
+                Event::Attrib => {
+                    let watched = self.inodes.get(&wd).copied();
+                    for index in indexes {
+                        changes.push((index, Change::Attrib));
+                        // A file deleted or replaced while still open, e.g. by
+                        // its tailer, only tells of its link count changing.
+                        // Other attribute changes leave the watch alone.
+                        self.follow(index, watched, &mut changes);
+                    }
+                },
+                Event::Removed => {
+                    self.unwatch_file(&wd);
+                    for index in indexes {
+                        changes.push((index, Change::Removed));
+                        if self.rewatch(index, false) {
+                            changes.push((index, Change::Created));
+                        }
+                    }
+                },
+                Event::Created(_) | Event::Ignored | Event::Overflow => (),
+            }
+            return changes;
+        }
+        let name = match event {
+            Event::Created(name) => name,
+            // The directory itself is gone.
+            Event::Ignored => {
+                if let Some(indexes) = self.dirs.remove(&wd) {
+                    self.missing.extend(indexes);
+                }
+                if let Some(indexes) = self.scans.remove(&wd) {
+                    changes.extend(indexes.iter().map(|i| (*i, Change::Removed)));
+                }
+                return changes;
+            },
+            _ => return changes,
+        };
+        if let Some(indexes) = self.scans.get(&wd) {
+            changes.extend(indexes.iter().map(|i| (*i, Change::Created)));
+        }
+        if let Some(indexes) = self.dirs.get(&wd).cloned() {
+            for index in indexes {
+                if self.paths.get(&index).and_then(|p| p.file_name()) != Some(name.as_os_str()) {
+                    continue;
+                }
+                self.unwatch_dir(&wd, index);
+                if self.rewatch(index, false) {
+                    changes.push((index, Change::Created));
+                }
+            }
+        }
+        changes
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tracing::warn;
+
+use super::{Backend, Event, Events, WatchId};
+
+/// Watches paths with the native API of the platform through the `notify`
+/// crate: FSEvents on macOS, ReadDirectoryChangesW on Windows, kqueue on
+/// the BSDs and inotify on Linux.
+pub struct NotifyBackend {
+    watcher: RecommendedWatcher,
+    events: UnboundedReceiver<notify::Result<notify::Event>>,
+    /// Watched paths, normalized, with their id and whether they are directories.
+    watches: HashMap<PathBuf, (WatchId, bool)>,
+    next_id: WatchId,
+}
+
+fn io_error(error: notify::Error) -> io::Error {
+    match error.kind {
+        notify::ErrorKind::Io(e) => e,
+        notify::ErrorKind::PathNotFound => io::Error::from(io::ErrorKind::NotFound),
+        _ => io::Error::other(error),
+    }
+}
+
+/// Resolves `path` the way the platform reports it in events, also when
+/// it no longer exists.
+fn normalize(path: &Path) -> PathBuf {
+    if let Ok(path) = fs::canonicalize(path) {
+        return path;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => fs::canonicalize(parent).map_or_else(|_| path.to_path_buf(), |parent| parent.join(name)),
+        _ => path.to_path_buf(),
+    }
+}
+
+impl NotifyBackend {
+    pub fn new() -> io::Result<NotifyBackend> {
+        let (sender, events) = mpsc::unbounded_channel();
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        }).map_err(io_error)?;
+        Ok(NotifyBackend { watcher, events, watches: HashMap::new(), next_id: 1 })
+    }
+
+    fn add(&mut self, path: &Path, dir: bool) -> io::Result<WatchId> {
+        let path = normalize(path);
+        if let Some((id, _)) = self.watches.get(&path) {
+            return Ok(*id);
+        }
+        self.watcher.watch(&path, RecursiveMode::NonRecursive).map_err(io_error)?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.watches.insert(path, (id, dir));
+        Ok(id)
+    }
+
+    fn watched(&self, path: &Path, dir: bool) -> Option<WatchId> {
+        self.watches.get(path).filter(|(_, is_dir)| *is_dir == dir).map(|(id, _)| *id)
+    }
+
+    /// An entry appeared at `path`.
+    fn appeared(&mut self, path: &Path, events: &mut Vec<(WatchId, Event)>) {
+        let path = normalize(path);
+        if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+            if let Some(id) = self.watched(parent, true) {
+                events.push((id, Event::Created(name.to_os_string())));
+            }
+        }
+    }
+
+    /// The entry at `path` was renamed away or deleted.
+    fn gone(&mut self, path: &Path, events: &mut Vec<(WatchId, Event)>) {
+        let path = normalize(path);
+        if let Some(id) = self.watched(&path, false) {
+            events.push((id, Event::Removed));
+        }
+        if let Some(id) = self.watched(&path, true) {
+            self.remove(id);
+            events.push((id, Event::Ignored));
+        }
+    }
+
+    fn translate(&mut self, event: notify::Event, events: &mut Vec<(WatchId, Event)>) {
+        if event.need_rescan() {
+            events.push((0, Event::Overflow));
+            return;
+        }
+        let paths = event.paths;
+        match event.kind {
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                paths.iter().for_each(|path| self.appeared(path, events));
+            },
+            EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                paths.iter().for_each(|path| self.gone(path, events));
+            },
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths.len() == 2 => {
+                self.gone(&paths[0], events);
+                self.appeared(&paths[1], events);
+            },
+            // Some platforms do not tell which side of a rename a path is.
+            EventKind::Modify(ModifyKind::Name(_)) => {
+                for path in &paths {
+                    match path.exists() {
+                        true => self.appeared(path, events),
+                        false => self.gone(path, events),
+                    }
+                }
+            },
+            EventKind::Modify(ModifyKind::Metadata(_)) => {
+                for path in paths {
+                    if let Some(id) = self.watched(&normalize(&path), false) {
+                        events.push((id, Event::Attrib));
+                    }
+                }
+            },
+            EventKind::Modify(_) | EventKind::Any => {
+                for path in paths {
+                    if let Some(id) = self.watched(&normalize(&path), false) {
+                        events.push((id, Event::Modified));
+                    }
+                }
+            },
+            EventKind::Access(_) | EventKind::Other => (),
+        }
+    }
+}
+
+impl Backend for NotifyBackend {
+    fn watch_file(&mut self, path: &Path) -> io::Result<WatchId> {
+        self.add(path, false)
+    }
+
+    fn watch_dir(&mut self, path: &Path) -> io::Result<WatchId> {
+        self.add(path, true)
+    }
+
+    fn remove(&mut self, watch: WatchId) {
+        let path = self.watches.iter().find(|(_, (id, _))| *id == watch).map(|(path, _)| path.clone());
+        if let Some(path) = path {
+            self.watches.remove(&path);
+            let _ = self.watcher.unwatch(&path);
+        }
+    }
+
+    fn next(&mut self) -> Events<'_> {
+        Box::pin(async move {
+            let first = self.events.recv().await
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "file watcher stopped"))?;
+            let mut batch = vec![first];
+            while let Ok(event) = self.events.try_recv() {
+                batch.push(event);
+            }
+            let mut events = Vec::new();
+            for event in batch {
+                match event {
+                    Ok(event) => self.translate(event, &mut events),
+                    Err(e) => warn!("File watch error: {}", e),
+                }
+            }
+            // A change is often reported for both the file and its directory.
+            let mut seen = Vec::new();
+            events.retain(|event| {
+                let new = !seen.contains(event);
+                if new {
+                    seen.push(event.clone());
+                }
+                new
+            });
+            Ok(events)
+        })
+    }
+}
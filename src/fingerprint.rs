@@ -0,0 +1,49 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+/// Tells alerts about the same error apart from alerts about others, so
+/// repeats of one error can be held back.
+#[derive(Clone)]
+pub struct Fingerprinter {
+    pub window: i64,
+    pattern: Option<Regex>,
+}
+
+impl Fingerprinter {
+    /// With `pattern`, a line's fingerprint is its first capture group, or
+    /// the whole match without groups. Other lines are normalized instead.
+    pub fn new(window: i64, pattern: Option<&str>) -> Result<Fingerprinter, regex::Error> {
+        Ok(Fingerprinter { window, pattern: pattern.map(Regex::new).transpose()? })
+    }
+
+    pub fn fingerprint(&self, line: &str) -> String {
+        let captured = self.pattern.as_ref().and_then(|pattern| {
+            let captures = pattern.captures(line)?;
+            captures.get(1).or_else(|| captures.get(0)).map(|found| found.as_str().to_string())
+        });
+        captured.unwrap_or_else(|| normalize(line))
+    }
+}
+
+/// Replaces what usually differs between repeats of the same message, such
+/// as ids, hashes, numbers and times, with placeholders.
+fn normalize(line: &str) -> String {
+    static VARIABLE: OnceLock<Vec<(Regex, &str)>> = OnceLock::new();
+    let variable = VARIABLE.get_or_init(|| {
+        [
+            (r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}", "<uuid>"),
+            (r"\b0x[0-9a-fA-F]+\b", "<hex>"),
+            (r"\b[0-9a-fA-F]*[0-9][0-9a-fA-F]*[a-fA-F][0-9a-fA-F]*\b", "<hex>"),
+            (r"\d+", "<n>"),
+        ]
+        .iter()
+        .map(|&(pattern, placeholder)| (Regex::new(pattern).unwrap(), placeholder))
+        .collect()
+    });
+    let mut line = line.trim().to_string();
+    for (pattern, placeholder) in variable {
+        line = pattern.replace_all(&line, *placeholder).into_owned();
+    }
+    line
+}
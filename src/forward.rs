@@ -0,0 +1,316 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use chrono::{DateTime, Local};
+use serde_json::{json, Map, Value};
+use tracing::warn;
+
+use crate::config::{ElasticsearchConfig, GelfConfig, KafkaConfig, LokiConfig, OutputConfig, OutputEntry, Severity};
+use crate::metrics;
+use crate::notify::gelf;
+use crate::notify::kafka::{self, Message, Refused};
+use crate::notify::{hostname, NotifyError};
+
+/// A matched line on its way to the outputs.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub log_id: String,
+    /// The first rule the line matched, and its severity.
+    pub rule: String,
+    pub severity: Severity,
+    pub line: String,
+    pub fields: Option<Map<String, Value>>,
+    pub timestamp: DateTime<Local>,
+}
+
+struct Buffer {
+    records: VecDeque<Record>,
+    /// Unix time in milliseconds lines were last sent, or tried to be.
+    last_flush: i64,
+    /// A batch is on its way, so the next one waits to keep the order.
+    sending: bool,
+}
+
+/// One store lines are shipped to, with the lines waiting for it.
+pub struct Output {
+    config: OutputEntry,
+    buffer: Mutex<Buffer>,
+}
+
+impl Output {
+    fn new(config: OutputEntry, now: i64) -> Output {
+        let buffer = Buffer { records: VecDeque::new(), last_flush: now, sending: false };
+        Output { config, buffer: Mutex::new(buffer) }
+    }
+
+    pub fn name(&self) -> &str {
+        self.config.name()
+    }
+
+    fn accepts(&self, log_id: &str) -> bool {
+        self.config.logs.is_empty() || self.config.logs.iter().any(|id| id == log_id)
+    }
+
+    /// Adds records to the buffer, dropping the oldest ones beyond its
+    /// size. Failed batches are put back `in_front`.
+    fn add(&self, records: Vec<Record>, in_front: bool) {
+        let mut buffer = self.buffer.lock().unwrap();
+        if in_front {
+            for record in records.into_iter().rev() {
+                buffer.records.push_front(record);
+            }
+        } else {
+            buffer.records.extend(records);
+        }
+        let excess = buffer.records.len().saturating_sub(self.config.max_buffer);
+        if excess > 0 {
+            buffer.records.drain(..excess);
+            metrics::forward_dropped(self.name(), excess as u64);
+            warn!("Dropped {} lines for {}, as its buffer is full.", excess, self.name());
+        }
+    }
+
+    /// Takes the next batch if it is full or has waited long enough.
+    fn take(&self, now: i64, all: bool) -> Option<Vec<Record>> {
+        let mut buffer = self.buffer.lock().unwrap();
+        let waited = now - buffer.last_flush >= self.config.flush_seconds as i64 * 1000;
+        let due = buffer.records.len() >= self.config.batch_size || (waited && !buffer.records.is_empty());
+        if buffer.sending || !(due || all && !buffer.records.is_empty()) {
+            return None;
+        }
+        let count = buffer.records.len().min(self.config.batch_size.max(1));
+        buffer.sending = true;
+        buffer.last_flush = now;
+        Some(buffer.records.drain(..count).collect())
+    }
+
+    /// Sends a batch taken from the buffer, putting it back on failure to
+    /// be tried again with the next one. Returns false in that case.
+    pub fn send(&self, batch: Vec<Record>) -> bool {
+        // Only Elasticsearch rejects some lines of a batch and takes the others.
+        let result = match &self.config.output {
+            OutputConfig::Loki(loki) => push_loki(loki, &batch).map(|()| 0),
+            OutputConfig::Elasticsearch(elasticsearch) => push_elasticsearch(elasticsearch, &batch),
+            OutputConfig::Kafka(config) => push_kafka(config, &batch).map(|()| 0),
+            OutputConfig::Gelf(config) => push_gelf(config, &batch).map(|()| 0),
+        };
+        let sent = match result {
+            Ok(rejected) => {
+                metrics::forwarded(self.name(), (batch.len() - rejected) as u64);
+                if rejected > 0 {
+                    metrics::forward_dropped(self.name(), rejected as u64);
+                }
+                true
+            },
+            // Sending rejected lines again would not help.
+            Err(e) if rejected(&e) => {
+                metrics::forward_dropped(self.name(), batch.len() as u64);
+                warn!("{} rejected {} lines, dropping them: {}", self.name(), batch.len(), e);
+                true
+            },
+            Err(e) => {
+                warn!("Failed to send {} lines to {}, keeping them: {}", batch.len(), self.name(), e);
+                self.add(batch, true);
+                false
+            },
+        };
+        self.buffer.lock().unwrap().sending = false;
+        sent
+    }
+}
+
+/// Collects matched lines for every output and hands them out in batches.
+#[derive(Default)]
+pub struct Forwarder {
+    outputs: Mutex<Vec<Arc<Output>>>,
+}
+
+impl Forwarder {
+    /// Takes the outputs of a new configuration, keeping the lines waiting
+    /// for outputs that did not change.
+    pub fn configure(&self, configs: &[OutputEntry]) {
+        let now = Local::now().timestamp_millis();
+        let mut outputs = self.outputs.lock().unwrap();
+        let mut old = std::mem::take(&mut *outputs);
+        for config in configs {
+            match old.iter().position(|output| output.config == *config) {
+                Some(position) => outputs.push(old.remove(position)),
+                None => outputs.push(Arc::new(Output::new(config.clone(), now))),
+            }
+        }
+        for output in old {
+            let left = output.buffer.lock().unwrap().records.len();
+            if left > 0 {
+                warn!("Dropping {} lines for {}, which is no longer configured.", left, output.name());
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.lock().unwrap().is_empty()
+    }
+
+    /// Queues a matched line for every output taking lines of its log.
+    pub fn push(&self, record: Record) {
+        for output in self.outputs.lock().unwrap().iter().filter(|output| output.accepts(&record.log_id)) {
+            output.add(vec![record.clone()], false);
+        }
+    }
+
+    /// The batches to send now.
+    pub fn due(&self, now: i64) -> Vec<(Arc<Output>, Vec<Record>)> {
+        self.outputs.lock().unwrap().iter()
+            .filter_map(|output| output.take(now, false).map(|batch| (output.clone(), batch)))
+            .collect()
+    }
+
+    /// Sends every line left, e.g. when shutting down, giving up on an
+    /// output once it fails.
+    pub fn flush(&self) {
+        let outputs = self.outputs.lock().unwrap().clone();
+        for output in outputs {
+            while let Some(batch) = output.take(Local::now().timestamp_millis(), true) {
+                if !output.send(batch) {
+                    let left = output.buffer.lock().unwrap().records.len();
+                    warn!("Dropping {} lines for {} on shutdown.", left, output.name());
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Whether the store refused the request itself, rather than being busy
+/// or out of reach.
+fn rejected(error: &NotifyError) -> bool {
+    match error.downcast_ref::<ureq::Error>() {
+        Some(ureq::Error::Status(code, _)) => (400..500).contains(code) && *code != 408 && *code != 429,
+        _ => error.is::<Refused>(),
+    }
+}
+
+fn request(method: &str, url: &str, headers: &BTreeMap<String, String>) -> ureq::Request {
+    let mut request = ureq::request(method, url);
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+    request
+}
+
+/// Pushes lines as streams labelled by log, rule and host.
+fn push_loki(config: &LokiConfig, batch: &[Record]) -> Result<(), NotifyError> {
+    let host = hostname();
+    let mut streams: BTreeMap<(&str, &str), Vec<Value>> = BTreeMap::new();
+    for record in batch {
+        let nanos = record.timestamp.timestamp_nanos_opt().unwrap_or_default();
+        streams.entry((&record.log_id, &record.rule)).or_default().push(json!([nanos.to_string(), record.line]));
+    }
+    let streams: Vec<Value> = streams.into_iter()
+        .map(|((log_id, rule), values)| {
+            let mut labels: Map<String, Value> = config.labels.iter().map(|(k, v)| (k.clone(), json!(v))).collect();
+            labels.insert("log_id".to_string(), json!(log_id));
+            labels.insert("rule".to_string(), json!(rule));
+            labels.insert("host".to_string(), json!(host));
+            json!({ "stream": labels, "values": values })
+        })
+        .collect();
+    let url = format!("{}/loki/api/v1/push", config.url.trim_end_matches('/'));
+    let mut request = request("POST", &url, &config.headers);
+    if let Some(tenant) = &config.tenant {
+        request = request.set("X-Scope-OrgID", tenant);
+    }
+    request.send_json(json!({ "streams": streams }))?;
+    Ok(())
+}
+
+/// Indexes lines as documents with the bulk API.
+fn push_elasticsearch(config: &ElasticsearchConfig, batch: &[Record]) -> Result<usize, NotifyError> {
+    let host = hostname();
+    let mut body = String::new();
+    for record in batch {
+        let mut index = String::new();
+        if write!(index, "{}", record.timestamp.format(&config.index)).is_err() {
+            index = config.index.clone();
+        }
+        let mut document = json!({
+            "@timestamp": record.timestamp.to_rfc3339(),
+            "message": record.line,
+            "log_id": record.log_id,
+            "rule": record.rule,
+            "host": host,
+        });
+        if let Some(fields) = &record.fields {
+            document["fields"] = Value::Object(fields.clone());
+        }
+        body.push_str(&json!({ "create": { "_index": index } }).to_string());
+        body.push('\n');
+        body.push_str(&document.to_string());
+        body.push('\n');
+    }
+    let url = format!("{}/_bulk", config.url.trim_end_matches('/'));
+    let mut request = request("POST", &url, &config.headers).set("Content-Type", "application/x-ndjson");
+    if let Some(username) = &config.username {
+        let credentials = format!("{}:{}", username, config.password.as_deref().unwrap_or_default());
+        let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+        request = request.set("Authorization", &format!("Basic {}", encoded));
+    }
+    let response: Value = request.send_string(&body)?.into_json()?;
+    // The bulk API answers 200 even when documents were rejected, which
+    // are left out rather than sent again. Returns how many.
+    if response["errors"].as_bool() != Some(true) {
+        return Ok(0);
+    }
+    let errors: Vec<&Value> = response["items"].as_array().into_iter().flatten()
+        .map(|item| &item["create"]["error"])
+        .filter(|error| !error.is_null())
+        .collect();
+    let reason = errors.first().and_then(|error| error["reason"].as_str()).unwrap_or("unknown error");
+    warn!("Elasticsearch rejected {} of {} lines: {}", errors.len(), batch.len(), reason);
+    Ok(errors.len().min(batch.len()))
+}
+
+/// Produces lines as JSON messages, keyed by their log.
+fn push_kafka(config: &KafkaConfig, batch: &[Record]) -> Result<(), NotifyError> {
+    let host = hostname();
+    let mut messages = Vec::new();
+    for record in batch {
+        let mut value = json!({
+            "timestamp": record.timestamp.to_rfc3339(),
+            "log_id": record.log_id,
+            "rule": record.rule,
+            "line": record.line,
+            "host": host,
+        });
+        if let Some(fields) = &record.fields {
+            value["fields"] = Value::Object(fields.clone());
+        }
+        messages.push(Message {
+            key: record.log_id.clone(),
+            value: serde_json::to_vec(&value)?,
+            timestamp: record.timestamp.timestamp_millis(),
+        });
+    }
+    kafka::produce(config, &messages)
+}
+
+/// Sends lines as GELF messages, their first line as the short message.
+fn push_gelf(config: &GelfConfig, batch: &[Record]) -> Result<(), NotifyError> {
+    let messages = batch.iter()
+        .map(|record| {
+            let short = record.line.lines().next().unwrap_or_default();
+            let full = Some(record.line.as_str()).filter(|line| line.contains('\n'));
+            let mut message = gelf::message(short, full, record.severity, record.timestamp);
+            gelf::add_field(&mut message, "log_id", &json!(record.log_id));
+            gelf::add_field(&mut message, "rule", &json!(record.rule));
+            for (name, value) in record.fields.iter().flatten() {
+                if name != "log_id" && name != "rule" {
+                    gelf::add_field(&mut message, name, value);
+                }
+            }
+            message
+        })
+        .collect();
+    gelf::send(config, messages)
+}
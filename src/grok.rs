@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+
+use crate::config::{Config, GrokConfig, LogConfigs};
+
+/// How deep patterns may refer to one another, so loops are caught.
+const MAX_DEPTH: usize = 32;
+
+/// The patterns every configuration can use, in the format of pattern
+/// files: a name, a space and a regex per line.
+const LIBRARY: &str = r#"
+USERNAME [a-zA-Z0-9._-]+
+USER %{USERNAME}
+EMAILLOCALPART [a-zA-Z0-9!#$%&'*+/=?^_`{|}~-]+(?:\.[a-zA-Z0-9!#$%&'*+/=?^_`{|}~-]+)*
+EMAILADDRESS %{EMAILLOCALPART}@%{HOSTNAME}
+INT [+-]?[0-9]+
+BASE10NUM [+-]?(?:[0-9]+(?:\.[0-9]+)?|\.[0-9]+)
+NUMBER %{BASE10NUM}
+BASE16NUM [+-]?(?:0x)?[0-9A-Fa-f]+
+BASE16FLOAT \b[+-]?(?:0x)?(?:[0-9A-Fa-f]+(?:\.[0-9A-Fa-f]*)?|\.[0-9A-Fa-f]+)\b
+POSINT \b[1-9][0-9]*\b
+NONNEGINT \b[0-9]+\b
+WORD \b\w+\b
+NOTSPACE \S+
+SPACE \s*
+DATA .*?
+GREEDYDATA .*
+QUOTEDSTRING "(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*'|`(?:[^`\\]|\\.)*`
+UUID [A-Fa-f0-9]{8}-(?:[A-Fa-f0-9]{4}-){3}[A-Fa-f0-9]{12}
+URN urn:[0-9A-Za-z][0-9A-Za-z-]{0,31}:(?:%[0-9a-fA-F]{2}|[0-9A-Za-z()+,.:=@;$_!*'/?#-])+
+MAC %{CISCOMAC}|%{WINDOWSMAC}|%{COMMONMAC}
+CISCOMAC (?:[A-Fa-f0-9]{4}\.){2}[A-Fa-f0-9]{4}
+WINDOWSMAC (?:[A-Fa-f0-9]{2}-){5}[A-Fa-f0-9]{2}
+COMMONMAC (?:[A-Fa-f0-9]{2}:){5}[A-Fa-f0-9]{2}
+IPV6 (?:[0-9A-Fa-f]{1,4}:){7}[0-9A-Fa-f]{1,4}|(?:[0-9A-Fa-f]{1,4}:){1,7}:|(?:[0-9A-Fa-f]{1,4}:){1,6}:[0-9A-Fa-f]{1,4}|(?:[0-9A-Fa-f]{1,4}:){1,5}(?::[0-9A-Fa-f]{1,4}){1,2}|(?:[0-9A-Fa-f]{1,4}:){1,4}(?::[0-9A-Fa-f]{1,4}){1,3}|(?:[0-9A-Fa-f]{1,4}:){1,3}(?::[0-9A-Fa-f]{1,4}){1,4}|(?:[0-9A-Fa-f]{1,4}:){1,2}(?::[0-9A-Fa-f]{1,4}){1,5}|[0-9A-Fa-f]{1,4}:(?::[0-9A-Fa-f]{1,4}){1,6}|:(?:(?::[0-9A-Fa-f]{1,4}){1,7}|:)
+IPV4 \b(?:(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])\.){3}(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])\b
+IP %{IPV4}|%{IPV6}
+HOSTNAME \b[0-9A-Za-z][0-9A-Za-z-]{0,62}(?:\.[0-9A-Za-z][0-9A-Za-z-]{0,62})*\.?\b
+IPORHOST %{IP}|%{HOSTNAME}
+HOSTPORT %{IPORHOST}:%{POSINT}
+PATH %{UNIXPATH}|%{WINPATH}
+UNIXPATH (?:/[\w_%!$@:.,+~-]*)+
+TTY /dev/(?:pts|tty(?:[pq])?)(?:\w+)?/?(?:[0-9]+)
+WINPATH (?:[A-Za-z]+:|\\)(?:\\[^\\?*]*)+
+URIPROTO [A-Za-z](?:[A-Za-z0-9+\-.]+)+
+URIHOST %{IPORHOST}(?::%{POSINT})?
+URIPATH (?:/[A-Za-z0-9$.+!*'(){},~:;=@#%&_\-]*)+
+URIQUERY [A-Za-z0-9$.+!*'|(){},~@#%&/=:;_?\-\[\]<>]*
+URIPARAM \?%{URIQUERY}
+URIPATHPARAM %{URIPATH}(?:\?%{URIQUERY})?
+URI %{URIPROTO}://(?:%{USER}(?::[^@]*)?@)?%{URIHOST}(?:%{URIPATHPARAM})?
+MONTH \b(?:[Jj]an(?:uary|uar)?|[Ff]eb(?:ruary|ruar)?|[Mm](?:a|ä)?r(?:ch|z)?|[Aa]pr(?:il)?|[Mm]a(?:y|i)?|[Jj]un(?:e|i)?|[Jj]ul(?:y|i)?|[Aa]ug(?:ust)?|[Ss]ep(?:tember)?|[Oo](?:c|k)?t(?:ober)?|[Nn]ov(?:ember)?|[Dd]e(?:c|z)(?:ember)?)\b
+MONTHNUM 0?[1-9]|1[0-2]
+MONTHNUM2 0[1-9]|1[0-2]
+MONTHDAY (?:0[1-9])|(?:[12][0-9])|(?:3[01])|[1-9]
+DAY \b(?:Mon(?:day)?|Tue(?:sday)?|Wed(?:nesday)?|Thu(?:rsday)?|Fri(?:day)?|Sat(?:urday)?|Sun(?:day)?)\b
+YEAR (?:\d\d){1,2}
+HOUR 2[0123]|[01]?[0-9]
+MINUTE [0-5][0-9]
+SECOND (?:[0-5]?[0-9]|60)(?:[:.,][0-9]+)?
+TIME %{HOUR}:%{MINUTE}(?::%{SECOND})?
+DATE_US %{MONTHNUM}[/-]%{MONTHDAY}[/-]%{YEAR}
+DATE_EU %{MONTHDAY}[./-]%{MONTHNUM}[./-]%{YEAR}
+ISO8601_TIMEZONE Z|[+-]%{HOUR}(?::?%{MINUTE})
+ISO8601_SECOND %{SECOND}
+TIMESTAMP_ISO8601 %{YEAR}-%{MONTHNUM}-%{MONTHDAY}[T ]%{HOUR}:?%{MINUTE}(?::?%{SECOND})?%{ISO8601_TIMEZONE}?
+DATE %{DATE_US}|%{DATE_EU}
+DATESTAMP %{DATE}[- ]%{TIME}
+TZ [A-Z]{3}
+DATESTAMP_RFC822 %{DAY} %{MONTH} %{MONTHDAY} %{YEAR} %{TIME} %{TZ}
+DATESTAMP_RFC2822 %{DAY}, %{MONTHDAY} %{MONTH} %{YEAR} %{TIME} %{ISO8601_TIMEZONE}
+DATESTAMP_OTHER %{DAY} %{MONTH} %{MONTHDAY} %{TIME} %{TZ} %{YEAR}
+HTTPDATE %{MONTHDAY}/%{MONTH}/%{YEAR}:%{TIME} %{INT}
+SYSLOGTIMESTAMP %{MONTH} +%{MONTHDAY} %{TIME}
+PROG [\x21-\x5a\x5c\x5e-\x7e]+
+SYSLOGPROG %{PROG:program}(?:\[%{POSINT:pid}\])?
+SYSLOGHOST %{IPORHOST}
+SYSLOGFACILITY <%{NONNEGINT:facility}.%{NONNEGINT:priority}>
+SYSLOGBASE %{SYSLOGTIMESTAMP:timestamp} (?:%{SYSLOGFACILITY} )?%{SYSLOGHOST:logsource} %{SYSLOGPROG}:
+LOGLEVEL [Aa]lert|ALERT|[Tt]race|TRACE|[Dd]ebug|DEBUG|[Nn]otice|NOTICE|[Ii]nfo(?:rmation)?|INFO(?:RMATION)?|[Ww]arn(?:ing)?|WARN(?:ING)?|[Ee]rr(?:or)?|ERR(?:OR)?|[Cc]rit(?:ical)?|CRIT(?:ICAL)?|[Ff]atal|FATAL|[Ss]evere|SEVERE|EMERG(?:ENCY)?|[Ee]merg(?:ency)?
+HTTPDUSER %{EMAILADDRESS}|%{USER}
+COMMONAPACHELOG %{IPORHOST:clientip} %{HTTPDUSER:ident} %{HTTPDUSER:auth} \[%{HTTPDATE:timestamp}\] "(?:%{WORD:verb} %{NOTSPACE:request}(?: HTTP/%{NUMBER:httpversion})?|%{DATA:rawrequest})" %{NUMBER:response} (?:%{NUMBER:bytes}|-)
+COMBINEDAPACHELOG %{COMMONAPACHELOG} %{QUOTEDSTRING:referrer} %{QUOTEDSTRING:agent}
+"#;
+
+/// Named patterns such as `%{IPV4}` or `%{TIMESTAMP_ISO8601}`, as known
+/// from grok, that regexes can refer to.
+pub struct Grok {
+    patterns: HashMap<String, String>,
+}
+
+impl Grok {
+    /// The library, with the patterns of `config` added or replacing them.
+    /// Files that fail to load are left out and returned by their setting.
+    pub fn new(config: Option<&GrokConfig>) -> (Grok, Vec<(String, String)>) {
+        let mut grok = Grok { patterns: HashMap::new() };
+        grok.add(LIBRARY).expect("the grok library is well-formed");
+        let mut problems = Vec::new();
+        let config = match config {
+            Some(config) => config,
+            None => return (grok, problems),
+        };
+        for (index, path) in config.files.iter().enumerate() {
+            let setting = format!("grok.files[{}]", index);
+            let loaded = fs::read_to_string(path)
+                .map_err(|e| format!("failed to read {}: {}", path, e))
+                .and_then(|text| {
+                    grok.add(&text).map_err(|line| format!("line {} of {} is no NAME and pattern", line, path))
+                });
+            if let Err(e) = loaded {
+                problems.push((setting, e));
+            }
+        }
+        grok.patterns.extend(config.patterns.iter().map(|(name, pattern)| (name.clone(), pattern.clone())));
+        (grok, problems)
+    }
+
+    /// Adds the patterns of a pattern file, or returns the first line that
+    /// is not one, adding none of them.
+    fn add(&mut self, text: &str) -> Result<(), usize> {
+        let mut patterns = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(char::is_whitespace) {
+                Some((name, pattern)) if !pattern.trim().is_empty() => {
+                    patterns.push((name.to_string(), pattern.trim().to_string()));
+                },
+                _ => return Err(index + 1),
+            }
+        }
+        self.patterns.extend(patterns);
+        Ok(())
+    }
+
+    /// Replaces each `%{NAME}` in `pattern` with the regex of that name, and
+    /// each `%{NAME:field}` with a group named `field` around it. A type
+    /// after the field, as in `%{NUMBER:ms:int}`, is ignored.
+    pub fn expand(&self, pattern: &str) -> Result<String, String> {
+        self.expand_at(pattern, 0)
+    }
+
+    fn expand_at(&self, pattern: &str, depth: usize) -> Result<String, String> {
+        static REFERENCE: OnceLock<Regex> = OnceLock::new();
+        let reference = REFERENCE.get_or_init(|| {
+            Regex::new(r"%\{(\w+)(?::([\w.\[\]]+))?(?::\w+)?\}").unwrap()
+        });
+        if !pattern.contains("%{") {
+            return Ok(pattern.to_string());
+        }
+        if depth >= MAX_DEPTH {
+            return Err(format!("grok patterns refer to each other in a loop at {}", pattern));
+        }
+        let mut error = None;
+        let expanded = reference.replace_all(pattern, |captures: &Captures| {
+            let name = &captures[1];
+            let inner = match self.patterns.get(name) {
+                Some(inner) => self.expand_at(inner, depth + 1),
+                None => Err(format!("unknown grok pattern {}", name)),
+            };
+            match (inner, captures.get(2)) {
+                (Ok(inner), Some(field)) => format!("(?P<{}>{})", field.as_str(), inner),
+                (Ok(inner), None) => format!("(?:{})", inner),
+                (Err(e), _) => {
+                    error.get_or_insert(e);
+                    String::new()
+                },
+            }
+        });
+        match error {
+            Some(e) => Err(e),
+            None => Ok(expanded.into_owned()),
+        }
+    }
+}
+
+/// Expands the grok patterns in the regexes of every log entry and its
+/// rules, returning what failed by the path of the setting. Settings that
+/// failed are left matching themselves literally.
+pub fn expand(config: &mut Config) -> Vec<(String, String)> {
+    let (grok, mut problems) = Grok::new(config.grok.as_ref());
+    let single = matches!(config.log, LogConfigs::Single(_));
+    let mut expand = |path: String, pattern: &mut String| match grok.expand(pattern) {
+        Ok(expanded) => *pattern = expanded,
+        Err(e) => {
+            problems.push((path, e));
+            *pattern = regex::escape(pattern);
+        },
+    };
+    for (index, log) in config.log.as_mut_slice().iter_mut().enumerate() {
+        let path = if single { "log".to_string() } else { format!("log[{}]", index) };
+        for (index, pattern) in log.patterns.iter_mut().enumerate() {
+            expand(format!("{}.patterns[{}]", path, index), pattern);
+        }
+        for (index, pattern) in log.exclude.iter_mut().enumerate() {
+            expand(format!("{}.exclude[{}]", path, index), pattern);
+        }
+        for (index, pattern) in log.extract.iter_mut().flat_map(|extract| &mut extract.patterns).enumerate() {
+            expand(format!("{}.extract.patterns[{}]", path, index), pattern);
+        }
+        for (index, rule) in log.rules.iter_mut().enumerate() {
+            let path = format!("{}.rules[{}]", path, index);
+            for (index, pattern) in rule.patterns.iter_mut().enumerate() {
+                expand(format!("{}.patterns[{}]", path, index), pattern);
+            }
+            for (index, pattern) in rule.exclude.iter_mut().enumerate() {
+                expand(format!("{}.exclude[{}]", path, index), pattern);
+            }
+            for (name, pattern) in rule.terms.iter_mut() {
+                expand(format!("{}.terms.{}", path, name), pattern);
+            }
+            for (index, pattern) in rule.extract.iter_mut().flat_map(|extract| &mut extract.patterns).enumerate() {
+                expand(format!("{}.extract.patterns[{}]", path, index), pattern);
+            }
+            if let Some(pattern) = rule.value.as_mut().and_then(|value| value.pattern.as_mut()) {
+                expand(format!("{}.value.pattern", path), pattern);
+            }
+        }
+    }
+    problems
+}
@@ -0,0 +1,86 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection};
+
+use crate::notify::{Alert, Delivery};
+
+/// Record of every fired alert, kept in a SQLite database.
+pub struct History {
+    conn: Mutex<Connection>,
+}
+
+/// One alert as stored in the history.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub timestamp: String,
+    pub log_id: String,
+    pub rule: String,
+    pub count: usize,
+    /// Channels the alert was sent through, comma separated.
+    pub channels: String,
+    /// `ok`, or the failures per channel.
+    pub result: String,
+}
+
+impl History {
+    /// Opens the database at `path`, creating it if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<History> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS alerts (
+                id INTEGER PRIMARY KEY,
+                timestamp TEXT NOT NULL,
+                log_id TEXT NOT NULL,
+                rule TEXT NOT NULL,
+                count INTEGER NOT NULL,
+                channels TEXT NOT NULL,
+                result TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS alerts_timestamp ON alerts (timestamp);",
+        )?;
+        Ok(History { conn: Mutex::new(conn) })
+    }
+
+    pub fn record(&self, alert: &Alert, deliveries: &[Delivery]) -> rusqlite::Result<()> {
+        let channels: Vec<&str> = deliveries.iter().map(|d| d.channel.as_str()).collect();
+        let failures: Vec<String> = deliveries.iter()
+            .filter_map(|d| d.error.as_ref().map(|e| format!("{}: {}", d.channel, e)))
+            .collect();
+        let result = if failures.is_empty() { "ok".to_string() } else { failures.join("; ") };
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO alerts (timestamp, log_id, rule, count, channels, result)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                alert.timestamp.to_rfc3339(),
+                alert.log_id,
+                alert.rule,
+                alert.count as i64,
+                channels.join(","),
+                result,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the latest `limit` alerts, newest first, optionally of one log only.
+    pub fn recent(&self, limit: usize, log_id: Option<&str>) -> rusqlite::Result<Vec<HistoryEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT timestamp, log_id, rule, count, channels, result FROM alerts
+             WHERE ?1 IS NULL OR log_id = ?1
+             ORDER BY id DESC LIMIT ?2",
+        )?;
+        let rows = statement.query_map(params![log_id, limit as i64], |row| {
+            Ok(HistoryEntry {
+                timestamp: row.get(0)?,
+                log_id: row.get(1)?,
+                rule: row.get(2)?,
+                count: row.get::<_, i64>(3)? as usize,
+                channels: row.get(4)?,
+                result: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
@@ -0,0 +1,191 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Local};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::warn;
+
+use crate::metrics;
+
+/// Liveness information reported on `/healthz`.
+pub struct Health {
+    started: DateTime<Local>,
+    status: Mutex<Status>,
+}
+
+#[derive(Default)]
+struct Status {
+    files: Vec<String>,
+    last_event: Option<DateTime<Local>>,
+    last_alert: Option<DateTime<Local>>,
+}
+
+impl Health {
+    pub fn new() -> Health {
+        Health { started: Local::now(), status: Mutex::new(Status::default()) }
+    }
+
+    pub fn set_files(&self, files: Vec<String>) {
+        self.status.lock().unwrap().files = files;
+    }
+
+    pub fn event(&self) {
+        self.status.lock().unwrap().last_event = Some(Local::now());
+    }
+
+    pub fn alert(&self) {
+        self.status.lock().unwrap().last_alert = Some(Local::now());
+    }
+
+    fn render(&self) -> String {
+        let status = self.status.lock().unwrap();
+        json!({
+            "status": "ok",
+            "uptime_seconds": (Local::now() - self.started).num_seconds(),
+            "watched_files": status.files,
+            "last_event": status.last_event.map(|t| t.to_rfc3339()),
+            "last_alert": status.last_alert.map(|t| t.to_rfc3339()),
+        }).to_string()
+    }
+}
+
+/// Alerts acknowledged on `/ack`, until the watcher stops their reminders.
+#[derive(Default)]
+pub struct Acknowledgements {
+    pending: Mutex<Vec<Acknowledgement>>,
+    token: Option<String>,
+}
+
+pub struct Acknowledgement {
+    /// Also matches the instances of the log, e.g. its containers.
+    pub log_id: String,
+    /// All rules of the log when not set.
+    pub rule: Option<String>,
+}
+
+impl Acknowledgement {
+    pub fn matches(&self, log_id: &str, rule: &str) -> bool {
+        let log = log_id == self.log_id
+            || log_id.strip_prefix(self.log_id.as_str()).is_some_and(|rest| rest.starts_with('/'));
+        log && self.rule.as_ref().is_none_or(|name| name == rule)
+    }
+}
+
+impl Acknowledgements {
+    /// Only takes requests with `token`, if set.
+    pub fn new(token: Option<String>) -> Acknowledgements {
+        Acknowledgements { pending: Mutex::new(Vec::new()), token }
+    }
+
+    pub fn take(&self) -> Vec<Acknowledgement> {
+        std::mem::take(&mut *self.pending.lock().unwrap())
+    }
+
+    /// Takes `POST /ack?log=<id>&rule=<name>`, with the bearer token of
+    /// the `Authorization` header.
+    fn receive(&self, query: &str, token: Option<&str>) -> Result<(), (&'static str, &'static str)> {
+        if let Some(expected) = &self.token {
+            if !token.is_some_and(|token| same(token.as_bytes(), expected.as_bytes())) {
+                return Err(("401 Unauthorized", "a valid bearer token is required\n"));
+            }
+        }
+        let mut log_id = None;
+        let mut rule = None;
+        for pair in query.split('&') {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match key {
+                "log" => log_id = Some(decode(value)),
+                "rule" => rule = Some(decode(value)),
+                _ => {},
+            }
+        }
+        let log_id = log_id.filter(|id| !id.is_empty()).ok_or(("400 Bad Request", "the log parameter is missing\n"))?;
+        self.pending.lock().unwrap().push(Acknowledgement { log_id, rule });
+        Ok(())
+    }
+}
+
+/// Compares in a time that does not tell how much of a token is right.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |difference, (x, y)| difference | (x ^ y)) == 0
+}
+
+/// Decodes a query parameter, with `+` for spaces and `%XX` escapes.
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        if let Some(byte) = escaped {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(if bytes[i] == b'+' { b' ' } else { bytes[i] });
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Serves the monitoring endpoints until the task is dropped.
+pub async fn serve(listener: TcpListener, health: Arc<Health>, acks: Arc<Acknowledgements>) {
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("Failed to accept HTTP connection: {}", e);
+                continue;
+            }
+        };
+        let health = health.clone();
+        let acks = acks.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, &health, &acks).await {
+                warn!("Failed to answer HTTP request: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle(mut stream: TcpStream, health: &Health, acks: &Acknowledgements) -> io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    // Only the request line and headers matter; stop at their end.
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let target = parts.next().unwrap_or("");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let token = request.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+        .and_then(|(_, value)| value.trim().strip_prefix("Bearer "));
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", "text/plain; version=0.0.4", metrics::render()),
+        ("GET", "/healthz") => ("200 OK", "application/json", health.render()),
+        ("POST", "/ack") => match acks.receive(query, token) {
+            Ok(()) => ("200 OK", "text/plain", "acknowledged\n".to_string()),
+            Err((status, e)) => (status, "text/plain", e.to_string()),
+        },
+        ("GET", _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "method not allowed\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body,
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -0,0 +1,156 @@
+//! Fills the configuration in from the environment: `${NAME}` placeholders
+//! in settings, and settings given as `LOG_ALARMER__<SECTION>__<KEY>`
+//! variables.
+
+use std::env;
+
+use serde_yaml::{Mapping, Value};
+
+use crate::config::Config;
+use crate::error::Error;
+
+/// Prefix of the variables overriding settings. Sections and keys are
+/// separated by two underscores, list items are given by their index.
+const OVERRIDE_PREFIX: &str = "LOG_ALARMER__";
+
+/// Replaces the placeholders in the settings of `config` with the values of
+/// the environment variables they name, then applies the overrides.
+pub fn apply(config: Config) -> Result<Config, Error> {
+    let overrides: Vec<(String, String)> = env::vars()
+        .filter(|(name, _)| name.starts_with(OVERRIDE_PREFIX))
+        .collect();
+    let mut value = serde_yaml::to_value(&config).map_err(|e| Error::Config(e.to_string()))?;
+    if overrides.is_empty() && !has_placeholder(&value) {
+        return Ok(config);
+    }
+    substitute(&mut value, &mut String::new())?;
+    let names = overrides.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", ");
+    for (name, text) in overrides {
+        let keys: Vec<String> = name[OVERRIDE_PREFIX.len()..].split("__").map(str::to_lowercase).collect();
+        set(&mut value, &keys, text).map_err(|message| Error::Environment { setting: name.clone(), message })?;
+    }
+    serde_yaml::from_value(value).map_err(|e| Error::Environment { setting: names, message: e.to_string() })
+}
+
+fn has_placeholder(value: &Value) -> bool {
+    match value {
+        Value::String(text) => text.contains("${"),
+        Value::Sequence(items) => items.iter().any(has_placeholder),
+        Value::Mapping(map) => map.iter().any(|(_, value)| has_placeholder(value)),
+        _ => false,
+    }
+}
+
+/// Replaces the placeholders under `value`, whose setting is at `path`.
+fn substitute(value: &mut Value, path: &mut String) -> Result<(), Error> {
+    match value {
+        Value::String(text) => {
+            *text = expand(text).map_err(|message| Error::Environment { setting: path.clone(), message })?;
+        },
+        Value::Sequence(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                let len = path.len();
+                path.push_str(&format!("[{}]", index));
+                substitute(item, path)?;
+                path.truncate(len);
+            }
+        },
+        Value::Mapping(map) => {
+            for (key, item) in map.iter_mut() {
+                let len = path.len();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key.as_str().unwrap_or_default());
+                substitute(item, path)?;
+                path.truncate(len);
+            }
+        },
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Expands `${NAME}` and `${NAME:-default}` in `text`. `$${` stands for a
+/// literal `${`.
+fn expand(text: &str) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(escaped) = after.strip_prefix("${") {
+            expanded.push_str("${");
+            rest = escaped;
+            continue;
+        }
+        if !after.starts_with('{') {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+        let end = after.find('}').ok_or_else(|| format!("unclosed placeholder in {}", text))?;
+        let placeholder = &after[1..end];
+        let (name, default) = match placeholder.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (placeholder, None),
+        };
+        match (env::var(name), default) {
+            (Ok(value), _) => expanded.push_str(&value),
+            (Err(_), Some(default)) => expanded.push_str(default),
+            (Err(_), None) => return Err(format!("environment variable {} is not set", name)),
+        }
+        rest = &after[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Sets the setting at `keys` under `value` to `text`, creating sections as
+/// needed. The text replaces a string as it is, and otherwise is read as
+/// YAML, e.g. as a number.
+fn set(value: &mut Value, keys: &[String], text: String) -> Result<(), String> {
+    let (key, rest) = match keys.split_first() {
+        Some(split) => split,
+        None => {
+            *value = match value {
+                Value::String(_) => Value::String(text),
+                _ => serde_yaml::from_str(&text).unwrap_or(Value::String(text)),
+            };
+            return Ok(());
+        },
+    };
+    if key.is_empty() {
+        return Err("has an empty section".to_string());
+    }
+    if value.is_null() {
+        *value = Value::Mapping(Mapping::new());
+    }
+    // A single section given as a map, such as `log`, is its item 0.
+    if let Value::Mapping(map) = &*value {
+        if key == "0" && !map.iter().any(|(name, _)| name.as_str() == Some("0")) {
+            return set(value, rest, text);
+        }
+    }
+    match value {
+        Value::Mapping(map) => {
+            // Keys given in capitals also match keys that are not, e.g. headers.
+            let existing = map.iter().map(|(name, _)| name.clone())
+                .find(|name| name.as_str().is_some_and(|name| name.eq_ignore_ascii_case(key)));
+            let name = existing.unwrap_or_else(|| Value::String(key.clone()));
+            let entry = map.entry(name).or_insert(Value::Null);
+            set(entry, rest, text)
+        },
+        Value::Sequence(items) => {
+            let index: usize = key.parse().map_err(|_| format!("{} is not the index of a list item", key))?;
+            if index > items.len() {
+                return Err(format!("the list has only {} items", items.len()));
+            }
+            if index == items.len() {
+                items.push(Value::Null);
+            }
+            set(&mut items[index], rest, text)
+        },
+        _ => Err(format!("{} is not a section", key)),
+    }
+}
@@ -0,0 +1,51 @@
+//! Watches log files and raises alarms when errors show up in them.
+//!
+//! The [`Watcher`] follows the configured files, applies an [`AlertRule`]
+//! to what is written to them and hands raised [`Alert`]s to every
+//! [`Notifier`].
+
+pub use config::{read_configuration, Config};
+pub use error::Error;
+pub use history::{History, HistoryEntry};
+pub use notify::{Alert, Notifier, NotifyError};
+pub use rule::AlertRule;
+pub use system::{DiskUsage, Snapshot};
+pub use validate::{check_configuration, validate_configuration, Level, Problem};
+pub use watcher::{Reloader, Watcher};
+
+pub mod config;
+pub mod history;
+pub mod notify;
+
+mod baseline;
+mod composite;
+mod condition;
+mod cron;
+mod dispatch;
+mod error;
+mod extract;
+mod files;
+mod fingerprint;
+mod forward;
+mod grok;
+mod http;
+mod interpolate;
+mod logfmt;
+mod logpath;
+mod matcher;
+mod measure;
+mod metrics;
+mod multiline;
+mod queue;
+mod ratelimit;
+mod retry;
+mod rule;
+mod schedule;
+mod script;
+mod source;
+mod state;
+mod system;
+mod tail;
+mod validate;
+mod watcher;
+mod window;
@@ -0,0 +1,91 @@
+use serde_json::{Map, Value};
+
+/// Parses a logfmt line such as `level=error msg="upstream timed out"
+/// duration=1.2s` into its fields, or `None` if it holds no `key=value`.
+///
+/// Values stay strings, a key on its own is `true`, and keys with dots such
+/// as `http.status` lead into nested objects, as in JSON lines.
+pub fn parse(line: &str) -> Option<Map<String, Value>> {
+    let mut fields = Map::new();
+    let mut paired = false;
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        let end = rest.find(|c: char| c == '=' || c.is_whitespace()).unwrap_or(rest.len());
+        let key = &rest[..end];
+        rest = &rest[end..];
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                paired = true;
+                let (value, after) = value(after);
+                rest = after;
+                Value::String(value)
+            },
+            None => Value::Bool(true),
+        };
+        if !key.is_empty() && !key.contains('"') {
+            insert(&mut fields, key, value);
+        }
+        rest = rest.trim_start();
+    }
+    Some(fields).filter(|_| paired)
+}
+
+/// The value at the start of `text`, quoted or up to the next space, and
+/// what follows it.
+fn value(text: &str) -> (String, &str) {
+    let quoted = match text.strip_prefix('"') {
+        Some(quoted) => quoted,
+        None => {
+            let end = text.find(char::is_whitespace).unwrap_or(text.len());
+            return (text[..end].to_string(), &text[end..]);
+        },
+    };
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return (value, &quoted[index + 1..]),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, escaped)) => value.push(escaped),
+                None => value.push('\\'),
+            },
+            c => value.push(c),
+        }
+    }
+    // An unterminated quote runs to the end of the line.
+    (value, "")
+}
+
+fn insert(fields: &mut Map<String, Value>, key: &str, value: Value) {
+    let (parent, last) = match key.rsplit_once('.') {
+        // `a=1 a.b=2` keeps the second key as it is.
+        Some((parent, last)) if !parent.is_empty() && !last.is_empty() && nests(fields, parent) => (parent, last),
+        _ => {
+            fields.insert(key.to_string(), value);
+            return;
+        },
+    };
+    let mut object = fields;
+    for part in parent.split('.') {
+        match object.entry(part.to_string()).or_insert_with(|| Value::Object(Map::new())) {
+            Value::Object(nested) => object = nested,
+            _ => return,
+        }
+    }
+    object.insert(last.to_string(), value);
+}
+
+/// Whether the keys along `parent` are objects or not yet set.
+fn nests(fields: &Map<String, Value>, parent: &str) -> bool {
+    let mut object = fields;
+    for part in parent.split('.') {
+        match object.get(part) {
+            Some(Value::Object(nested)) => object = nested,
+            Some(_) => return false,
+            None => return true,
+        }
+    }
+    true
+}
@@ -0,0 +1,97 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use glob::Pattern;
+
+/// The files a configured `path` stands for.
+pub enum LogPath {
+    File(PathBuf),
+    /// Every file of `dir`, or also of its subdirectories when
+    /// `recursive`, whose name matches `pattern`.
+    Glob { dir: PathBuf, pattern: Pattern, recursive: bool },
+}
+
+impl LogPath {
+    /// A directory stands for all of its files. Wildcards are only
+    /// supported in the file name, e.g. `/var/log/app/*.log`.
+    pub fn parse(path: &str, recursive: bool) -> Result<LogPath, String> {
+        let path = PathBuf::from(path);
+        if path.is_dir() {
+            return Ok(LogPath::Glob { dir: path, pattern: Pattern::new("*").unwrap(), recursive });
+        }
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        if !name.contains(['*', '?', '[']) {
+            return Ok(LogPath::File(path));
+        }
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        if dir.to_string_lossy().contains(['*', '?', '[']) {
+            return Err(format!("wildcards are only supported in the file name: {}", path.display()));
+        }
+        let pattern = Pattern::new(&name).map_err(|e| format!("invalid pattern {}: {}", name, e))?;
+        Ok(LogPath::Glob { dir, pattern, recursive })
+    }
+
+    pub fn is_glob(&self) -> bool {
+        matches!(self, LogPath::Glob { .. })
+    }
+
+    /// The directories watched for new files, for globs.
+    /// Fails with `NotFound` if the directory is missing.
+    pub fn dirs(&self) -> io::Result<Vec<PathBuf>> {
+        let (dir, recursive) = match self {
+            LogPath::File(_) => return Ok(Vec::new()),
+            LogPath::Glob { dir, recursive, .. } => (dir, *recursive),
+        };
+        fs::read_dir(dir)?;
+        match recursive {
+            false => Ok(vec![dir.clone()]),
+            true => {
+                let mut dirs = vec![dir.clone()];
+                let mut next = 0;
+                while next < dirs.len() {
+                    for entry in fs::read_dir(&dirs[next])? {
+                        let entry = entry?;
+                        // Symbolic links are not followed, so there are no cycles.
+                        if entry.file_type()?.is_dir() {
+                            dirs.push(entry.path());
+                        }
+                    }
+                    next += 1;
+                }
+                Ok(dirs)
+            },
+        }
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        match self {
+            LogPath::File(file) => file == path,
+            LogPath::Glob { pattern, .. } => {
+                path.file_name().is_some_and(|name| pattern.matches(&name.to_string_lossy()))
+                    && path.is_file()
+            },
+        }
+    }
+
+    /// The files currently standing for the path, sorted.
+    pub fn files(&self) -> io::Result<Vec<PathBuf>> {
+        if let LogPath::File(file) = self {
+            return Ok(vec![file.clone()]);
+        }
+        let mut files = Vec::new();
+        for dir in self.dirs()? {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if self.matches(&path) {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
@@ -1,121 +1,354 @@
-use std::error::Error;
+use std::fs::OpenOptions;
+use std::future::Future;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
 use std::process::exit;
-use std::thread::sleep;
-use std::time::Duration;
 
-use chrono::Local;
-use inotify::{EventMask, Inotify, WatchMask};
-use lettre::{SmtpClient, Transport};
-use lettre::smtp::authentication::Credentials;
-use lettre_email::EmailBuilder;
-use serde::{Deserialize, Serialize};
+use clap::{Parser, Subcommand, ValueEnum};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-struct Config {
-    log: LogConfig,
-    email: EmailConfig,
+use log_monitor::config::Format;
+use log_monitor::notify::build_notifiers;
+use log_monitor::{read_configuration, validate_configuration, Alert, Config, History, Level, Problem, Watcher};
+
+const DEFAULT_CONFIG: &str = "./application.yml";
+
+/// Used instead of the default configuration file if it does not exist.
+const OTHER_CONFIGS: [&str; 2] = ["./application.toml", "./application.json"];
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Detect log change and send email alarm.")]
+struct Cli {
+    /// Path of the configuration file, in YAML, or TOML or JSON by its
+    /// extension
+    #[arg(short, long, env = "LOG_ALARMER_CONFIG", default_value = DEFAULT_CONFIG)]
+    config: String,
+
+    /// Print alerts instead of sending them
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Read the lines of the only configured log from standard input
+    #[arg(long)]
+    stdin: bool,
+
+    /// Verbosity of the alarmer's own messages, e.g. `debug` or
+    /// `warn,log_monitor::watcher=debug`
+    #[arg(long, env = "LOG_ALARMER_LOG_LEVEL", default_value = "info")]
+    log_level: String,
+
+    /// Format of the alarmer's own messages
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-struct LogConfig {
-    id: String,
-    path: String,
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum LogFormat {
+    Text,
+    /// One JSON object per message
+    Json,
 }
 
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Watch the configured logs (the default)
+    Run,
+    /// Show recently fired alerts from the history database
+    History {
+        /// Number of alerts to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Only show alerts of this log id
+        #[arg(long)]
+        log: Option<String>,
+    },
+    /// Send a test alert through every configured notifier
+    TestNotify,
+    /// Check the configuration file and report every problem in it
+    Validate,
+    /// Write a commented sample configuration
+    Init {
+        /// Where to write it, by default the configuration file
+        path: Option<String>,
+        /// Defaults to the extension of the path, or YAML
+        #[arg(long, value_enum)]
+        format: Option<SampleFormat>,
+        /// Replace the file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum SampleFormat {
+    Yaml,
+    Toml,
+}
+
+const YAML_SAMPLE: &str = include_str!("samples/application.yml");
+const TOML_SAMPLE: &str = include_str!("samples/application.toml");
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-struct EmailConfig {
-    username: String,
-    password: String,
-    stmp: String,
-    target: String,
-    count_threshold: i32,
-    time_threshold: i64,
+#[tokio::main]
+async fn main() {
+    let mut cli = Cli::parse();
+    if cli.config == DEFAULT_CONFIG && !Path::new(DEFAULT_CONFIG).exists() {
+        if let Some(other) = OTHER_CONFIGS.iter().find(|other| Path::new(other).exists()) {
+            cli.config = other.to_string();
+        }
+    }
+    init_logging(&cli);
+    match &cli.command {
+        Some(Command::Init { path, format, force }) => return init(&cli.config, path.as_deref(), *format, *force),
+        Some(Command::Validate) => return validate(&cli.config),
+        None | Some(Command::Run) => check(&cli.config),
+        _ => (),
+    }
+    let config = match read_configuration(&cli.config) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+            exit(1);
+        }
+    };
+    match cli.command {
+        None | Some(Command::Run) => run(&cli, config).await,
+        Some(Command::History { limit, log }) => history(&config, limit, log.as_deref()),
+        Some(Command::TestNotify) => test_notify(&config),
+        Some(Command::Validate) | Some(Command::Init { .. }) => (),
+    }
 }
 
-fn main() {
-    match read_configuration() {
-        Ok(config) => {
-            monitor_log(&config);
+/// Writes the sample configuration to `path`, or else to the configuration
+/// file, or `./application.toml` for a TOML sample if that is the default.
+fn init(config: &str, path: Option<&str>, format: Option<SampleFormat>, force: bool) {
+    let path = match path {
+        Some(path) => path,
+        None if config == DEFAULT_CONFIG && format == Some(SampleFormat::Toml) => OTHER_CONFIGS[0],
+        None => config,
+    };
+    let format = match (format, Format::of(path)) {
+        (Some(format), _) => format,
+        (None, Format::Yaml) => SampleFormat::Yaml,
+        (None, Format::Toml) => SampleFormat::Toml,
+        (None, Format::Json) => {
+            error!("There is no JSON sample; choose one with --format.");
+            exit(1);
+        },
+    };
+    let sample = if format == SampleFormat::Toml { TOML_SAMPLE } else { YAML_SAMPLE };
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    let written = options.open(path).and_then(|mut file| file.write_all(sample.as_bytes()));
+    match written {
+        Ok(()) => println!("Wrote a sample configuration to {}.", path),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            error!("{} already exists; pass --force to replace it.", path);
+            exit(1);
         },
         Err(e) => {
-            eprintln!("{:?}", e);
+            error!("Failed to write {}: {}", path, e);
             exit(1);
+        },
+    }
+}
+
+fn problems_of(path: &str) -> Vec<Problem> {
+    validate_configuration(path).unwrap_or_else(|e| {
+        error!("{}", e);
+        exit(1);
+    })
+}
+
+fn validate(path: &str) {
+    let problems = problems_of(path);
+    for problem in &problems {
+        println!("{}: {}", problem.level.as_str(), problem);
+    }
+    let errors = problems.iter().filter(|problem| problem.level == Level::Error).count();
+    let warnings = problems.len() - errors;
+    if errors > 0 {
+        println!("{} is invalid: {}, {}.", path, counted(errors, "error"), counted(warnings, "warning"));
+        exit(1);
+    }
+    if warnings > 0 {
+        println!("{} is valid, with {}.", path, counted(warnings, "warning"));
+    } else {
+        println!("{} is valid.", path);
+    }
+}
+
+fn counted(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+/// Reports the problems of the configuration before watching, and stops
+/// if any of them is an error.
+fn check(path: &str) {
+    let problems = problems_of(path);
+    for problem in &problems {
+        match problem.level {
+            Level::Error => error!("{}: {}", path, problem),
+            Level::Warning => warn!("{}: {}", path, problem),
         }
     }
+    if problems.iter().any(|problem| problem.level == Level::Error) {
+        error!("Invalid configuration {}, not starting.", path);
+        exit(1);
+    }
+}
+
+/// Writes the alarmer's own messages to standard error, leaving standard
+/// output to the alerts of dry runs and the output of subcommands.
+fn init_logging(cli: &Cli) {
+    let filter = EnvFilter::try_new(&cli.log_level).unwrap_or_else(|e| {
+        eprintln!("Invalid --log-level {}: {}", cli.log_level, e);
+        exit(1);
+    });
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal());
+    match cli.log_format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
 }
 
-#[allow(unused_must_use)]
-fn monitor_log(config: &Config) {
-    let mut inotify = Inotify::init().expect("Failed to initialize inotify");
-    inotify.add_watch(config.log.path.clone(), WatchMask::MODIFY
-        | WatchMask::ATTRIB | WatchMask::DELETE_SELF).expect("Failed to add inotify watch");
-    let mut buffer = [0u8; 40960];
-    let mut count = 0;
-    let mut last_time = Local::now().timestamp_millis();
-    loop {
-        let events = inotify
-            .read_events_blocking(&mut buffer)
-            .expect("Failed to read inotify events");
-        for event in events {
-            if event.mask == EventMask::MODIFY {
-                println!("File modified: {:?}", event.name);
-                count += 1;
-            } else if event.mask == EventMask::ATTRIB {
-                println!("File attribute modified: {:?}", event.name);
-                inotify.rm_watch(event.wd);
-                inotify.add_watch(config.log.path.clone(), WatchMask::MODIFY
-                    | WatchMask::ATTRIB | WatchMask::DELETE_SELF).expect("Failed to add inotify watch");
-                count += 1;
-            } else {
-                println!("File deleted: {:?}", event.name);
-                sleep(Duration::from_millis(1000));
-                inotify.rm_watch(event.wd);
-                inotify.add_watch(config.log.path.clone(), WatchMask::MODIFY
-                    | WatchMask::ATTRIB | WatchMask::DELETE_SELF).expect("Failed to add inotify watch");
+fn test_notify(config: &Config) {
+    let notifiers = build_notifiers(config).unwrap_or_else(|e| {
+        error!("Invalid notifier configuration: {}", e);
+        exit(1);
+    });
+    if notifiers.is_empty() {
+        error!("No notifiers configured.");
+        exit(1);
+    }
+    let mut alert = Alert::test();
+    alert.labels = config.labels.clone();
+    let mut failed = 0;
+    for notifier in &notifiers {
+        match notifier.notify(&alert) {
+            Ok(()) => println!("{:<12} ok", notifier.name()),
+            Err(e) => {
+                println!("{:<12} FAILED: {}", notifier.name(), e);
+                failed += 1;
             }
         }
+    }
+    if failed > 0 {
+        error!("{} of {} notifiers failed.", failed, notifiers.len());
+        exit(1);
+    }
+}
 
-        if count >= config.email.count_threshold as usize &&
-            Local::now().timestamp_millis() - last_time >= config.email.time_threshold {
-            send_email(config);
-            count = 0;
-            last_time = Local::now().timestamp_millis();
+fn history(config: &Config, limit: usize, log: Option<&str>) {
+    let path = match &config.history_db {
+        Some(path) => path,
+        None => {
+            error!("No history_db configured.");
+            exit(1);
+        }
+    };
+    let entries = History::open(path).and_then(|history| history.recent(limit, log));
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Failed to read history {}: {}", path, e);
+            exit(1);
         }
+    };
+    for entry in entries.iter().rev() {
+        println!("{}  {:<16} {:>6}  {:<24} [{}] {}",
+            entry.timestamp, entry.log_id, entry.count, entry.rule, entry.channels, entry.result);
     }
 }
 
-fn send_email(config: &Config) {
-    let email = EmailBuilder::new()
-        .to(config.email.target.as_str())
-        .from(config.email.username.as_str())
-        .subject("Bot: ERROR Occurred!!")
-        .text(format!("Multiple error occurred on {} at {}", config.log.id, Local::now().to_string()))
-        .build()
-        .unwrap();
-    let creds = Credentials::new(
-        config.email.username.clone(),
-        config.email.password.clone(),
-    );
-    let mut mailer = SmtpClient::new_simple(config.email.stmp.as_str())
-        .unwrap()
-        .credentials(creds)
-        .smtp_utf8(true)
-        .transport();
-
-    let result = mailer.send(email.into());
-    if result.is_ok() {
-        println!("Email sent.");
-    } else {
-        eprintln!("Email failed to send: {}", result.err().unwrap().to_string());
+async fn run(cli: &Cli, mut config: Config) {
+    if cli.stdin {
+        match config.log.as_mut_slice() {
+            [log] => {
+                log.stdin = true;
+                log.path.clear();
+                log.journal = None;
+                log.docker = None;
+                log.syslog = None;
+            },
+            _ => {
+                error!("--stdin needs a configuration with a single log, or set stdin: true on one of them.");
+                exit(1);
+            },
+        }
+    }
+    let config_dry_run = config.dry_run;
+    let mut watcher = match Watcher::new(config) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            error!("Invalid configuration: {}", e);
+            exit(1);
+        }
+    };
+    // Reloading would drop the override of --stdin.
+    if !cli.stdin {
+        watcher.config_path(&cli.config);
+    }
+    watcher.dry_run(cli.dry_run);
+    if cli.dry_run || config_dry_run {
+        info!("Dry run: alerts are printed instead of sent.");
+    }
+    #[cfg(unix)]
+    match signal(SignalKind::hangup()) {
+        Ok(mut hangup) => {
+            let reloader = watcher.reloader();
+            tokio::spawn(async move {
+                while hangup.recv().await.is_some() {
+                    info!("Received SIGHUP, reloading configuration.");
+                    reloader.reload();
+                }
+            });
+        },
+        Err(e) => warn!("Failed to install SIGHUP handler, reloading on SIGHUP is unavailable: {}", e),
+    }
+    let shutdown = shutdown_signal().unwrap_or_else(|e| {
+        error!("Failed to install shutdown signal handlers: {}", e);
+        exit(1);
+    });
+    if let Err(e) = watcher.run_until(shutdown).await {
+        error!("Stopped watching logs: {}", e);
+        exit(1);
     }
-    mailer.close();
 }
 
-fn read_configuration() -> Result<Config, Box<dyn Error>> {
-    let f = std::fs::File::open("./application.yml")?;
-    let d: Config = serde_yaml::from_reader(f).unwrap();
-    Ok(d)
+/// Completes on SIGINT or SIGTERM.
+#[cfg(unix)]
+fn shutdown_signal() -> io::Result<impl Future<Output = ()>> {
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    Ok(async move {
+        tokio::select! {
+            _ = interrupt.recv() => (),
+            _ = terminate.recv() => (),
+        }
+    })
 }
 
-
+/// Completes on Ctrl+C.
+#[cfg(not(unix))]
+fn shutdown_signal() -> io::Result<impl Future<Output = ()>> {
+    Ok(async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to wait for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    })
+}
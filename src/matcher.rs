@@ -0,0 +1,40 @@
+use regex::RegexSet;
+
+/// Decides whether a log line counts towards the alarm.
+#[derive(Clone)]
+pub struct Matcher {
+    keywords: Vec<String>,
+    patterns: RegexSet,
+}
+
+impl Matcher {
+    /// Compiles a set of keywords and regex patterns.
+    pub fn new(keywords: &[String], patterns: &[String]) -> Result<Matcher, regex::Error> {
+        Ok(Matcher {
+            keywords: keywords.to_vec(),
+            patterns: RegexSet::new(patterns)?,
+        })
+    }
+
+    /// Human readable form of the condition, used to identify alerts.
+    pub fn describe(&self) -> String {
+        let conditions: Vec<&str> = self.keywords.iter()
+            .map(String::as_str)
+            .chain(self.patterns.patterns().iter().map(String::as_str))
+            .collect();
+        if conditions.is_empty() {
+            "*".to_string()
+        } else {
+            conditions.join(" | ")
+        }
+    }
+
+    /// A line matches if it contains any keyword or matches any pattern.
+    /// With neither configured every line matches.
+    pub fn is_match(&self, line: &str) -> bool {
+        if self.keywords.is_empty() && self.patterns.is_empty() {
+            return true;
+        }
+        self.keywords.iter().any(|k| line.contains(k.as_str())) || self.patterns.is_match(line)
+    }
+}
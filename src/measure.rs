@@ -0,0 +1,77 @@
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::condition::lookup;
+use crate::config::ValueConfig;
+
+/// Takes a number out of log lines, such as a latency or a queue depth,
+/// and tells whether it is beyond its limits.
+#[derive(Clone)]
+pub struct Measure {
+    text: String,
+    source: Source,
+    above: Option<f64>,
+    below: Option<f64>,
+    average: bool,
+}
+
+#[derive(Clone)]
+enum Source {
+    Pattern(Regex),
+    Field(Vec<String>),
+}
+
+impl Measure {
+    pub fn new(config: &ValueConfig) -> Result<Measure, String> {
+        let (source, name) = match (&config.pattern, &config.field) {
+            (Some(pattern), None) => {
+                let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+                if regex.captures_len() < 2 {
+                    return Err(format!("pattern {} captures no group", pattern));
+                }
+                (Source::Pattern(regex), format!("/{}/", pattern))
+            },
+            (None, Some(field)) => (Source::Field(field.split('.').map(String::from).collect()), field.clone()),
+            _ => return Err("needs either a pattern or a field".to_string()),
+        };
+        if config.above.is_none() && config.below.is_none() {
+            return Err("needs a limit, above or below".to_string());
+        }
+        let name = if config.average { format!("avg({})", name) } else { name };
+        let text = config.above.map(|above| format!("{} > {}", name, above)).into_iter()
+            .chain(config.below.map(|below| format!("{} < {}", name, below)))
+            .collect::<Vec<_>>()
+            .join(" || ");
+        Ok(Measure { text, source, above: config.above, below: config.below, average: config.average })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// The number in `line`, or in its `fields`.
+    pub fn extract(&self, line: &str, fields: Option<&Map<String, Value>>) -> Option<f64> {
+        match &self.source {
+            Source::Pattern(regex) => {
+                let captures = regex.captures(line)?;
+                let group = captures.name("value").or_else(|| captures.get(1))?;
+                group.as_str().trim().parse().ok()
+            },
+            Source::Field(path) => match lookup(fields?, path)? {
+                Value::Number(number) => number.as_f64(),
+                Value::String(text) => text.trim().parse().ok(),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn beyond(&self, value: f64) -> bool {
+        self.above.is_some_and(|above| value > above) || self.below.is_some_and(|below| value < below)
+    }
+
+    /// Whether the average within the window is held to the limits, rather
+    /// than each value.
+    pub fn averaged(&self) -> bool {
+        self.average
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// Counters exposed on the `/metrics` endpoint in the Prometheus text format.
+struct Counter {
+    name: &'static str,
+    help: &'static str,
+    values: Mutex<BTreeMap<Vec<(&'static str, String)>, u64>>,
+}
+
+impl Counter {
+    const fn new(name: &'static str, help: &'static str) -> Counter {
+        Counter { name, help, values: Mutex::new(BTreeMap::new()) }
+    }
+
+    fn inc(&self, labels: &[(&'static str, &str)]) {
+        self.add(labels, 1);
+    }
+
+    fn add(&self, labels: &[(&'static str, &str)], count: u64) {
+        let key = labels.iter().map(|(k, v)| (*k, v.to_string())).collect();
+        *self.values.lock().unwrap().entry(key).or_insert(0) += count;
+    }
+
+    fn render(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} counter", self.name);
+        for (labels, value) in self.values.lock().unwrap().iter() {
+            let labels: Vec<String> = labels.iter()
+                .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+                .collect();
+            if labels.is_empty() {
+                let _ = writeln!(out, "{} {}", self.name, value);
+            } else {
+                let _ = writeln!(out, "{}{{{}}} {}", self.name, labels.join(","), value);
+            }
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+static EVENTS: Counter = Counter::new(
+    "log_alarmer_events_total", "File events received, by file and event type.");
+static MATCHES: Counter = Counter::new(
+    "log_alarmer_matches_total", "Lines or events that matched a rule.");
+static ALERTS_SENT: Counter = Counter::new(
+    "log_alarmer_alerts_sent_total", "Alerts delivered, by channel.");
+static NOTIFY_FAILURES: Counter = Counter::new(
+    "log_alarmer_notify_failures_total", "Alerts that failed to be delivered, by channel.");
+static RATE_LIMITED: Counter = Counter::new(
+    "log_alarmer_rate_limited_total", "Alerts dropped by the rate limit of a channel.");
+static NOTIFY_DROPPED: Counter = Counter::new(
+    "log_alarmer_notify_dropped_total", "Alerts dropped as the notification queue was full, by channel.");
+static FORWARDED: Counter = Counter::new(
+    "log_alarmer_forwarded_total", "Matched lines sent to an output.");
+static FORWARD_DROPPED: Counter = Counter::new(
+    "log_alarmer_forward_dropped_total", "Matched lines dropped as the buffer of an output was full, or rejected.");
+
+pub fn event(file: &str, mask: &str) {
+    EVENTS.inc(&[("file", file), ("mask", mask)]);
+}
+
+pub fn matched(log: &str, rule: &str) {
+    MATCHES.inc(&[("log", log), ("rule", rule)]);
+}
+
+pub fn alert_sent(channel: &str) {
+    ALERTS_SENT.inc(&[("channel", channel)]);
+}
+
+pub fn notify_failed(channel: &str) {
+    NOTIFY_FAILURES.inc(&[("channel", channel)]);
+}
+
+pub fn rate_limited(channel: &str) {
+    RATE_LIMITED.inc(&[("channel", channel)]);
+}
+
+pub fn notify_dropped(channel: &str) {
+    NOTIFY_DROPPED.inc(&[("channel", channel)]);
+}
+
+pub fn forwarded(output: &str, count: u64) {
+    FORWARDED.add(&[("output", output)], count);
+}
+
+pub fn forward_dropped(output: &str, count: u64) {
+    FORWARD_DROPPED.add(&[("output", output)], count);
+}
+
+/// Renders every counter in the Prometheus text exposition format.
+pub fn render() -> String {
+    let mut out = String::new();
+    let counters = [
+        &EVENTS, &MATCHES, &ALERTS_SENT, &NOTIFY_FAILURES, &RATE_LIMITED, &NOTIFY_DROPPED, &FORWARDED, &FORWARD_DROPPED,
+    ];
+    for counter in counters {
+        counter.render(&mut out);
+    }
+    out
+}
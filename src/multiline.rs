@@ -0,0 +1,72 @@
+use regex::Regex;
+
+use crate::config::MultilineConfig;
+
+/// Joins lines into multiline entries, such as stack traces, so that each
+/// entry is matched and counted once.
+#[derive(Clone)]
+pub struct Grouping {
+    pattern: Regex,
+    /// Whether `pattern` matches the first line of an entry rather than the
+    /// lines continuing one.
+    starts: bool,
+    max_lines: usize,
+    timeout: i64,
+}
+
+/// The lines of an entry that may not be complete yet.
+#[derive(Default)]
+pub struct Pending {
+    lines: Vec<String>,
+    updated: i64,
+}
+
+impl Grouping {
+    pub fn new(config: &MultilineConfig) -> Result<Grouping, String> {
+        let (pattern, starts) = match (&config.start, &config.continuation) {
+            (Some(start), None) => (start, true),
+            (None, Some(continuation)) => (continuation, false),
+            _ => return Err("multiline needs either start or continuation".to_string()),
+        };
+        Ok(Grouping {
+            pattern: Regex::new(pattern).map_err(|e| e.to_string())?,
+            starts,
+            max_lines: config.max_lines.max(1),
+            timeout: config.timeout,
+        })
+    }
+
+    /// Adds `lines` to the entry in `pending` and returns the entries they
+    /// complete.
+    pub fn push(&self, pending: &mut Pending, lines: Vec<String>, now: i64) -> Vec<String> {
+        let mut entries = Vec::new();
+        for line in lines {
+            let continues = self.pattern.is_match(&line) != self.starts;
+            if (!continues && !pending.lines.is_empty()) || pending.lines.len() >= self.max_lines {
+                entries.extend(pending.take());
+            }
+            pending.lines.push(line);
+        }
+        pending.updated = now;
+        entries
+    }
+
+    /// The pending entry, once no line was added to it for the timeout.
+    pub fn expire(&self, pending: &mut Pending, now: i64) -> Option<String> {
+        if now - pending.updated < self.timeout {
+            return None;
+        }
+        pending.take()
+    }
+}
+
+impl Pending {
+    pub fn take(&mut self) -> Option<String> {
+        if self.lines.is_empty() {
+            return None;
+        }
+        let entry = self.lines.join("\n");
+        self.lines.clear();
+        Some(entry)
+    }
+}
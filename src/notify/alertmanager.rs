@@ -0,0 +1,98 @@
+use chrono::Duration;
+use serde_json::{json, Map, Value};
+use tracing::warn;
+
+use crate::config::AlertmanagerConfig;
+use crate::notify::{hostname, primary_ip, Alert, Notifier, NotifyError};
+
+/// Hands alerts to Prometheus Alertmanager, which routes, groups and
+/// silences them like those of Prometheus.
+pub struct AlertmanagerNotifier {
+    config: AlertmanagerConfig,
+}
+
+impl AlertmanagerNotifier {
+    pub fn new(config: AlertmanagerConfig) -> AlertmanagerNotifier {
+        AlertmanagerNotifier { config }
+    }
+
+    /// Labels identify the alert, so its resolution carries the same ones.
+    /// Extracted values are labels too, without replacing the others.
+    fn labels(&self, alert: &Alert) -> Map<String, Value> {
+        let mut labels = Map::new();
+        for (name, value) in alert.extracted.iter().chain(&alert.labels).chain(&self.config.labels) {
+            labels.insert(name.clone(), json!(value));
+        }
+        labels.insert("alertname".to_string(), json!(alert.rule));
+        labels.insert("log_id".to_string(), json!(alert.log_id));
+        labels.insert("rule".to_string(), json!(alert.rule));
+        labels.insert("severity".to_string(), json!(alert.severity.as_str()));
+        labels.insert("instance".to_string(), json!(hostname()));
+        labels
+    }
+
+    /// Posts to every configured Alertmanager, as they share alerts only
+    /// when each of them receives them. Fails only if none accepted it.
+    fn post(&self, alert: Value) -> Result<(), NotifyError> {
+        let body = Value::Array(vec![alert]);
+        let (mut accepted, mut failure) = (false, None);
+        for url in &self.config.url {
+            let mut request = ureq::post(&format!("{}/api/v2/alerts", url.trim_end_matches('/')));
+            for (name, value) in &self.config.headers {
+                request = request.set(name, value);
+            }
+            match request.send_json(body.clone()) {
+                Ok(_) => accepted = true,
+                Err(e) => {
+                    warn!("Alertmanager {} did not accept the alert: {}", url, e);
+                    failure = Some(e);
+                },
+            }
+        }
+        match failure {
+            Some(e) if !accepted => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Notifier for AlertmanagerNotifier {
+    fn name(&self) -> &str {
+        "alertmanager"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let mut annotations = json!({
+            "summary": alert.summary(),
+            "count": alert.count.to_string(),
+        });
+        if let Some(ip) = primary_ip() {
+            annotations["ip"] = json!(ip);
+        }
+        if let Some(system) = &alert.system {
+            annotations["system"] = json!(system.to_string());
+        }
+        if !alert.lines.is_empty() {
+            annotations["description"] = json!(alert.lines.join("\n"));
+        }
+        let mut body = json!({
+            "labels": self.labels(alert),
+            "annotations": annotations,
+            "startsAt": alert.timestamp.to_rfc3339(),
+        });
+        // Without endsAt Alertmanager resolves the alert after its
+        // resolve_timeout, as no further updates follow.
+        if let Some(seconds) = self.config.ends_after_seconds {
+            body["endsAt"] = json!((alert.timestamp + Duration::seconds(seconds as i64)).to_rfc3339());
+        }
+        self.post(body)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.post(json!({
+            "labels": self.labels(alert),
+            "annotations": { "summary": alert.summary() },
+            "endsAt": alert.timestamp.to_rfc3339(),
+        }))
+    }
+}
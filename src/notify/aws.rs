@@ -0,0 +1,325 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::notify::NotifyError;
+
+/// Temporary credentials are renewed this long before they expire.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(300);
+
+/// How long to wait for the metadata endpoints, which only answer on AWS.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+/// Credentials, and when they expire if they are temporary.
+type Expiring = (Credentials, Option<DateTime<Utc>>);
+
+/// Credentials as the container and instance metadata endpoints return them.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MetadataCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    #[serde(default)]
+    token: Option<String>,
+    #[serde(default)]
+    expiration: Option<DateTime<Utc>>,
+}
+
+/// Finds credentials the way the AWS SDKs do: in the configuration, the
+/// environment, the shared credentials file, a web identity token (e.g.
+/// of an EKS service account), the ECS container endpoint or the EC2
+/// instance metadata.
+pub struct CredentialChain {
+    configured: Option<Credentials>,
+    profile: Option<String>,
+    region: String,
+    cached: Mutex<Option<(Credentials, Option<Instant>)>>,
+}
+
+impl CredentialChain {
+    pub fn new(configured: Option<Credentials>, profile: Option<String>, region: &str) -> CredentialChain {
+        CredentialChain { configured, profile, region: region.to_string(), cached: Mutex::new(None) }
+    }
+
+    pub fn credentials(&self) -> Result<Credentials, NotifyError> {
+        if let Some(credentials) = &self.configured {
+            return Ok(credentials.clone());
+        }
+        let mut cached = self.cached.lock().unwrap();
+        if let Some((credentials, expires)) = cached.as_ref() {
+            if expires.is_none_or(|expires| Instant::now() < expires) {
+                return Ok(credentials.clone());
+            }
+        }
+        let (credentials, expiration) = self.resolve()?;
+        let expires = expiration.map(|expiration| {
+            let lifetime = (expiration - Utc::now()).to_std().unwrap_or_default();
+            Instant::now() + lifetime.saturating_sub(EXPIRY_MARGIN)
+        });
+        *cached = Some((credentials.clone(), expires));
+        Ok(credentials)
+    }
+
+    fn resolve(&self) -> Result<Expiring, NotifyError> {
+        if self.profile.is_none() {
+            if let (Some(access_key_id), Some(secret_access_key)) =
+                (env("AWS_ACCESS_KEY_ID"), env("AWS_SECRET_ACCESS_KEY"))
+            {
+                let session_token = env("AWS_SESSION_TOKEN");
+                return Ok((Credentials { access_key_id, secret_access_key, session_token }, None));
+            }
+        }
+        if let Some(credentials) = self.shared_file()? {
+            return Ok((credentials, None));
+        }
+        if let (Some(token_file), Some(role_arn)) = (env("AWS_WEB_IDENTITY_TOKEN_FILE"), env("AWS_ROLE_ARN")) {
+            return self.web_identity(&token_file, &role_arn);
+        }
+        let agent = ureq::AgentBuilder::new().timeout(METADATA_TIMEOUT).build();
+        if let Some(uri) = env("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI") {
+            let url = format!("http://169.254.170.2{}", uri);
+            return metadata(agent.get(&url).call()?.into_json()?);
+        }
+        if let Some(url) = env("AWS_CONTAINER_CREDENTIALS_FULL_URI") {
+            let mut request = agent.get(&url);
+            if let Some(token) = env("AWS_CONTAINER_AUTHORIZATION_TOKEN") {
+                request = request.set("Authorization", &token);
+            }
+            return metadata(request.call()?.into_json()?);
+        }
+        instance_metadata(&agent)
+            .map_err(|e| format!("no AWS credentials found, not even in the instance metadata: {}", e).into())
+    }
+
+    /// The profile of `~/.aws/credentials`, or of the file named by
+    /// `AWS_SHARED_CREDENTIALS_FILE`.
+    fn shared_file(&self) -> Result<Option<Credentials>, NotifyError> {
+        let path = match env("AWS_SHARED_CREDENTIALS_FILE") {
+            Some(path) => PathBuf::from(path),
+            None => match env("HOME").or_else(|| env("USERPROFILE")) {
+                Some(home) => PathBuf::from(home).join(".aws").join("credentials"),
+                None => return Ok(None),
+            },
+        };
+        let profile = self.profile.clone().or_else(|| env("AWS_PROFILE"));
+        let profile = profile.unwrap_or_else(|| "default".to_string());
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) if self.profile.is_none() => return Ok(None),
+            Err(e) => return Err(format!("cannot read {}: {}", path.display(), e).into()),
+        };
+        let (mut section, mut access_key_id, mut secret_access_key, mut session_token) =
+            (String::new(), None, None, None);
+        for line in text.lines().map(str::trim) {
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].trim().to_string();
+            } else if let Some((key, value)) = line.split_once('=') {
+                if section != profile {
+                    continue;
+                }
+                let value = Some(value.trim().to_string());
+                match key.trim() {
+                    "aws_access_key_id" => access_key_id = value,
+                    "aws_secret_access_key" => secret_access_key = value,
+                    "aws_session_token" => session_token = value,
+                    _ => (),
+                }
+            }
+        }
+        match (access_key_id, secret_access_key) {
+            (Some(access_key_id), Some(secret_access_key)) => {
+                Ok(Some(Credentials { access_key_id, secret_access_key, session_token }))
+            },
+            _ if self.profile.is_some() => {
+                Err(format!("{} has no credentials for the profile {}", path.display(), profile).into())
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Exchanges a web identity token for credentials of `role_arn`.
+    fn web_identity(&self, token_file: &str, role_arn: &str) -> Result<Expiring, NotifyError> {
+        let token = std::fs::read_to_string(token_file)?;
+        let session = env("AWS_ROLE_SESSION_NAME").unwrap_or_else(|| "log-alarmer".to_string());
+        let url = format!("https://sts.{}.amazonaws.com/", self.region);
+        let response = ureq::get(&url)
+            .query("Action", "AssumeRoleWithWebIdentity")
+            .query("Version", "2011-06-15")
+            .query("RoleArn", role_arn)
+            .query("RoleSessionName", &session)
+            .query("WebIdentityToken", token.trim())
+            .call()
+            .map_err(failure)?
+            .into_string()?;
+        let field = |name| tag(&response, name).ok_or_else(|| format!("STS answered without {}", name));
+        let credentials = Credentials {
+            access_key_id: field("AccessKeyId")?,
+            secret_access_key: field("SecretAccessKey")?,
+            session_token: Some(field("SessionToken")?),
+        };
+        Ok((credentials, field("Expiration")?.parse().ok()))
+    }
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn metadata(response: MetadataCredentials) -> Result<Expiring, NotifyError> {
+    let credentials = Credentials {
+        access_key_id: response.access_key_id,
+        secret_access_key: response.secret_access_key,
+        session_token: response.token,
+    };
+    Ok((credentials, response.expiration))
+}
+
+/// The credentials of the role of the EC2 instance, through IMDSv2.
+fn instance_metadata(agent: &ureq::Agent) -> Result<Expiring, NotifyError> {
+    let base = "http://169.254.169.254/latest";
+    let token = agent
+        .put(&format!("{}/api/token", base))
+        .set("X-aws-ec2-metadata-token-ttl-seconds", "21600")
+        .call()?
+        .into_string()?;
+    let url = format!("{}/meta-data/iam/security-credentials/", base);
+    let roles = agent.get(&url).set("X-aws-ec2-metadata-token", &token).call()?.into_string()?;
+    let role = roles.lines().next().ok_or("the instance has no IAM role")?;
+    let response = agent.get(&format!("{}{}", url, role)).set("X-aws-ec2-metadata-token", &token).call()?;
+    metadata(response.into_json()?)
+}
+
+/// The text of the first element `name` of an XML response.
+pub fn tag(xml: &str, name: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(xml[start..end].to_string())
+}
+
+/// Fails with the message AWS explains an error with.
+pub fn failure(error: ureq::Error) -> NotifyError {
+    match error {
+        ureq::Error::Status(code, response) => {
+            let body = response.into_string().unwrap_or_default();
+            match (tag(&body, "Code"), tag(&body, "Message")) {
+                (Some(kind), Some(message)) => format!("{} ({}): {}", kind, code, message).into(),
+                _ => format!("status code {}", code).into(),
+            }
+        },
+        error => error.into(),
+    }
+}
+
+/// Signs a POST of a form to `host` with Signature Version 4, returning the
+/// headers to send along with it.
+pub fn sign(
+    credentials: &Credentials,
+    service: &str,
+    region: &str,
+    host: &str,
+    body: &str,
+) -> Result<Vec<(&'static str, String)>, NotifyError> {
+    let now = Utc::now();
+    let mut headers = vec![
+        ("content-type", "application/x-www-form-urlencoded; charset=utf-8".to_string()),
+        ("host", host.to_string()),
+        ("x-amz-date", now.format("%Y%m%dT%H%M%SZ").to_string()),
+    ];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+    let authorization = authorization(credentials, (service, region), now, "POST", &headers, body)?;
+    headers.retain(|(name, _)| *name != "host");
+    headers.push(("authorization", authorization));
+    Ok(headers)
+}
+
+/// The `Authorization` header of a request to `/` without a query, made
+/// at `now` with `headers` in lower case and sorted, all of them signed.
+fn authorization(
+    credentials: &Credentials,
+    (service, region): (&str, &str),
+    now: DateTime<Utc>,
+    method: &str,
+    headers: &[(&str, String)],
+    body: &str,
+) -> Result<String, NotifyError> {
+    let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let signed: Vec<_> = headers.iter().map(|(name, _)| *name).collect();
+    let signed = signed.join(";");
+    let canonical: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+    let body = hex(&Sha256::digest(body.as_bytes()));
+    let request = format!("{}\n/\n\n{}\n{}\n{}", method, canonical, signed, body);
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let request = hex(&Sha256::digest(request.as_bytes()));
+    let to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", timestamp, scope, request);
+    let mut key = hmac(format!("AWS4{}", credentials.secret_access_key).as_bytes(), date.as_bytes())?;
+    for part in [region, service, "aws4_request"] {
+        key = hmac(&key, part.as_bytes())?;
+    }
+    let signature = hex(&hmac(&key, to_sign.as_bytes())?);
+    Ok(format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id, scope, signed, signature
+    ))
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> Result<Vec<u8>, NotifyError> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)?;
+    mac.update(message);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// The get-vanilla example of the Signature Version 4 test suite.
+    #[test]
+    fn authorization_matches_get_vanilla() {
+        let credentials = Credentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        };
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let headers = [
+            ("host", "example.amazonaws.com".to_string()),
+            ("x-amz-date", "20150830T123600Z".to_string()),
+        ];
+        let authorization = authorization(&credentials, ("service", "us-east-1"), now, "GET", &headers, "").unwrap();
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn tag_finds_the_first_element() {
+        let xml = "<Error><Code>Throttling</Code><Message>Rate exceeded</Message></Error>";
+        assert_eq!(tag(xml, "Code").as_deref(), Some("Throttling"));
+        assert_eq!(tag(xml, "Message").as_deref(), Some("Rate exceeded"));
+        assert_eq!(tag(xml, "RequestId"), None);
+    }
+}
@@ -0,0 +1,73 @@
+use notify_rust::{Notification, Timeout};
+
+use crate::config::{DesktopConfig, Severity};
+use crate::notify::{Alert, Notifier, NotifyError};
+
+/// Shows alerts as popups of the desktop the alarmer runs on, through
+/// D-Bus on Linux and the notification centers of macOS and Windows.
+pub struct DesktopNotifier {
+    config: DesktopConfig,
+}
+
+impl DesktopNotifier {
+    pub fn new(config: DesktopConfig) -> DesktopNotifier {
+        DesktopNotifier { config }
+    }
+
+    fn show(&self, alert: &Alert, body: String, icon: &str) -> Result<(), NotifyError> {
+        let mut notification = Notification::new();
+        notification
+            .appname("log-alarmer")
+            .summary(&alert.summary())
+            .body(&body)
+            .icon(self.config.icon.as_deref().unwrap_or(icon));
+        if let Some(seconds) = self.config.timeout_seconds {
+            notification.timeout(if seconds == 0 {
+                Timeout::Never
+            } else {
+                Timeout::Milliseconds(seconds.saturating_mul(1000))
+            });
+        }
+        #[cfg(all(unix, not(target_os = "macos")))]
+        notification.urgency(match (alert.resolved, alert.severity) {
+            (true, _) | (false, Severity::Info) => notify_rust::Urgency::Low,
+            (false, Severity::Warning) => notify_rust::Urgency::Normal,
+            (false, Severity::Critical) => notify_rust::Urgency::Critical,
+        });
+        notification.show()?;
+        Ok(())
+    }
+}
+
+impl Notifier for DesktopNotifier {
+    fn name(&self) -> &str {
+        "desktop"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let mut body = format!("{} at {}", alert.rule, alert.timestamp.format("%H:%M:%S"));
+        if alert.suppressed > 0 {
+            body.push_str(&format!(" ({} more during cooldown)", alert.suppressed));
+        }
+        body.push_str(&format!("\nHost: {}", alert.origin()));
+        if let Some(system) = &alert.system {
+            body.push_str(&format!("\nSystem: {}", system));
+        }
+        for line in &alert.lines {
+            body.push('\n');
+            body.push_str(line);
+        }
+        // Icon names of the freedesktop.org icon theme.
+        let icon = match alert.severity {
+            Severity::Info => "dialog-information",
+            Severity::Warning => "dialog-warning",
+            Severity::Critical => "dialog-error",
+        };
+        self.show(alert, body, icon)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let body = format!("{} at {}\nHost: {}", alert.rule, alert.timestamp.format("%H:%M:%S"), alert.origin());
+        self.show(alert, body, "emblem-default")
+    }
+}
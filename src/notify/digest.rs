@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::Map;
+
+use crate::config::Severity;
+use crate::notify::{Alert, Notifier, NotifyError};
+
+/// What a digest reports about one rule of one log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestEntry {
+    pub log_id: String,
+    pub rule: String,
+    pub severity: Severity,
+    /// Matches of all the alerts collected.
+    pub count: usize,
+    pub first: DateTime<Local>,
+    pub last: DateTime<Local>,
+    /// The most recent matching line.
+    pub sample: Option<String>,
+    /// When the alarm cleared, unless it fired again since.
+    #[serde(default)]
+    pub resolved: Option<DateTime<Local>>,
+}
+
+/// Collects the alerts of a notifier sending digests, to be sent through it
+/// as one summary once the interval after the first of them is over.
+/// Resolutions are reported along with the matches.
+pub(crate) struct Digest {
+    name: String,
+    collected: Mutex<Collected>,
+}
+
+struct Collected {
+    /// Milliseconds between summaries, 0 once the notifier no longer sends
+    /// digests.
+    interval: i64,
+    /// When the summary of the entries is due.
+    due: i64,
+    entries: Vec<DigestEntry>,
+}
+
+impl Digest {
+    /// A digest for the notifier called `channel`.
+    pub fn new(channel: &str, interval: i64) -> Digest {
+        Digest {
+            name: format!("{} digest", channel),
+            collected: Mutex::new(Collected { interval, due: 0, entries: Vec::new() }),
+        }
+    }
+
+    /// Changes the interval, from the next summary on.
+    pub fn set_interval(&self, interval: i64) {
+        self.collected.lock().unwrap().interval = interval;
+    }
+
+    /// Whether the notifier stopped sending digests.
+    pub fn retired(&self) -> bool {
+        self.collected.lock().unwrap().interval == 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.collected.lock().unwrap().entries.is_empty()
+    }
+
+    /// Takes out the summary if it is due, at once in a retired digest.
+    pub fn due(&self, now: i64) -> Option<Alert> {
+        let mut collected = self.collected.lock().unwrap();
+        if collected.interval > 0 && now < collected.due {
+            return None;
+        }
+        summary(std::mem::take(&mut collected.entries))
+    }
+
+    /// Takes out the summary of what was collected so far.
+    pub fn take(&self) -> Option<Alert> {
+        summary(std::mem::take(&mut self.collected.lock().unwrap().entries))
+    }
+}
+
+impl Notifier for Digest {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let sample = alert.lines.last().cloned();
+        self.collect(alert, |entry| {
+            entry.count += alert.count;
+            entry.severity = entry.severity.max(alert.severity);
+            entry.last = alert.timestamp;
+            entry.sample = sample.or_else(|| entry.sample.take());
+            entry.resolved = None;
+        });
+        Ok(())
+    }
+
+    /// Notes when the alarm cleared, in the entry of its matches if it has
+    /// one.
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.collect(alert, |entry| entry.resolved = Some(alert.timestamp));
+        Ok(())
+    }
+}
+
+impl Digest {
+    /// Updates the entry of the rule and log of `alert`, added first if
+    /// there is none.
+    fn collect(&self, alert: &Alert, update: impl FnOnce(&mut DigestEntry)) {
+        let mut collected = self.collected.lock().unwrap();
+        if collected.entries.is_empty() {
+            collected.due = Local::now().timestamp_millis() + collected.interval;
+        }
+        let position = collected.entries.iter()
+            .position(|entry| entry.log_id == alert.log_id && entry.rule == alert.rule);
+        let position = position.unwrap_or_else(|| {
+            collected.entries.push(DigestEntry {
+                log_id: alert.log_id.clone(),
+                rule: alert.rule.clone(),
+                severity: alert.severity,
+                count: 0,
+                first: alert.timestamp,
+                last: alert.timestamp,
+                sample: None,
+                resolved: None,
+            });
+            collected.entries.len() - 1
+        });
+        update(&mut collected.entries[position]);
+    }
+}
+
+/// One alert summing up `entries`, listed one per line.
+fn summary(entries: Vec<DigestEntry>) -> Option<Alert> {
+    let first = entries.iter().map(|entry| entry.first).min()?;
+    let mut logs: Vec<&str> = entries.iter().map(|entry| entry.log_id.as_str()).collect();
+    logs.sort_unstable();
+    logs.dedup();
+    let timestamp = Local::now();
+    let lines = entries.iter()
+        .map(|entry| format!(
+            "{} on {}: {} matches from {} to {}{}{}",
+            entry.rule, entry.log_id, entry.count,
+            entry.first.format("%H:%M:%S"), entry.last.format("%H:%M:%S"),
+            entry.sample.as_ref().map(|sample| format!(", e.g. {}", sample)).unwrap_or_default(),
+            entry.resolved.map(|at| format!(", resolved at {}", at.format("%H:%M:%S"))).unwrap_or_default(),
+        ))
+        .collect();
+    Some(Alert {
+        log_id: logs.join(", "),
+        rule: "digest".to_string(),
+        severity: entries.iter().map(|entry| entry.severity).max().unwrap_or_default(),
+        count: entries.iter().map(|entry| entry.count).sum(),
+        window: (timestamp - first).num_milliseconds(),
+        suppressed: 0,
+        fingerprint: None,
+        repeats: 0,
+        absent_for: None,
+        timestamp,
+        lines,
+        fields: Map::new(),
+        extracted: BTreeMap::new(),
+        labels: BTreeMap::new(),
+        system: None,
+        digest: entries,
+        resolved: false,
+        escalation: 0,
+        since: None,
+    })
+}
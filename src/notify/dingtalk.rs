@@ -0,0 +1,72 @@
+use chrono::Local;
+use serde_json::json;
+
+use crate::config::DingTalkConfig;
+use crate::notify::{robot_result, sign, Alert, Notifier, NotifyError};
+use crate::rule::truncate;
+
+/// Longest markdown text sent, well within what DingTalk accepts.
+const MAX_TEXT: usize = 4000;
+
+/// Sends alerts as markdown messages of a DingTalk custom robot, signed
+/// when the robot has a secret.
+pub struct DingTalkNotifier {
+    config: DingTalkConfig,
+}
+
+impl DingTalkNotifier {
+    pub fn new(config: DingTalkConfig) -> DingTalkNotifier {
+        DingTalkNotifier { config }
+    }
+
+    fn send(&self, title: String, mut text: String) -> Result<(), NotifyError> {
+        // Mentions only notify when the numbers are also in the text.
+        for mobile in &self.config.at_mobiles {
+            text.push_str(&format!(" @{}", mobile));
+        }
+        let mut request = ureq::post(&self.config.webhook_url);
+        if let Some(secret) = &self.config.secret {
+            let timestamp = Local::now().timestamp_millis().to_string();
+            let signature = sign(secret.as_bytes(), format!("{}\n{}", timestamp, secret).as_bytes())?;
+            request = request.query("timestamp", &timestamp).query("sign", &signature);
+        }
+        let response = request.send_json(json!({
+            "msgtype": "markdown",
+            "markdown": { "title": title, "text": truncate(text, MAX_TEXT) },
+            "at": { "atMobiles": self.config.at_mobiles, "isAtAll": self.config.at_all },
+        }))?;
+        robot_result(response)
+    }
+}
+
+impl Notifier for DingTalkNotifier {
+    fn name(&self) -> &str {
+        "dingtalk"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let mut text = format!(
+            "### {}\n\n- Rule: {}\n- Severity: {}\n- Time: {}\n- Host: {}\n",
+            alert.summary(), alert.rule, alert.severity.as_str(), alert.timestamp.format("%Y-%m-%d %H:%M:%S %z"),
+            alert.origin(),
+        );
+        if let Some(system) = &alert.system {
+            text.push_str(&format!("- System: {}\n", system));
+        }
+        if alert.suppressed > 0 {
+            text.push_str(&format!("- During cooldown: {}\n", alert.suppressed));
+        }
+        if !alert.lines.is_empty() {
+            text.push('\n');
+            for line in &alert.lines {
+                text.push_str(&format!("> {}\n\n", line));
+            }
+        }
+        self.send(alert.summary(), text)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let at = alert.timestamp.format("%Y-%m-%d %H:%M:%S %z");
+        self.send(alert.summary(), format!("### {}\n\n- Time: {}\n- Host: {}\n", alert.summary(), at, alert.origin()))
+    }
+}
@@ -0,0 +1,88 @@
+use serde_json::json;
+
+use crate::config::{DiscordConfig, Severity};
+use crate::notify::{Alert, Notifier, NotifyError};
+use crate::rule::truncate;
+
+/// Longest message content Discord accepts.
+const MAX_CONTENT: usize = 2000;
+
+/// Longest embed description Discord accepts.
+const MAX_DESCRIPTION: usize = 4096;
+
+/// Posts alerts to a Discord webhook, as embeds colored by severity unless
+/// plain messages are configured.
+pub struct DiscordNotifier {
+    config: DiscordConfig,
+}
+
+impl DiscordNotifier {
+    pub fn new(config: DiscordConfig) -> DiscordNotifier {
+        DiscordNotifier { config }
+    }
+
+    fn color(alert: &Alert) -> u32 {
+        if alert.resolved {
+            return 0x2ecc71;
+        }
+        match alert.severity {
+            Severity::Info => 0x3498db,
+            Severity::Warning => 0xf39c12,
+            Severity::Critical => 0xe74c3c,
+        }
+    }
+
+    fn post(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let limit = if self.config.embed { MAX_DESCRIPTION } else { MAX_CONTENT };
+        let mut text = String::new();
+        if alert.suppressed > 0 {
+            text.push_str(&format!("{} more during cooldown\n", alert.suppressed));
+        }
+        if let Some(system) = &alert.system {
+            text.push_str(&format!("System: {}\n", system));
+        }
+        if !alert.lines.is_empty() && !alert.resolved {
+            // Leaves room for the summary and the fences around the lines.
+            let lines = truncate(alert.lines.join("\n"), (limit - 200).saturating_sub(text.len()));
+            text.push_str(&format!("```\n{}\n```", lines));
+        }
+        let mut body = if self.config.embed {
+            json!({
+                "embeds": [{
+                    "title": alert.summary(),
+                    "description": text,
+                    "color": DiscordNotifier::color(alert),
+                    "timestamp": alert.timestamp.to_rfc3339(),
+                    "fields": [
+                        { "name": "Log", "value": alert.log_id, "inline": true },
+                        { "name": "Rule", "value": alert.rule, "inline": true },
+                        { "name": "Severity", "value": alert.severity.as_str(), "inline": true },
+                        { "name": "Host", "value": alert.origin() },
+                    ],
+                }],
+            })
+        } else {
+            let at = alert.timestamp.format("%Y-%m-%d %H:%M:%S %z");
+            json!({ "content": format!("**{}** at {} on {}\n{}", alert.summary(), at, alert.origin(), text) })
+        };
+        if let Some(username) = &self.config.username {
+            body["username"] = json!(username);
+        }
+        ureq::post(&self.config.webhook_url).send_json(body)?;
+        Ok(())
+    }
+}
+
+impl Notifier for DiscordNotifier {
+    fn name(&self) -> &str {
+        "discord"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.post(alert)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.post(alert)
+    }
+}
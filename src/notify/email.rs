@@ -0,0 +1,426 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use handlebars::{no_escape, Handlebars};
+use lettre::{
+    ClientSecurity, ClientTlsParameters, SendableEmail, SendmailTransport, SmtpClient, SmtpTransport, Transport,
+};
+use lettre::smtp::authentication::{Credentials, Mechanism};
+use lettre::smtp::client::net::NetworkStream;
+use lettre::smtp::client::InnerClient;
+use lettre::smtp::commands::{DataCommand, EhloCommand, MailCommand, RcptCommand};
+use lettre::smtp::extension::{ClientId, Extension, MailParameter, ServerInfo};
+use lettre::smtp::ConnectionReuseParameters;
+use lettre_email::{Email, EmailBuilder};
+use native_tls::{Certificate, TlsConnector};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::config::{EmailConfig, EmailTransport, TlsMode};
+use crate::notify::aws::hex;
+use crate::notify::oauth2::TokenSource;
+use crate::notify::{self, hostname, Alert, Notifier, NotifyError};
+
+const SUBJECT: &str = "Bot: ERROR Occurred!!";
+
+pub struct EmailNotifier {
+    config: EmailConfig,
+    tokens: Option<TokenSource>,
+    /// The subject and text body templates, rendered without escaping.
+    text: Handlebars<'static>,
+    /// The HTML body template, with HTML escaping.
+    html: Handlebars<'static>,
+    /// The connection of the last mail, kept for the next ones.
+    connection: Mutex<Option<Connection>>,
+}
+
+struct Connection {
+    host: String,
+    mailer: Mailer,
+    used: Instant,
+}
+
+/// The transport of lettre, or a session over a stream whose certificate
+/// was checked against the pinned one, which lettre cannot do.
+enum Mailer {
+    Transport(SmtpTransport),
+    Pinned(InnerClient, ServerInfo),
+}
+
+impl Mailer {
+    fn send(&mut self, email: SendableEmail) -> Result<(), NotifyError> {
+        let (client, server) = match self {
+            Mailer::Transport(transport) => {
+                transport.send(email)?;
+                return Ok(());
+            },
+            Mailer::Pinned(client, server) => (client, server),
+        };
+        let mut parameters = Vec::new();
+        if server.supports_feature(Extension::SmtpUtfEight) {
+            parameters.push(MailParameter::SmtpUtfEight);
+        }
+        client.command(MailCommand::new(email.envelope().from().cloned(), parameters))?;
+        for to in email.envelope().to() {
+            client.command(RcptCommand::new(to.clone(), Vec::new()))?;
+        }
+        client.command(DataCommand)?;
+        client.message(Box::new(email.message()))?;
+        Ok(())
+    }
+
+    /// Whether the server still answers. Lettre checks its connection
+    /// itself when sending, and opens it again if needed.
+    fn connected(&mut self) -> bool {
+        match self {
+            Mailer::Transport(_) => true,
+            Mailer::Pinned(client, _) => client.is_connected(),
+        }
+    }
+
+    fn close(&mut self) {
+        match self {
+            Mailer::Transport(transport) => transport.close(),
+            Mailer::Pinned(client, _) => client.close(),
+        }
+    }
+}
+
+impl EmailNotifier {
+    /// Fails if one of the templates does not parse.
+    pub fn new(config: EmailConfig) -> Result<EmailNotifier, NotifyError> {
+        let tokens = config.oauth2.clone().map(TokenSource::new);
+        let mut text = Handlebars::new();
+        text.register_escape_fn(no_escape);
+        text.register_template_string("subject", config.subject.as_deref().unwrap_or(SUBJECT))?;
+        if let Some(body) = &config.body {
+            text.register_template_string("body", body)?;
+        }
+        let mut html = Handlebars::new();
+        if let Some(body) = &config.html_body {
+            html.register_template_string("body", body)?;
+        }
+        Ok(EmailNotifier { config, tokens, text, html, connection: Mutex::new(None) })
+    }
+
+    fn client(&self, host: &str) -> Result<SmtpClient, NotifyError> {
+        let relay = self.config.transport == EmailTransport::Relay;
+        let tls = || -> Result<ClientTlsParameters, NotifyError> {
+            Ok(ClientTlsParameters::new(host.to_string(), self.connector()?))
+        };
+        // Relays are trusted, and take mails in plaintext whatever `tls` is.
+        let security = if relay {
+            ClientSecurity::None
+        } else {
+            match self.config.tls {
+                TlsMode::None => ClientSecurity::None,
+                TlsMode::Starttls => ClientSecurity::Required(tls()?),
+                TlsMode::Implicit => ClientSecurity::Wrapper(tls()?),
+            }
+        };
+        let mut client = SmtpClient::new((host, self.config.port()), security)?;
+        if let Some(seconds) = self.config.timeout_seconds {
+            client = client.timeout(Some(Duration::from_secs(seconds)));
+        }
+        Ok(client)
+    }
+
+    fn connector(&self) -> Result<TlsConnector, NotifyError> {
+        let mut builder = TlsConnector::builder();
+        if let Some(file) = &self.config.ca_file {
+            for certificate in Certificate::stack_from_pem(&fs::read(file)?)? {
+                builder.add_root_certificate(certificate);
+            }
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Opens a session with `host`, reaching TLS as `tls` says, if it
+    /// presents the certificate of `fingerprint`, and logs in.
+    fn pinned(&self, host: &str, fingerprint: &str) -> Result<Mailer, NotifyError> {
+        let timeout = Duration::from_secs(self.config.timeout_seconds.unwrap_or(60));
+        let stream = notify::connect((host, self.config.port()), timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        if self.config.tls == TlsMode::Starttls {
+            starttls(&stream)?;
+        }
+        // The fingerprint is what the server is trusted by, whatever the
+        // certificate is issued by or named.
+        let connector = TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true)
+            .build()?;
+        let mut tls = connector.connect(host, stream)?;
+        let certificate = tls.peer_certificate()?.ok_or("the server presented no certificate")?;
+        let actual = hex(&Sha256::digest(certificate.to_der()?));
+        let pinned = fingerprint.chars().filter(char::is_ascii_hexdigit).collect::<String>().to_lowercase();
+        if actual != pinned {
+            let message = format!("{} presented a certificate of fingerprint {}, not the pinned one", host, actual);
+            return Err(message.into());
+        }
+        if self.config.tls == TlsMode::Implicit {
+            reply(&mut BufReader::new(&mut tls))?; // greeting
+        }
+        let mut client = InnerClient::new();
+        client.set_stream(NetworkStream::Tls(tls));
+        let server = ServerInfo::from_response(&client.command(EhloCommand::new(ClientId::new(hostname())))?)?;
+        // The mechanisms lettre tries over TLS.
+        let (credentials, mechanism) = self.credentials()?;
+        let mechanisms = mechanism.map_or(vec![Mechanism::Plain, Mechanism::Login], |mechanism| vec![mechanism]);
+        let mechanism = mechanisms.iter().find(|mechanism| server.supports_auth_mechanism(**mechanism))
+            .ok_or_else(|| format!("{} offers none of the login mechanisms {:?}", host, mechanisms))?;
+        client.auth(*mechanism, &credentials)?;
+        Ok(Mailer::Pinned(client, server))
+    }
+
+    /// The credentials to log in with, and the mechanism if only one will do.
+    fn credentials(&self) -> Result<(Credentials, Option<Mechanism>), NotifyError> {
+        let username = self.config.username.clone();
+        match &self.tokens {
+            Some(tokens) => Ok((Credentials::new(username, tokens.token()?), Some(Mechanism::Xoauth2))),
+            None => Ok((Credentials::new(username, self.config.password.clone()), None)),
+        }
+    }
+
+    fn send(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let vars = alert.variables();
+        let text = if self.text.has_template("body") {
+            self.text.render("body", &vars)?
+        } else {
+            body(alert)
+        };
+        let mut subject = self.text.render("subject", &vars)?;
+        if alert.resolved {
+            subject = format!("Resolved: {}", subject);
+        } else if let Some(since) = unresolved_since(alert) {
+            subject = format!("Still unresolved since {}: {}", since, subject);
+        }
+        let mut builder = EmailBuilder::new()
+            .from(self.config.from.as_deref().unwrap_or(&self.config.username))
+            .subject(subject);
+        builder = if self.html.has_template("body") {
+            builder.alternative(self.html.render("body", &vars)?, text)
+        } else {
+            builder.text(text)
+        };
+        for to in self.config.recipients() {
+            builder = builder.to(to.as_str());
+        }
+        for cc in &self.config.cc {
+            builder = builder.cc(cc.as_str());
+        }
+        for bcc in &self.config.bcc {
+            builder = builder.bcc(bcc.as_str());
+        }
+        if let Some(reply_to) = &self.config.reply_to {
+            builder = builder.reply_to(reply_to.as_str());
+        }
+        let email = builder.build()?;
+        match self.config.transport {
+            EmailTransport::Smtp => self.submit(&email, true),
+            EmailTransport::Relay => self.submit(&email, false),
+            EmailTransport::Sendmail => {
+                SendmailTransport::new_with_command(self.config.sendmail_command.as_str()).send(email.into())?;
+                Ok(())
+            },
+        }
+    }
+
+    /// Sends the mail through the first SMTP server that takes it, logged in
+    /// to if `login`. Fails with the error of the last one.
+    fn submit(&self, email: &Email, login: bool) -> Result<(), NotifyError> {
+        let localhost = ["localhost".to_string()];
+        let hosts = if self.config.smtp.is_empty() { &localhost[..] } else { &self.config.smtp[..] };
+        let mut failure = None;
+        for (index, host) in hosts.iter().enumerate() {
+            match self.submit_to(host, email.clone().into(), login) {
+                Ok(()) => return Ok(()),
+                Err(e) if index + 1 < hosts.len() => {
+                    warn!("SMTP server {} did not take the mail, trying the next one: {}", host, e);
+                },
+                Err(e) => failure = Some(e),
+            }
+        }
+        Err(failure.unwrap_or_else(|| "no SMTP server to send to".into()))
+    }
+
+    /// Sends the mail over the open connection to `host`, unless it has been
+    /// idle for too long. Connections the server closed meanwhile are opened
+    /// again, as a NOOP finds.
+    fn submit_to(&self, host: &str, email: SendableEmail, login: bool) -> Result<(), NotifyError> {
+        let idle = Duration::from_secs(self.config.idle_timeout_seconds);
+        let mut connection = self.connection.lock().unwrap();
+        let mut current = match connection.take() {
+            Some(mut open) if open.host == host && open.used.elapsed() < idle => {
+                if !open.mailer.connected() {
+                    open.mailer.close();
+                    open.mailer = self.transport(host, login)?;
+                }
+                open
+            },
+            stale => {
+                if let Some(mut stale) = stale {
+                    stale.mailer.close();
+                }
+                Connection { host: host.to_string(), mailer: self.transport(host, login)?, used: Instant::now() }
+            },
+        };
+
+        let result = current.mailer.send(email);
+        if result.is_ok() && !idle.is_zero() {
+            current.used = Instant::now();
+            *connection = Some(current);
+        } else {
+            current.mailer.close();
+        }
+        if let (Err(_), Some(tokens)) = (&result, &self.tokens) {
+            tokens.invalidate();
+        }
+        result?;
+        Ok(())
+    }
+
+    fn transport(&self, host: &str, login: bool) -> Result<Mailer, NotifyError> {
+        match &self.config.certificate_fingerprint {
+            Some(fingerprint) if login && self.config.tls != TlsMode::None => return self.pinned(host, fingerprint),
+            _ => (),
+        }
+        let mut client = self.client(host)?.smtp_utf8(true).connection_reuse(ConnectionReuseParameters::ReuseUnlimited);
+        if login {
+            let (credentials, mechanism) = self.credentials()?;
+            client = client.credentials(credentials);
+            if let Some(mechanism) = mechanism {
+                client = client.authentication_mechanism(mechanism);
+            }
+        }
+        Ok(Mailer::Transport(client.transport()))
+    }
+}
+
+impl Drop for EmailNotifier {
+    /// Says goodbye to the server of a connection still open.
+    fn drop(&mut self) {
+        if let Some(mut open) = self.connection.get_mut().ok().and_then(Option::take) {
+            open.mailer.close();
+        }
+    }
+}
+
+/// Asks the server to start TLS on `stream`.
+fn starttls(stream: &TcpStream) -> Result<(), NotifyError> {
+    let mut reader = BufReader::new(stream);
+    let mut writer = stream;
+    reply(&mut reader)?;
+    writer.write_all(format!("EHLO {}\r\n", hostname()).as_bytes())?;
+    reply(&mut reader)?;
+    writer.write_all(b"STARTTLS\r\n")?;
+    reply(&mut reader)
+}
+
+/// Reads an SMTP reply, failing unless it is positive.
+fn reply(reader: &mut impl BufRead) -> Result<(), NotifyError> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err("the server closed the connection".into());
+        }
+        // Every line but the last has a dash after the code.
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return if line.starts_with('2') {
+                Ok(())
+            } else {
+                Err(format!("the server answered {}", line.trim_end()).into())
+            };
+        }
+    }
+}
+
+/// When the alarm of a reminder fired.
+fn unresolved_since(alert: &Alert) -> Option<String> {
+    let since = alert.since.filter(|_| alert.escalation > 0)?;
+    Some(since.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+fn body(alert: &Alert) -> String {
+    if !alert.digest.is_empty() {
+        return digest_body(alert);
+    }
+    let mut text = match unresolved_since(alert) {
+        Some(since) => format!("Still unresolved since {}, reminder {}.\n\n", since, alert.escalation),
+        None => String::new(),
+    };
+    if alert.absent_for.is_some() || alert.resolved {
+        text.push_str(&format!("{} at {}\nHost: {}\n", alert.summary(), alert.timestamp, alert.origin()));
+        return text;
+    }
+    text.push_str(&format!("Multiple error occurred on {} at {}\n", alert.log_id, alert.timestamp));
+    text.push_str(&format!("Host: {}\n", alert.origin()));
+    if let Some(system) = &alert.system {
+        text.push_str(&format!("System: {}\n", system));
+    }
+    text.push_str(&format!("\n{} matches of: {}\n", alert.count, alert.rule));
+    if alert.suppressed > 0 {
+        text.push_str(&format!("{} more matches during the cooldown.\n", alert.suppressed));
+    }
+    if alert.repeats > 0 {
+        text.push_str(&format!("Seen {} more times since the last alert.\n", alert.repeats));
+    }
+    if !alert.lines.is_empty() {
+        text.push_str("\nRecent lines:\n\n");
+        for line in &alert.lines {
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// A table of the alerts summed up by a digest.
+fn digest_body(alert: &Alert) -> String {
+    let mut rows = vec![["Rule", "Log", "Count", "First", "Last", "Resolved", "Sample"].map(String::from)];
+    for entry in &alert.digest {
+        rows.push([
+            entry.rule.clone(),
+            entry.log_id.clone(),
+            entry.count.to_string(),
+            entry.first.format("%Y-%m-%d %H:%M:%S").to_string(),
+            entry.last.format("%Y-%m-%d %H:%M:%S").to_string(),
+            entry.resolved.map(|at| at.format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_default(),
+            entry.sample.clone().unwrap_or_default(),
+        ]);
+    }
+    let mut widths = [0; 7];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut text = format!("{} at {}\nHost: {}\n\n", alert.summary(), alert.timestamp, alert.origin());
+    for row in &rows {
+        let cells: Vec<String> = row.iter().zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        text.push_str(cells.join("  ").trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.send(alert)
+    }
+
+    /// Mails the alert again, with `Resolved: ` before the subject.
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.send(alert)
+    }
+}
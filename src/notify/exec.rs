@@ -0,0 +1,128 @@
+use std::io::{Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use tracing::debug;
+
+use crate::config::ExecConfig;
+use crate::notify::{Alert, Notifier, NotifyError};
+use crate::rule::truncate;
+
+/// Longest output of the command quoted in errors.
+const MAX_OUTPUT: usize = 500;
+
+/// Runs a local command for each alert, e.g. to restart a service or to
+/// reach systems no other notifier speaks to.
+pub struct ExecNotifier {
+    config: ExecConfig,
+}
+
+impl ExecNotifier {
+    pub fn new(config: ExecConfig) -> ExecNotifier {
+        ExecNotifier { config }
+    }
+
+    fn run(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C").arg(&self.config.command);
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(&self.config.command);
+            command
+        };
+        let vars = alert.variables();
+        for (key, value) in &vars {
+            command.env(format!("ALERT_{}", key.to_uppercase()), env_value(key, value));
+        }
+        command.envs(&self.config.env);
+        if let Some(dir) = &self.config.working_dir {
+            command.current_dir(dir);
+        }
+        let stdin = if self.config.stdin { Stdio::piped() } else { Stdio::null() };
+        let mut child = command.stdin(stdin).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            let input = serde_json::to_vec(&Value::Object(vars))?;
+            // Commands are free not to read their input.
+            thread::spawn(move || stdin.write_all(&input));
+        }
+        let stdout = capture(child.stdout.take());
+        let stderr = capture(child.stderr.take());
+        let status = wait(&mut child, Duration::from_secs(self.config.timeout_seconds))?;
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        if !stdout.trim().is_empty() {
+            debug!("{} printed: {}", self.config.command, stdout.trim_end());
+        }
+        if !status.success() {
+            let mut message = format!("command failed with {}", status);
+            if !stderr.trim().is_empty() {
+                message.push_str(&format!(": {}", truncate(stderr.trim().to_string(), MAX_OUTPUT)));
+            }
+            return Err(message.into());
+        }
+        Ok(())
+    }
+}
+
+/// Reads the output of the command while it runs, so it never blocks on a
+/// full pipe.
+fn capture<R: Read + Send + 'static>(output: Option<R>) -> JoinHandle<String> {
+    thread::spawn(move || {
+        let mut text = Vec::new();
+        if let Some(mut output) = output {
+            let _ = output.read_to_end(&mut text);
+        }
+        String::from_utf8_lossy(&text).into_owned()
+    })
+}
+
+/// Waits for the command to exit, killing it after `timeout`.
+fn wait(child: &mut Child, timeout: Duration) -> Result<std::process::ExitStatus, NotifyError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("command killed after {} s", timeout.as_secs()).into());
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// The variable `key` as the command sees it in its environment: strings
+/// as they are, sample lines one per line and anything else as JSON.
+fn env_value(key: &str, value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        Value::Array(lines) if key == "lines" => {
+            lines.iter().filter_map(Value::as_str).collect::<Vec<_>>().join("\n")
+        },
+        value => value.to_string(),
+    }
+}
+
+impl Notifier for ExecNotifier {
+    fn name(&self) -> &str {
+        "exec"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.run(alert)
+    }
+
+    /// Runs the command again, with `ALERT_STATUS` set to `resolved`.
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        if !self.config.send_resolved {
+            return Ok(());
+        }
+        self.run(alert)
+    }
+}
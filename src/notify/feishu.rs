@@ -0,0 +1,88 @@
+use chrono::Local;
+use serde_json::{json, Value};
+
+use crate::config::{FeishuConfig, Severity};
+use crate::notify::{robot_result, sign, Alert, Notifier, NotifyError};
+use crate::rule::truncate;
+
+/// Longest text of the matching lines in a card.
+const MAX_LINES: usize = 4000;
+
+/// Sends alerts as interactive cards of a Feishu (Lark) custom bot, signed
+/// when the bot has a secret.
+pub struct FeishuNotifier {
+    config: FeishuConfig,
+}
+
+impl FeishuNotifier {
+    pub fn new(config: FeishuConfig) -> FeishuNotifier {
+        FeishuNotifier { config }
+    }
+
+    fn card(alert: &Alert) -> Value {
+        let template = match alert.severity {
+            _ if alert.resolved => "green",
+            Severity::Info => "blue",
+            Severity::Warning => "orange",
+            Severity::Critical => "red",
+        };
+        let field = |name: &str, value: String| json!({
+            "is_short": true,
+            "text": { "tag": "lark_md", "content": format!("**{}**\n{}", name, value) },
+        });
+        let mut fields = vec![
+            field("Log", alert.log_id.clone()),
+            field("Host", alert.origin()),
+            field("Rule", alert.rule.clone()),
+            field("Severity", alert.severity.as_str().to_string()),
+            field("Time", alert.timestamp.format("%Y-%m-%d %H:%M:%S %z").to_string()),
+        ];
+        if !alert.resolved {
+            fields.insert(2, field("Count", alert.count.to_string()));
+        }
+        if alert.suppressed > 0 {
+            fields.push(field("During cooldown", alert.suppressed.to_string()));
+        }
+        if let Some(system) = &alert.system {
+            fields.push(field("System", system.to_string()));
+        }
+        let mut elements = vec![json!({ "tag": "div", "fields": fields })];
+        if !alert.lines.is_empty() && !alert.resolved {
+            elements.push(json!({ "tag": "hr" }));
+            elements.push(json!({
+                "tag": "div",
+                "text": { "tag": "plain_text", "content": truncate(alert.lines.join("\n"), MAX_LINES) },
+            }));
+        }
+        json!({
+            "config": { "wide_screen_mode": true },
+            "header": { "title": { "tag": "plain_text", "content": alert.summary() }, "template": template },
+            "elements": elements,
+        })
+    }
+
+    fn send(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let mut body = json!({ "msg_type": "interactive", "card": FeishuNotifier::card(alert) });
+        if let Some(secret) = &self.config.secret {
+            // The key is made of the timestamp and secret, the message is empty.
+            let timestamp = Local::now().timestamp().to_string();
+            body["timestamp"] = json!(timestamp);
+            body["sign"] = json!(sign(format!("{}\n{}", timestamp, secret).as_bytes(), b"")?);
+        }
+        robot_result(ureq::post(&self.config.webhook_url).send_json(body)?)
+    }
+}
+
+impl Notifier for FeishuNotifier {
+    fn name(&self) -> &str {
+        "feishu"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.send(alert)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.send(alert)
+    }
+}
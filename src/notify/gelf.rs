@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use native_tls::TlsConnector;
+use serde_json::{json, Map, Value};
+
+use crate::config::{GelfConfig, GelfProtocol, Severity};
+use crate::notify::{hostname, primary_ip, Alert, Notifier, NotifyError};
+
+/// Bytes of a UDP datagram Graylog takes, and the header of a chunk.
+const CHUNK_SIZE: usize = 8192;
+const CHUNK_HEADER: usize = 12;
+/// Graylog drops messages of more chunks.
+const MAX_CHUNKS: usize = 128;
+
+/// A GELF message with the fields every message has.
+pub fn message(short: &str, full: Option<&str>, severity: Severity, timestamp: DateTime<Local>) -> Map<String, Value> {
+    // Syslog levels: critical, warning and informational.
+    let level = match severity {
+        Severity::Info => 6,
+        Severity::Warning => 4,
+        Severity::Critical => 2,
+    };
+    let mut message = Map::new();
+    message.insert("version".to_string(), json!("1.1"));
+    message.insert("host".to_string(), json!(hostname()));
+    message.insert("short_message".to_string(), json!(short));
+    if let Some(full) = full {
+        message.insert("full_message".to_string(), json!(full));
+    }
+    message.insert("timestamp".to_string(), json!(timestamp.timestamp_millis() as f64 / 1000.0));
+    message.insert("level".to_string(), json!(level));
+    message
+}
+
+/// Adds `value` as additional fields, named after `name` and, for objects,
+/// the keys within it, as GELF only takes strings and numbers.
+pub fn add_field(message: &mut Map<String, Value>, name: &str, value: &Value) {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '.' || c == '-';
+    let name: String = name.chars().map(|c| if valid(c) { c } else { '_' }).collect();
+    match value {
+        Value::Null => (),
+        Value::String(_) | Value::Number(_) => {
+            // `_id` is reserved for the id Graylog gives the message.
+            if name != "id" {
+                message.insert(format!("_{}", name), value.clone());
+            }
+        },
+        Value::Bool(flag) => {
+            message.insert(format!("_{}", name), json!(flag.to_string()));
+        },
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                add_field(message, &format!("{}_{}", name, key), value);
+            }
+        },
+        Value::Array(_) => {
+            message.insert(format!("_{}", name), json!(value.to_string()));
+        },
+    }
+}
+
+/// Sends messages to the input, each as a datagram of up to 128 chunks or,
+/// over TCP, ended by a null byte.
+pub fn send(config: &GelfConfig, messages: Vec<Map<String, Value>>) -> Result<(), NotifyError> {
+    let timeout = Some(Duration::from_secs(config.timeout_seconds));
+    let payloads = messages.into_iter().map(|mut message| {
+        for (name, value) in &config.fields {
+            message.entry(format!("_{}", name)).or_insert_with(|| json!(value));
+        }
+        Value::Object(message).to_string()
+    });
+    match config.protocol {
+        GelfProtocol::Udp => {
+            let socket = UdpSocket::bind(if config.address.starts_with('[') { "[::]:0" } else { "0.0.0.0:0" })?;
+            socket.set_write_timeout(timeout)?;
+            socket.connect(&config.address)?;
+            for payload in payloads {
+                send_datagram(&socket, payload.as_bytes())?;
+            }
+        },
+        GelfProtocol::Tcp => {
+            let tcp = TcpStream::connect(&config.address)?;
+            tcp.set_write_timeout(timeout)?;
+            let mut stream: Box<dyn Write> = if config.tls {
+                let host = config.address.rsplit_once(':').map_or(config.address.as_str(), |(host, _)| host);
+                Box::new(TlsConnector::new()?.connect(host.trim_start_matches('[').trim_end_matches(']'), tcp)?)
+            } else {
+                Box::new(tcp)
+            };
+            for payload in payloads {
+                stream.write_all(payload.as_bytes())?;
+                stream.write_all(&[0])?;
+            }
+            stream.flush()?;
+        },
+    }
+    Ok(())
+}
+
+fn send_datagram(socket: &UdpSocket, payload: &[u8]) -> Result<(), NotifyError> {
+    if payload.len() <= CHUNK_SIZE {
+        socket.send(payload)?;
+        return Ok(());
+    }
+    let chunks: Vec<&[u8]> = payload.chunks(CHUNK_SIZE - CHUNK_HEADER).collect();
+    if chunks.len() > MAX_CHUNKS {
+        return Err(format!("a message of {} bytes is too large for GELF over UDP", payload.len()).into());
+    }
+    let id = fastrand::u64(..).to_be_bytes();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let mut datagram = vec![0x1e, 0x0f];
+        datagram.extend(id);
+        datagram.extend([i as u8, chunks.len() as u8]);
+        datagram.extend(*chunk);
+        socket.send(&datagram)?;
+    }
+    Ok(())
+}
+
+/// Sends alerts as GELF messages to a Graylog input, with their fields,
+/// e.g. those of a JSON line, as additional fields.
+pub struct GelfNotifier {
+    config: GelfConfig,
+}
+
+impl GelfNotifier {
+    pub fn new(config: GelfConfig) -> GelfNotifier {
+        GelfNotifier { config }
+    }
+
+    fn publish(&self, alert: &Alert, severity: Severity) -> Result<(), NotifyError> {
+        let full = alert.lines.join("\n");
+        let full = Some(full.as_str()).filter(|full| !full.is_empty());
+        let mut message = message(&alert.summary(), full, severity, alert.timestamp);
+        let mut fields = BTreeMap::new();
+        fields.insert("log_id", json!(alert.log_id));
+        fields.insert("rule", json!(alert.rule));
+        fields.insert("severity", json!(alert.severity.as_str()));
+        fields.insert("status", json!(if alert.resolved { "resolved" } else { "firing" }));
+        fields.insert("count", json!(alert.count));
+        fields.insert("fingerprint", json!(alert.fingerprint));
+        fields.insert("ip", json!(primary_ip()));
+        fields.insert("system", json!(alert.system.as_ref().map(ToString::to_string)));
+        for (name, value) in &alert.labels {
+            fields.entry(name.as_str()).or_insert_with(|| json!(value));
+        }
+        for (name, value) in &fields {
+            add_field(&mut message, name, value);
+        }
+        for (name, value) in &alert.fields {
+            if !fields.contains_key(name.as_str()) {
+                add_field(&mut message, name, value);
+            }
+        }
+        send(&self.config, vec![message])
+    }
+}
+
+impl Notifier for GelfNotifier {
+    fn name(&self) -> &str {
+        "gelf"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.publish(alert, alert.severity)
+    }
+
+    /// Sends the alert again at the informational level, with `_status`
+    /// set to `resolved`.
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.publish(alert, Severity::Info)
+    }
+}
@@ -0,0 +1,56 @@
+use serde_json::json;
+
+use crate::config::{GotifyConfig, Severity};
+use crate::notify::{Alert, Notifier, NotifyError};
+
+/// Sends alerts as messages of a Gotify application.
+pub struct GotifyNotifier {
+    config: GotifyConfig,
+}
+
+impl GotifyNotifier {
+    pub fn new(config: GotifyConfig) -> GotifyNotifier {
+        GotifyNotifier { config }
+    }
+
+    fn send(&self, title: String, message: String, priority: u8) -> Result<(), NotifyError> {
+        let url = format!("{}/message", self.config.url.trim_end_matches('/'));
+        ureq::post(&url)
+            .set("X-Gotify-Key", &self.config.token)
+            .send_json(json!({ "title": title, "message": message, "priority": priority }))?;
+        Ok(())
+    }
+}
+
+impl Notifier for GotifyNotifier {
+    fn name(&self) -> &str {
+        "gotify"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        // Gotify's clients notify from priority 4 on and insist from 8 on.
+        let priority = self.config.priority.unwrap_or(match alert.severity {
+            Severity::Info => 2,
+            Severity::Warning => 5,
+            Severity::Critical => 8,
+        });
+        let mut message = format!("{} at {}", alert.rule, alert.timestamp.format("%Y-%m-%d %H:%M:%S %z"));
+        if alert.suppressed > 0 {
+            message.push_str(&format!(" ({} more during cooldown)", alert.suppressed));
+        }
+        message.push_str(&format!("\nHost: {}", alert.origin()));
+        if let Some(system) = &alert.system {
+            message.push_str(&format!("\nSystem: {}", system));
+        }
+        for line in &alert.lines {
+            message.push('\n');
+            message.push_str(line);
+        }
+        self.send(alert.summary(), message, priority)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let at = alert.timestamp.format("%Y-%m-%d %H:%M:%S %z");
+        self.send(alert.summary(), format!("{} at {}\nHost: {}", alert.rule, at, alert.origin()), 2)
+    }
+}
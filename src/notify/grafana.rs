@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde_json::{json, Value};
+
+use crate::config::GrafanaConfig;
+use crate::notify::{hostname, Alert, Notifier, NotifyError};
+
+/// Marks alerts as annotations of Grafana, so they show on the graphs of
+/// dashboards, as regions up to their resolution.
+pub struct GrafanaNotifier {
+    config: GrafanaConfig,
+    /// The annotations of alarms still firing, by log and rule.
+    open: Mutex<HashMap<(String, String), i64>>,
+}
+
+impl GrafanaNotifier {
+    pub fn new(config: GrafanaConfig) -> GrafanaNotifier {
+        GrafanaNotifier { config, open: Mutex::new(HashMap::new()) }
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        ureq::request(method, &format!("{}/api/annotations{}", self.config.url.trim_end_matches('/'), path))
+            .set("Authorization", &format!("Bearer {}", self.config.token))
+    }
+
+    fn create(&self, alert: &Alert, text: String) -> Result<i64, NotifyError> {
+        let mut tags = vec![alert.log_id.clone(), alert.rule.clone(), alert.severity.as_str().to_string()];
+        tags.push(hostname());
+        tags.extend(alert.labels.iter().map(|(name, value)| format!("{}:{}", name, value)));
+        tags.extend(self.config.tags.iter().cloned());
+        let mut body = json!({ "time": alert.timestamp.timestamp_millis(), "tags": tags, "text": text });
+        if let Some(uid) = &self.config.dashboard_uid {
+            body["dashboardUID"] = json!(uid);
+        }
+        if let Some(panel) = self.config.panel_id {
+            body["panelId"] = json!(panel);
+        }
+        let response: Value = self.request("POST", "").send_json(body)?.into_json()?;
+        response["id"].as_i64().ok_or_else(|| "Grafana answered no annotation id".into())
+    }
+}
+
+impl Notifier for GrafanaNotifier {
+    fn name(&self) -> &str {
+        "grafana"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let mut text = alert.summary();
+        for line in &alert.lines {
+            text.push('\n');
+            text.push_str(line);
+        }
+        let id = self.create(alert, text)?;
+        // Reminders of an alarm keep the annotation of its first alert.
+        let key = (alert.log_id.clone(), alert.rule.clone());
+        self.open.lock().unwrap().entry(key).or_insert(id);
+        Ok(())
+    }
+
+    /// Ends the annotation of the alarm at its resolution, or marks the
+    /// resolution on its own if that one is unknown, e.g. after a restart.
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let key = (alert.log_id.clone(), alert.rule.clone());
+        let id = self.open.lock().unwrap().get(&key).copied();
+        match id {
+            Some(id) => {
+                self.request("PATCH", &format!("/{}", id))
+                    .send_json(json!({ "timeEnd": alert.timestamp.timestamp_millis() }))?;
+            },
+            None => {
+                self.create(alert, alert.summary())?;
+            },
+        }
+        self.open.lock().unwrap().remove(&key);
+        Ok(())
+    }
+}
@@ -0,0 +1,526 @@
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+use native_tls::TlsConnector;
+use serde_json::Value;
+use thiserror::Error;
+use tracing::debug;
+
+use crate::config::KafkaConfig;
+use crate::notify::{self, Alert, Notifier, NotifyError};
+
+const PRODUCE: i16 = 0;
+const METADATA: i16 = 3;
+/// Versions of the requests, known to brokers since Kafka 1.0.
+const PRODUCE_VERSION: i16 = 3;
+const METADATA_VERSION: i16 = 4;
+
+trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+/// Connections to the brokers by host and port, kept while producing so
+/// that each is opened once.
+type Connections = BTreeMap<(String, u16), Box<dyn Stream>>;
+
+/// A message for the topic of a `KafkaConfig`.
+pub struct Message {
+    /// Messages of one key go to the same partition, keeping their order.
+    pub key: String,
+    pub value: Vec<u8>,
+    /// Unix time in milliseconds.
+    pub timestamp: i64,
+}
+
+/// A broker refused messages for a reason that sending them again would
+/// not change, e.g. a missing permission.
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct Refused(String);
+
+struct Metadata {
+    /// Host and port by node id.
+    brokers: BTreeMap<i32, (String, u16)>,
+    /// The leaders of the partitions of the topic, by partition.
+    leaders: Vec<i32>,
+}
+
+/// Produces the messages to the topic, sending those the brokers did not
+/// take again up to `retries` times.
+pub fn produce(config: &KafkaConfig, messages: &[Message]) -> Result<(), NotifyError> {
+    let mut pending: Vec<&Message> = messages.iter().collect();
+    let mut connections = Connections::new();
+    let mut retries = 0;
+    loop {
+        let failure = match attempt(config, &mut connections, &mut pending) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.is::<Refused>() || retries == config.retries => return Err(e),
+            Err(e) => e,
+        };
+        retries += 1;
+        debug!("Producing {} messages to {} again: {}", pending.len(), config.topic, failure);
+        thread::sleep(Duration::from_millis(250 << retries.min(5)));
+    }
+}
+
+/// Produces the pending messages once, leaving those that failed.
+fn attempt(config: &KafkaConfig, connections: &mut Connections, pending: &mut Vec<&Message>)
+    -> Result<(), NotifyError> {
+    let metadata = fetch_metadata(config, connections)?;
+    // Partitions are chosen among all of them, so that a key keeps its
+    // partition while a leader is away.
+    let mut batches: BTreeMap<i32, BTreeMap<i32, Vec<&Message>>> = BTreeMap::new();
+    let (mut failed, mut failure): (Vec<&Message>, Option<NotifyError>) = (Vec::new(), None);
+    for message in pending.iter() {
+        let partition = crc32c(message.key.as_bytes()) as usize % metadata.leaders.len();
+        match metadata.leaders[partition] {
+            leader if leader < 0 || !metadata.brokers.contains_key(&leader) => {
+                failure = Some(format!("partition {} of {} has no leader", partition, config.topic).into());
+                failed.push(message);
+            },
+            leader => batches.entry(leader).or_default().entry(partition as i32).or_default().push(message),
+        }
+    }
+    for (leader, partitions) in &batches {
+        let (host, port) = &metadata.brokers[leader];
+        match send(config, connections, host, *port, partitions) {
+            Ok(retry) => {
+                for (partition, code) in retry {
+                    failure = Some(broker_error(code, &format!("partition {} of {}", partition, config.topic)));
+                    failed.extend(&partitions[&partition]);
+                }
+            },
+            Err(e) if e.is::<Refused>() => return Err(e),
+            Err(e) => {
+                failure = Some(format!("broker {}:{}: {}", host, port, e).into());
+                failed.extend(partitions.values().flatten());
+            },
+        }
+    }
+    *pending = failed;
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn connect(config: &KafkaConfig, host: &str, port: u16) -> Result<Box<dyn Stream>, NotifyError> {
+    let timeout = Duration::from_secs(config.timeout_seconds);
+    let tcp = notify::connect((host, port), timeout)?;
+    tcp.set_read_timeout(Some(timeout))?;
+    tcp.set_write_timeout(Some(timeout))?;
+    if config.tls {
+        Ok(Box::new(TlsConnector::new()?.connect(host, tcp)?))
+    } else {
+        Ok(Box::new(tcp))
+    }
+}
+
+/// Sends a request to a broker and reads its response, unless none
+/// `answered`. The connection is opened if there is none yet, and closed
+/// when it fails.
+fn call(
+    config: &KafkaConfig,
+    connections: &mut Connections,
+    (host, port): (&str, u16),
+    api: (i16, i16),
+    body: &[u8],
+    answered: bool,
+) -> Result<Vec<u8>, NotifyError> {
+    let key = (host.to_string(), port);
+    let stream = match connections.entry(key.clone()) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => entry.insert(connect(config, host, port)?),
+    };
+    let response = exchange(config, &mut **stream, api, body, answered);
+    if response.is_err() {
+        connections.remove(&key);
+    }
+    response
+}
+
+fn exchange(
+    config: &KafkaConfig,
+    stream: &mut dyn Stream,
+    api: (i16, i16),
+    body: &[u8],
+    answered: bool,
+) -> Result<Vec<u8>, NotifyError> {
+    let correlation = fastrand::i32(0..);
+    let mut request = Vec::new();
+    request.extend(api.0.to_be_bytes());
+    request.extend(api.1.to_be_bytes());
+    request.extend(correlation.to_be_bytes());
+    string(&mut request, config.client_id.as_deref().unwrap_or("log-alarmer"));
+    request.extend(body);
+    let mut frame = (request.len() as i32).to_be_bytes().to_vec();
+    frame.extend(request);
+    stream.write_all(&frame)?;
+    stream.flush()?;
+    if !answered {
+        return Ok(Vec::new());
+    }
+    let mut length = [0; 4];
+    stream.read_exact(&mut length)?;
+    let mut response = vec![0; i32::from_be_bytes(length).max(0) as usize];
+    stream.read_exact(&mut response)?;
+    let mut reader = Reader(&response);
+    if reader.i32()? != correlation {
+        return Err("the broker answered another request".into());
+    }
+    Ok(reader.0.to_vec())
+}
+
+/// Asks the configured brokers in turn for the partitions of the topic
+/// and their leaders.
+fn fetch_metadata(config: &KafkaConfig, connections: &mut Connections) -> Result<Metadata, NotifyError> {
+    let mut failure: NotifyError = "no brokers are configured".into();
+    for address in &config.brokers {
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse()?),
+            None => (address.as_str(), 9092),
+        };
+        match metadata(config, connections, host, port) {
+            Ok(metadata) => return Ok(metadata),
+            Err(e) if e.is::<Refused>() => return Err(e),
+            Err(e) => failure = format!("broker {}: {}", address, e).into(),
+        }
+    }
+    Err(failure)
+}
+
+fn metadata(config: &KafkaConfig, connections: &mut Connections, host: &str, port: u16)
+    -> Result<Metadata, NotifyError> {
+    let mut body = 1i32.to_be_bytes().to_vec();
+    string(&mut body, &config.topic);
+    body.push(1); // allow auto topic creation, if the brokers do
+    let response = call(config, connections, (host, port), (METADATA, METADATA_VERSION), &body, true)?;
+    let mut reader = Reader(&response);
+    reader.i32()?; // throttle time
+    let mut brokers = BTreeMap::new();
+    for _ in 0..reader.i32()? {
+        let node = reader.i32()?;
+        let host = reader.string()?.unwrap_or_default();
+        let port = reader.i32()? as u16;
+        reader.string()?; // rack
+        brokers.insert(node, (host, port));
+    }
+    reader.string()?; // cluster id
+    reader.i32()?; // controller id
+    for _ in 0..reader.i32()? {
+        let code = reader.i16()?;
+        let name = reader.string()?.unwrap_or_default();
+        reader.take(1)?; // internal
+        let mut leaders = BTreeMap::new();
+        for _ in 0..reader.i32()? {
+            reader.i16()?; // error of the partition, e.g. without a leader
+            let partition = reader.i32()?;
+            leaders.insert(partition, reader.i32()?);
+            for _ in 0..2 {
+                // replicas and in-sync replicas
+                let count = reader.i32()?.max(0) as usize;
+                reader.take(count * 4)?;
+            }
+        }
+        if name != config.topic {
+            continue;
+        }
+        if code != 0 {
+            return Err(broker_error(code, &format!("topic {}", name)));
+        }
+        if leaders.is_empty() {
+            return Err(format!("topic {} has no partitions", name).into());
+        }
+        return Ok(Metadata { brokers, leaders: leaders.into_values().collect() });
+    }
+    Err(format!("the broker did not describe topic {}", config.topic).into())
+}
+
+/// Produces batches to the partitions a broker leads, returning those it
+/// answered with an error to try again.
+fn send(
+    config: &KafkaConfig,
+    connections: &mut Connections,
+    host: &str,
+    port: u16,
+    partitions: &BTreeMap<i32, Vec<&Message>>,
+) -> Result<Vec<(i32, i16)>, NotifyError> {
+    let mut body = (-1i16).to_be_bytes().to_vec(); // no transaction
+    body.extend(config.acks.to_be_bytes());
+    body.extend((config.timeout_seconds as i32 * 1000).to_be_bytes());
+    body.extend(1i32.to_be_bytes());
+    string(&mut body, &config.topic);
+    body.extend((partitions.len() as i32).to_be_bytes());
+    for (partition, messages) in partitions {
+        let batch = record_batch(messages);
+        body.extend(partition.to_be_bytes());
+        body.extend((batch.len() as i32).to_be_bytes());
+        body.extend(batch);
+    }
+    // Without acknowledgements the broker does not answer at all.
+    let api = (PRODUCE, PRODUCE_VERSION);
+    let response = call(config, connections, (host, port), api, &body, config.acks != 0)?;
+    let mut retry = Vec::new();
+    if config.acks == 0 {
+        return Ok(retry);
+    }
+    let mut reader = Reader(&response);
+    for _ in 0..reader.i32()? {
+        reader.string()?;
+        for _ in 0..reader.i32()? {
+            let partition = reader.i32()?;
+            let code = reader.i16()?;
+            reader.take(16)?; // base offset and log append time
+            if code == 0 {
+                continue;
+            }
+            let error = broker_error(code, &format!("partition {} of {}", partition, config.topic));
+            if error.is::<Refused>() {
+                return Err(error);
+            }
+            retry.push((partition, code));
+        }
+    }
+    Ok(retry)
+}
+
+/// The messages of a partition as a batch of records, in the format of
+/// Kafka 0.11 and later.
+fn record_batch(messages: &[&Message]) -> Vec<u8> {
+    let first = messages.iter().map(|message| message.timestamp).min().unwrap_or_default();
+    let last = messages.iter().map(|message| message.timestamp).max().unwrap_or_default();
+    let mut records = Vec::new();
+    for (i, message) in messages.iter().enumerate() {
+        let mut record = vec![0]; // attributes
+        varint(&mut record, message.timestamp - first);
+        varint(&mut record, i as i64);
+        varint(&mut record, message.key.len() as i64);
+        record.extend(message.key.as_bytes());
+        varint(&mut record, message.value.len() as i64);
+        record.extend(&message.value);
+        varint(&mut record, 0); // headers
+        varint(&mut records, record.len() as i64);
+        records.extend(record);
+    }
+    // The part the checksum covers.
+    let mut body = 0i16.to_be_bytes().to_vec(); // attributes, i.e. uncompressed
+    body.extend((messages.len() as i32 - 1).to_be_bytes());
+    body.extend(first.to_be_bytes());
+    body.extend(last.to_be_bytes());
+    body.extend((-1i64).to_be_bytes()); // no producer id,
+    body.extend((-1i16).to_be_bytes()); // epoch
+    body.extend((-1i32).to_be_bytes()); // or sequence
+    body.extend((messages.len() as i32).to_be_bytes());
+    body.extend(records);
+    let mut batch = 0i64.to_be_bytes().to_vec(); // base offset
+    batch.extend((body.len() as i32 + 9).to_be_bytes());
+    batch.extend((-1i32).to_be_bytes()); // partition leader epoch
+    batch.push(2); // magic
+    batch.extend(crc32c(&body).to_be_bytes());
+    batch.extend(body);
+    batch
+}
+
+/// An error code of a broker, as `Refused` unless it may pass.
+fn broker_error(code: i16, subject: &str) -> NotifyError {
+    let (name, retriable) = match code {
+        3 => ("unknown topic or partition", true),
+        5 => ("leader not available", true),
+        6 => ("not the leader", true),
+        7 => ("request timed out", true),
+        10 => ("message too large", false),
+        19 | 20 => ("not enough replicas", true),
+        29 => ("not authorized for the topic", false),
+        56 => ("storage error", true),
+        87 => ("record rejected", false),
+        _ => ("error", false),
+    };
+    let message = format!("{}: {} (code {})", subject, name, code);
+    if retriable {
+        message.into()
+    } else {
+        Box::new(Refused(message))
+    }
+}
+
+/// A string as Kafka encodes it, after its length.
+fn string(bytes: &mut Vec<u8>, text: &str) {
+    bytes.extend((text.len() as i16).to_be_bytes());
+    bytes.extend(text.as_bytes());
+}
+
+/// A zigzag varint, with 7 bits per byte and the highest bit telling
+/// whether another byte follows.
+fn varint(bytes: &mut Vec<u8>, value: i64) {
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn crc32c(bytes: &[u8]) -> u32 {
+    static TABLE: OnceLock<[u32; 256]> = OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        let mut table = [0; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut crc = i as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 { crc >> 1 ^ 0x82f6_3b78 } else { crc >> 1 };
+            }
+            *entry = crc;
+        }
+        table
+    });
+    !bytes.iter().fold(!0, |crc, byte| table[((crc ^ *byte as u32) & 0xff) as usize] ^ crc >> 8)
+}
+
+/// Reads a response from its start.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], NotifyError> {
+        if count > self.0.len() {
+            return Err("the broker sent a truncated response".into());
+        }
+        let (taken, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn i16(&mut self) -> Result<i16, NotifyError> {
+        Ok(i16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, NotifyError> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn string(&mut self) -> Result<Option<String>, NotifyError> {
+        match self.i16()? {
+            length if length < 0 => Ok(None),
+            length => Ok(Some(String::from_utf8_lossy(self.take(length as usize)?).into_owned())),
+        }
+    }
+}
+
+/// Produces alerts as JSON to a Kafka topic, keyed by their log.
+pub struct KafkaNotifier {
+    config: KafkaConfig,
+}
+
+impl KafkaNotifier {
+    pub fn new(config: KafkaConfig) -> KafkaNotifier {
+        KafkaNotifier { config }
+    }
+
+    fn publish(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let message = Message {
+            key: alert.log_id.clone(),
+            value: serde_json::to_vec(&Value::Object(alert.variables()))?,
+            timestamp: alert.timestamp.timestamp_millis(),
+        };
+        produce(&self.config, &[message])
+    }
+}
+
+impl Notifier for KafkaNotifier {
+    fn name(&self) -> &str {
+        "kafka"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.publish(alert)
+    }
+
+    /// Produces the alert again, with `status` set to `resolved`.
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.publish(alert)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes a zigzag varint, returning it and the bytes it took.
+    fn decode(bytes: &[u8]) -> (i64, usize) {
+        let mut value = 0u64;
+        for (i, byte) in bytes.iter().enumerate() {
+            value |= ((byte & 0x7f) as u64) << (7 * i);
+            if byte & 0x80 == 0 {
+                return ((value >> 1) as i64 ^ -((value & 1) as i64), i + 1);
+            }
+        }
+        panic!("unterminated varint {:?}", bytes);
+    }
+
+    fn encode(value: i64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        varint(&mut bytes, value);
+        bytes
+    }
+
+    #[test]
+    fn crc32c_matches_rfc_3720() {
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+        assert_eq!(crc32c(&[0; 32]), 0x8a91_36aa);
+        assert_eq!(crc32c(&[0xff; 32]), 0x62a8_ab43);
+    }
+
+    #[test]
+    fn varint_zigzags() {
+        assert_eq!(encode(0), [0x00]);
+        assert_eq!(encode(-1), [0x01]);
+        assert_eq!(encode(1), [0x02]);
+        assert_eq!(encode(-64), [0x7f]);
+        assert_eq!(encode(64), [0x80, 0x01]);
+        assert_eq!(encode(300), [0xd8, 0x04]);
+    }
+
+    #[test]
+    fn varint_round_trips() {
+        for value in [0, 1, -1, 63, -64, 64, 8191, -8192, i32::MAX as i64, i64::MIN, i64::MAX] {
+            let bytes = encode(value);
+            assert_eq!(decode(&bytes), (value, bytes.len()), "{}", value);
+        }
+    }
+
+    #[test]
+    fn record_batch_encodes_one_message() {
+        let message = Message { key: "a".to_string(), value: b"b".to_vec(), timestamp: 1000 };
+        let mut expected = vec![0; 8]; // base offset
+        expected.extend([0, 0, 0, 58]); // length
+        expected.extend([0xff; 4]); // partition leader epoch
+        expected.push(2); // magic
+        expected.extend([0x49, 0x06, 0xb1, 0xb3]); // crc
+        expected.extend([0, 0]); // attributes
+        expected.extend([0; 4]); // last offset delta
+        expected.extend([0, 0, 0, 0, 0, 0, 0x03, 0xe8]); // first timestamp
+        expected.extend([0, 0, 0, 0, 0, 0, 0x03, 0xe8]); // max timestamp
+        expected.extend([0xff; 14]); // producer id, epoch and sequence
+        expected.extend([0, 0, 0, 1]); // records
+        expected.extend([0x10, 0, 0, 0, 0x02, b'a', 0x02, b'b', 0]);
+        assert_eq!(record_batch(&[&message]), expected);
+    }
+
+    #[test]
+    fn record_batch_counts_offsets_and_timestamps_from_the_first() {
+        let first = Message { key: "k".to_string(), value: Vec::new(), timestamp: 2000 };
+        let second = Message { key: "k".to_string(), value: Vec::new(), timestamp: 2003 };
+        let batch = record_batch(&[&first, &second]);
+        assert_eq!(batch[23..27], [0, 0, 0, 1]); // last offset delta
+        assert_eq!(i64::from_be_bytes(batch[27..35].try_into().unwrap()), 2000);
+        assert_eq!(i64::from_be_bytes(batch[35..43].try_into().unwrap()), 2003);
+        assert_eq!(crc32c(&batch[21..]).to_be_bytes(), batch[17..21]);
+        // The second record: length, attributes, timestamp delta 3 and
+        // offset delta 1.
+        assert_eq!(batch[69..73], [0x0e, 0, 0x06, 0x02]);
+    }
+}
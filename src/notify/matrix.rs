@@ -0,0 +1,118 @@
+use std::sync::Mutex;
+
+use handlebars::html_escape;
+use serde_json::{json, Value};
+use url::form_urlencoded::byte_serialize;
+
+use crate::config::{MatrixConfig, Severity};
+use crate::notify::{Alert, Notifier, NotifyError};
+
+/// Posts alerts as formatted messages to a Matrix room, e.g. of Element.
+pub struct MatrixNotifier {
+    config: MatrixConfig,
+    /// The id of the room, once looked up for an alias.
+    room: Mutex<Option<String>>,
+}
+
+impl MatrixNotifier {
+    pub fn new(config: MatrixConfig) -> MatrixNotifier {
+        MatrixNotifier { config, room: Mutex::new(None) }
+    }
+
+    fn api(&self, path: &str) -> String {
+        format!("{}/_matrix/client/v3/{}", self.config.homeserver.trim_end_matches('/'), path)
+    }
+
+    fn room_id(&self) -> Result<String, NotifyError> {
+        if !self.config.room_id.starts_with('#') {
+            return Ok(self.config.room_id.clone());
+        }
+        let mut room = self.room.lock().unwrap();
+        if let Some(id) = room.as_ref() {
+            return Ok(id.clone());
+        }
+        let url = self.api(&format!("directory/room/{}", encode(&self.config.room_id)));
+        let response: Value = ureq::get(&url)
+            .set("Authorization", &format!("Bearer {}", self.config.access_token))
+            .call()?
+            .into_json()?;
+        let id = match response["room_id"].as_str() {
+            Some(id) => id.to_string(),
+            None => return Err(format!("the homeserver knows no room {}", self.config.room_id).into()),
+        };
+        *room = Some(id.clone());
+        Ok(id)
+    }
+
+    fn send(&self, body: String, html: String) -> Result<(), NotifyError> {
+        // Keeps the homeserver from posting a message twice when retried.
+        let txn = format!("{}-{:08x}", chrono::Utc::now().timestamp_millis(), fastrand::u32(..));
+        let path = format!("rooms/{}/send/m.room.message/{}", encode(&self.room_id()?), txn);
+        ureq::put(&self.api(&path))
+            .set("Authorization", &format!("Bearer {}", self.config.access_token))
+            .send_json(json!({
+                "msgtype": if self.config.notice { "m.notice" } else { "m.text" },
+                "body": body,
+                "format": "org.matrix.custom.html",
+                "formatted_body": html,
+            }))?;
+        Ok(())
+    }
+}
+
+fn encode(segment: &str) -> String {
+    byte_serialize(segment.as_bytes()).collect()
+}
+
+impl Notifier for MatrixNotifier {
+    fn name(&self) -> &str {
+        "matrix"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let color = match alert.severity {
+            Severity::Info => "#2f81f7",
+            Severity::Warning => "#d29922",
+            Severity::Critical => "#f85149",
+        };
+        let at = alert.timestamp.format("%Y-%m-%d %H:%M:%S %z");
+        let mut body = format!("{}\n{} ({}) at {}", alert.summary(), alert.rule, alert.severity.as_str(), at);
+        let mut html = format!(
+            "<strong><font data-mx-color=\"{}\">{}</font></strong><br>{} ({}) at {}",
+            color,
+            html_escape(&alert.summary()),
+            html_escape(&alert.rule),
+            alert.severity.as_str(),
+            at,
+        );
+        if alert.suppressed > 0 {
+            let more = format!(", {} more during cooldown", alert.suppressed);
+            body.push_str(&more);
+            html.push_str(&more);
+        }
+        body.push_str(&format!("\nHost: {}", alert.origin()));
+        html.push_str(&format!("<br>Host: {}", html_escape(&alert.origin())));
+        if let Some(system) = &alert.system {
+            body.push_str(&format!("\nSystem: {}", system));
+            html.push_str(&format!("<br>System: {}", html_escape(&system.to_string())));
+        }
+        if !alert.lines.is_empty() {
+            body.push_str(&format!("\n\n{}", alert.lines.join("\n")));
+            html.push_str(&format!("<pre><code>{}</code></pre>", html_escape(&alert.lines.join("\n"))));
+        }
+        self.send(body, html)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let at = alert.timestamp.format("%Y-%m-%d %H:%M:%S %z");
+        let body = format!("{}\n{} at {}\nHost: {}", alert.summary(), alert.rule, at, alert.origin());
+        let html = format!(
+            "<strong><font data-mx-color=\"#3fb950\">{}</font></strong><br>{} at {}<br>Host: {}",
+            html_escape(&alert.summary()),
+            html_escape(&alert.rule),
+            at,
+            html_escape(&alert.origin()),
+        );
+        self.send(body, html)
+    }
+}
@@ -0,0 +1,470 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use base64::Engine;
+use chrono::{DateTime, Local};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::Sha256;
+use sysinfo::System;
+use tracing::{info, info_span, warn};
+
+use crate::config::{Config, NotifierConfig, Severity};
+use crate::metrics;
+use crate::system::Snapshot;
+
+pub use alertmanager::AlertmanagerNotifier;
+pub use desktop::DesktopNotifier;
+pub use digest::DigestEntry;
+pub use dingtalk::DingTalkNotifier;
+pub use discord::DiscordNotifier;
+pub use email::EmailNotifier;
+pub use exec::ExecNotifier;
+pub use feishu::FeishuNotifier;
+pub use gelf::GelfNotifier;
+pub use gotify::GotifyNotifier;
+pub use grafana::GrafanaNotifier;
+pub use kafka::KafkaNotifier;
+pub use matrix::MatrixNotifier;
+pub use mqtt::MqttNotifier;
+pub use ntfy::NtfyNotifier;
+pub use opsgenie::OpsgenieNotifier;
+pub use pagerduty::PagerDutyNotifier;
+pub use pushover::PushoverNotifier;
+pub use sentry::SentryNotifier;
+pub use slack::SlackNotifier;
+pub use snmp::SnmpNotifier;
+pub use sns::SnsNotifier;
+pub use teams::TeamsNotifier;
+pub use telegram::TelegramNotifier;
+pub use twilio::TwilioNotifier;
+pub use webhook::WebhookNotifier;
+pub use wecom::WeComNotifier;
+pub use zabbix::ZabbixNotifier;
+
+mod alertmanager;
+mod aws;
+mod desktop;
+pub(crate) mod digest;
+mod dingtalk;
+mod discord;
+mod email;
+mod exec;
+mod feishu;
+pub(crate) mod gelf;
+mod gotify;
+mod grafana;
+pub(crate) mod kafka;
+mod matrix;
+mod mqtt;
+mod ntfy;
+mod oauth2;
+mod opsgenie;
+mod pagerduty;
+mod pushover;
+mod sentry;
+mod slack;
+pub(crate) mod snmp;
+mod sns;
+mod teams;
+mod telegram;
+mod twilio;
+mod webhook;
+mod wecom;
+mod zabbix;
+
+pub type NotifyError = Box<dyn Error + Send + Sync>;
+
+/// An alarm raised for a watched log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub log_id: String,
+    /// The condition that raised the alert.
+    pub rule: String,
+    #[serde(default)]
+    pub severity: Severity,
+    pub count: usize,
+    /// Milliseconds `count` was reached in, 0 when unbounded.
+    #[serde(default)]
+    pub window: i64,
+    /// Matches seen during the cooldown after the previous alert.
+    pub suppressed: usize,
+    /// Identifies the error of alerts on rules with deduplication.
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+    /// Matches of alerts about the same error that were held back, for an
+    /// alert reporting them.
+    #[serde(default)]
+    pub repeats: usize,
+    /// Set on alerts raised because nothing matched for this many
+    /// milliseconds.
+    #[serde(default)]
+    pub absent_for: Option<i64>,
+    pub timestamp: DateTime<Local>,
+    /// The most recent matching lines, oldest first.
+    pub lines: Vec<String>,
+    /// The fields of the last matching line, if it was parsed as JSON.
+    #[serde(default)]
+    pub fields: Map<String, Value>,
+    /// Values the rule took from the last matching line, by name.
+    #[serde(default)]
+    pub extracted: BTreeMap<String, String>,
+    /// The labels configured for the alarmer, such as its environment.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// The state of the machine when the alert was raised, if the log
+    /// asks for it.
+    #[serde(default)]
+    pub system: Option<Snapshot>,
+    /// The alerts summed up by a digest, one entry per rule and log.
+    #[serde(default)]
+    pub digest: Vec<DigestEntry>,
+    /// Set on alerts telling that an alarm cleared.
+    #[serde(default)]
+    pub resolved: bool,
+    /// Number of the reminder, for reminders of alarms that did not resolve.
+    #[serde(default)]
+    pub escalation: u32,
+    /// When the alarm a reminder is of fired.
+    #[serde(default)]
+    pub since: Option<DateTime<Local>>,
+}
+
+impl Alert {
+    /// A sample alert used to check that channels are set up correctly.
+    pub fn test() -> Alert {
+        Alert {
+            log_id: "test".to_string(),
+            rule: "test-notify".to_string(),
+            severity: Severity::default(),
+            count: 1,
+            window: 0,
+            suppressed: 0,
+            fingerprint: None,
+            repeats: 0,
+            absent_for: None,
+            timestamp: Local::now(),
+            lines: vec!["This is a test alert sent by log-alarmer.".to_string()],
+            fields: Map::new(),
+            extracted: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            system: None,
+            digest: Vec::new(),
+            resolved: false,
+            escalation: 0,
+            since: None,
+        }
+    }
+
+    /// One line describing the alert, e.g. `20 errors on node1`.
+    pub fn summary(&self) -> String {
+        if self.resolved {
+            return format!("Resolved: {} on {}", self.rule, self.log_id);
+        }
+        if self.escalation > 0 {
+            return format!("Still unresolved ({}): {} on {}", self.escalation, self.rule, self.log_id);
+        }
+        match self.absent_for {
+            Some(millis) => format!("No {} on {} for {} s", self.rule, self.log_id, millis / 1000),
+            None if !self.digest.is_empty() => format!("Digest of {} errors on {}", self.count, self.log_id),
+            None if self.repeats > 0 => format!("Error on {} seen {} more times", self.log_id, self.repeats),
+            None => format!("{} errors on {}", self.count, self.log_id),
+        }
+    }
+
+    /// Where the alert comes from, e.g. `web-1 (10.0.0.5), environment=prod`.
+    pub fn origin(&self) -> String {
+        let mut origin = hostname();
+        if let Some(ip) = primary_ip() {
+            origin.push_str(&format!(" ({})", ip));
+        }
+        for (name, value) in &self.labels {
+            origin.push_str(&format!(", {}={}", name, value));
+        }
+        origin
+    }
+
+    /// The alert fields by name, as used by templates and JSON payloads.
+    pub fn variables(&self) -> Map<String, Value> {
+        let mut vars = Map::new();
+        vars.insert("log_id".to_string(), json!(self.log_id));
+        vars.insert("rule".to_string(), json!(self.rule));
+        vars.insert("severity".to_string(), json!(self.severity.as_str()));
+        vars.insert("status".to_string(), json!(if self.resolved { "resolved" } else { "firing" }));
+        vars.insert("hostname".to_string(), json!(hostname()));
+        vars.insert("ip".to_string(), json!(primary_ip()));
+        vars.insert("labels".to_string(), json!(self.labels));
+        vars.insert("system".to_string(), json!(self.system.as_ref().map(Snapshot::to_string)));
+        vars.insert("count".to_string(), json!(self.count));
+        vars.insert("window".to_string(), json!(self.window));
+        vars.insert("suppressed".to_string(), json!(self.suppressed));
+        vars.insert("fingerprint".to_string(), json!(self.fingerprint));
+        vars.insert("repeats".to_string(), json!(self.repeats));
+        vars.insert("escalation".to_string(), json!(self.escalation));
+        vars.insert("since".to_string(), json!(self.since.map(|since| since.to_rfc3339())));
+        vars.insert("absent_for".to_string(), json!(self.absent_for));
+        vars.insert("timestamp".to_string(), json!(self.timestamp.to_rfc3339()));
+        vars.insert("lines".to_string(), json!(self.lines));
+        vars.insert("fields".to_string(), Value::Object(self.fields.clone()));
+        vars.insert("extracted".to_string(), json!(self.extracted));
+        vars.insert("digest".to_string(), json!(self.digest));
+        vars
+    }
+}
+
+/// Name of the machine the alarmer runs on, looked up once.
+pub fn hostname() -> String {
+    static HOSTNAME: OnceLock<String> = OnceLock::new();
+    HOSTNAME.get_or_init(|| {
+        std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .map(|name| name.trim().to_string())
+            .ok()
+            .filter(|name| !name.is_empty())
+            // gethostname, e.g. on macOS and the BSDs.
+            .or_else(System::host_name)
+            .or_else(|| std::env::var("COMPUTERNAME").ok())
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .unwrap_or_else(|| "localhost".to_string())
+    }).clone()
+}
+
+/// The address of the machine on the network, the one its default route
+/// leaves from, looked up once.
+pub fn primary_ip() -> Option<String> {
+    static PRIMARY_IP: OnceLock<Option<String>> = OnceLock::new();
+    PRIMARY_IP.get_or_init(|| {
+        // Connecting a UDP socket only picks the route, nothing is sent.
+        let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+        socket.connect("192.0.2.1:9").ok()?;
+        Some(socket.local_addr().ok()?.ip()).filter(|ip| !ip.is_unspecified()).map(|ip| ip.to_string())
+    }).clone()
+}
+
+/// Connects to the first address of `address` that answers within
+/// `timeout`.
+pub(crate) fn connect(address: impl ToSocketAddrs, timeout: Duration) -> io::Result<TcpStream> {
+    let mut failure = io::Error::new(io::ErrorKind::NotFound, "the address resolves to nothing");
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => failure = e,
+        }
+    }
+    Err(failure)
+}
+
+/// The base64 HMAC-SHA256 of `message` with `key`, as chat robots expect
+/// signed requests.
+pub(crate) fn sign(key: &[u8], message: &[u8]) -> Result<String, NotifyError> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)?;
+    mac.update(message);
+    Ok(base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes()))
+}
+
+/// Fails with the message of chat robots that answer errors successfully,
+/// with a nonzero `errcode` or `code` in the body.
+pub(crate) fn robot_result(response: ureq::Response) -> Result<(), NotifyError> {
+    let body: Value = response.into_json()?;
+    let code = body.get("errcode").or_else(|| body.get("code")).and_then(Value::as_i64).unwrap_or(0);
+    if code == 0 {
+        return Ok(());
+    }
+    let message = body.get("errmsg").or_else(|| body.get("msg")).and_then(Value::as_str).unwrap_or("unknown error");
+    Err(format!("error {}: {}", code, message).into())
+}
+
+/// A channel alerts can be delivered through.
+pub trait Notifier: Send + Sync {
+    /// Short name of the channel, used in logs.
+    fn name(&self) -> &str;
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError>;
+
+    /// Called when a previously notified alert has cleared. Channels
+    /// without a notion of resolution ignore it.
+    fn resolve(&self, _alert: &Alert) -> Result<(), NotifyError> {
+        Ok(())
+    }
+}
+
+/// Hands alerts to a closure registered by an embedding program.
+pub(crate) struct Callback<F> {
+    callback: F,
+}
+
+impl<F> Callback<F> {
+    pub fn new(callback: F) -> Callback<F> {
+        Callback { callback }
+    }
+}
+
+impl<F: Fn(&Alert) + Send + Sync> Notifier for Callback<F> {
+    fn name(&self) -> &str {
+        "callback"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        (self.callback)(alert);
+        Ok(())
+    }
+}
+
+/// Prints alerts instead of sending them through the wrapped channel.
+pub(crate) struct DryRun {
+    name: String,
+}
+
+impl DryRun {
+    pub fn new(inner: Arc<dyn Notifier>) -> DryRun {
+        DryRun { name: format!("{} (dry-run)", inner.name()) }
+    }
+
+    /// Prints the alerts of a configuration without channels.
+    pub fn unrouted() -> DryRun {
+        DryRun { name: "dry-run".to_string() }
+    }
+}
+
+impl Notifier for DryRun {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        println!("[{}] {} matches of {} on {} at {}",
+            self.name, alert.count, alert.rule, alert.log_id, alert.timestamp);
+        for line in &alert.lines {
+            println!("[{}]   {}", self.name, line);
+        }
+        Ok(())
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        println!("[{}] resolved {} on {}", self.name, alert.rule, alert.log_id);
+        Ok(())
+    }
+}
+
+/// A configured notifier under its own name.
+struct Named {
+    name: String,
+    inner: Arc<dyn Notifier>,
+}
+
+impl Notifier for Named {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.inner.notify(alert)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.inner.resolve(alert)
+    }
+}
+
+/// Creates every notifier configured in `config`.
+pub fn build_notifiers(config: &Config) -> Result<Vec<Arc<dyn Notifier>>, NotifyError> {
+    let mut notifiers: Vec<Arc<dyn Notifier>> = Vec::new();
+    if let Some(email) = &config.email {
+        notifiers.push(Arc::new(EmailNotifier::new(email.clone())?));
+    }
+    for entry in &config.notifiers {
+        let notifier: Arc<dyn Notifier> = match &entry.notifier {
+            NotifierConfig::Slack(slack) => Arc::new(SlackNotifier::new(slack.clone())),
+            NotifierConfig::Telegram(telegram) => Arc::new(TelegramNotifier::new(telegram.clone())),
+            NotifierConfig::Webhook(webhook) => Arc::new(WebhookNotifier::new(webhook.clone())),
+            NotifierConfig::PagerDuty(pagerduty) => Arc::new(PagerDutyNotifier::new(pagerduty.clone())),
+            NotifierConfig::Discord(discord) => Arc::new(DiscordNotifier::new(discord.clone())),
+            NotifierConfig::Teams(teams) => Arc::new(TeamsNotifier::new(teams.clone())),
+            NotifierConfig::DingTalk(dingtalk) => Arc::new(DingTalkNotifier::new(dingtalk.clone())),
+            NotifierConfig::WeCom(wecom) => Arc::new(WeComNotifier::new(wecom.clone())),
+            NotifierConfig::Feishu(feishu) => Arc::new(FeishuNotifier::new(feishu.clone())),
+            NotifierConfig::Twilio(twilio) => Arc::new(TwilioNotifier::new(twilio.clone())),
+            NotifierConfig::Ntfy(ntfy) => Arc::new(NtfyNotifier::new(ntfy.clone())),
+            NotifierConfig::Gotify(gotify) => Arc::new(GotifyNotifier::new(gotify.clone())),
+            NotifierConfig::Pushover(pushover) => Arc::new(PushoverNotifier::new(pushover.clone())),
+            NotifierConfig::Mqtt(mqtt) => Arc::new(MqttNotifier::new(mqtt.clone())),
+            NotifierConfig::Exec(exec) => Arc::new(ExecNotifier::new(exec.clone())),
+            NotifierConfig::Desktop(desktop) => Arc::new(DesktopNotifier::new(desktop.clone())),
+            NotifierConfig::Sns(sns) => Arc::new(SnsNotifier::new(sns.clone())),
+            NotifierConfig::Matrix(matrix) => Arc::new(MatrixNotifier::new(matrix.clone())),
+            NotifierConfig::Alertmanager(alertmanager) => {
+                Arc::new(AlertmanagerNotifier::new(alertmanager.clone()))
+            },
+            NotifierConfig::Sentry(sentry) => Arc::new(SentryNotifier::new(sentry.clone())),
+            NotifierConfig::Kafka(kafka) => Arc::new(KafkaNotifier::new(kafka.clone())),
+            NotifierConfig::Gelf(gelf) => Arc::new(GelfNotifier::new(gelf.clone())),
+            NotifierConfig::Zabbix(zabbix) => Arc::new(ZabbixNotifier::new(zabbix.clone())),
+            NotifierConfig::Snmp(snmp) => {
+                Arc::new(SnmpNotifier::new(snmp.clone(), config.state_file.as_deref().map(Path::new)))
+            },
+            NotifierConfig::Grafana(grafana) => Arc::new(GrafanaNotifier::new(grafana.clone())),
+            NotifierConfig::Opsgenie(opsgenie) => Arc::new(OpsgenieNotifier::new(opsgenie.clone())),
+        };
+        match &entry.name {
+            Some(name) => notifiers.push(Arc::new(Named { name: name.clone(), inner: notifier })),
+            None => notifiers.push(notifier),
+        }
+    }
+    Ok(notifiers)
+}
+
+/// Outcome of sending an alert through one channel.
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub channel: String,
+    pub error: Option<String>,
+}
+
+/// Sends `alert` through every notifier, reporting the outcome of each.
+pub fn dispatch(notifiers: &[Arc<dyn Notifier>], alert: &Alert) -> Vec<Delivery> {
+    let mut deliveries = Vec::new();
+    for notifier in notifiers {
+        let _span = info_span!("notify", channel = notifier.name(), log = %alert.log_id, rule = %alert.rule).entered();
+        let error = match notifier.notify(alert) {
+            Ok(()) => {
+                info!("Alert for {} sent via {}.", alert.log_id, notifier.name());
+                metrics::alert_sent(notifier.name());
+                None
+            },
+            Err(e) => {
+                warn!("Alert for {} failed to send via {}: {}", alert.log_id, notifier.name(), e);
+                metrics::notify_failed(notifier.name());
+                Some(e.to_string())
+            },
+        };
+        deliveries.push(Delivery { channel: notifier.name().to_string(), error });
+    }
+    deliveries
+}
+
+/// Tells every notifier that `alert` has cleared, reporting the outcome of
+/// each.
+pub fn resolve(notifiers: &[Arc<dyn Notifier>], alert: &Alert) -> Vec<Delivery> {
+    let mut deliveries = Vec::new();
+    for notifier in notifiers {
+        let _span = info_span!("resolve", channel = notifier.name(), log = %alert.log_id, rule = %alert.rule).entered();
+        let error = match notifier.resolve(alert) {
+            Ok(()) => {
+                info!("Resolve for {} sent via {}.", alert.log_id, notifier.name());
+                None
+            },
+            Err(e) => {
+                metrics::notify_failed(notifier.name());
+                warn!("Resolve for {} failed to send via {}: {}", alert.log_id, notifier.name(), e);
+                Some(e.to_string())
+            },
+        };
+        deliveries.push(Delivery { channel: notifier.name().to_string(), error });
+    }
+    deliveries
+}
@@ -0,0 +1,176 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use native_tls::TlsConnector;
+use serde_json::Value;
+
+use crate::config::MqttConfig;
+use crate::notify::webhook::render;
+use crate::notify::{hostname, Alert, Notifier, NotifyError};
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const PUBREC: u8 = 0x50;
+const PUBREL: u8 = 0x62;
+const PUBCOMP: u8 = 0x70;
+const DISCONNECT: u8 = 0xe0;
+
+/// Identifies the one message published per connection.
+const PACKET_ID: u16 = 1;
+
+trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+/// Publishes alerts as JSON to a topic of an MQTT broker, speaking MQTT
+/// 3.1.1 over a connection opened for each alert.
+pub struct MqttNotifier {
+    config: MqttConfig,
+    /// Held while publishing with a configured client id, as the broker
+    /// drops a connection when another one takes its id.
+    publishing: Mutex<()>,
+}
+
+impl MqttNotifier {
+    pub fn new(config: MqttConfig) -> MqttNotifier {
+        MqttNotifier { config, publishing: Mutex::new(()) }
+    }
+
+    fn connect(&self) -> Result<Box<dyn Stream>, NotifyError> {
+        let timeout = Some(Duration::from_secs(self.config.timeout_seconds));
+        let port = self.config.port.unwrap_or(if self.config.tls { 8883 } else { 1883 });
+        let tcp = TcpStream::connect((self.config.host.as_str(), port))?;
+        tcp.set_read_timeout(timeout)?;
+        tcp.set_write_timeout(timeout)?;
+        let mut stream: Box<dyn Stream> = if self.config.tls {
+            Box::new(TlsConnector::new()?.connect(&self.config.host, tcp)?)
+        } else {
+            Box::new(tcp)
+        };
+        // Connections of several alerts at once need ids of their own.
+        let client_id = self.config.client_id.clone()
+            .unwrap_or_else(|| format!("log-alarmer-{}-{:04x}", hostname(), fastrand::u16(..)));
+        let mut flags = 0x02; // clean session
+        let mut payload = string(&client_id);
+        if let Some(username) = &self.config.username {
+            flags |= 0x80;
+            payload.extend(string(username));
+            if let Some(password) = &self.config.password {
+                flags |= 0x40;
+                payload.extend(string(password));
+            }
+        }
+        let mut body = string("MQTT");
+        body.extend([4, flags, 0, 60]); // protocol level 3.1.1, keep alive of 60 s
+        body.extend(payload);
+        write_packet(&mut stream, CONNECT, &body)?;
+        let (kind, ack) = read_packet(&mut stream)?;
+        if kind != CONNACK || ack.len() < 2 {
+            return Err("the broker did not acknowledge the connection".into());
+        }
+        match ack[1] {
+            0 => Ok(stream),
+            4 => Err("the broker rejected the username or password".into()),
+            5 => Err("not authorized by the broker".into()),
+            code => Err(format!("the broker refused the connection with code {}", code).into()),
+        }
+    }
+
+    fn publish(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let vars = alert.variables();
+        let topic = match render(&Value::String(self.config.topic.clone()), &vars) {
+            Value::String(topic) => topic,
+            topic => topic.to_string(),
+        };
+        let _publishing = self.config.client_id.as_ref().map(|_| self.publishing.lock().unwrap());
+        let mut stream = self.connect()?;
+        let qos = self.config.qos;
+        let mut body = string(&topic);
+        if qos > 0 {
+            body.extend(PACKET_ID.to_be_bytes());
+        }
+        body.extend(serde_json::to_vec(&Value::Object(vars))?);
+        write_packet(&mut stream, PUBLISH | qos << 1 | self.config.retain as u8, &body)?;
+        match qos {
+            1 => expect(&mut stream, PUBACK)?,
+            2 => {
+                expect(&mut stream, PUBREC)?;
+                write_packet(&mut stream, PUBREL, &PACKET_ID.to_be_bytes())?;
+                expect(&mut stream, PUBCOMP)?;
+            },
+            _ => (),
+        }
+        write_packet(&mut stream, DISCONNECT, &[])?;
+        Ok(())
+    }
+}
+
+/// A string as MQTT encodes it, after its length.
+fn string(text: &str) -> Vec<u8> {
+    let mut bytes = (text.len() as u16).to_be_bytes().to_vec();
+    bytes.extend(text.as_bytes());
+    bytes
+}
+
+fn write_packet(stream: &mut dyn Stream, kind: u8, body: &[u8]) -> io::Result<()> {
+    let mut packet = vec![kind];
+    // The remaining length takes 7 bits per byte, the highest bit telling
+    // whether another byte follows.
+    let mut length = body.len();
+    loop {
+        let byte = (length % 128) as u8;
+        length /= 128;
+        packet.push(if length > 0 { byte | 0x80 } else { byte });
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend(body);
+    stream.write_all(&packet)?;
+    stream.flush()
+}
+
+fn read_packet(stream: &mut dyn Stream) -> io::Result<(u8, Vec<u8>)> {
+    let mut byte = [0];
+    stream.read_exact(&mut byte)?;
+    let kind = byte[0] & 0xf0;
+    let mut length = 0;
+    for shift in (0..28).step_by(7) {
+        stream.read_exact(&mut byte)?;
+        length |= ((byte[0] & 0x7f) as usize) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body)?;
+    Ok((kind, body))
+}
+
+/// Waits for the acknowledgement `kind` of the published message.
+fn expect(stream: &mut dyn Stream, kind: u8) -> Result<(), NotifyError> {
+    let (received, _) = read_packet(stream)?;
+    if received != kind & 0xf0 {
+        return Err(format!("unexpected packet {:#x} from the broker", received).into());
+    }
+    Ok(())
+}
+
+impl Notifier for MqttNotifier {
+    fn name(&self) -> &str {
+        "mqtt"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.publish(alert)
+    }
+
+    /// Publishes the alert again, with `status` set to `resolved`.
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.publish(alert)
+    }
+}
@@ -0,0 +1,75 @@
+use base64::Engine;
+
+use crate::config::{NtfyConfig, Severity};
+use crate::notify::{Alert, Notifier, NotifyError};
+
+/// Publishes alerts to an ntfy topic, as push notifications for its
+/// subscribers.
+pub struct NtfyNotifier {
+    config: NtfyConfig,
+}
+
+impl NtfyNotifier {
+    pub fn new(config: NtfyConfig) -> NtfyNotifier {
+        NtfyNotifier { config }
+    }
+
+    fn publish(&self, title: String, message: String, priority: u8, tag: &str) -> Result<(), NotifyError> {
+        let mut request = ureq::post(&self.config.url)
+            .set("Title", &header_value(&title))
+            .set("Priority", &priority.to_string())
+            .set("Tags", tag);
+        if let Some(token) = &self.config.token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        } else if let Some(username) = &self.config.username {
+            let credentials = format!("{}:{}", username, self.config.password.as_deref().unwrap_or_default());
+            let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+            request = request.set("Authorization", &format!("Basic {}", encoded));
+        }
+        request.send_string(&message)?;
+        Ok(())
+    }
+}
+
+/// Headers can only hold ASCII, so other text is RFC 2047 encoded, which
+/// ntfy decodes.
+fn header_value(text: &str) -> String {
+    if text.is_ascii() {
+        return text.to_string();
+    }
+    format!("=?UTF-8?B?{}?=", base64::engine::general_purpose::STANDARD.encode(text))
+}
+
+impl Notifier for NtfyNotifier {
+    fn name(&self) -> &str {
+        "ntfy"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        // ntfy's priorities run from 1 (min) to 5 (max), 3 being the default.
+        let priority = self.config.priority.unwrap_or(match alert.severity {
+            Severity::Info => 2,
+            Severity::Warning => 3,
+            Severity::Critical => 5,
+        });
+        let mut message = format!("{} at {}", alert.rule, alert.timestamp.format("%Y-%m-%d %H:%M:%S %z"));
+        if alert.suppressed > 0 {
+            message.push_str(&format!(" ({} more during cooldown)", alert.suppressed));
+        }
+        message.push_str(&format!("\nHost: {}", alert.origin()));
+        if let Some(system) = &alert.system {
+            message.push_str(&format!("\nSystem: {}", system));
+        }
+        for line in &alert.lines {
+            message.push('\n');
+            message.push_str(line);
+        }
+        self.publish(alert.summary(), message, priority, "rotating_light")
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let at = alert.timestamp.format("%Y-%m-%d %H:%M:%S %z");
+        let message = format!("{} at {}\nHost: {}", alert.rule, at, alert.origin());
+        self.publish(alert.summary(), message, 2, "white_check_mark")
+    }
+}
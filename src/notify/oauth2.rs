@@ -0,0 +1,75 @@
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::config::OAuth2Config;
+use crate::notify::NotifyError;
+
+/// Tokens are refreshed this long before they expire.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+/// Hands out OAuth2 access tokens, refreshing them when they expire.
+pub struct TokenSource {
+    config: OAuth2Config,
+    cached: Mutex<Option<(String, Instant)>>,
+}
+
+impl TokenSource {
+    pub fn new(config: OAuth2Config) -> TokenSource {
+        TokenSource { config, cached: Mutex::new(None) }
+    }
+
+    pub fn token(&self) -> Result<String, NotifyError> {
+        if let Some(command) = &self.config.token_command {
+            return run(command);
+        }
+        let mut cached = self.cached.lock().unwrap();
+        if let Some((token, expires)) = cached.as_ref() {
+            if Instant::now() < *expires {
+                return Ok(token.clone());
+            }
+        }
+        let response: TokenResponse = ureq::post(&self.config.token_url)
+            .send_form(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", &self.config.client_id),
+                ("client_secret", &self.config.client_secret),
+                ("refresh_token", &self.config.refresh_token),
+            ])?
+            .into_json()?;
+        let lifetime = Duration::from_secs(response.expires_in.unwrap_or(3600));
+        let expires = Instant::now() + lifetime.saturating_sub(EXPIRY_MARGIN);
+        *cached = Some((response.access_token.clone(), expires));
+        Ok(response.access_token)
+    }
+
+    /// Drops the cached token, e.g. after the server rejected it.
+    pub fn invalidate(&self) {
+        *self.cached.lock().unwrap() = None;
+    }
+}
+
+fn run(command: &str) -> Result<String, NotifyError> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").arg("/C").arg(command).output()?
+    } else {
+        Command::new("sh").arg("-c").arg(command).output()?
+    };
+    if !output.status.success() {
+        return Err(format!("token command failed with {}", output.status).into());
+    }
+    let token = String::from_utf8(output.stdout)?.trim().to_string();
+    if token.is_empty() {
+        return Err("token command printed no token".into());
+    }
+    Ok(token)
+}
@@ -0,0 +1,98 @@
+use serde_json::{json, Value};
+use url::form_urlencoded::byte_serialize;
+
+use crate::config::{OpsgenieConfig, Severity};
+use crate::notify::{hostname, Alert, Notifier, NotifyError};
+use crate::rule::truncate;
+
+/// Opsgenie cuts messages and descriptions beyond these lengths.
+const MAX_MESSAGE: usize = 130;
+const MAX_DESCRIPTION: usize = 15000;
+
+/// Creates and closes alerts through the Opsgenie Alerts API.
+pub struct OpsgenieNotifier {
+    config: OpsgenieConfig,
+}
+
+impl OpsgenieNotifier {
+    pub fn new(config: OpsgenieConfig) -> OpsgenieNotifier {
+        OpsgenieNotifier { config }
+    }
+
+    /// Opsgenie counts alerts of the same alias as one, until it is closed.
+    fn alias(alert: &Alert) -> String {
+        format!("log-alarmer/{}/{}", alert.log_id, alert.rule)
+    }
+
+    fn post(&self, path: &str, body: Value) -> Result<(), NotifyError> {
+        ureq::post(&format!("{}/v2/alerts{}", self.config.url.trim_end_matches('/'), path))
+            .set("Authorization", &format!("GenieKey {}", self.config.api_key))
+            .send_json(body)?;
+        Ok(())
+    }
+
+    fn priority(&self, severity: Severity) -> String {
+        self.config.priorities.get(&severity).cloned().unwrap_or_else(|| {
+            match severity {
+                Severity::Info => "P5",
+                Severity::Warning => "P3",
+                Severity::Critical => "P1",
+            }
+            .to_string()
+        })
+    }
+}
+
+impl Notifier for OpsgenieNotifier {
+    fn name(&self) -> &str {
+        "opsgenie"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let mut tags = vec![alert.log_id.clone(), alert.rule.clone(), alert.severity.as_str().to_string()];
+        tags.extend(alert.labels.iter().map(|(name, value)| format!("{}:{}", name, value)));
+        tags.extend(self.config.tags.iter().cloned());
+        let mut description = format!("{} at {}", alert.rule, alert.timestamp.to_rfc3339());
+        if alert.suppressed > 0 {
+            description.push_str(&format!(" ({} more during cooldown)", alert.suppressed));
+        }
+        description.push_str(&format!("\nHost: {}", alert.origin()));
+        if let Some(system) = &alert.system {
+            description.push_str(&format!("\nSystem: {}", system));
+        }
+        for line in &alert.lines {
+            description.push('\n');
+            description.push_str(line);
+        }
+        let mut body = json!({
+            // Leaves room for the ellipsis marking the cut.
+            "message": truncate(alert.summary(), MAX_MESSAGE - 1),
+            "alias": OpsgenieNotifier::alias(alert),
+            "description": truncate(description, MAX_DESCRIPTION - 1),
+            "tags": tags,
+            "entity": alert.log_id,
+            "source": hostname(),
+            "priority": self.priority(alert.severity),
+            "details": {
+                "log_id": alert.log_id,
+                "rule": alert.rule,
+                "count": alert.count.to_string(),
+                "suppressed": alert.suppressed.to_string(),
+                "repeats": alert.repeats.to_string(),
+                "fingerprint": alert.fingerprint.clone().unwrap_or_default(),
+            },
+        });
+        if let Some(team) = &self.config.team {
+            body["responders"] = json!([{ "type": "team", "name": team }]);
+        }
+        self.post("", body)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let alias: String = byte_serialize(OpsgenieNotifier::alias(alert).as_bytes()).collect();
+        self.post(
+            &format!("/{}/close?identifierType=alias", alias),
+            json!({ "source": hostname(), "note": alert.summary() }),
+        )
+    }
+}
@@ -0,0 +1,60 @@
+use serde_json::json;
+
+use crate::config::PagerDutyConfig;
+use crate::notify::{Alert, Notifier, NotifyError};
+
+/// Triggers and resolves incidents through the PagerDuty Events API v2.
+pub struct PagerDutyNotifier {
+    config: PagerDutyConfig,
+}
+
+impl PagerDutyNotifier {
+    pub fn new(config: PagerDutyConfig) -> PagerDutyNotifier {
+        PagerDutyNotifier { config }
+    }
+
+    fn dedup_key(alert: &Alert) -> String {
+        format!("log-alarmer/{}/{}", alert.log_id, alert.rule)
+    }
+}
+
+impl Notifier for PagerDutyNotifier {
+    fn name(&self) -> &str {
+        "pagerduty"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        ureq::post(&self.config.url).send_json(json!({
+            "routing_key": self.config.routing_key,
+            "event_action": "trigger",
+            "dedup_key": PagerDutyNotifier::dedup_key(alert),
+            "client": "log-alarmer",
+            "payload": {
+                "summary": alert.summary(),
+                "source": alert.log_id,
+                "severity": self.config.severity,
+                "timestamp": alert.timestamp.to_rfc3339(),
+                "custom_details": {
+                    "rule": alert.rule,
+                    "host": alert.origin(),
+                    "system": alert.system.as_ref().map(ToString::to_string),
+                    "count": alert.count,
+                    "suppressed": alert.suppressed,
+                    "fingerprint": alert.fingerprint,
+                    "repeats": alert.repeats,
+                    "lines": alert.lines,
+                },
+            },
+        }))?;
+        Ok(())
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        ureq::post(&self.config.url).send_json(json!({
+            "routing_key": self.config.routing_key,
+            "event_action": "resolve",
+            "dedup_key": PagerDutyNotifier::dedup_key(alert),
+        }))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use crate::config::{PushoverConfig, Severity};
+use crate::notify::{Alert, Notifier, NotifyError};
+use crate::rule::truncate;
+
+/// Longest message Pushover accepts.
+const MAX_MESSAGE: usize = 1024;
+
+/// Priority of messages that repeat until acknowledged.
+const EMERGENCY: i8 = 2;
+
+/// Sends alerts as Pushover notifications, with a priority by severity.
+pub struct PushoverNotifier {
+    config: PushoverConfig,
+}
+
+impl PushoverNotifier {
+    pub fn new(config: PushoverConfig) -> PushoverNotifier {
+        PushoverNotifier { config }
+    }
+
+    fn priority(&self, severity: Severity) -> i8 {
+        self.config.priorities.get(&severity).copied().unwrap_or(match severity {
+            Severity::Info => -1,
+            Severity::Warning => 0,
+            Severity::Critical => 1,
+        })
+    }
+
+    /// Tags emergency messages so that they can be cancelled once the alarm
+    /// clears.
+    fn tag(alert: &Alert) -> String {
+        let tag = format!("log-alarmer-{}-{}", alert.log_id, alert.rule);
+        tag.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect()
+    }
+
+    fn send(&self, title: String, message: String, priority: i8, tag: Option<String>) -> Result<(), NotifyError> {
+        let url = format!("{}/1/messages.json", self.config.api_url);
+        let priority = priority.to_string();
+        let retry = self.config.retry_seconds.to_string();
+        let expire = self.config.expire_seconds.to_string();
+        let message = truncate(message, MAX_MESSAGE - 1);
+        let mut form = vec![
+            ("token", self.config.app_token.as_str()),
+            ("user", self.config.user_key.as_str()),
+            ("title", title.as_str()),
+            ("message", message.as_str()),
+            ("priority", priority.as_str()),
+        ];
+        if let Some(tag) = &tag {
+            form.extend([("retry", retry.as_str()), ("expire", expire.as_str()), ("tags", tag.as_str())]);
+        }
+        if let Some(device) = &self.config.device {
+            form.push(("device", device));
+        }
+        if let Some(sound) = &self.config.sound {
+            form.push(("sound", sound));
+        }
+        ureq::post(&url).send_form(&form)?;
+        Ok(())
+    }
+}
+
+impl Notifier for PushoverNotifier {
+    fn name(&self) -> &str {
+        "pushover"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let priority = self.priority(alert.severity);
+        let mut message = format!("{} at {}", alert.rule, alert.timestamp.format("%Y-%m-%d %H:%M:%S %z"));
+        if alert.suppressed > 0 {
+            message.push_str(&format!(" ({} more during cooldown)", alert.suppressed));
+        }
+        message.push_str(&format!("\nHost: {}", alert.origin()));
+        if let Some(system) = &alert.system {
+            message.push_str(&format!("\nSystem: {}", system));
+        }
+        for line in &alert.lines {
+            message.push('\n');
+            message.push_str(line);
+        }
+        let tag = Some(PushoverNotifier::tag(alert)).filter(|_| priority >= EMERGENCY);
+        self.send(alert.summary(), message, priority, tag)
+    }
+
+    /// Stops the emergency messages of the alarm from repeating, then says
+    /// that it cleared.
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        if self.priority(alert.severity) >= EMERGENCY {
+            let url = format!("{}/1/receipts/cancel_by_tag/{}.json", self.config.api_url, PushoverNotifier::tag(alert));
+            ureq::post(&url).send_form(&[("token", &self.config.app_token)])?;
+        }
+        let at = alert.timestamp.format("%Y-%m-%d %H:%M:%S %z");
+        self.send(alert.summary(), format!("{} at {}\nHost: {}", alert.rule, at, alert.origin()), -1, None)
+    }
+}
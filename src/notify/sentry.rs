@@ -0,0 +1,165 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::config::{SentryConfig, Severity};
+use crate::notify::{hostname, primary_ip, Alert, Notifier, NotifyError};
+
+/// Turns alerts into events of a Sentry project, with stack traces of
+/// Java and Python found in their lines as exceptions.
+pub struct SentryNotifier {
+    config: SentryConfig,
+}
+
+impl SentryNotifier {
+    pub fn new(config: SentryConfig) -> SentryNotifier {
+        SentryNotifier { config }
+    }
+
+    fn event(&self, alert: &Alert) -> Value {
+        let level = match alert.severity {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "fatal",
+        };
+        // The most recent line stands for the alert.
+        let entry = alert.lines.last().map(String::as_str).unwrap_or_default();
+        let message = entry.lines().next().filter(|line| !line.trim().is_empty()).unwrap_or(&alert.rule);
+        // Repeats of a deduplicated error make one issue, other alerts are
+        // grouped by Sentry, e.g. by their stack trace.
+        let fingerprint = match &alert.fingerprint {
+            Some(fingerprint) => json!([alert.log_id, alert.rule, fingerprint]),
+            None => json!(["{{ default }}"]),
+        };
+        let mut event = json!({
+            "event_id": format!("{:032x}", fastrand::u128(..)),
+            "timestamp": alert.timestamp.to_rfc3339(),
+            "platform": "other",
+            "level": level,
+            "logger": alert.log_id,
+            "server_name": hostname(),
+            "logentry": { "formatted": message },
+            "fingerprint": fingerprint,
+            "tags": { "log_id": alert.log_id, "rule": alert.rule, "severity": alert.severity.as_str() },
+            "extra": { "count": alert.count, "suppressed": alert.suppressed, "ip": primary_ip(), "lines": alert.lines },
+        });
+        for (name, value) in &alert.labels {
+            event["tags"][name] = json!(value);
+        }
+        if let Some(system) = &alert.system {
+            event["extra"]["system"] = json!(system);
+        }
+        let exceptions = exceptions(entry);
+        if !exceptions.is_empty() {
+            event["exception"] = json!({ "values": exceptions });
+        }
+        if let Some(environment) = &self.config.environment {
+            event["environment"] = json!(environment);
+        }
+        if let Some(release) = &self.config.release {
+            event["release"] = json!(release);
+        }
+        event
+    }
+}
+
+/// The exceptions of a stack trace in `entry`, causes first as Sentry
+/// expects them.
+fn exceptions(entry: &str) -> Vec<Value> {
+    static PATTERNS: OnceLock<[Regex; 4]> = OnceLock::new();
+    let [header, java_frame, python_frame, python_error] = PATTERNS.get_or_init(|| {
+        [
+            Regex::new(r"^(?:Caused by: )?([A-Za-z_$][\w$.]*)(?::\s*(.*))?$").unwrap(),
+            Regex::new(r"^\s*at ([\w$.<>]+)\.([\w$<>]+)\(([^:)]*)(?::(\d+))?\)").unwrap(),
+            Regex::new(r#"^\s*File "(.+)", line (\d+), in (.+)$"#).unwrap(),
+            Regex::new(r"^([A-Za-z_][\w.]*)(?::\s*(.*))?$").unwrap(),
+        ]
+    });
+    let lines: Vec<&str> = entry.lines().collect();
+    let mut exceptions = Vec::new();
+    if let Some(start) = lines.iter().rposition(|line| line.starts_with("Traceback (most recent call last)")) {
+        let mut frames = Vec::new();
+        for line in &lines[start + 1..] {
+            if let Some(frame) = python_frame.captures(line) {
+                frames.push(json!({
+                    "filename": &frame[1],
+                    "lineno": frame[2].parse::<u64>().ok(),
+                    "function": &frame[3],
+                }));
+            } else if let Some(error) = python_error.captures(line) {
+                let value = error.get(2).map_or("", |value| value.as_str());
+                exceptions.push(exception(&error[1], value, frames));
+                break;
+            }
+        }
+        return exceptions;
+    }
+    let mut i = 0;
+    while i < lines.len() {
+        // An exception is named on the line before its first frame.
+        if i == 0 || !java_frame.is_match(lines[i]) || java_frame.is_match(lines[i - 1]) {
+            i += 1;
+            continue;
+        }
+        let (kind, value) = match header.captures(lines[i - 1].trim()) {
+            Some(found) => (found[1].to_string(), found.get(2).map_or("", |value| value.as_str()).to_string()),
+            None => ("Exception".to_string(), lines[i - 1].trim().to_string()),
+        };
+        let mut frames = Vec::new();
+        while i < lines.len() {
+            match java_frame.captures(lines[i]) {
+                Some(frame) => frames.push(json!({
+                    "module": &frame[1],
+                    "function": &frame[2],
+                    "filename": &frame[3],
+                    "lineno": frame.get(4).and_then(|line| line.as_str().parse::<u64>().ok()),
+                })),
+                None if lines[i].trim_start().starts_with("...") => (),
+                None => break,
+            }
+            i += 1;
+        }
+        // Java lists the innermost call first, Sentry the outermost.
+        frames.reverse();
+        exceptions.insert(0, exception(&kind, &value, frames));
+    }
+    exceptions
+}
+
+fn exception(kind: &str, value: &str, frames: Vec<Value>) -> Value {
+    json!({ "type": kind, "value": value, "stacktrace": { "frames": frames } })
+}
+
+impl Notifier for SentryNotifier {
+    fn name(&self) -> &str {
+        "sentry"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let dsn = Url::parse(&self.config.dsn)?;
+        let (path, project) = dsn.path().trim_end_matches('/').rsplit_once('/').ok_or("the DSN names no project")?;
+        let mut url = dsn.clone();
+        url.set_username("").map_err(|_| "the DSN is no URL of a server")?;
+        url.set_password(None).map_err(|_| "the DSN is no URL of a server")?;
+        url.set_path(&format!("{}/api/{}/envelope/", path, project));
+        let event = self.event(alert);
+        let envelope = format!(
+            "{}\n{}\n{}\n",
+            json!({ "event_id": event["event_id"], "dsn": self.config.dsn }),
+            json!({ "type": "event" }),
+            event,
+        );
+        let auth = format!(
+            "Sentry sentry_version=7, sentry_client=log-alarmer/{}, sentry_key={}",
+            env!("CARGO_PKG_VERSION"),
+            dsn.username(),
+        );
+        ureq::post(url.as_str())
+            .set("X-Sentry-Auth", &auth)
+            .set("Content-Type", "application/x-sentry-envelope")
+            .send_string(&envelope)?;
+        Ok(())
+    }
+}
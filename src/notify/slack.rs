@@ -0,0 +1,60 @@
+use serde_json::json;
+
+use crate::config::SlackConfig;
+use crate::notify::{Alert, Notifier, NotifyError};
+
+/// Posts alerts to a Slack incoming webhook.
+pub struct SlackNotifier {
+    config: SlackConfig,
+}
+
+impl SlackNotifier {
+    pub fn new(config: SlackConfig) -> SlackNotifier {
+        SlackNotifier { config }
+    }
+
+    fn post(&self, text: String) -> Result<(), NotifyError> {
+        let mut body = json!({ "text": text });
+        if let Some(channel) = &self.config.channel {
+            body["channel"] = json!(channel);
+        }
+        if let Some(username) = &self.config.username {
+            body["username"] = json!(username);
+        }
+        ureq::post(&self.config.webhook_url).send_json(body)?;
+        Ok(())
+    }
+}
+
+impl Notifier for SlackNotifier {
+    fn name(&self) -> &str {
+        "slack"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let at = alert.timestamp.format("%Y-%m-%d %H:%M:%S %z");
+        let mut text = match alert.absent_for {
+            Some(_) => format!(":zzz: {} at {}", alert.summary(), at),
+            None => format!(":rotating_light: *{}* errors on `{}` at {}", alert.count, alert.log_id, at),
+        };
+        if alert.suppressed > 0 {
+            text.push_str(&format!(" ({} more during cooldown)", alert.suppressed));
+        }
+        if alert.repeats > 0 {
+            text.push_str(&format!(" (seen {} more times)", alert.repeats));
+        }
+        text.push_str(&format!("\nHost: {}", alert.origin()));
+        if let Some(system) = &alert.system {
+            text.push_str(&format!("\nSystem: {}", system));
+        }
+        if !alert.lines.is_empty() {
+            text.push_str(&format!("\n```{}```", alert.lines.join("\n")));
+        }
+        self.post(text)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let at = alert.timestamp.format("%Y-%m-%d %H:%M:%S %z");
+        self.post(format!(":white_check_mark: {} at {}\nHost: {}", alert.summary(), at, alert.origin()))
+    }
+}
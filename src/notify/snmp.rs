@@ -0,0 +1,402 @@
+use std::convert::TryInto;
+use std::net::UdpSocket;
+use std::path::Path;
+use std::time::Instant;
+
+use aes::cipher::BlockCipherEncrypt;
+use aes::Aes128;
+use hmac::{Hmac, KeyInit, Mac};
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::config::{Severity, SnmpAuth, SnmpConfig};
+use crate::notify::aws::hex;
+use crate::notify::{hostname, Alert, Notifier, NotifyError};
+use crate::rule::truncate;
+use crate::state::State;
+
+pub(crate) const INTEGER: u8 = 0x02;
+pub(crate) const OCTET_STRING: u8 = 0x04;
+pub(crate) const OBJECT_IDENTIFIER: u8 = 0x06;
+pub(crate) const SEQUENCE: u8 = 0x30;
+const GAUGE: u8 = 0x42;
+const TIME_TICKS: u8 = 0x43;
+const TRAP: u8 = 0xa7;
+
+const SYS_UP_TIME: &str = "1.3.6.1.2.1.1.3.0";
+const SNMP_TRAP_OID: &str = "1.3.6.1.6.3.1.1.4.1.0";
+
+/// Samples longer than this are cut to keep the trap in one datagram.
+const MAX_LINE: usize = 1024;
+
+/// Sends alerts as SNMPv2c or SNMPv3 traps, for managers that take
+/// nothing else.
+pub struct SnmpNotifier {
+    config: SnmpConfig,
+    /// The engine of this sender, which is authoritative for its traps.
+    engine_id: Vec<u8>,
+    /// How often the engine started, and when it did this time.
+    boots: u32,
+    started: Instant,
+}
+
+impl SnmpNotifier {
+    /// Counts a start of the engine in `state_file`, if there is one.
+    pub fn new(config: SnmpConfig, state_file: Option<&Path>) -> SnmpNotifier {
+        let engine_id = config.engine_id.as_deref().and_then(|id| from_hex(id).ok()).unwrap_or_else(|| {
+            // An engine id of the Net-SNMP enterprise in text format, so
+            // that it stays the same across restarts.
+            let mut id = vec![0x80, 0x00, 0x1f, 0x88, 0x04];
+            id.extend(hostname().bytes().take(27));
+            id
+        });
+        let boots = state_file.map_or(1, |path| boot(&engine_id, path));
+        SnmpNotifier { config, engine_id, boots, started: Instant::now() }
+    }
+
+    /// The trap PDU, of `event` 1 for alerts and 2 for resolutions.
+    fn pdu(&self, alert: &Alert, event: u32) -> Result<Vec<u8>, NotifyError> {
+        let base = self.config.enterprise_oid.trim_start_matches('.');
+        let object = |index: u32| format!("{}.1.{}.0", base, index);
+        let severity = match alert.severity {
+            Severity::Info => 1,
+            Severity::Warning => 2,
+            Severity::Critical => 3,
+        };
+        let line = alert.lines.last().map(|line| truncate(line.clone(), MAX_LINE)).unwrap_or_default();
+        let uptime = (self.started.elapsed().as_millis() / 10) as u32 as i64;
+        let bindings = [
+            binding(SYS_UP_TIME, integer(TIME_TICKS, uptime))?,
+            binding(SNMP_TRAP_OID, tlv(OBJECT_IDENTIFIER, &oid(&format!("{}.0.{}", base, event))?))?,
+            binding(&object(1), tlv(OCTET_STRING, alert.log_id.as_bytes()))?,
+            binding(&object(2), tlv(OCTET_STRING, alert.rule.as_bytes()))?,
+            binding(&object(3), integer(INTEGER, severity))?,
+            binding(&object(4), integer(GAUGE, alert.count.min(u32::MAX as usize) as i64))?,
+            binding(&object(5), tlv(OCTET_STRING, alert.summary().as_bytes()))?,
+            binding(&object(6), tlv(OCTET_STRING, hostname().as_bytes()))?,
+            binding(&object(7), tlv(OCTET_STRING, line.as_bytes()))?,
+        ];
+        let mut pdu = integer(INTEGER, fastrand::i32(1..) as i64);
+        pdu.extend(integer(INTEGER, 0)); // error status
+        pdu.extend(integer(INTEGER, 0)); // error index
+        pdu.extend(tlv(SEQUENCE, &bindings.concat()));
+        Ok(tlv(TRAP, &pdu))
+    }
+
+    fn community_message(&self, pdu: Vec<u8>) -> Vec<u8> {
+        let mut message = integer(INTEGER, 1); // version 2c
+        message.extend(tlv(OCTET_STRING, self.config.community.as_bytes()));
+        message.extend(pdu);
+        tlv(SEQUENCE, &message)
+    }
+
+    /// A message of the user-based security model, authenticated and
+    /// encrypted as configured.
+    fn usm_message(&self, pdu: Vec<u8>) -> Result<Vec<u8>, NotifyError> {
+        let auth = match (self.config.auth_protocol, &self.config.auth_password) {
+            (Some(protocol), Some(password)) => Some((protocol, localize(protocol, password, &self.engine_id))),
+            _ => None,
+        };
+        let privacy = match (&auth, &self.config.priv_password) {
+            (Some((protocol, _)), Some(password)) => Some(localize(*protocol, password, &self.engine_id)),
+            _ => None,
+        };
+        let (boots, time) = (self.boots, self.started.elapsed().as_secs() as u32);
+        let mut scoped = tlv(OCTET_STRING, &self.engine_id);
+        scoped.extend(tlv(OCTET_STRING, b"")); // context name
+        scoped.extend(pdu);
+        let scoped = tlv(SEQUENCE, &scoped);
+        let (data, salt) = match &privacy {
+            Some(key) => {
+                let salt = fastrand::u64(..).to_be_bytes();
+                let mut iv = boots.to_be_bytes().to_vec();
+                iv.extend(time.to_be_bytes());
+                iv.extend(salt);
+                (tlv(OCTET_STRING, &aes_cfb(&key[..16], &iv, &scoped)?), salt.to_vec())
+            },
+            None => (scoped, Vec::new()),
+        };
+        let flags = match (&auth, &privacy) {
+            (Some(_), Some(_)) => 0x03,
+            (Some(_), None) => 0x01,
+            _ => 0x00,
+        };
+        let mut global = integer(INTEGER, fastrand::i32(1..) as i64);
+        global.extend(integer(INTEGER, 65507)); // maximum size
+        global.extend(tlv(OCTET_STRING, &[flags]));
+        global.extend(integer(INTEGER, 3)); // security model
+        let global = tlv(SEQUENCE, &global);
+        let mac_length = auth.as_ref().map_or(0, |(protocol, _)| protocol.mac_length());
+        let mut before_mac = tlv(OCTET_STRING, &self.engine_id);
+        before_mac.extend(integer(INTEGER, boots as i64));
+        before_mac.extend(integer(INTEGER, time as i64));
+        before_mac.extend(tlv(OCTET_STRING, self.config.username.as_deref().unwrap_or_default().as_bytes()));
+        let mut parameters = before_mac.clone();
+        parameters.extend(tlv(OCTET_STRING, &vec![0; mac_length]));
+        parameters.extend(tlv(OCTET_STRING, &salt));
+        let security = tlv(OCTET_STRING, &tlv(SEQUENCE, &parameters));
+        let version = integer(INTEGER, 3);
+        let content = [version.as_slice(), &global, &security, &data].concat();
+        let mut message = tlv(SEQUENCE, &content);
+        if let Some((protocol, key)) = &auth {
+            // The MAC is computed over the message with zeros in its place,
+            // found after the headers of the message, of the security
+            // parameters and of the MAC itself.
+            let headers = message.len() - content.len() + security.len() - parameters.len() + 2;
+            let at = headers + version.len() + global.len() + before_mac.len();
+            let mac = protocol.mac(key, &message)?;
+            message[at..at + mac_length].copy_from_slice(&mac[..mac_length]);
+        }
+        Ok(message)
+    }
+
+    fn send(&self, alert: &Alert, event: u32) -> Result<(), NotifyError> {
+        let pdu = self.pdu(alert, event)?;
+        let message = if self.config.version == "3" { self.usm_message(pdu)? } else { self.community_message(pdu) };
+        let target = if self.config.target.contains(':') && !self.config.target.ends_with(']') {
+            self.config.target.clone()
+        } else {
+            format!("{}:162", self.config.target)
+        };
+        let socket = UdpSocket::bind(if target.starts_with('[') { "[::]:0" } else { "0.0.0.0:0" })?;
+        socket.connect(&target)?;
+        socket.send(&message)?;
+        Ok(())
+    }
+}
+
+impl SnmpAuth {
+    /// Bytes of the MAC sent, of HMAC-96 or, for SHA-256, HMAC-192.
+    fn mac_length(self) -> usize {
+        match self {
+            SnmpAuth::Md5 | SnmpAuth::Sha => 12,
+            SnmpAuth::Sha256 => 24,
+        }
+    }
+
+    fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            SnmpAuth::Md5 => Md5::digest(data).to_vec(),
+            SnmpAuth::Sha => Sha1::digest(data).to_vec(),
+            SnmpAuth::Sha256 => Sha256::digest(data).to_vec(),
+        }
+    }
+
+    fn mac(self, key: &[u8], data: &[u8]) -> Result<Vec<u8>, NotifyError> {
+        let mac = match self {
+            SnmpAuth::Md5 => Hmac::<Md5>::new_from_slice(key)?.chain_update(data).finalize().into_bytes().to_vec(),
+            SnmpAuth::Sha => Hmac::<Sha1>::new_from_slice(key)?.chain_update(data).finalize().into_bytes().to_vec(),
+            SnmpAuth::Sha256 => {
+                Hmac::<Sha256>::new_from_slice(key)?.chain_update(data).finalize().into_bytes().to_vec()
+            },
+        };
+        Ok(mac)
+    }
+}
+
+/// Counts a start of the engine in the state file and returns the count,
+/// or 1 without a readable one. Managers drop traps whose boots and time
+/// are behind those they saw, as after a restart without it.
+fn boot(engine_id: &[u8], path: &Path) -> u32 {
+    let mut state = match State::load(path) {
+        Ok(state) => state,
+        Err(e) => {
+            warn!("Failed to read the SNMP engine boots from {}: {}", path.display(), e);
+            return 1;
+        },
+    };
+    // Up to the largest value of snmpEngineBoots.
+    let boots = state.engine_boots.entry(hex(engine_id)).or_insert(0);
+    *boots = (*boots + 1).min(i32::MAX as u32);
+    let boots = *boots;
+    if let Err(e) = state.save(path) {
+        warn!("Failed to save the SNMP engine boots to {}: {}", path.display(), e);
+    }
+    boots
+}
+
+/// The key of a password for the engine, as RFC 3414 derives it.
+fn localize(protocol: SnmpAuth, password: &str, engine_id: &[u8]) -> Vec<u8> {
+    let expanded: Vec<u8> = password.bytes().cycle().take(1 << 20).collect();
+    let key = protocol.digest(&expanded);
+    protocol.digest(&[key.as_slice(), engine_id, &key].concat())
+}
+
+/// AES-128 in 128-bit CFB mode, as RFC 3826 encrypts the scoped PDU.
+fn aes_cfb(key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>, NotifyError> {
+    let cipher = Aes128::new_from_slice(key).map_err(|_| "invalid AES key")?;
+    let mut feedback: [u8; 16] = iv.try_into()?;
+    let mut encrypted = Vec::with_capacity(data.len());
+    for chunk in data.chunks(16) {
+        let mut block = feedback.into();
+        cipher.encrypt_block(&mut block);
+        let stream: [u8; 16] = block.into();
+        let start = encrypted.len();
+        encrypted.extend(chunk.iter().zip(stream).map(|(byte, key)| byte ^ key));
+        if chunk.len() == 16 {
+            feedback.copy_from_slice(&encrypted[start..]);
+        }
+    }
+    Ok(encrypted)
+}
+
+fn binding(name: &str, value: Vec<u8>) -> Result<Vec<u8>, NotifyError> {
+    let mut binding = tlv(OBJECT_IDENTIFIER, &oid(name)?);
+    binding.extend(value);
+    Ok(tlv(SEQUENCE, &binding))
+}
+
+/// A value of BER, after its tag and length.
+pub(crate) fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![tag];
+    if content.len() < 0x80 {
+        bytes.push(content.len() as u8);
+    } else {
+        let length = (content.len() as u32).to_be_bytes();
+        let significant: Vec<u8> = length.iter().copied().skip_while(|byte| *byte == 0).collect();
+        bytes.push(0x80 | significant.len() as u8);
+        bytes.extend(significant);
+    }
+    bytes.extend(content);
+    bytes
+}
+
+/// An integer in the fewest bytes of two's complement.
+fn integer(tag: u8, value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    // A leading byte is redundant if the next one has the same sign.
+    let redundant = |i: usize| bytes[i] == 0 && bytes[i + 1] < 0x80 || bytes[i] == 0xff && bytes[i + 1] >= 0x80;
+    while start < 7 && redundant(start) {
+        start += 1;
+    }
+    tlv(tag, &bytes[start..])
+}
+
+fn oid(text: &str) -> Result<Vec<u8>, NotifyError> {
+    let arcs = text.trim_start_matches('.').split('.').map(str::parse::<u64>).collect::<Result<Vec<u64>, _>>()
+        .map_err(|_| format!("{} is not an OID", text))?;
+    if arcs.len() < 2 {
+        return Err(format!("{} is not an OID", text).into());
+    }
+    let mut bytes = Vec::new();
+    for arc in std::iter::once(arcs[0] * 40 + arcs[1]).chain(arcs[2..].iter().copied()) {
+        // Base 128, the highest bit telling whether another byte follows.
+        let mut digits = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            digits.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        bytes.extend(digits.iter().rev());
+    }
+    Ok(bytes)
+}
+
+pub(crate) fn from_hex(text: &str) -> Result<Vec<u8>, NotifyError> {
+    let text = text.trim_start_matches("0x");
+    if !text.len().is_multiple_of(2) {
+        return Err("odd number of hex digits".into());
+    }
+    (0..text.len()).step_by(2).map(|i| Ok(u8::from_str_radix(&text[i..i + 2], 16)?)).collect()
+}
+
+impl Notifier for SnmpNotifier {
+    fn name(&self) -> &str {
+        "snmp"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.send(alert, 1)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.send(alert, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const ENGINE_ID: &str = "000000000000000000000002";
+
+    #[test]
+    fn tlv_encodes_short_and_long_lengths() {
+        assert_eq!(tlv(OCTET_STRING, &[]), [0x04, 0x00]);
+        assert_eq!(tlv(OCTET_STRING, &[0; 0x7f])[..2], [0x04, 0x7f]);
+        assert_eq!(tlv(OCTET_STRING, &[0; 0x80])[..3], [0x04, 0x81, 0x80]);
+        assert_eq!(tlv(OCTET_STRING, &[0; 0x100])[..4], [0x04, 0x82, 0x01, 0x00]);
+        assert_eq!(tlv(OCTET_STRING, &[0; 0x100]).len(), 0x104);
+    }
+
+    #[test]
+    fn integer_takes_the_fewest_bytes() {
+        assert_eq!(integer(INTEGER, 0), [0x02, 0x01, 0x00]);
+        assert_eq!(integer(INTEGER, 127), [0x02, 0x01, 0x7f]);
+        assert_eq!(integer(INTEGER, 128), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(integer(INTEGER, -1), [0x02, 0x01, 0xff]);
+        assert_eq!(integer(INTEGER, -129), [0x02, 0x02, 0xff, 0x7f]);
+        assert_eq!(integer(INTEGER, 65507), [0x02, 0x03, 0x00, 0xff, 0xe3]);
+    }
+
+    #[test]
+    fn oid_encodes_arcs_in_base_128() {
+        assert_eq!(oid("1.3.6.1.2.1.1.3.0").unwrap(), [0x2b, 6, 1, 2, 1, 1, 3, 0]);
+        assert_eq!(oid(".1.3.6.1.4.1.8072").unwrap(), [0x2b, 6, 1, 4, 1, 0xbf, 0x08]);
+        assert!(oid("1").is_err());
+    }
+
+    /// The keys of RFC 3414 A.3, and of RFC 7860 for SHA-256.
+    #[test]
+    fn localize_matches_rfc_3414() {
+        let engine_id = from_hex(ENGINE_ID).unwrap();
+        let keys = [
+            (SnmpAuth::Md5, "526f5eed9fcce26f8964c2930787d82b"),
+            (SnmpAuth::Sha, "6695febc9288e36282235fc7151f128497b38f3f"),
+            (SnmpAuth::Sha256, "8982e0e549e866db361a6b625d84cccc11162d453ee8ce3a6445c2d6776f0f8b"),
+        ];
+        for (protocol, key) in keys {
+            assert_eq!(hex(&localize(protocol, "maplesyrup", &engine_id)), key, "{:?}", protocol);
+        }
+    }
+
+    #[test]
+    fn boot_counts_starts_in_the_state_file() {
+        let path = std::env::temp_dir().join(format!("log-alarmer-snmp-{}.json", std::process::id()));
+        let engine_id = from_hex(ENGINE_ID).unwrap();
+        assert_eq!(boot(&engine_id, &path), 1);
+        assert_eq!(boot(&engine_id, &path), 2);
+        assert_eq!(boot(&[0x80], &path), 1);
+        assert_eq!(State::load(&path).unwrap().engine_boots[ENGINE_ID], 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn usm_message_places_the_mac_after_the_username() {
+        for (protocol, name) in [(SnmpAuth::Md5, "md5"), (SnmpAuth::Sha, "sha"), (SnmpAuth::Sha256, "sha256")] {
+            let config = serde_json::from_value(json!({
+                "target": "localhost",
+                "version": "3",
+                "username": "user",
+                "auth_protocol": name,
+                "auth_password": "maplesyrup",
+                "engine_id": ENGINE_ID,
+            }))
+            .unwrap();
+            let notifier = SnmpNotifier::new(config, None);
+            let message = notifier.usm_message(tlv(TRAP, &[])).unwrap();
+            let length = protocol.mac_length();
+            let username = [&tlv(OCTET_STRING, b"user")[..], &[OCTET_STRING, length as u8]].concat();
+            let at = message.windows(username.len()).position(|window| window == username).unwrap() + username.len();
+            let mut zeroed = message.clone();
+            zeroed[at..at + length].fill(0);
+            let key = localize(protocol, "maplesyrup", &notifier.engine_id);
+            assert_eq!(message[at..at + length], protocol.mac(&key, &zeroed).unwrap()[..length], "{}", name);
+        }
+    }
+}
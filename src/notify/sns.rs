@@ -0,0 +1,104 @@
+use sha2::{Digest, Sha256};
+use url::form_urlencoded::Serializer;
+use url::Url;
+
+use crate::config::SnsConfig;
+use crate::notify::aws::{self, CredentialChain, Credentials};
+use crate::notify::{Alert, Notifier, NotifyError};
+
+/// SNS subjects are ASCII of at most this many characters.
+const MAX_SUBJECT: usize = 100;
+
+/// Publishes alerts to an AWS SNS topic, to reach its subscribers.
+pub struct SnsNotifier {
+    config: SnsConfig,
+    region: String,
+    credentials: CredentialChain,
+}
+
+impl SnsNotifier {
+    pub fn new(config: SnsConfig) -> SnsNotifier {
+        let region = config.region().unwrap_or_default();
+        let configured = match (&config.access_key_id, &config.secret_access_key) {
+            (Some(access_key_id), Some(secret_access_key)) => Some(Credentials {
+                access_key_id: access_key_id.clone(),
+                secret_access_key: secret_access_key.clone(),
+                session_token: None,
+            }),
+            _ => None,
+        };
+        let credentials = CredentialChain::new(configured, config.profile.clone(), &region);
+        SnsNotifier { config, region, credentials }
+    }
+
+    fn publish(&self, alert: &Alert, message: String) -> Result<(), NotifyError> {
+        let url = match &self.config.endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None => format!("https://sns.{}.amazonaws.com/", self.region),
+        };
+        let parsed = Url::parse(&url)?;
+        let host = match (parsed.host_str(), parsed.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(format!("{} names no host", url).into()),
+        };
+        let subject: String = alert.summary().chars().filter(|c| c.is_ascii() && !c.is_ascii_control()).collect();
+        let subject = subject.chars().take(MAX_SUBJECT).collect::<String>();
+        let mut form = Serializer::new(String::new());
+        form.append_pair("Action", "Publish")
+            .append_pair("Version", "2010-03-31")
+            .append_pair("TopicArn", &self.config.topic_arn)
+            .append_pair("Subject", &subject)
+            .append_pair("Message", &message);
+        // Subscriptions can filter on these attributes.
+        let status = if alert.resolved { "resolved" } else { "firing" };
+        let attributes = [("log_id", alert.log_id.as_str()), ("severity", alert.severity.as_str())];
+        for (i, (name, value)) in attributes.iter().chain(&[("status", status)]).enumerate() {
+            let entry = format!("MessageAttributes.entry.{}", i + 1);
+            form.append_pair(&format!("{}.Name", entry), name)
+                .append_pair(&format!("{}.Value.DataType", entry), "String")
+                .append_pair(&format!("{}.Value.StringValue", entry), value);
+        }
+        if self.config.topic_arn.ends_with(".fifo") {
+            let id = aws::hex(&Sha256::digest(format!("{}{}", status, message).as_bytes()));
+            form.append_pair("MessageGroupId", &alert.log_id).append_pair("MessageDeduplicationId", &id);
+        }
+        let body = form.finish();
+        let credentials = self.credentials.credentials()?;
+        let mut request = ureq::post(&url);
+        for (name, value) in aws::sign(&credentials, "sns", &self.region, &host, &body)? {
+            request = request.set(name, &value);
+        }
+        request.send_string(&body).map_err(aws::failure)?;
+        Ok(())
+    }
+}
+
+impl Notifier for SnsNotifier {
+    fn name(&self) -> &str {
+        "sns"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let mut message = format!("{}\n\n{} at {}", alert.summary(), alert.rule, alert.timestamp.to_rfc3339());
+        if alert.suppressed > 0 {
+            message.push_str(&format!(" ({} more during cooldown)", alert.suppressed));
+        }
+        message.push_str(&format!("\nHost: {}", alert.origin()));
+        if let Some(system) = &alert.system {
+            message.push_str(&format!("\nSystem: {}", system));
+        }
+        for line in &alert.lines {
+            message.push('\n');
+            message.push_str(line);
+        }
+        self.publish(alert, message)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let message = format!(
+            "{}\n\n{} at {}\nHost: {}", alert.summary(), alert.rule, alert.timestamp.to_rfc3339(), alert.origin(),
+        );
+        self.publish(alert, message)
+    }
+}
@@ -0,0 +1,77 @@
+use serde_json::{json, Value};
+
+use crate::config::{Severity, TeamsConfig};
+use crate::notify::{Alert, Notifier, NotifyError};
+
+/// Posts alerts to a Microsoft Teams incoming webhook as Adaptive Cards.
+pub struct TeamsNotifier {
+    config: TeamsConfig,
+}
+
+impl TeamsNotifier {
+    pub fn new(config: TeamsConfig) -> TeamsNotifier {
+        TeamsNotifier { config }
+    }
+
+    fn card(alert: &Alert) -> Value {
+        let color = match alert.severity {
+            _ if alert.resolved => "Good",
+            Severity::Info => "Accent",
+            Severity::Warning => "Warning",
+            Severity::Critical => "Attention",
+        };
+        let mut facts = vec![
+            json!({ "title": "Log", "value": alert.log_id }),
+            json!({ "title": "Host", "value": alert.origin() }),
+            json!({ "title": "Rule", "value": alert.rule }),
+            json!({ "title": "Severity", "value": alert.severity.as_str() }),
+            json!({ "title": "Time", "value": alert.timestamp.format("%Y-%m-%d %H:%M:%S %z").to_string() }),
+        ];
+        if !alert.resolved {
+            facts.insert(2, json!({ "title": "Count", "value": alert.count.to_string() }));
+        }
+        if alert.suppressed > 0 {
+            facts.push(json!({ "title": "During cooldown", "value": alert.suppressed.to_string() }));
+        }
+        if let Some(system) = &alert.system {
+            facts.push(json!({ "title": "System", "value": system.to_string() }));
+        }
+        let mut body = vec![
+            json!({ "type": "TextBlock", "text": alert.summary(), "weight": "Bolder", "size": "Medium",
+                "color": color, "wrap": true }),
+            json!({ "type": "FactSet", "facts": facts }),
+        ];
+        if !alert.lines.is_empty() && !alert.resolved {
+            body.push(json!({ "type": "TextBlock", "text": alert.lines.join("\n\n"), "fontType": "Monospace",
+                "wrap": true, "separator": true }));
+        }
+        json!({
+            "type": "message",
+            "attachments": [{
+                "contentType": "application/vnd.microsoft.card.adaptive",
+                "content": {
+                    "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                    "type": "AdaptiveCard",
+                    "version": "1.4",
+                    "msteams": { "width": "Full" },
+                    "body": body,
+                },
+            }],
+        })
+    }
+}
+
+impl Notifier for TeamsNotifier {
+    fn name(&self) -> &str {
+        "teams"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        ureq::post(&self.config.webhook_url).send_json(TeamsNotifier::card(alert))?;
+        Ok(())
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.notify(alert)
+    }
+}
@@ -0,0 +1,61 @@
+use serde_json::json;
+
+use crate::config::TelegramConfig;
+use crate::notify::{Alert, Notifier, NotifyError};
+use crate::rule::truncate;
+
+/// Longest message text the Bot API accepts.
+const MAX_TEXT: usize = 4096;
+
+/// Sends alerts as messages from a Telegram bot.
+pub struct TelegramNotifier {
+    config: TelegramConfig,
+}
+
+impl TelegramNotifier {
+    pub fn new(config: TelegramConfig) -> TelegramNotifier {
+        TelegramNotifier { config }
+    }
+
+    fn send(&self, text: String) -> Result<(), NotifyError> {
+        let url = format!("{}/bot{}/sendMessage", self.config.api_url, self.config.bot_token);
+        // Leaves room for the ellipsis marking the cut.
+        let text = truncate(text, MAX_TEXT - 1);
+        ureq::post(&url).send_json(json!({
+            "chat_id": self.config.chat_id,
+            "text": text,
+            "disable_web_page_preview": true,
+        }))?;
+        Ok(())
+    }
+}
+
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> &str {
+        "telegram"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let mut text = format!(
+            "{} at {}",
+            alert.summary(), alert.timestamp.format("%Y-%m-%d %H:%M:%S %z"),
+        );
+        if alert.suppressed > 0 {
+            text.push_str(&format!(" ({} more during cooldown)", alert.suppressed));
+        }
+        text.push_str(&format!("\nHost: {}", alert.origin()));
+        if let Some(system) = &alert.system {
+            text.push_str(&format!("\nSystem: {}", system));
+        }
+        if !alert.lines.is_empty() {
+            text.push_str("\n\n");
+            text.push_str(&alert.lines.join("\n"));
+        }
+        self.send(text)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let at = alert.timestamp.format("%Y-%m-%d %H:%M:%S %z");
+        self.send(format!("{} at {}\nHost: {}", alert.summary(), at, alert.origin()))
+    }
+}
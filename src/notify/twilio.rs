@@ -0,0 +1,56 @@
+use base64::Engine;
+
+use crate::config::TwilioConfig;
+use crate::notify::{Alert, Notifier, NotifyError};
+use crate::rule::truncate;
+
+/// Longest message sent, two SMS segments.
+const MAX_BODY: usize = 300;
+
+/// Sends alerts as text messages through Twilio, one to each number.
+pub struct TwilioNotifier {
+    config: TwilioConfig,
+}
+
+impl TwilioNotifier {
+    pub fn new(config: TwilioConfig) -> TwilioNotifier {
+        TwilioNotifier { config }
+    }
+
+    fn send(&self, body: String) -> Result<(), NotifyError> {
+        let url = format!("{}/2010-04-01/Accounts/{}/Messages.json", self.config.api_url, self.config.account_sid);
+        let credentials = format!("{}:{}", self.config.account_sid, self.config.auth_token);
+        let authorization = format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(credentials));
+        let body = truncate(body, MAX_BODY);
+        for to in &self.config.to {
+            ureq::post(&url)
+                .set("Authorization", &authorization)
+                .send_form(&[("To", to), ("From", &self.config.from), ("Body", &body)])?;
+        }
+        Ok(())
+    }
+}
+
+impl Notifier for TwilioNotifier {
+    fn name(&self) -> &str {
+        "twilio"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let mut body = format!(
+            "{} ({}) at {} on {}", alert.summary(), alert.rule, alert.timestamp.format("%H:%M"), alert.origin(),
+        );
+        if let Some(line) = alert.lines.last() {
+            body.push_str(": ");
+            body.push_str(line);
+        }
+        self.send(body)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        if !self.config.send_resolved {
+            return Ok(());
+        }
+        self.send(format!("{} at {} on {}", alert.summary(), alert.timestamp.format("%H:%M"), alert.origin()))
+    }
+}
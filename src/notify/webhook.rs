@@ -0,0 +1,96 @@
+use serde_json::{Map, Value};
+
+use crate::config::WebhookConfig;
+use crate::notify::{Alert, Notifier, NotifyError};
+
+/// Sends alerts to an arbitrary HTTP endpoint with a templated JSON body.
+pub struct WebhookNotifier {
+    config: WebhookConfig,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: WebhookConfig) -> WebhookNotifier {
+        WebhookNotifier { config }
+    }
+}
+
+/// Replaces `{{name}}` placeholders in every string of `template`. Names
+/// may lead into objects, e.g. `{{fields.user}}`.
+///
+/// A string consisting of a single placeholder is replaced by the value
+/// itself, so `"{{count}}"` becomes a number and `"{{lines}}"` an array.
+pub fn render(template: &Value, vars: &Map<String, Value>) -> Value {
+    match template {
+        Value::String(s) => {
+            let trimmed = s.trim();
+            if let Some(name) = trimmed.strip_prefix("{{").and_then(|t| t.strip_suffix("}}")) {
+                if let Some(value) = lookup(vars, name.trim()) {
+                    return value.clone();
+                }
+            }
+            let mut out = String::new();
+            let mut rest = s.as_str();
+            while let Some(start) = rest.find("{{") {
+                let end = match rest[start..].find("}}") {
+                    Some(end) => start + end,
+                    None => break,
+                };
+                out.push_str(&rest[..start]);
+                match lookup(vars, rest[start + 2..end].trim()) {
+                    Some(value) => out.push_str(&text(value)),
+                    None => out.push_str(&rest[start..end + 2]),
+                }
+                rest = &rest[end + 2..];
+            }
+            out.push_str(rest);
+            Value::String(out)
+        },
+        Value::Array(items) => Value::Array(items.iter().map(|i| render(i, vars)).collect()),
+        Value::Object(fields) => Value::Object(
+            fields.iter().map(|(k, v)| (k.clone(), render(v, vars))).collect()
+        ),
+        v => v.clone(),
+    }
+}
+
+fn lookup<'a>(vars: &'a Map<String, Value>, name: &str) -> Option<&'a Value> {
+    let mut keys = name.split('.');
+    let first = vars.get(keys.next()?);
+    keys.try_fold(first?, |value, key| value.get(key))
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(v) => v.clone(),
+        Value::Array(items) => items.iter()
+            .map(|i| i.as_str().map(String::from).unwrap_or_else(|| i.to_string()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        v => v.to_string(),
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let vars = alert.variables();
+        let body = match &self.config.body {
+            Some(template) => render(template, &vars),
+            None => Value::Object(vars),
+        };
+        let mut request = ureq::request(&self.config.method, &self.config.url);
+        for (name, value) in &self.config.headers {
+            request = request.set(name, value);
+        }
+        request.send_json(body)?;
+        Ok(())
+    }
+
+    /// Sends the same request, with `status` set to `resolved`.
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.notify(alert)
+    }
+}
@@ -0,0 +1,64 @@
+use serde_json::json;
+
+use crate::config::{Severity, WeComConfig};
+use crate::notify::{robot_result, Alert, Notifier, NotifyError};
+use crate::rule::truncate;
+
+/// Longest markdown content sent, in characters; WeChat Work accepts 4096
+/// bytes.
+const MAX_CONTENT: usize = 1300;
+
+/// Sends alerts as markdown messages of a WeChat Work (企业微信) group bot.
+pub struct WeComNotifier {
+    config: WeComConfig,
+}
+
+impl WeComNotifier {
+    pub fn new(config: WeComConfig) -> WeComNotifier {
+        WeComNotifier { config }
+    }
+
+    fn send(&self, content: String) -> Result<(), NotifyError> {
+        let mut content = truncate(content, MAX_CONTENT);
+        for user in &self.config.mentioned_users {
+            content.push_str(&format!(" <@{}>", user));
+        }
+        let response = ureq::post(&self.config.webhook_url).send_json(json!({
+            "msgtype": "markdown",
+            "markdown": { "content": content },
+        }))?;
+        robot_result(response)
+    }
+}
+
+impl Notifier for WeComNotifier {
+    fn name(&self) -> &str {
+        "wecom"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let color = if alert.severity == Severity::Critical { "warning" } else { "comment" };
+        let mut content = format!(
+            "**{}**\n> Rule: <font color=\"{}\">{}</font>\n> Severity: {}\n> Time: {}\n> Host: {}\n",
+            alert.summary(), color, alert.rule, alert.severity.as_str(),
+            alert.timestamp.format("%Y-%m-%d %H:%M:%S %z"), alert.origin(),
+        );
+        if let Some(system) = &alert.system {
+            content.push_str(&format!("> System: {}\n", system));
+        }
+        if alert.suppressed > 0 {
+            content.push_str(&format!("> During cooldown: {}\n", alert.suppressed));
+        }
+        for line in &alert.lines {
+            content.push_str(&format!("\n`{}`", line.replace('`', "'")));
+        }
+        self.send(content)
+    }
+
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let at = alert.timestamp.format("%Y-%m-%d %H:%M:%S %z");
+        self.send(format!(
+            "**<font color=\"info\">{}</font>**\n> Time: {}\n> Host: {}", alert.summary(), at, alert.origin(),
+        ))
+    }
+}
@@ -0,0 +1,108 @@
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+
+use crate::config::ZabbixConfig;
+use crate::notify::webhook::render;
+use crate::notify::{hostname, Alert, Notifier, NotifyError};
+
+/// Starts every packet of the Zabbix protocol, before its flags.
+const HEADER: &[u8] = b"ZBXD";
+/// Zabbix refuses larger responses than this, and so do we.
+const MAX_RESPONSE: usize = 1 << 20;
+
+/// Submits alerts as values of a trapper item, as `zabbix_sender` does, so
+/// that Zabbix triggers and their actions take them on.
+pub struct ZabbixNotifier {
+    config: ZabbixConfig,
+}
+
+impl ZabbixNotifier {
+    pub fn new(config: ZabbixConfig) -> ZabbixNotifier {
+        ZabbixNotifier { config }
+    }
+
+    /// Submits the summary of the alert, or `value` with its placeholders
+    /// filled in.
+    fn submit(&self, alert: &Alert, value: Option<&str>) -> Result<(), NotifyError> {
+        let vars = alert.variables();
+        let text = |template: &str| match render(&Value::String(template.to_string()), &vars) {
+            Value::String(text) => text,
+            value => value.to_string(),
+        };
+        let clock = alert.timestamp.timestamp();
+        let request = json!({
+            "request": "sender data",
+            "data": [{
+                "host": self.config.host.as_deref().map_or_else(hostname, text),
+                "key": text(&self.config.key),
+                "value": value.map_or_else(|| alert.summary(), text),
+                "clock": clock,
+                "ns": alert.timestamp.timestamp_subsec_nanos(),
+            }],
+            "clock": clock,
+        });
+        let response = self.exchange(&serde_json::to_vec(&request)?)?;
+        if response["response"] != "success" {
+            let info = response["info"].as_str().unwrap_or("no reason given");
+            return Err(format!("the server refused the value: {}", info).into());
+        }
+        // The server answers success even if it had no such item.
+        let info = response["info"].as_str().unwrap_or_default();
+        if !info.contains("failed: 0") {
+            return Err(format!("the server did not take the value ({}), check host and key", info).into());
+        }
+        Ok(())
+    }
+
+    fn exchange(&self, data: &[u8]) -> Result<Map<String, Value>, NotifyError> {
+        let timeout = Some(Duration::from_secs(self.config.timeout_seconds));
+        let address = if self.config.server.contains(':') && !self.config.server.ends_with(']') {
+            self.config.server.clone()
+        } else {
+            format!("{}:10051", self.config.server)
+        };
+        let mut stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        let mut packet = HEADER.to_vec();
+        packet.push(0x01);
+        packet.extend((data.len() as u64).to_le_bytes());
+        packet.extend(data);
+        stream.write_all(&packet)?;
+        let mut header = [0; 13];
+        stream.read_exact(&mut header)?;
+        if &header[..4] != HEADER {
+            return Err("the server did not answer in the Zabbix protocol".into());
+        }
+        // The length takes 4 bytes, followed by 4 reserved ones.
+        let length = u32::from_le_bytes(header[5..9].try_into()?) as usize;
+        if length > MAX_RESPONSE || header[4] & 0x02 != 0 {
+            return Err("the server answered too much, or compressed".into());
+        }
+        let mut response = vec![0; length];
+        stream.read_exact(&mut response)?;
+        Ok(serde_json::from_slice(&response)?)
+    }
+}
+
+impl Notifier for ZabbixNotifier {
+    fn name(&self) -> &str {
+        "zabbix"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.submit(alert, self.config.value.as_deref())
+    }
+
+    /// Submits `resolved_value`, if set, for triggers to recover on.
+    fn resolve(&self, alert: &Alert) -> Result<(), NotifyError> {
+        match &self.config.resolved_value {
+            Some(value) => self.submit(alert, Some(value)),
+            None => Ok(()),
+        }
+    }
+}
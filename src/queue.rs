@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use tokio::sync::mpsc::{self, Permit};
+use tokio::sync::Mutex;
+use tokio::task::{self, JoinHandle};
+
+use crate::config::NotifyQueueConfig;
+
+/// Sending one notification through its channels.
+pub(crate) type Job = Box<dyn FnOnce() + Send>;
+
+/// Notifications waiting for the senders, tasks each sending one at a time
+/// on tokio's blocking thread pool. Once `capacity` are waiting, there is
+/// no room for more until a sender is done.
+pub(crate) struct NotifyQueue {
+    sender: mpsc::Sender<Job>,
+    senders: Vec<JoinHandle<()>>,
+}
+
+impl NotifyQueue {
+    pub fn start(config: &NotifyQueueConfig) -> NotifyQueue {
+        let (sender, receiver) = mpsc::channel::<Job>(config.capacity.max(1));
+        let receiver = Arc::new(Mutex::new(receiver));
+        let senders = (0..config.senders.max(1))
+            .map(|_| {
+                let receiver = receiver.clone();
+                tokio::spawn(async move {
+                    loop {
+                        let job = receiver.lock().await.recv().await;
+                        match job {
+                            Some(job) => {
+                                let _ = task::spawn_blocking(job).await;
+                            },
+                            None => break,
+                        }
+                    }
+                })
+            })
+            .collect();
+        NotifyQueue { sender, senders }
+    }
+
+    /// Room for one more notification, unless the queue is full.
+    pub fn slot(&self) -> Option<Permit<'_, Job>> {
+        self.sender.try_reserve().ok()
+    }
+
+    /// Queues `job`, waiting for room if need be.
+    pub async fn push(&self, job: Job) {
+        // The senders only stop once the queue is closed.
+        let _ = self.sender.send(job).await;
+    }
+
+    /// Waits for the senders to send everything queued.
+    pub async fn close(self) {
+        drop(self.sender);
+        for sender in self.senders {
+            let _ = sender.await;
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::config::RateLimitConfig;
+
+/// A token bucket limiting the alerts sent through one notifier, refilled
+/// steadily so that at most `count` alerts go out per period.
+pub(crate) struct RateLimiter {
+    config: RateLimitConfig,
+    /// Buckets by rule, or a single one under the empty key.
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    /// When `tokens` was computed, in milliseconds.
+    updated: i64,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> RateLimiter {
+        RateLimiter { config, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    /// Takes a token for an alert of `rule`, if one is left.
+    pub fn allow(&self, rule: &str, now: i64) -> bool {
+        let capacity = self.config.count as f64;
+        let period = (self.config.per_seconds.max(1) * 1000) as f64;
+        let key = if self.config.per_rule { rule } else { "" };
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket { tokens: capacity, updated: now });
+        let refilled = (now - bucket.updated).max(0) as f64 * capacity / period;
+        bucket.tokens = (bucket.tokens + refilled).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
@@ -0,0 +1,187 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, info_span, warn};
+
+use crate::config::RetryConfig;
+use crate::metrics;
+use crate::notify::{Alert, Notifier};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Fire,
+    Resolve,
+}
+
+/// A notification waiting to be sent again through one channel.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Pending {
+    pub channel: String,
+    pub kind: Kind,
+    pub alert: Alert,
+    /// Attempts made so far.
+    pub attempts: u32,
+    /// Unix time in milliseconds of the next attempt.
+    pub next_attempt: i64,
+    /// The channel that failed. Entries read back from the queue file
+    /// are matched to a notifier by name instead.
+    #[serde(skip)]
+    pub notifier: Option<Arc<dyn Notifier>>,
+}
+
+impl Pending {
+    /// Sends the notification once more.
+    pub fn send(&self, notifiers: &[Arc<dyn Notifier>]) -> Result<(), String> {
+        let notifier = self.notifier.clone()
+            .or_else(|| notifiers.iter().find(|n| n.name() == self.channel).cloned())
+            .ok_or_else(|| format!("channel {} is no longer configured", self.channel))?;
+        let result = match self.kind {
+            Kind::Fire => notifier.notify(&self.alert),
+            Kind::Resolve => notifier.resolve(&self.alert),
+        };
+        result.map_err(|e| e.to_string())
+    }
+}
+
+/// Failed notifications, retried with exponential backoff.
+pub struct RetryQueue {
+    config: Mutex<Option<RetryConfig>>,
+    pending: Mutex<Vec<Pending>>,
+}
+
+impl RetryQueue {
+    /// Creates a queue retrying according to `config`, or not at all.
+    pub fn new(config: Option<RetryConfig>) -> RetryQueue {
+        RetryQueue { config: Mutex::new(config), pending: Mutex::new(Vec::new()) }
+    }
+
+    pub fn configure(&self, config: Option<RetryConfig>) {
+        *self.config.lock().unwrap() = config;
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        self.config.lock().unwrap().as_ref()
+            .and_then(|config| config.queue_file.as_ref().map(PathBuf::from))
+    }
+
+    /// Reads back the retries left over from a previous run.
+    pub fn load(&self) -> io::Result<()> {
+        let path = match self.path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let loaded: Vec<Pending> = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(file)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        if !loaded.is_empty() {
+            info!("Loaded {} pending notifications from {}.", loaded.len(), path.display());
+        }
+        self.pending.lock().unwrap().extend(loaded);
+        Ok(())
+    }
+
+    /// Queues a notification that failed for the first time, returning it
+    /// if it is not retried.
+    pub fn push(&self, notifier: &Arc<dyn Notifier>, kind: Kind, alert: &Alert, now: i64) -> Option<Pending> {
+        let pending = Pending {
+            channel: notifier.name().to_string(),
+            kind,
+            alert: alert.clone(),
+            attempts: 1,
+            next_attempt: now,
+            notifier: Some(notifier.clone()),
+        };
+        self.reschedule(pending, now)
+    }
+
+    /// Queues `pending` again after another failure, unless it ran out of
+    /// attempts, in which case it is returned.
+    pub fn reschedule(&self, mut pending: Pending, now: i64) -> Option<Pending> {
+        let config = match self.config.lock().unwrap().clone() {
+            Some(config) => config,
+            None => return Some(pending),
+        };
+        if pending.attempts >= config.max_attempts {
+            warn!("Giving up on {} for {} after {} attempts.",
+                pending.channel, pending.alert.log_id, pending.attempts);
+            return Some(pending);
+        }
+        let delay = backoff(&config, pending.attempts);
+        pending.next_attempt = now + delay;
+        info!("Retrying {} for {} in {} ms.", pending.channel, pending.alert.log_id, delay);
+        self.pending.lock().unwrap().push(pending);
+        self.save();
+        None
+    }
+
+    /// Takes out the notifications whose next attempt is due.
+    pub fn due(&self, now: i64) -> Vec<Pending> {
+        let mut pending = self.pending.lock().unwrap();
+        if !pending.iter().any(|p| p.next_attempt <= now) {
+            return Vec::new();
+        }
+        let (due, waiting) = pending.drain(..).partition(|p| p.next_attempt <= now);
+        *pending = waiting;
+        drop(pending);
+        self.save();
+        due
+    }
+
+    /// Sends `pending` and queues it again if it fails, returning it once
+    /// it ran out of attempts.
+    pub fn retry(&self, mut pending: Pending, notifiers: &[Arc<dyn Notifier>], now: i64) -> Option<Pending> {
+        pending.attempts += 1;
+        let span = info_span!("notify", channel = %pending.channel, log = %pending.alert.log_id, rule = %pending.alert.rule);
+        let _span = span.entered();
+        match pending.send(notifiers) {
+            Ok(()) => {
+                info!("Alert for {} sent via {} after {} attempts.",
+                    pending.alert.log_id, pending.channel, pending.attempts);
+                metrics::alert_sent(&pending.channel);
+                None
+            },
+            Err(e) => {
+                warn!("Alert for {} failed to send via {}: {}", pending.alert.log_id, pending.channel, e);
+                metrics::notify_failed(&pending.channel);
+                self.reschedule(pending, now)
+            },
+        }
+    }
+
+    /// Writes the queue to the queue file, if one is configured.
+    pub fn save(&self) {
+        let path = match self.path() {
+            Some(path) => path,
+            None => return,
+        };
+        let pending = self.pending.lock().unwrap().clone();
+        if let Err(e) = save(&path, &pending) {
+            error!("Failed to save retry queue {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Doubles the delay with every attempt, capped and with up to 50% jitter
+/// so that channels recovering together are not hit at once.
+fn backoff(config: &RetryConfig, attempts: u32) -> i64 {
+    let exponent = attempts.saturating_sub(1).min(30);
+    let delay = config.initial_delay_seconds.saturating_mul(1 << exponent)
+        .min(config.max_delay_seconds)
+        .saturating_mul(1000);
+    let jitter = fastrand::u64(0..=delay / 2);
+    (delay - jitter) as i64
+}
+
+fn save(path: &Path, pending: &[Pending]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    serde_json::to_writer_pretty(File::create(&tmp)?, pending)
+        .map_err(io::Error::other)?;
+    fs::rename(tmp, path)
+}
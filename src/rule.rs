@@ -0,0 +1,976 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use chrono::{Local, TimeZone, Utc};
+use regex::RegexSet;
+use rhai::Dynamic;
+use serde_json::{json, Map, Value};
+use tracing::warn;
+
+use crate::baseline::Baseline;
+use crate::composite::Composite;
+use crate::condition::Condition;
+use crate::config::{
+    AnomalyConfig, BurstConfig, Config, DedupConfig, DistinctConfig, EscalationConfig, ExtractConfig, LogConfig,
+    RuleConfig, Severity, TimedThresholds, ValueConfig,
+};
+use crate::error::Error;
+use crate::extract::Extractor;
+use crate::fingerprint::Fingerprinter;
+use crate::matcher::Matcher;
+use crate::measure::Measure;
+use crate::notify::Alert;
+use crate::schedule::Thresholds;
+use crate::script::Script;
+use crate::state::RuleRecord;
+use crate::window::SlidingWindow;
+
+/// Most matches a distinct error count remembers, should its window not
+/// drop them soon enough.
+const MAX_ERRORS: usize = 100_000;
+
+/// Decides which log lines count as errors and when they raise an alert.
+#[derive(Clone)]
+pub struct AlertRule {
+    name: Option<String>,
+    matcher: Matcher,
+    conditions: Vec<Condition>,
+    composite: Option<Composite>,
+    script: Option<Script>,
+    measure: Option<Measure>,
+    exclude: RegexSet,
+    count_threshold: usize,
+    window: i64,
+    timed: Vec<Thresholds>,
+    cooldown: i64,
+    resolve_after: Option<i64>,
+    clear_threshold: Option<usize>,
+    absent_after: Option<i64>,
+    sample_lines: usize,
+    max_line_length: usize,
+    targets: Vec<String>,
+    severity: Severity,
+    dedup: Option<Fingerprinter>,
+    escalation: Option<EscalationConfig>,
+    burst: Option<BurstConfig>,
+    anomaly: Option<AnomalyConfig>,
+    /// The different errors needed, and how they are told apart.
+    distinct: Option<(usize, Fingerprinter)>,
+    extractor: Option<Extractor>,
+}
+
+impl AlertRule {
+    /// A rule counting lines that contain any of `keywords` or match any of
+    /// `patterns`, alerting on every match until thresholds are set.
+    pub fn new(keywords: &[String], patterns: &[String]) -> Result<AlertRule, regex::Error> {
+        Ok(AlertRule {
+            name: None,
+            matcher: Matcher::new(keywords, patterns)?,
+            conditions: Vec::new(),
+            composite: None,
+            script: None,
+            measure: None,
+            exclude: RegexSet::empty(),
+            count_threshold: 1,
+            window: 0,
+            timed: Vec::new(),
+            cooldown: 0,
+            resolve_after: None,
+            clear_threshold: None,
+            absent_after: None,
+            sample_lines: 5,
+            max_line_length: 500,
+            targets: Vec::new(),
+            severity: Severity::default(),
+            dedup: None,
+            escalation: None,
+            burst: None,
+            anomaly: None,
+            distinct: None,
+            extractor: None,
+        })
+    }
+
+    /// Builds the rules of a configured log entry: its named rules, plus
+    /// one from its own keywords and patterns if it has no named rules or
+    /// sets any of them.
+    pub fn rules_of(config: &Config, log: &LogConfig) -> Result<Vec<AlertRule>, Error> {
+        let mut rules = Vec::new();
+        if log.rules.is_empty() || !log.keywords.is_empty() || !log.patterns.is_empty() || !log.fields.is_empty() {
+            rules.push(AlertRule::from_config(config, log)?);
+        }
+        for rule in &log.rules {
+            rules.push(AlertRule::from_rule_config(config, log, rule)?);
+        }
+        Ok(rules)
+    }
+
+    /// Builds the rule from the keywords and patterns of a log entry.
+    pub fn from_config(config: &Config, log: &LogConfig) -> Result<AlertRule, Error> {
+        let (count, window) = config.thresholds(log);
+        let mut rule = AlertRule::new(&log.keywords, &log.patterns)?
+            .fields(&log.fields)?
+            .exclude(&log.exclude)?
+            .threshold(count, window)
+            .timed_thresholds(&log.thresholds, timezone(config))?
+            .cooldown_seconds(log.cooldown_seconds)
+            .sample_lines(log.sample_lines, log.max_line_length)
+            .severity(log.severity)
+            .dedup(log.dedup.as_ref())?
+            .escalation(log.escalation.as_ref())
+            .burst(log.burst.as_ref())
+            .anomaly(log.anomaly.as_ref())
+            .distinct(log.distinct.as_ref())?
+            .extract(log.extract.as_ref())?;
+        rule.resolve_after = log.resolve_after;
+        rule.clear_threshold = log.clear_threshold;
+        Ok(rule)
+    }
+
+    /// Builds a named rule of a log entry.
+    pub fn from_rule_config(config: &Config, log: &LogConfig, rule: &RuleConfig)
+        -> Result<AlertRule, Error> {
+        let (count, window) = config.thresholds(log);
+        let count = rule.count_threshold.map_or(count, |count| count.max(1) as usize);
+        let mut built = AlertRule::new(&rule.keywords, &rule.patterns)?
+            .fields(&rule.fields)?
+            .terms(&rule.terms, rule.when.as_deref())?
+            .script(rule.script.as_deref())?
+            .value(rule.value.as_ref())?
+            .exclude(&[log.exclude.as_slice(), rule.exclude.as_slice()].concat())?
+            .named(&rule.name)
+            .threshold(count, rule.time_threshold.unwrap_or(window))
+            .timed_thresholds(
+                if rule.thresholds.is_empty() { &log.thresholds } else { &rule.thresholds },
+                timezone(config),
+            )?
+            .cooldown_seconds(rule.cooldown_seconds.unwrap_or(log.cooldown_seconds))
+            .sample_lines(log.sample_lines, log.max_line_length)
+            .notify(&rule.notify)
+            .severity(rule.severity.unwrap_or(log.severity))
+            .dedup(rule.dedup.as_ref().or(log.dedup.as_ref()))?
+            .escalation(rule.escalation.as_ref().or(log.escalation.as_ref()))
+            .burst(rule.burst.as_ref().or(log.burst.as_ref()))
+            .anomaly(rule.anomaly.as_ref().or(log.anomaly.as_ref()))
+            .distinct(rule.distinct.as_ref().or(log.distinct.as_ref()))?
+            .extract(rule.extract.as_ref().or(log.extract.as_ref()))?;
+        built.resolve_after = rule.resolve_after.or(log.resolve_after);
+        built.clear_threshold = rule.clear_threshold.or(log.clear_threshold);
+        built.absent_after = rule.absent_after;
+        Ok(built)
+    }
+
+    /// Only count lines whose JSON fields pass all of `conditions`, e.g.
+    /// `level == "error"`.
+    pub fn fields(mut self, conditions: &[String]) -> Result<AlertRule, String> {
+        self.conditions = conditions.iter().map(|condition| Condition::parse(condition)).collect::<Result<_, _>>()?;
+        Ok(self)
+    }
+
+    /// Only count lines matching one of `terms`, and only alert once those
+    /// that matched within the window satisfy `when`, e.g. `refused and
+    /// exhausted`. Without terms every line may count.
+    pub fn terms(mut self, terms: &BTreeMap<String, String>, when: Option<&str>) -> Result<AlertRule, String> {
+        self.composite = if terms.is_empty() && when.is_none() {
+            None
+        } else {
+            Some(Composite::new(terms, when)?)
+        };
+        Ok(self)
+    }
+
+    /// Let the Rhai script at `path` decide about the lines the rule
+    /// matched.
+    pub fn script(mut self, path: Option<&str>) -> Result<AlertRule, String> {
+        self.script = path.map(Script::load).transpose()?;
+        Ok(self)
+    }
+
+    /// Only count lines holding a number beyond the limits of `config`, or
+    /// with `average`, alert once the average within the window is.
+    pub fn value(mut self, config: Option<&ValueConfig>) -> Result<AlertRule, String> {
+        self.measure = config.map(Measure::new).transpose()?;
+        Ok(self)
+    }
+
+    /// Never count lines matching any of `patterns`, e.g. known harmless
+    /// errors.
+    pub fn exclude(mut self, patterns: &[String]) -> Result<AlertRule, regex::Error> {
+        self.exclude = RegexSet::new(patterns)?;
+        Ok(self)
+    }
+
+    /// Marks the rule's alerts, so they go to the notifiers subscribed to
+    /// this severity.
+    pub fn severity(mut self, severity: Severity) -> AlertRule {
+        self.severity = severity;
+        self
+    }
+
+    /// Raise at most one alert about an error, told apart by the line that
+    /// raised it, per window, and report the repeats held back once the
+    /// window ends.
+    pub fn dedup(mut self, config: Option<&DedupConfig>) -> Result<AlertRule, regex::Error> {
+        self.dedup = config.map(|config| Fingerprinter::new(config.window, config.pattern.as_deref())).transpose()?;
+        Ok(self)
+    }
+
+    /// Send reminders of an alert until it resolves or is acknowledged, also
+    /// to the escalation's notifiers.
+    pub fn escalation(mut self, config: Option<&EscalationConfig>) -> AlertRule {
+        self.escalation = config.cloned();
+        self
+    }
+
+    /// Alert as soon as `count` matches fall within a window of their own,
+    /// however the thresholds are set.
+    pub fn burst(mut self, config: Option<&BurstConfig>) -> AlertRule {
+        self.burst = config.cloned();
+        self
+    }
+
+    /// Alert when the matches of a minute are well above the rate learned
+    /// so far, instead of at the count threshold.
+    pub fn anomaly(mut self, config: Option<&AnomalyConfig>) -> AlertRule {
+        self.anomaly = config.cloned();
+        self
+    }
+
+    /// Alert once `count` different errors matched within the window,
+    /// instead of at the count threshold.
+    pub fn distinct(mut self, config: Option<&DistinctConfig>) -> Result<AlertRule, regex::Error> {
+        self.distinct = match config {
+            Some(config) => Some((config.count, Fingerprinter::new(0, config.pattern.as_deref())?)),
+            None => None,
+        };
+        Ok(self)
+    }
+
+    /// Carries values of the last matching line into alerts.
+    pub fn extract(mut self, config: Option<&ExtractConfig>) -> Result<AlertRule, String> {
+        self.extractor = config.map(Extractor::new).transpose()?;
+        Ok(self)
+    }
+
+    /// Names the rule in alerts and the state file instead of its condition.
+    pub fn named(mut self, name: &str) -> AlertRule {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Sends alerts only through the notifiers with these names.
+    pub fn notify(mut self, targets: &[String]) -> AlertRule {
+        self.targets = targets.to_vec();
+        self
+    }
+
+    /// Alert once `count` matches happened within `window` milliseconds.
+    pub fn threshold(mut self, count: usize, window: i64) -> AlertRule {
+        self.count_threshold = count.max(1);
+        self.window = window;
+        self
+    }
+
+    /// Use other thresholds while they apply, the first of them that does.
+    /// Their expressions are in `timezone` unless they name their own.
+    pub fn timed_thresholds(mut self, configs: &[TimedThresholds], timezone: Option<&str>)
+        -> Result<AlertRule, String> {
+        self.timed = configs.iter().map(|config| Thresholds::new(config, timezone)).collect::<Result<_, _>>()?;
+        Ok(self)
+    }
+
+    /// The count threshold and window that apply at `now` (milliseconds).
+    fn thresholds_at(&self, now: i64) -> (usize, i64) {
+        let at = match Utc.timestamp_millis_opt(now).single() {
+            Some(at) if !self.timed.is_empty() => at,
+            _ => return (self.count_threshold, self.window),
+        };
+        match self.timed.iter().find(|timed| timed.applies(at)) {
+            Some(timed) => (timed.count.unwrap_or(self.count_threshold), timed.window.unwrap_or(self.window)),
+            None => (self.count_threshold, self.window),
+        }
+    }
+
+    /// Suppress further alerts for `seconds` after one was raised.
+    pub fn cooldown_seconds(mut self, seconds: u64) -> AlertRule {
+        self.cooldown = seconds as i64 * 1000;
+        self
+    }
+
+    /// Resolve a raised alert after `millis` milliseconds without matches.
+    pub fn resolve_after(mut self, millis: i64) -> AlertRule {
+        self.resolve_after = Some(millis);
+        self
+    }
+
+    /// Resolve a raised alert once fewer than `count` matches were within the
+    /// window for the `resolve_after` time, rather than none at all.
+    pub fn clear_threshold(mut self, count: usize) -> AlertRule {
+        self.clear_threshold = Some(count);
+        self
+    }
+
+    /// Alert when nothing matched for `millis` milliseconds instead of when
+    /// too much did.
+    pub fn absent_after(mut self, millis: i64) -> AlertRule {
+        self.absent_after = Some(millis);
+        self
+    }
+
+    /// Keep the last `count` matching lines, cut to `max_length` characters.
+    pub fn sample_lines(mut self, count: usize, max_length: usize) -> AlertRule {
+        self.sample_lines = count;
+        self.max_line_length = max_length;
+        self
+    }
+
+    /// The rule's name, or a human readable form of its condition.
+    pub fn name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        let matcher = self.matcher.describe();
+        let conditions = self.conditions.iter()
+            .map(Condition::as_str)
+            .chain(self.composite.as_ref().map(Composite::as_str))
+            .chain(self.measure.as_ref().map(Measure::as_str))
+            .collect::<Vec<_>>();
+        if conditions.is_empty() {
+            return matcher;
+        }
+        let conditions = conditions.join(" && ");
+        if matcher == "*" {
+            return conditions;
+        }
+        format!("{} && {}", matcher, conditions)
+    }
+
+    /// The severity set by `severity`.
+    pub fn level(&self) -> Severity {
+        self.severity
+    }
+
+    /// Names of the notifiers alerts go to, empty for all of them.
+    pub fn targets(&self) -> &[String] {
+        &self.targets
+    }
+
+    /// Notifiers reminders go to besides the targets.
+    pub fn escalation_targets(&self) -> &[String] {
+        self.escalation.as_ref().map_or(&[], |escalation| escalation.notify.as_slice())
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        self.matches(line, None)
+    }
+
+    /// Like `is_match`, for a line that may have been parsed into fields.
+    /// Rules testing fields never match lines without them.
+    pub fn matches(&self, line: &str, fields: Option<&Map<String, Value>>) -> bool {
+        if self.exclude.is_match(line) {
+            return false;
+        }
+        if !self.conditions.is_empty() {
+            match fields {
+                Some(fields) if self.conditions.iter().all(|condition| condition.is_match(fields)) => (),
+                _ => return false,
+            }
+        }
+        if self.composite.as_ref().is_some_and(|composite| !composite.is_match(line)) {
+            return false;
+        }
+        if let Some(measure) = &self.measure {
+            match measure.extract(line, fields) {
+                Some(value) if measure.averaged() || measure.beyond(value) => (),
+                _ => return false,
+            }
+        }
+        self.matcher.is_match(line)
+    }
+}
+
+/// What a rule decided on a check.
+pub(crate) enum Outcome {
+    Fire(Alert),
+    /// A reminder of an alert that did not resolve.
+    Escalate(Alert),
+    Resolve(Alert),
+}
+
+/// A rule together with the matches it has seen on one log.
+pub(crate) struct RuleState {
+    pub rule: AlertRule,
+    window: SlidingWindow,
+    /// When each term of the composite condition matched.
+    terms: Vec<SlidingWindow>,
+    /// What the script remembers between lines.
+    script_state: Dynamic,
+    /// Whether the script failed on the last line, so failures are only
+    /// logged once in a row.
+    script_failed: bool,
+    /// The most urgent severity the script gave since the last alert.
+    severity: Option<Severity>,
+    /// Fields the script added for the next match.
+    extra: Map<String, Value>,
+    /// The values of the lines, when their average is held to limits.
+    values: VecDeque<(i64, f64)>,
+    /// Matches within the window of the burst, if the rule has one.
+    burst: SlidingWindow,
+    burst_until: i64,
+    /// The rate of matches learned, with anomaly detection.
+    baseline: Baseline,
+    /// Fingerprints of the matches within the window, with a distinct
+    /// error count, and how often each is among them.
+    errors: VecDeque<(i64, String)>,
+    distinct: HashMap<String, usize>,
+    /// The window that applies now, with timed thresholds.
+    span: i64,
+    last_match: i64,
+    firing: bool,
+    /// Since when a firing alert is below the clear threshold.
+    clear_since: Option<i64>,
+    cooldown_until: i64,
+    suppressed: usize,
+    recent: VecDeque<String>,
+    /// Fields of the last matching line.
+    fields: Map<String, Value>,
+    /// Values extracted from the last matching line, and those of the
+    /// firing alert, which its resolution carries again.
+    extracted: BTreeMap<String, String>,
+    fired: BTreeMap<String, String>,
+    /// Fingerprint of the last matching line, with deduplication.
+    fingerprint: Option<String>,
+    /// Errors alerted recently, by fingerprint.
+    seen: HashMap<String, Seen>,
+    /// Alert held back by the schedule.
+    held: Option<Alert>,
+    /// The next reminder of the firing alert, with escalation.
+    reminder: Option<Reminder>,
+}
+
+impl RuleState {
+    pub fn new(rule: AlertRule) -> RuleState {
+        RuleState {
+            window: SlidingWindow::new(rule.window),
+            span: rule.window,
+            terms: term_windows(&rule),
+            script_state: Dynamic::from_map(rhai::Map::new()),
+            script_failed: false,
+            severity: None,
+            extra: Map::new(),
+            values: VecDeque::new(),
+            burst: SlidingWindow::new(burst_window(&rule)),
+            burst_until: 0,
+            baseline: Baseline::default(),
+            errors: VecDeque::new(),
+            distinct: HashMap::new(),
+            recent: VecDeque::with_capacity(rule.sample_lines),
+            rule,
+            // Absence is measured from the start.
+            last_match: Local::now().timestamp_millis(),
+            firing: false,
+            clear_since: None,
+            cooldown_until: 0,
+            suppressed: 0,
+            fields: Map::new(),
+            extracted: BTreeMap::new(),
+            fired: BTreeMap::new(),
+            fingerprint: None,
+            seen: HashMap::new(),
+            held: None,
+            reminder: None,
+        }
+    }
+
+    /// Replaces the rule definition, keeping the matches seen so far.
+    pub fn update(&mut self, rule: AlertRule) {
+        self.span = rule.window;
+        self.window.set_span(rule.window);
+        if rule.composite.as_ref().map(Composite::names) == self.rule.composite.as_ref().map(Composite::names) {
+            self.terms.iter_mut().for_each(|window| window.set_span(rule.window));
+        } else {
+            self.terms = term_windows(&rule);
+        }
+        self.burst.set_span(burst_window(&rule));
+        if rule.anomaly.is_none() {
+            self.baseline = Baseline::default();
+        }
+        if rule.extractor.is_none() {
+            self.extracted.clear();
+        }
+        if rule.distinct.is_none() {
+            self.errors.clear();
+            self.distinct.clear();
+        }
+        while self.recent.len() > rule.sample_lines {
+            self.recent.pop_front();
+        }
+        if !rule.measure.as_ref().is_some_and(Measure::averaged) {
+            self.values.clear();
+        }
+        self.rule = rule;
+    }
+
+    /// Lets the rule's script decide whether a line the rule matched
+    /// counts. Lines the script fails on do not.
+    pub fn accepts(&mut self, line: &str, fields: Option<&Map<String, Value>>) -> bool {
+        let script = match &self.rule.script {
+            Some(script) => script,
+            None => return true,
+        };
+        match script.check(&mut self.script_state, line, fields) {
+            Ok(verdict) => {
+                self.script_failed = false;
+                if verdict.matched {
+                    self.severity = self.severity.max(verdict.severity);
+                    self.extra = verdict.fields;
+                }
+                verdict.matched
+            },
+            Err(e) => {
+                if !self.script_failed {
+                    warn!("Script {} failed, not counting the line: {}", script.path(), e);
+                }
+                self.script_failed = true;
+                false
+            },
+        }
+    }
+
+    /// Records one match, with the matching line and its fields if there
+    /// are any.
+    pub fn record(&mut self, line: Option<String>, fields: Option<&Map<String, Value>>) {
+        if let Some(fields) = fields {
+            self.fields = fields.clone();
+        }
+        self.fields.extend(std::mem::take(&mut self.extra));
+        let now = Local::now().timestamp_millis();
+        if let (Some(measure), Some(line)) = (&self.rule.measure, &line) {
+            if let Some(value) = measure.extract(line, fields) {
+                self.fields.insert("value".to_string(), json!(value));
+                if measure.averaged() {
+                    self.values.push_back((now, value));
+                }
+            }
+        }
+        if let (Some(extractor), Some(line)) = (&self.rule.extractor, &line) {
+            self.extracted = extractor.extract(line, fields);
+        }
+        if let (Some(dedup), Some(line)) = (&self.rule.dedup, &line) {
+            self.fingerprint = Some(dedup.fingerprint(line));
+        }
+        self.last_match = now;
+        self.window.push(self.last_match);
+        if self.rule.burst.is_some() {
+            self.burst.push(self.last_match);
+        }
+        if let Some(anomaly) = &self.rule.anomaly {
+            self.baseline.push(anomaly, now);
+        }
+        if let (Some((_, fingerprinter)), Some(line)) = (&self.rule.distinct, &line) {
+            let fingerprint = fingerprinter.fingerprint(line);
+            *self.distinct.entry(fingerprint.clone()).or_insert(0) += 1;
+            self.errors.push_back((now, fingerprint));
+            if self.errors.len() > MAX_ERRORS {
+                self.forget_error();
+            }
+        }
+        if let (Some(composite), Some(line)) = (&self.rule.composite, &line) {
+            for term in composite.matching(line) {
+                self.terms[term].push(self.last_match);
+            }
+        }
+        if self.last_match < self.cooldown_until && self.rule.absent_after.is_none() {
+            self.suppressed += 1;
+        }
+        if let Some(line) = line {
+            if self.rule.sample_lines == 0 {
+                return;
+            }
+            if self.recent.len() == self.rule.sample_lines {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(truncate(line, self.rule.max_line_length));
+        }
+    }
+
+    fn alert(&self, log_id: &str, count: usize) -> Alert {
+        Alert {
+            log_id: log_id.to_string(),
+            rule: self.rule.name(),
+            severity: self.severity.unwrap_or(self.rule.severity),
+            count,
+            window: self.span.max(0),
+            suppressed: self.suppressed,
+            absent_for: None,
+            timestamp: Local::now(),
+            lines: self.recent.iter().cloned().collect(),
+            fields: self.fields.clone(),
+            extracted: self.extracted.clone(),
+            labels: BTreeMap::new(),
+            system: None,
+            digest: Vec::new(),
+            resolved: false,
+            escalation: 0,
+            since: None,
+            fingerprint: None,
+            repeats: 0,
+        }
+    }
+
+    pub fn record_state(&self) -> RuleRecord {
+        RuleRecord {
+            firing: self.firing,
+            cooldown_until: self.cooldown_until,
+            suppressed: self.suppressed,
+            baseline: self.rule.anomaly.as_ref().map(|_| self.baseline.clone()),
+        }
+    }
+
+    pub fn restore(&mut self, record: &RuleRecord) {
+        self.firing = record.firing;
+        self.cooldown_until = record.cooldown_until;
+        self.suppressed = record.suppressed;
+        if let (Some(_), Some(baseline)) = (&self.rule.anomaly, &record.baseline) {
+            self.baseline = baseline.clone();
+        }
+        // Lets a restored alert resolve if nothing matches after the restart.
+        self.last_match = Local::now().timestamp_millis();
+    }
+
+    /// Returns alerts for matches suppressed by the cooldown and repeats
+    /// held back that were never reported, e.g. when shutting down.
+    pub fn flush(&mut self, log_id: &str) -> Vec<Alert> {
+        let mut alerts: Vec<Alert> = self.seen.drain().filter_map(|(_, seen)| seen.held).collect();
+        if self.suppressed > 0 {
+            alerts.push(self.alert(log_id, self.suppressed));
+            self.suppressed = 0;
+            self.recent.clear();
+        }
+        alerts
+    }
+
+    /// Keeps `alert` back to be sent later, merged with the alerts held
+    /// before it.
+    pub fn hold(&mut self, alert: Alert) {
+        merge(&mut self.held, alert, self.rule.sample_lines);
+    }
+
+    /// Holds back `alert` if an alert about the same error was raised within
+    /// the deduplication window.
+    fn deduplicate(&mut self, mut alert: Alert, now: i64) -> Option<Alert> {
+        let window = match &self.rule.dedup {
+            Some(dedup) => dedup.window,
+            None => return Some(alert),
+        };
+        let fingerprint = self.fingerprint.take().unwrap_or_default();
+        alert.fingerprint = Some(fingerprint.clone());
+        match self.seen.get_mut(&fingerprint) {
+            Some(seen) if now < seen.until => {
+                alert.repeats = alert.count;
+                // Left to the next alert sent, which resets them.
+                alert.suppressed = 0;
+                merge(&mut seen.held, alert, self.rule.sample_lines);
+                None
+            },
+            _ => {
+                self.seen.insert(fingerprint, Seen { until: now + window, held: None });
+                Some(alert)
+            },
+        }
+    }
+
+    /// Reports the repeats of an error whose deduplication window ended, and
+    /// forgets errors that did not repeat.
+    fn report_repeats(&mut self, now: i64) -> Option<Alert> {
+        let window = self.rule.dedup.as_ref()?.window;
+        self.seen.retain(|_, seen| now < seen.until || seen.held.is_some());
+        let seen = self.seen.values_mut().find(|seen| now >= seen.until)?;
+        seen.until = now + window;
+        let mut alert = seen.held.take()?;
+        alert.timestamp = Local::now();
+        Some(alert)
+    }
+
+    /// Takes out the alert held back so far.
+    pub fn release(&mut self) -> Option<Alert> {
+        self.held.take()
+    }
+
+    /// Checks the thresholds at `now` (milliseconds).
+    pub fn check(&mut self, log_id: &str, now: i64) -> Option<Outcome> {
+        if let Some(alert) = self.remind(now) {
+            return Some(Outcome::Escalate(alert));
+        }
+        if let Some(absent_after) = self.rule.absent_after {
+            return self.check_absence(log_id, now, absent_after);
+        }
+        if let Some(alert) = self.report_repeats(now) {
+            return Some(Outcome::Fire(alert));
+        }
+        if let Some(alert) = self.check_burst(log_id, now) {
+            return Some(Outcome::Fire(alert));
+        }
+        let threshold = self.apply_thresholds(now);
+        let count = self.window.count(now);
+        let average = self.average(now);
+        let beyond = match (&self.rule.measure, average) {
+            (Some(measure), Some(average)) => measure.beyond(average),
+            _ => true,
+        };
+        let distinct = self.distinct_errors(now);
+        let exceeded = if let Some(anomaly) = &self.rule.anomaly {
+            self.baseline.exceeded(anomaly, now)
+        } else if let Some((needed, _)) = &self.rule.distinct {
+            distinct >= *needed
+        } else {
+            count >= threshold
+        };
+        if exceeded && self.terms_hold(now) && beyond {
+            if now < self.cooldown_until {
+                return None;
+            }
+            let mut alert = self.alert(log_id, count);
+            if let Some(average) = average {
+                alert.fields.insert("average".to_string(), json!(average));
+            }
+            if let Some(anomaly) = &self.rule.anomaly {
+                self.baseline.report();
+                alert.count = self.baseline.rate();
+                alert.window = 60_000;
+                alert.fields.insert("baseline".to_string(), json!(self.baseline.mean));
+                alert.fields.insert("limit".to_string(), json!(self.baseline.limit(anomaly)));
+            }
+            if self.rule.distinct.is_some() {
+                // The most frequent errors first.
+                let mut errors: Vec<_> = self.distinct.drain().collect();
+                errors.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+                let errors: Vec<String> = errors.into_iter().map(|(error, _)| error).collect();
+                alert.fields.insert("distinct".to_string(), json!(distinct));
+                alert.fields.insert("errors".to_string(), json!(errors));
+                self.errors.clear();
+            }
+            // The matches are counted by the alert even when it is held back
+            // as a repeat, which leaves the alarm as it is.
+            let alert = self.deduplicate(alert, now);
+            self.window.clear();
+            self.values.clear();
+            self.terms.iter_mut().for_each(SlidingWindow::clear);
+            self.severity = None;
+            self.recent.clear();
+            let alert = alert?;
+            self.firing = true;
+            self.fired = alert.extracted.clone();
+            self.clear_since = None;
+            self.suppressed = 0;
+            self.cooldown_until = now + self.rule.cooldown;
+            self.schedule_reminder(&alert, now);
+            return Some(Outcome::Fire(alert));
+        }
+        let resolve_after = self.rule.resolve_after.filter(|_| self.firing)?;
+        let clear_since = match self.rule.clear_threshold {
+            Some(threshold) if count >= threshold => {
+                self.clear_since = None;
+                return None;
+            },
+            Some(_) => *self.clear_since.get_or_insert(now),
+            None => self.last_match,
+        };
+        if now - clear_since < resolve_after {
+            return None;
+        }
+        self.firing = false;
+        self.clear_since = None;
+        Some(Outcome::Resolve(self.resolution(log_id, count)))
+    }
+
+    /// An alert about a burst of matches, which leaves the thresholds and
+    /// the firing alarm alone.
+    fn check_burst(&mut self, log_id: &str, now: i64) -> Option<Alert> {
+        let burst = self.rule.burst.as_ref()?;
+        let count = self.burst.count(now);
+        if count < burst.count || now < self.burst_until {
+            return None;
+        }
+        self.burst_until = now + burst.cooldown_seconds as i64 * 1000;
+        let mut alert = self.alert(log_id, count);
+        alert.rule = format!("{} (burst)", alert.rule);
+        alert.window = burst.window;
+        alert.lines.drain(..alert.lines.len().saturating_sub(count));
+        self.burst.clear();
+        Some(alert)
+    }
+
+    /// Moves the windows to the thresholds that apply at `now`, returning
+    /// the count threshold.
+    fn apply_thresholds(&mut self, now: i64) -> usize {
+        let (threshold, span) = self.rule.thresholds_at(now);
+        if span != self.span {
+            self.span = span;
+            self.window.set_span(span);
+            self.terms.iter_mut().for_each(|window| window.set_span(span));
+        }
+        threshold
+    }
+
+    /// The number of different errors within the window.
+    fn distinct_errors(&mut self, now: i64) -> usize {
+        let span = self.span;
+        while self.errors.front().is_some_and(|(at, _)| span > 0 && now - at >= span) {
+            self.forget_error();
+        }
+        self.distinct.len()
+    }
+
+    /// Drops the oldest match from the distinct error count.
+    fn forget_error(&mut self) {
+        if let Some((_, fingerprint)) = self.errors.pop_front() {
+            if let Some(count) = self.distinct.get_mut(&fingerprint) {
+                *count -= 1;
+                if *count == 0 {
+                    self.distinct.remove(&fingerprint);
+                }
+            }
+        }
+    }
+
+    /// The average of the values within the window, for rules holding it
+    /// to limits.
+    fn average(&mut self, now: i64) -> Option<f64> {
+        let window = self.span;
+        while self.values.front().is_some_and(|(at, _)| window > 0 && now - at >= window) {
+            self.values.pop_front();
+        }
+        if self.values.is_empty() {
+            return None;
+        }
+        Some(self.values.iter().map(|(_, value)| value).sum::<f64>() / self.values.len() as f64)
+    }
+
+    /// Whether the terms that matched within the window satisfy the
+    /// composite condition, if there is one.
+    fn terms_hold(&mut self, now: i64) -> bool {
+        let composite = match &self.rule.composite {
+            Some(composite) => composite,
+            None => return true,
+        };
+        let present: Vec<bool> = self.terms.iter_mut().map(|window| window.count(now) > 0).collect();
+        composite.holds(&|term| present[term])
+    }
+
+    /// An alert telling that the alarm cleared, which ends the reminders.
+    fn resolution(&mut self, log_id: &str, count: usize) -> Alert {
+        self.reminder = None;
+        let mut alert = self.alert(log_id, count);
+        alert.extracted = std::mem::take(&mut self.fired);
+        alert.resolved = true;
+        alert
+    }
+
+    /// Stops the reminders of the firing alert, returning whether there
+    /// were any left.
+    pub fn acknowledge(&mut self) -> bool {
+        self.reminder.take().is_some()
+    }
+
+    fn schedule_reminder(&mut self, alert: &Alert, now: i64) {
+        self.reminder = self.rule.escalation.as_ref().map(|escalation| Reminder {
+            due: now + escalation.after_seconds as i64 * 1000,
+            sent: 0,
+            alert: alert.clone(),
+        });
+    }
+
+    /// Returns a reminder of the firing alert, once it is due.
+    fn remind(&mut self, now: i64) -> Option<Alert> {
+        let escalation = self.rule.escalation.as_ref()?;
+        let reminder = self.reminder.as_mut()?;
+        if now < reminder.due {
+            return None;
+        }
+        reminder.sent += 1;
+        reminder.due = now + escalation.after_seconds as i64 * 1000;
+        let mut alert = reminder.alert.clone();
+        alert.escalation = reminder.sent;
+        alert.since = Some(alert.timestamp);
+        alert.timestamp = Local::now();
+        if reminder.sent >= escalation.times {
+            self.reminder = None;
+        }
+        Some(alert)
+    }
+
+    fn check_absence(&mut self, log_id: &str, now: i64, absent_after: i64) -> Option<Outcome> {
+        let silent = now - self.last_match >= absent_after;
+        if silent && !self.firing && now >= self.cooldown_until {
+            self.firing = true;
+            self.cooldown_until = now + self.rule.cooldown;
+            let mut alert = self.alert(log_id, 0);
+            alert.absent_for = Some(now - self.last_match);
+            self.fired = alert.extracted.clone();
+            self.schedule_reminder(&alert, now);
+            return Some(Outcome::Fire(alert));
+        }
+        if !silent && self.firing {
+            self.firing = false;
+            return Some(Outcome::Resolve(self.resolution(log_id, 0)));
+        }
+        None
+    }
+}
+
+/// The time zone of the schedule, which timed thresholds default to.
+fn timezone(config: &Config) -> Option<&str> {
+    config.schedule.as_ref().and_then(|schedule| schedule.timezone.as_deref())
+}
+
+fn term_windows(rule: &AlertRule) -> Vec<SlidingWindow> {
+    let count = rule.composite.as_ref().map_or(0, |composite| composite.names().len());
+    (0..count).map(|_| SlidingWindow::new(rule.window)).collect()
+}
+
+/// The span of the burst window of `rule`, 0 when it has none.
+fn burst_window(rule: &AlertRule) -> i64 {
+    rule.burst.as_ref().map_or(0, |burst| burst.window)
+}
+
+/// A reminder of a firing alert.
+struct Reminder {
+    /// When it is sent, in milliseconds.
+    due: i64,
+    /// Reminders sent so far.
+    sent: u32,
+    alert: Alert,
+}
+
+/// An error alerted recently.
+struct Seen {
+    /// End of the deduplication window, in milliseconds.
+    until: i64,
+    /// Repeats held back since.
+    held: Option<Alert>,
+}
+
+/// Merges `alert` into the alert in `held`, keeping the most recent
+/// `sample_lines` lines.
+fn merge(held: &mut Option<Alert>, alert: Alert, sample_lines: usize) {
+    match held.as_mut() {
+        Some(held) => {
+            held.count += alert.count;
+            held.suppressed += alert.suppressed;
+            held.repeats += alert.repeats;
+            held.lines.extend(alert.lines);
+            let excess = held.lines.len().saturating_sub(sample_lines);
+            held.lines.drain(..excess);
+        },
+        None => *held = Some(alert),
+    }
+}
+
+/// Shortens `line` to at most `max` characters, marking the cut.
+pub(crate) fn truncate(mut line: String, max: usize) -> String {
+    if let Some((index, _)) = line.char_indices().nth(max) {
+        line.truncate(index);
+        line.push('…');
+    }
+    line
+}
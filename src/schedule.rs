@@ -0,0 +1,150 @@
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+
+use crate::config::{QuietAction, QuietScope, ScheduleConfig, TimedThresholds};
+use crate::cron::Cron;
+
+struct QuietHours {
+    days: Vec<Weekday>,
+    from: NaiveTime,
+    to: NaiveTime,
+    scope: QuietScope,
+}
+
+impl QuietHours {
+    fn on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    fn contains(&self, at: NaiveDateTime) -> bool {
+        let (day, time) = (at.weekday(), at.time());
+        if self.from < self.to {
+            self.on(day) && self.from <= time && time < self.to
+        } else if self.from > self.to {
+            (self.on(day) && time >= self.from) || (self.on(day.pred()) && time < self.to)
+        } else {
+            self.on(day)
+        }
+    }
+}
+
+struct Maintenance {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    scope: QuietScope,
+}
+
+/// Quiet hours and maintenance windows during which alerts are held back.
+#[derive(Default)]
+pub struct Schedule {
+    timezone: Option<Tz>,
+    quiet_hours: Vec<QuietHours>,
+    maintenance: Vec<Maintenance>,
+}
+
+impl Schedule {
+    pub fn new(config: Option<&ScheduleConfig>) -> Result<Schedule, String> {
+        let config = match config {
+            Some(config) => config,
+            None => return Ok(Schedule::default()),
+        };
+        let mut schedule = Schedule::default();
+        if let Some(name) = &config.timezone {
+            schedule.timezone = Some(name.parse().map_err(|_| format!("unknown time zone {}", name))?);
+        }
+        for quiet in &config.quiet_hours {
+            let days = quiet.days.iter()
+                .map(|day| day.parse().map_err(|_| format!("invalid weekday {}", day)))
+                .collect::<Result<_, String>>()?;
+            schedule.quiet_hours.push(QuietHours {
+                days,
+                from: parse_time(&quiet.from)?,
+                to: parse_time(&quiet.to)?,
+                scope: quiet.scope.clone(),
+            });
+        }
+        for window in &config.maintenance {
+            schedule.maintenance.push(Maintenance {
+                start: schedule.parse_datetime(&window.start)?,
+                end: schedule.parse_datetime(&window.end)?,
+                scope: window.scope.clone(),
+            });
+        }
+        Ok(schedule)
+    }
+
+    fn parse_datetime(&self, text: &str) -> Result<DateTime<Utc>, String> {
+        if let Ok(at) = DateTime::parse_from_rfc3339(text) {
+            return Ok(at.with_timezone(&Utc));
+        }
+        let naive = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M")
+            .map_err(|_| format!("invalid date {}, expected YYYY-MM-DD HH:MM", text))?;
+        let at = match self.timezone {
+            Some(tz) => tz.from_local_datetime(&naive).earliest().map(|at| at.with_timezone(&Utc)),
+            None => Local.from_local_datetime(&naive).earliest().map(|at| at.with_timezone(&Utc)),
+        };
+        at.ok_or_else(|| format!("{} does not exist in the time zone", text))
+    }
+
+    /// What to do with an alert of `rule` on `log_id` raised at `at`, or
+    /// `None` to send it. Suppression wins over digests.
+    pub fn quiet(&self, log_id: &str, rule: &str, at: DateTime<Utc>) -> Option<QuietAction> {
+        let local = match self.timezone {
+            Some(tz) => at.with_timezone(&tz).naive_local(),
+            None => at.with_timezone(&Local).naive_local(),
+        };
+        let quiet = self.quiet_hours.iter()
+            .filter(|quiet| quiet.contains(local))
+            .map(|quiet| &quiet.scope);
+        let maintenance = self.maintenance.iter()
+            .filter(|window| window.start <= at && at < window.end)
+            .map(|window| &window.scope);
+        quiet.chain(maintenance)
+            .filter(|scope| applies(scope, log_id, rule))
+            .map(|scope| scope.action)
+            .min_by_key(|action| *action != QuietAction::Suppress)
+    }
+}
+
+/// Thresholds of a rule for the times a cron expression covers.
+#[derive(Clone)]
+pub struct Thresholds {
+    cron: Cron,
+    timezone: Option<Tz>,
+    pub count: Option<usize>,
+    pub window: Option<i64>,
+}
+
+impl Thresholds {
+    /// Compiles `config`, whose expression is in `timezone` unless it names
+    /// its own.
+    pub fn new(config: &TimedThresholds, timezone: Option<&str>) -> Result<Thresholds, String> {
+        let timezone = config.timezone.as_deref().or(timezone)
+            .map(|name| name.parse().map_err(|_| format!("unknown time zone {}", name)))
+            .transpose()?;
+        Ok(Thresholds {
+            cron: Cron::parse(&config.when)?,
+            timezone,
+            count: config.count_threshold.map(|count| count.max(1) as usize),
+            window: config.time_threshold,
+        })
+    }
+
+    pub fn applies(&self, at: DateTime<Utc>) -> bool {
+        let local = match self.timezone {
+            Some(tz) => at.with_timezone(&tz).naive_local(),
+            None => at.with_timezone(&Local).naive_local(),
+        };
+        self.cron.contains(local)
+    }
+}
+
+fn applies(scope: &QuietScope, log_id: &str, rule: &str) -> bool {
+    (scope.logs.is_empty() || scope.logs.iter().any(|log| log == log_id))
+        && (scope.rules.is_empty() || scope.rules.iter().any(|name| name == rule))
+}
+
+fn parse_time(text: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(text, "%H:%M")
+        .map_err(|_| format!("invalid time {}, expected HH:MM", text))
+}
@@ -0,0 +1,92 @@
+use std::fs;
+use std::sync::Arc;
+
+use rhai::{CallFnOptions, Dynamic, Engine, Scope, AST};
+use serde_json::{Map, Value};
+
+use crate::config::Severity;
+
+/// Operations a call of a script may take at most, so a runaway loop does
+/// not stall the watcher.
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// A Rhai script that decides about the lines a rule matched, through its
+/// function `check(line, fields)`.
+#[derive(Clone)]
+pub struct Script {
+    path: String,
+    engine: Arc<Engine>,
+    ast: Arc<AST>,
+}
+
+/// What a script decided about a line.
+pub struct Verdict {
+    pub matched: bool,
+    pub severity: Option<Severity>,
+    /// Added to the fields of the alert.
+    pub fields: Map<String, Value>,
+}
+
+impl Script {
+    pub fn load(path: &str) -> Result<Script, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine.compile(&source).map_err(|e| format!("{} in {}", e, path))?;
+        if !ast.iter_functions().any(|function| function.name == "check" && function.params.len() == 2) {
+            return Err(format!("{} defines no function check(line, fields)", path));
+        }
+        Ok(Script { path: path.to_string(), engine: Arc::new(engine), ast: Arc::new(ast) })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Calls `check` on `line` and its fields, empty for plain lines. The
+    /// function sees `state` as `this`, so it can remember things from one
+    /// line to the next.
+    ///
+    /// `check` returns whether the line counts, nothing if it does not, or
+    /// a map of `matched`, `severity` and `fields`.
+    pub fn check(&self, state: &mut Dynamic, line: &str, fields: Option<&Map<String, Value>>)
+        -> Result<Verdict, String> {
+        let fields = match fields {
+            Some(fields) => rhai::serde::to_dynamic(fields).map_err(|e| e.to_string())?,
+            None => Dynamic::from_map(rhai::Map::new()),
+        };
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(state);
+        let result = self.engine
+            .call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &self.ast, "check", (line.to_string(), fields))
+            .map_err(|e| e.to_string())?;
+        verdict(result)
+    }
+}
+
+fn verdict(result: Dynamic) -> Result<Verdict, String> {
+    let mut verdict = Verdict { matched: false, severity: None, fields: Map::new() };
+    if result.is_unit() {
+        return Ok(verdict);
+    }
+    if let Ok(matched) = result.as_bool() {
+        verdict.matched = matched;
+        return Ok(verdict);
+    }
+    let type_name = result.type_name();
+    let map = result.try_cast::<rhai::Map>()
+        .ok_or_else(|| format!("check returned a {}, not a bool or a map", type_name))?;
+    verdict.matched = match map.get("matched") {
+        Some(matched) => matched.as_bool().map_err(|found| format!("matched is a {}, not a bool", found))?,
+        None => true,
+    };
+    if let Some(severity) = map.get("severity") {
+        let name = severity.clone().into_string().map_err(|found| format!("severity is a {}, not a string", found))?;
+        let severity = serde_json::from_value(Value::String(name.clone()))
+            .map_err(|_| format!("{} is not a severity", name))?;
+        verdict.severity = Some(severity);
+    }
+    if let Some(fields) = map.get("fields") {
+        verdict.fields = rhai::serde::from_dynamic(fields).map_err(|e| format!("fields are no map: {}", e))?;
+    }
+    Ok(verdict)
+}
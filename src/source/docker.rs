@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{Map, Value};
+use tokio::io::{AsyncReadExt, BufReader};
+use tokio::net::UnixStream;
+use tokio::task::JoinSet;
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+use super::{http, Lines, Sender};
+use crate::config::DockerConfig;
+
+/// How often the list of containers is fetched again.
+const REFRESH: Duration = Duration::from_secs(10);
+
+struct Container {
+    id: String,
+    name: String,
+}
+
+/// Follows the output of every matching container, picking up containers
+/// that start later.
+pub async fn follow(log_id: String, config: DockerConfig, sender: Sender) {
+    let mut followed = HashSet::new();
+    let mut tasks = JoinSet::new();
+    // Containers that show up later are read from the previous listing on.
+    let mut since = None;
+    loop {
+        let listed = timestamp();
+        match containers(&config).await {
+            Ok(found) => {
+                for container in found {
+                    if followed.insert(container.id.clone()) {
+                        let from = since.as_ref().unwrap_or(&listed).clone();
+                        tasks.spawn(follow_container(config.socket.clone(), container, from, log_id.clone(), sender.clone()));
+                    }
+                }
+                since = Some(listed);
+            },
+            Err(e) => warn!("Failed to list Docker containers for {}: {}", log_id, e),
+        }
+        let refresh = sleep(REFRESH);
+        tokio::pin!(refresh);
+        loop {
+            tokio::select! {
+                _ = &mut refresh => break,
+                Some(Ok(id)) = tasks.join_next() => {
+                    followed.remove(&id);
+                },
+            }
+        }
+        if sender.is_closed() {
+            return;
+        }
+    }
+}
+
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("{}.{:09}", now.as_secs(), now.subsec_nanos())
+}
+
+async fn containers(config: &DockerConfig) -> io::Result<Vec<Container>> {
+    let mut filters = Map::new();
+    if !config.names.is_empty() {
+        filters.insert("name".to_string(), config.names.clone().into());
+    }
+    if !config.labels.is_empty() {
+        filters.insert("label".to_string(), config.labels.clone().into());
+    }
+    let path = format!("/containers/json?filters={}", http::encode(&Value::Object(filters).to_string()));
+    let list = get(&config.socket, &path).await?;
+    let containers = list.as_array().map(Vec::as_slice).unwrap_or_default().iter()
+        .filter_map(|container| {
+            let id = container["Id"].as_str()?;
+            let name = container["Names"][0].as_str().map_or(id, |name| name.trim_start_matches('/'));
+            Some(Container { id: id.to_string(), name: name.to_string() })
+        })
+        .collect();
+    Ok(containers)
+}
+
+/// Sends what the container writes after `since`, and returns its id once
+/// it stopped.
+async fn follow_container(socket: String, container: Container, since: String, log_id: String, sender: Sender) -> String {
+    info!("Following Docker container {} ({})", container.name, log_id);
+    if let Err(e) = read(&socket, &container, &since, &log_id, &sender).await {
+        warn!("Failed to read the output of Docker container {}: {}", container.name, e);
+    }
+    container.id
+}
+
+async fn read(socket: &str, container: &Container, since: &str, log_id: &str, sender: &Sender) -> io::Result<()> {
+    let info = get(socket, &format!("/containers/{}/json", container.id)).await?;
+    let tty = info["Config"]["Tty"].as_bool().unwrap_or(false);
+    let path = format!("/containers/{}/logs?follow=true&stdout=true&stderr=true&since={}", container.id, since);
+    let mut body = request(socket, &path).await?;
+    // Incomplete last lines of stdout and stderr.
+    let mut pending = [Vec::new(), Vec::new()];
+    loop {
+        // Without a terminal both streams come in frames with an 8 byte header.
+        let (stream, payload) = if tty {
+            let mut buffer = vec![0; 8192];
+            let read = body.read(&mut buffer).await?;
+            buffer.truncate(read);
+            (0, buffer)
+        } else {
+            let mut header = [0; 8];
+            match body.read_exact(&mut header).await {
+                Ok(_) => (),
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            }
+            let mut payload = vec![0; u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize];
+            body.read_exact(&mut payload).await?;
+            (if header[0] == 2 { 1 } else { 0 }, payload)
+        };
+        if payload.is_empty() {
+            return Ok(());
+        }
+        let pending = &mut pending[stream];
+        pending.extend(payload);
+        let mut lines = Vec::new();
+        while let Some(end) = pending.iter().position(|byte| *byte == b'\n') {
+            let line = pending.drain(..=end).collect::<Vec<u8>>();
+            lines.push(String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).to_string());
+        }
+        if lines.is_empty() {
+            continue;
+        }
+        let lines = Lines {
+            log_id: log_id.to_string(),
+            instance: Some(container.name.clone()),
+            origin: container.name.clone(),
+            lines,
+        };
+        if sender.send(lines).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+async fn request(socket: &str, path: &str) -> io::Result<BufReader<UnixStream>> {
+    http::get(UnixStream::connect(socket).await?, "Docker API", path, "Host: docker\r\n").await
+}
+
+async fn get(socket: &str, path: &str) -> io::Result<Value> {
+    http::json(request(socket, path).await?).await
+}
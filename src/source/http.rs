@@ -0,0 +1,54 @@
+//! Just enough HTTP/1.0 to talk to the APIs sources read from.
+//!
+//! HTTP/1.0 keeps servers from chunking a response, which then simply ends
+//! when the connection is closed.
+
+use std::io;
+
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Sends a GET request on `stream` and returns the response body, or the
+/// error reported by `api`.
+pub async fn get<S>(mut stream: S, api: &str, path: &str, headers: &str) -> io::Result<BufReader<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(format!("GET {} HTTP/1.0\r\n{}\r\n", path, headers).as_bytes()).await?;
+    let mut body = BufReader::new(stream);
+    let mut status = String::new();
+    body.read_line(&mut status).await?;
+    loop {
+        let mut header = String::new();
+        if body.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+    let code = status.split_whitespace().nth(1).unwrap_or_default().to_string();
+    if code != "200" {
+        let mut message = String::new();
+        body.read_to_string(&mut message).await?;
+        let message = serde_json::from_str::<Value>(&message).ok()
+            .and_then(|error| error["message"].as_str().map(String::from))
+            .unwrap_or(message);
+        return Err(io::Error::other(format!("{} returned {}: {}", api, code, message.trim())));
+    }
+    Ok(body)
+}
+
+/// Reads a whole JSON response body.
+pub async fn json<S: AsyncRead + Unpin>(mut body: BufReader<S>) -> io::Result<Value> {
+    let mut bytes = Vec::new();
+    body.read_to_end(&mut bytes).await?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Percent-encodes `text` for a query string.
+pub fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
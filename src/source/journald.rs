@@ -0,0 +1,85 @@
+use std::io;
+use std::process::{ExitStatus, Stdio};
+
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::time::sleep;
+use tracing::warn;
+
+use super::{severity, Lines, Sender, RESTART_DELAY};
+use crate::config::JournalConfig;
+
+pub fn validate(config: &JournalConfig) -> io::Result<()> {
+    config.priority.as_deref().map(severity).transpose()?;
+    Ok(())
+}
+
+/// Sends the messages of new journal entries, restarting `journalctl`
+/// whenever it stops.
+pub async fn follow(log_id: String, config: JournalConfig, sender: Sender) {
+    loop {
+        match read(&log_id, &config, &sender).await {
+            Ok(status) => warn!("journalctl for {} exited with {}", log_id, status),
+            Err(e) => warn!("Failed to read the journal for {}: {}", log_id, e),
+        }
+        if sender.is_closed() {
+            return;
+        }
+        sleep(RESTART_DELAY).await;
+    }
+}
+
+async fn read(log_id: &str, config: &JournalConfig, sender: &Sender) -> io::Result<ExitStatus> {
+    let mut command = Command::new("journalctl");
+    command.args(["--follow", "--lines=0", "--output=json"]);
+    for unit in &config.units {
+        command.arg("--unit").arg(unit);
+    }
+    for identifier in &config.identifiers {
+        command.arg("--identifier").arg(identifier);
+    }
+    if let Some(priority) = &config.priority {
+        command.arg("--priority").arg(priority);
+    }
+    let mut child = command.stdout(Stdio::piped()).kill_on_drop(true).spawn()?;
+    let stdout = child.stdout.take().ok_or_else(|| io::Error::other("journalctl has no standard output"))?;
+    let mut entries = BufReader::new(stdout).lines();
+    while let Some(entry) = entries.next_line().await? {
+        let entry: Value = match serde_json::from_str(&entry) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        let message = match message(&entry) {
+            Some(message) => message,
+            None => continue,
+        };
+        let origin = entry["_SYSTEMD_UNIT"].as_str()
+            .or_else(|| entry["SYSLOG_IDENTIFIER"].as_str())
+            .unwrap_or("journal");
+        let lines = Lines {
+            log_id: log_id.to_string(),
+            instance: None,
+            origin: origin.to_string(),
+            lines: message.lines().map(String::from).collect(),
+        };
+        if sender.send(lines).is_err() {
+            let _ = child.start_kill();
+            break;
+        }
+    }
+    child.wait().await
+}
+
+/// The message of an entry, which the journal exports as an array of bytes
+/// when it is not valid UTF-8.
+fn message(entry: &Value) -> Option<String> {
+    match &entry["MESSAGE"] {
+        Value::String(message) => Some(message.clone()),
+        Value::Array(bytes) => {
+            let bytes = bytes.iter().map(|byte| byte.as_u64().map(|byte| byte as u8)).collect::<Option<Vec<u8>>>()?;
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,75 @@
+//! Sources of log lines other than files.
+
+use std::io;
+use std::time::Duration;
+
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+
+use crate::config::LogConfig;
+
+#[cfg(unix)]
+mod docker;
+mod http;
+mod journald;
+mod kubernetes;
+mod stdin;
+mod syslog;
+
+/// How long a source that stopped waits before starting again.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Lines read by a source for the log with `log_id`.
+pub struct Lines {
+    pub log_id: String,
+    /// Set by sources that treat each of their streams, e.g. containers, as
+    /// a log of its own, with the id `<log_id>/<instance>`.
+    pub instance: Option<String>,
+    /// Where the lines come from, e.g. a unit, for messages.
+    pub origin: String,
+    pub lines: Vec<String>,
+}
+
+pub type Sender = UnboundedSender<Lines>;
+
+/// Syslog severities, from the most important.
+const SEVERITIES: [&str; 8] = ["emerg", "alert", "crit", "err", "warning", "notice", "info", "debug"];
+
+/// The number of a severity given by name.
+pub(crate) fn severity(name: &str) -> io::Result<u8> {
+    match SEVERITIES.iter().position(|severity| *severity == name) {
+        Some(severity) => Ok(severity as u8),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown severity {}, expected one of {}", name, SEVERITIES.join(", ")),
+        )),
+    }
+}
+
+/// Starts reading the source of `log`, unless it reads files. The source
+/// stops when the task is aborted.
+pub fn spawn(log: &LogConfig, sender: &Sender) -> io::Result<Option<JoinHandle<()>>> {
+    if let Some(journal) = &log.journal {
+        journald::validate(journal)?;
+        let task = journald::follow(log.id.clone(), journal.clone(), sender.clone());
+        return Ok(Some(tokio::spawn(task)));
+    }
+    if let Some(docker) = &log.docker {
+        #[cfg(unix)]
+        return Ok(Some(tokio::spawn(docker::follow(log.id.clone(), docker.clone(), sender.clone()))));
+        #[cfg(not(unix))]
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "the Docker source is only supported on Unix"));
+    }
+    if let Some(kubernetes) = &log.kubernetes {
+        let cluster = kubernetes::connect(kubernetes)?;
+        return Ok(Some(tokio::spawn(kubernetes::follow(log.id.clone(), kubernetes.clone(), cluster, sender.clone()))));
+    }
+    if let Some(syslog) = &log.syslog {
+        let task = syslog::listen(log.id.clone(), syslog, sender.clone())?;
+        return Ok(Some(tokio::spawn(task)));
+    }
+    if log.stdin {
+        return Ok(Some(tokio::spawn(stdin::read(log.id.clone(), log.text_encoding(), sender.clone()))));
+    }
+    Ok(None)
+}
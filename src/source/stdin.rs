@@ -0,0 +1,49 @@
+use std::io::{self, BufRead};
+use std::thread;
+
+use encoding_rs::Encoding;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use super::{Lines, Sender};
+use crate::tail::decode;
+
+/// Sends the lines of standard input until it ends.
+///
+/// Standard input is read on a thread of its own, which a blocked read
+/// does not keep the runtime from shutting down. The thread stops at the
+/// next line once the task is aborted.
+pub async fn read(log_id: String, encoding: &'static Encoding, sender: Sender) {
+    let (lines, mut received) = mpsc::channel(1024);
+    let id = log_id.clone();
+    thread::spawn(move || {
+        let mut input = io::stdin().lock();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match input.read_until(b'\n', &mut line) {
+                Ok(0) => return,
+                Ok(_) => (),
+                Err(e) => {
+                    warn!("Failed to read standard input for {}: {}", id, e);
+                    return;
+                },
+            }
+            let line = decode(&line, encoding);
+            if lines.blocking_send(line).is_err() {
+                return;
+            }
+        }
+    });
+    while let Some(line) = received.recv().await {
+        let lines = Lines {
+            log_id: log_id.clone(),
+            instance: None,
+            origin: "stdin".to_string(),
+            lines: vec![line],
+        };
+        if sender.send(lines).is_err() {
+            return;
+        }
+    }
+}
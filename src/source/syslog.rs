@@ -0,0 +1,221 @@
+use std::future::{pending, Future};
+use std::io;
+use std::net::SocketAddr;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::task::JoinSet;
+use tracing::warn;
+
+use super::{severity, Lines, Sender};
+use crate::config::{SyslogConfig, SyslogProtocol};
+
+/// Longest accepted TCP frame, in bytes.
+const MAX_FRAME: usize = 1 << 20;
+
+/// Longest accepted length prefix of octet-counted frames, in digits.
+const MAX_LENGTH_DIGITS: usize = 10;
+
+/// Severity of messages that do not give one, `notice`.
+const DEFAULT_SEVERITY: u8 = 5;
+
+/// Binds the sockets, so errors show right away, and returns the task
+/// receiving messages on them.
+pub fn listen(log_id: String, config: &SyslogConfig, sender: Sender) -> io::Result<impl Future<Output = ()>> {
+    let max_severity = config.severity.as_deref().map(severity).transpose()?.unwrap_or(7);
+    let udp = match config.protocol {
+        SyslogProtocol::Udp | SyslogProtocol::Both => {
+            let socket = std::net::UdpSocket::bind(&config.bind)?;
+            socket.set_nonblocking(true)?;
+            Some(UdpSocket::from_std(socket)?)
+        },
+        SyslogProtocol::Tcp => None,
+    };
+    let tcp = match config.protocol {
+        SyslogProtocol::Tcp | SyslogProtocol::Both => {
+            let listener = std::net::TcpListener::bind(&config.bind)?;
+            listener.set_nonblocking(true)?;
+            Some(TcpListener::from_std(listener)?)
+        },
+        SyslogProtocol::Udp => None,
+    };
+    let receiver = Receiver { log_id, max_severity, sender };
+    Ok(async move {
+        let mut connections = JoinSet::new();
+        let mut buffer = vec![0; 65536];
+        while !receiver.sender.is_closed() {
+            tokio::select! {
+                received = recv(udp.as_ref(), &mut buffer) => match received {
+                    Ok((length, peer)) => receiver.receive(&buffer[..length], peer),
+                    Err(e) => warn!("Failed to receive syslog messages for {}: {}", receiver.log_id, e),
+                },
+                accepted = accept(tcp.as_ref()) => match accepted {
+                    Ok((stream, peer)) => {
+                        connections.spawn(read_stream(stream, peer, receiver.clone()));
+                    },
+                    Err(e) => warn!("Failed to accept syslog connection for {}: {}", receiver.log_id, e),
+                },
+                Some(_) = connections.join_next() => (),
+            }
+        }
+    })
+}
+
+async fn recv(socket: Option<&UdpSocket>, buffer: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+    match socket {
+        Some(socket) => socket.recv_from(buffer).await,
+        None => pending().await,
+    }
+}
+
+async fn accept(listener: Option<&TcpListener>) -> io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => pending().await,
+    }
+}
+
+/// Reads frames from a TCP connection until it is closed. As of RFC 6587,
+/// frames either start with their length or end with a newline.
+async fn read_stream(stream: TcpStream, peer: SocketAddr, receiver: Receiver) {
+    let mut reader = BufReader::new(stream);
+    let mut frame = Vec::new();
+    loop {
+        frame.clear();
+        let first = match reader.fill_buf().await {
+            Ok([]) | Err(_) => return,
+            Ok(buffer) => buffer[0],
+        };
+        let read = if first.is_ascii_digit() {
+            let mut length = Vec::new();
+            match read_until(&mut reader, b' ', MAX_LENGTH_DIGITS + 1, &mut length).await {
+                Ok(_) => match std::str::from_utf8(&length).ok().and_then(|length| length.trim().parse::<usize>().ok()) {
+                    Some(length) if length <= MAX_FRAME => {
+                        frame.resize(length, 0);
+                        reader.read_exact(&mut frame).await.map(|_| ())
+                    },
+                    _ => Err(io::Error::new(io::ErrorKind::InvalidData, "invalid frame length")),
+                },
+                Err(e) => Err(e),
+            }
+        } else {
+            read_until(&mut reader, b'\n', MAX_FRAME, &mut frame).await
+        };
+        if let Err(e) = read {
+            warn!("Closing syslog connection from {}: {}", peer, e);
+            return;
+        }
+        receiver.receive(&frame, peer);
+    }
+}
+
+/// Reads up to and including `delimiter`, failing when more than `max`
+/// bytes come without it, so peers cannot grow memory without limit.
+async fn read_until(reader: &mut BufReader<TcpStream>, delimiter: u8, max: usize, buffer: &mut Vec<u8>)
+    -> io::Result<()> {
+    (&mut *reader).take(max as u64 + 1).read_until(delimiter, buffer).await?;
+    if buffer.len() > max && buffer.last() != Some(&delimiter) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("no frame end within {} bytes", max)));
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+struct Receiver {
+    log_id: String,
+    max_severity: u8,
+    sender: Sender,
+}
+
+impl Receiver {
+    fn receive(&self, frame: &[u8], peer: SocketAddr) {
+        let frame = String::from_utf8_lossy(frame);
+        let message = parse(frame.trim_end_matches(['\r', '\n']));
+        if message.severity > self.max_severity || message.text.is_empty() {
+            return;
+        }
+        let lines = Lines {
+            log_id: self.log_id.clone(),
+            instance: None,
+            origin: message.host.map_or_else(|| peer.ip().to_string(), String::from),
+            lines: message.text.lines().map(String::from).collect(),
+        };
+        let _ = self.sender.send(lines);
+    }
+}
+
+struct Message<'a> {
+    severity: u8,
+    host: Option<&'a str>,
+    text: &'a str,
+}
+
+/// Parses an RFC 5424 or RFC 3164 message. What cannot be parsed is taken
+/// as the text.
+fn parse(frame: &str) -> Message<'_> {
+    let (severity, rest) = match priority(frame) {
+        Some((priority, rest)) => (priority % 8, rest),
+        None => (DEFAULT_SEVERITY, frame),
+    };
+    if let Some(rest) = rest.strip_prefix("1 ") {
+        // TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG
+        let mut fields = rest.splitn(6, ' ');
+        let host = fields.nth(1).filter(|host| *host != "-");
+        let text = skip_structured_data(fields.nth(3).unwrap_or_default());
+        return Message { severity, host, text: text.trim_start_matches('\u{feff}') };
+    }
+    // TIMESTAMP HOSTNAME TAG: MSG, where devices often leave out the hostname.
+    let rest = match rest.get(..16) {
+        Some(timestamp) if is_timestamp(timestamp) => &rest[16..],
+        _ => return Message { severity, host: None, text: rest },
+    };
+    let is_tag = |word: &str| word.ends_with(':') || word.contains('[');
+    let (host, rest) = match rest.split_once(' ') {
+        Some((word, after)) if !is_tag(word) => (Some(word), after),
+        _ => (None, rest),
+    };
+    let text = match rest.split_once(' ') {
+        Some((word, after)) if is_tag(word) => after,
+        _ => rest,
+    };
+    Message { severity, host, text }
+}
+
+/// Splits off `<PRI>`.
+fn priority(frame: &str) -> Option<(u8, &str)> {
+    let rest = frame.strip_prefix('<')?;
+    let (priority, rest) = rest.split_once('>')?;
+    match priority.len() {
+        1..=3 => Some((priority.parse().ok()?, rest)),
+        _ => None,
+    }
+}
+
+/// Whether `text` is like `Oct 11 22:14:15 `.
+fn is_timestamp(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes[..3].iter().all(u8::is_ascii_alphabetic)
+        && bytes[3] == b' ' && bytes[6] == b' ' && bytes[9] == b':' && bytes[12] == b':' && bytes[15] == b' '
+}
+
+/// Skips the structured data at the start of `text`: `-` or elements like
+/// `[id name="value"]`, whose values may contain escaped `]`.
+fn skip_structured_data(text: &str) -> &str {
+    if let Some(rest) = text.strip_prefix('-') {
+        return rest.strip_prefix(' ').unwrap_or(rest);
+    }
+    let (mut in_element, mut quoted, mut escaped) = (false, false, false);
+    for (index, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' if in_element => quoted = !quoted,
+            '[' if !in_element => in_element = true,
+            ']' if in_element && !quoted => in_element = false,
+            _ if in_element => (),
+            ' ' => return &text[index + 1..],
+            _ => return &text[index..],
+        }
+    }
+    ""
+}
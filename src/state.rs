@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::baseline::Baseline;
+
+/// What is kept across restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    pub logs: BTreeMap<String, LogRecord>,
+    /// Starts of the SNMP engines by engine id in hex, which their traps
+    /// count.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub engine_boots: BTreeMap<String, u32>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// Rule states by rule name.
+    #[serde(default)]
+    pub rules: BTreeMap<String, RuleRecord>,
+    /// How far files read in `tail` mode were read, by path.
+    #[serde(default)]
+    pub files: BTreeMap<String, FileRecord>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RuleRecord {
+    pub firing: bool,
+    pub cooldown_until: i64,
+    pub suppressed: usize,
+    /// The rate learned, with anomaly detection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<Baseline>,
+}
+
+/// A position in a file, which is identified by its device and inode so
+/// a rotation while stopped is noticed. Both are zero where unavailable.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileRecord {
+    pub device: u64,
+    pub inode: u64,
+    pub offset: u64,
+}
+
+impl State {
+    /// Loads the state file, or an empty state if there is none yet.
+    pub fn load(path: &Path) -> io::Result<State> {
+        match File::open(path) {
+            Ok(file) => serde_json::from_reader(file)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(State::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the state atomically, replacing the previous file.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        serde_json::to_writer_pretty(File::create(&tmp)?, self)
+            .map_err(io::Error::other)?;
+        fs::rename(tmp, path)
+    }
+}
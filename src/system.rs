@@ -0,0 +1,83 @@
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sysinfo::{Disks, System};
+
+/// The load, free memory and free disk space of the machine when an alert
+/// was raised, as a full disk or exhausted memory often causes the errors.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Load averages over 1, 5 and 15 minutes, not known on Windows.
+    pub load: Option<[f64; 3]>,
+    /// Bytes of memory available and in total.
+    pub memory_available: u64,
+    pub memory_total: u64,
+    /// The filesystem the log is on.
+    pub disk: Option<DiskUsage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiskUsage {
+    pub mount_point: String,
+    /// Bytes available to the alarmer's user and in total.
+    pub available: u64,
+    pub total: u64,
+}
+
+impl Snapshot {
+    /// Takes a snapshot, with the filesystem holding `path` if there is
+    /// one.
+    pub fn take(path: Option<&Path>) -> Snapshot {
+        let mut system = System::new();
+        system.refresh_memory();
+        let load = if cfg!(windows) {
+            None
+        } else {
+            let load = System::load_average();
+            Some([load.one, load.five, load.fifteen])
+        };
+        Snapshot {
+            load,
+            memory_available: system.available_memory(),
+            memory_total: system.total_memory(),
+            disk: path.and_then(disk),
+        }
+    }
+}
+
+/// The space of the filesystem `path` is on, found by its mount point.
+/// Globs and paths not created yet are on that of their closest existing
+/// directory.
+fn disk(path: &Path) -> Option<DiskUsage> {
+    let path = path.ancestors().find_map(|ancestor| ancestor.canonicalize().ok())
+        .or_else(|| Path::new(".").canonicalize().ok())?;
+    let disks = Disks::new_with_refreshed_list();
+    let disk = disks.iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())?;
+    Some(DiskUsage {
+        mount_point: disk.mount_point().display().to_string(),
+        available: disk.available_space(),
+        total: disk.total_space(),
+    })
+}
+
+/// E.g. `load 0.52 0.40 0.31, memory 2.9 of 7.8 GiB free, disk / 4.5 of
+/// 50.0 GiB free`.
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some([one, five, fifteen]) = self.load {
+            write!(f, "load {:.2} {:.2} {:.2}, ", one, five, fifteen)?;
+        }
+        write!(f, "memory {:.1} of {:.1} GiB free", gib(self.memory_available), gib(self.memory_total))?;
+        if let Some(disk) = &self.disk {
+            write!(f, ", disk {} {:.1} of {:.1} GiB free", disk.mount_point, gib(disk.available), gib(disk.total))?;
+        }
+        Ok(())
+    }
+}
+
+fn gib(bytes: u64) -> f64 {
+    bytes as f64 / (1u64 << 30) as f64
+}
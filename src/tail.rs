@@ -0,0 +1,196 @@
+use std::fs::{File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+
+use encoding_rs::{DecoderResult, Encoding, UTF_8};
+
+use crate::state::FileRecord;
+
+/// Longest line kept back until it is complete, in bytes. Longer lines are
+/// split.
+const MAX_LINE: usize = 64 * 1024;
+
+/// Reads content appended to a file since the last call.
+pub struct Tailer {
+    path: String,
+    file: Option<File>,
+    offset: u64,
+    /// The start of a line whose end was not written yet.
+    partial: Vec<u8>,
+    encoding: &'static Encoding,
+}
+
+impl Tailer {
+    /// Opens `path` positioned at its current end, so only new lines are seen.
+    pub fn open(path: &str) -> Tailer {
+        let mut tailer = Tailer { path: path.to_string(), file: None, offset: 0, partial: Vec::new(), encoding: UTF_8 };
+        if let Ok(mut file) = File::open(path) {
+            tailer.offset = file.seek(SeekFrom::End(0)).unwrap_or(0);
+            tailer.file = Some(file);
+        }
+        tailer
+    }
+
+    /// Decodes lines from `encoding` instead of UTF-8.
+    pub fn set_encoding(&mut self, encoding: &'static Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Reopens the file from the beginning, e.g. after it was recreated.
+    pub fn reopen(&mut self) {
+        self.file = File::open(&self.path).ok();
+        self.offset = 0;
+        self.partial.clear();
+    }
+
+    /// How far the open file was read, up to the last complete line.
+    pub fn position(&self) -> Option<FileRecord> {
+        let metadata = self.file.as_ref()?.metadata().ok()?;
+        let (device, inode) = file_id(&metadata);
+        Some(FileRecord { device, inode, offset: self.offset - self.partial.len() as u64 })
+    }
+
+    /// Continues reading from a position saved earlier, if the open file is
+    /// the one it was reached in and did not shrink since. Otherwise the
+    /// file was rotated or truncated in the meantime and is read from the
+    /// start.
+    pub fn resume(&mut self, position: &FileRecord) -> io::Result<()> {
+        let metadata = match self.file.as_ref() {
+            Some(file) => file.metadata()?,
+            None => return Ok(()),
+        };
+        let (device, inode) = file_id(&metadata);
+        let same = (device, inode) == (position.device, position.inode) && metadata.len() >= position.offset;
+        self.offset = if same { position.offset } else { 0 };
+        self.partial.clear();
+        Ok(())
+    }
+
+    /// Drains what is left in the current file and closes it.
+    ///
+    /// A rotated file is still readable through the open handle, so lines
+    /// written just before the rotation are not lost. An unfinished last
+    /// line is returned as it is.
+    pub fn close(&mut self) -> io::Result<Vec<String>> {
+        let lines = self.read_lines().map(|mut lines| {
+            if !self.partial.is_empty() {
+                lines.push(decode(&std::mem::take(&mut self.partial), self.encoding));
+            }
+            lines
+        });
+        self.file = None;
+        self.partial.clear();
+        lines
+    }
+
+    /// Returns the lines completed since the previous read.
+    ///
+    /// If the file shrank (e.g. copytruncate rotation) it is read again
+    /// from the start.
+    pub fn read_lines(&mut self) -> io::Result<Vec<String>> {
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => return Ok(Vec::new()),
+        };
+        if file.metadata()?.len() < self.offset {
+            self.offset = 0;
+            self.partial.clear();
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut content = std::mem::take(&mut self.partial);
+        self.offset += file.read_to_end(&mut content)? as u64;
+        let mut lines = Vec::new();
+        let mut rest = content.as_slice();
+        while let Some(end) = rest.iter().position(|byte| *byte == b'\n') {
+            split_long(&rest[..end], self.encoding, &mut lines);
+            rest = &rest[end + 1..];
+        }
+        // An unfinished line waits for its end, unless it is too long already.
+        while rest.len() > MAX_LINE {
+            let end = boundary(rest, MAX_LINE, self.encoding);
+            lines.push(decode(&rest[..end], self.encoding));
+            rest = &rest[end..];
+        }
+        self.partial = rest.to_vec();
+        Ok(lines)
+    }
+}
+
+/// Adds `line` to `lines`, in pieces of up to [`MAX_LINE`] bytes.
+fn split_long(mut line: &[u8], encoding: &'static Encoding, lines: &mut Vec<String>) {
+    while line.len() > MAX_LINE {
+        let end = boundary(line, MAX_LINE, encoding);
+        lines.push(decode(&line[..end], encoding));
+        line = &line[end..];
+    }
+    lines.push(decode(line, encoding));
+}
+
+/// The end of the last whole character of `encoding` within the first
+/// `max` bytes, before the bytes of a character they stop in the middle
+/// of. Only decoding from the start tells those apart in encodings such as
+/// GBK, whose second bytes may look like ASCII.
+fn boundary(bytes: &[u8], max: usize, encoding: &'static Encoding) -> usize {
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let mut text = String::with_capacity(decoder.max_utf8_buffer_length(max).unwrap_or(max * 3));
+    // The capacity is enough for all of it.
+    let _ = decoder.decode_to_string(&bytes[..max], &mut text, false);
+    text.reserve(decoder.max_utf8_buffer_length(0).unwrap_or(16));
+    match decoder.decode_to_string_without_replacement(&[], &mut text, true) {
+        (DecoderResult::Malformed(unfinished, _), _) if (unfinished as usize) < max => max - unfinished as usize,
+        _ => max,
+    }
+}
+
+/// Decodes `line`, replacing malformed bytes.
+pub(crate) fn decode(line: &[u8], encoding: &'static Encoding) -> String {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    encoding.decode_without_bom_handling(line).0.into_owned()
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> (u64, u64) {
+    (0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::GBK;
+
+    #[test]
+    fn boundary_keeps_utf8_characters_whole() {
+        let line = "aé€".as_bytes();
+        assert_eq!(boundary(line, 6, UTF_8), 6);
+        assert_eq!(boundary(line, 5, UTF_8), 3);
+        assert_eq!(boundary(line, 4, UTF_8), 3);
+        assert_eq!(boundary(line, 2, UTF_8), 1);
+    }
+
+    #[test]
+    fn boundary_keeps_gbk_characters_whole() {
+        // 错 is 0xb4 0xed and 误 is 0xce 0xf3 in GBK, and 0x41 is A.
+        let line = [0xb4, 0xed, 0xce, 0x41, 0x41];
+        assert_eq!(boundary(&line, 3, GBK), 2);
+        assert_eq!(boundary(&[0xb4, 0xed, 0xce, 0xf3], 4, GBK), 4);
+        assert_eq!(boundary(&[0x41, 0xb4, 0xed], 2, GBK), 1);
+    }
+
+    #[test]
+    fn long_gbk_lines_are_split_between_characters() {
+        // Odd offsets fall in the middle of characters after the x.
+        let text = format!("x{}", "错误".repeat(MAX_LINE));
+        let (encoded, _, _) = GBK.encode(&text);
+        let mut lines = Vec::new();
+        split_long(&encoded, GBK, &mut lines);
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| !line.contains('\u{fffd}')));
+        assert_eq!(lines.concat(), text);
+    }
+}
@@ -0,0 +1,1196 @@
+//! Checks a configuration file for everything that would make it fail or
+//! behave unexpectedly, reporting all problems at once.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use chrono::format::{Item, StrftimeItems};
+use encoding_rs::Encoding;
+use handlebars::Handlebars;
+use regex::Regex;
+use serde_yaml::Value;
+use toml::de::{DeTable, DeValue};
+use toml::Spanned;
+use url::Url;
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::Marker;
+
+use crate::composite::Composite;
+use crate::condition::Condition;
+use crate::config::{
+    line_at, AnomalyConfig, BurstConfig, Config, DedupConfig, DistinctConfig, EmailTransport, EscalationConfig,
+    ExtractConfig, Format, GelfConfig, GelfProtocol, KafkaConfig, LogConfig, LogConfigs, NotifierConfig, OutputConfig,
+    PushoverConfig, RateLimitConfig, RuleConfig, SnmpConfig, TimedThresholds, TlsMode,
+};
+use crate::error::Error;
+use crate::extract::{is_name, Extractor};
+use crate::grok;
+use crate::interpolate;
+use crate::logpath::LogPath;
+use crate::measure::Measure;
+use crate::multiline::Grouping;
+use crate::notify::snmp;
+use crate::schedule::{Schedule, Thresholds};
+use crate::script::Script;
+use crate::source;
+use crate::watcher::MIN_POLL_INTERVAL;
+
+const WEBHOOK_METHODS: [&str; 5] = ["GET", "POST", "PUT", "PATCH", "DELETE"];
+
+const PAGERDUTY_SEVERITIES: [&str; 4] = ["critical", "error", "warning", "info"];
+
+/// Room for a few inotify events with long file names.
+const MIN_WATCH_BUFFER: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// The configuration cannot be used.
+    Error,
+    /// The configuration works, but likely not as intended.
+    Warning,
+}
+
+impl Level {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+        }
+    }
+}
+
+/// Something wrong with the setting at `path`, e.g. `log[0].patterns[1]`.
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub level: Level,
+    pub path: String,
+    /// Line of the setting in the file, or of the closest section around
+    /// it if it is not set.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.line, self.path.is_empty()) {
+            (Some(line), false) => write!(f, "line {}, {}: {}", line, self.path, self.message),
+            (Some(line), true) => write!(f, "line {}: {}", line, self.message),
+            (None, false) => write!(f, "{}: {}", self.path, self.message),
+            (None, true) => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Reads the configuration file at `path` and returns every problem found
+/// in it, with the lines they are on. Only fails if the file cannot be
+/// read.
+pub fn validate_configuration(path: &str) -> Result<Vec<Problem>, Error> {
+    let text = std::fs::read_to_string(path).map_err(|source| Error::Read { path: path.to_string(), source })?;
+    let format = Format::of(path);
+    let config = match format.parse(&text).map(interpolate::apply) {
+        Ok(Ok(config)) => config,
+        Ok(Err(Error::Environment { setting, message })) => {
+            let path = if setting.starts_with("LOG_ALARMER__") { String::new() } else { setting.clone() };
+            let message = if path.is_empty() { format!("{}: {}", setting, message) } else { message };
+            let line = Some(&path).filter(|path| !path.is_empty())
+                .and_then(|path| Positions::of(&text, format).line(path));
+            return Ok(vec![Problem { level: Level::Error, path, line, message }]);
+        },
+        Ok(Err(e)) => return Err(e),
+        Err((mut message, line)) => {
+            // The line is reported on its own.
+            if let Some(at) = message.rfind(" at line ").filter(|_| line.is_some()) {
+                message.truncate(at);
+            }
+            return Ok(vec![Problem { level: Level::Error, path: String::new(), line, message }]);
+        },
+    };
+    let positions = Positions::of(&text, format);
+    let mut problems = positions.unknown_keys(&config);
+    let mut config = config;
+    let expanded = grok::expand(&mut config).into_iter()
+        .map(|(path, message)| Problem { level: Level::Error, path, line: None, message });
+    problems.extend(expanded);
+    problems.extend(check_configuration(&config));
+    for problem in problems.iter_mut() {
+        problem.line = positions.line(&problem.path);
+    }
+    problems.sort_by_key(|problem| problem.line);
+    Ok(problems)
+}
+
+/// Returns every problem found in `config`, without lines.
+pub fn check_configuration(config: &Config) -> Vec<Problem> {
+    let mut checker = Checker { config, names: config.notifier_names(), problems: Vec::new() };
+    checker.logs();
+    checker.email();
+    checker.notifiers();
+    checker.outputs();
+    checker.general();
+    checker.problems
+}
+
+struct Checker<'a> {
+    config: &'a Config,
+    names: Vec<&'a str>,
+    problems: Vec<Problem>,
+}
+
+impl Checker<'_> {
+    fn error(&mut self, path: &str, message: String) {
+        self.problems.push(Problem { level: Level::Error, path: path.to_string(), line: None, message });
+    }
+
+    fn warning(&mut self, path: &str, message: String) {
+        self.problems.push(Problem { level: Level::Warning, path: path.to_string(), line: None, message });
+    }
+
+    fn logs(&mut self) {
+        let config = self.config;
+        let logs = config.log.as_slice();
+        let single = matches!(config.log, LogConfigs::Single(_));
+        let mut ids = HashMap::new();
+        for (index, log) in logs.iter().enumerate() {
+            let path = if single { "log".to_string() } else { format!("log[{}]", index) };
+            if log.id.is_empty() {
+                self.error(&format!("{}.id", path), "must not be empty".to_string());
+            } else if let Some(first) = ids.insert(log.id.as_str(), index) {
+                self.error(&format!("{}.id", path), format!("{} is already the id of log[{}]", log.id, first));
+            }
+            self.log(&path, log);
+        }
+        if logs.iter().filter(|log| log.stdin).count() > 1 {
+            self.error("log", "only one log can read standard input".to_string());
+        }
+    }
+
+    fn log(&mut self, path: &str, log: &LogConfig) {
+        self.source(path, log);
+        self.thresholds(path, log.count_threshold, log.time_threshold);
+        self.timed_thresholds(&format!("{}.thresholds", path), &log.thresholds);
+        self.matching(path, &log.keywords, &log.patterns, &log.fields);
+        self.regexes(&format!("{}.exclude", path), &log.exclude);
+        self.resolution(path, log.resolve_after, log.clear_threshold);
+        self.dedup(&format!("{}.dedup", path), log.dedup.as_ref());
+        self.escalation(&format!("{}.escalation", path), log.escalation.as_ref());
+        self.burst(&format!("{}.burst", path), log.burst.as_ref());
+        self.anomaly(&format!("{}.anomaly", path), log.anomaly.as_ref());
+        let window = self.config.thresholds(log).1;
+        self.distinct(&format!("{}.distinct", path), log.distinct.as_ref(), window);
+        self.extract(&format!("{}.extract", path), log.extract.as_ref());
+        if let Some(multiline) = &log.multiline {
+            if let Err(e) = Grouping::new(multiline) {
+                self.error(&format!("{}.multiline", path), last_line(&e).to_string());
+            }
+        }
+        let mut names = HashMap::new();
+        for (index, rule) in log.rules.iter().enumerate() {
+            let rule_path = format!("{}.rules[{}]", path, index);
+            if rule.name.is_empty() {
+                self.error(&format!("{}.name", rule_path), "must not be empty".to_string());
+            } else if let Some(first) = names.insert(rule.name.as_str(), index) {
+                let message = format!("{} is already the name of rules[{}]", rule.name, first);
+                self.error(&format!("{}.name", rule_path), message);
+            }
+            self.rule(&rule_path, log, rule);
+        }
+    }
+
+    /// Checks what the log reads: exactly one source, and for files that
+    /// they are there. Sources are listed in the order they take precedence.
+    fn source(&mut self, path: &str, log: &LogConfig) {
+        let sources: Vec<&str> = [
+            ("journal", log.journal.is_some()),
+            ("docker", log.docker.is_some()),
+            ("kubernetes", log.kubernetes.is_some()),
+            ("syslog", log.syslog.is_some()),
+            ("stdin", log.stdin),
+            ("path", !log.path.is_empty()),
+        ].iter().filter(|(_, set)| *set).map(|(name, _)| *name).collect();
+        match sources.as_slice() {
+            [] => self.error(path, "needs a path or another source to read".to_string()),
+            [_] => (),
+            [first, others @ ..] => self.warning(path, format!(
+                "reads {} only, {} {} ignored", first, others.join(" and "),
+                if others.len() == 1 { "is" } else { "are" },
+            )),
+        }
+        if let Some(journal) = &log.journal {
+            if let Some(Err(e)) = journal.priority.as_deref().map(source::severity) {
+                self.error(&format!("{}.journal.priority", path), e.to_string());
+            }
+        }
+        if let Some(syslog) = &log.syslog {
+            if let Some(Err(e)) = syslog.severity.as_deref().map(source::severity) {
+                self.error(&format!("{}.syslog.severity", path), e.to_string());
+            }
+            self.address(&format!("{}.syslog.bind", path), &syslog.bind);
+        }
+        if let Some(interval) = log.poll_interval {
+            if interval < MIN_POLL_INTERVAL {
+                self.warning(&format!("{}.poll_interval", path),
+                    format!("{} ms is raised to {} ms", interval, MIN_POLL_INTERVAL));
+            }
+        }
+        if log.debounce.is_some_and(|debounce| debounce < 0) {
+            self.error(&format!("{}.debounce", path), "must not be negative".to_string());
+        }
+        if let Some(label) = &log.encoding {
+            let key = format!("{}.encoding", path);
+            match Encoding::for_label(label.as_bytes()) {
+                None => self.error(&key, format!("unknown encoding {}", label)),
+                // Lines are split at newline bytes, which these do not keep.
+                Some(encoding) if !encoding.is_ascii_compatible() => {
+                    self.error(&key, format!("{} is not supported", encoding.name()));
+                },
+                Some(_) if !matches!(sources.first(), Some(&"path") | Some(&"stdin")) => {
+                    self.warning(&key, "only applies to files and standard input".to_string());
+                },
+                Some(_) => (),
+            }
+        }
+        if sources.first() != Some(&"path") {
+            return;
+        }
+        let path = format!("{}.path", path);
+        let parsed = match LogPath::parse(&log.path, log.recursive) {
+            Ok(parsed) => parsed,
+            Err(e) => return self.error(&path, e),
+        };
+        let missing = match &parsed {
+            LogPath::File(file) => (!file.exists()).then_some(file.as_path()),
+            LogPath::Glob { dir, .. } => (!dir.is_dir()).then_some(dir.as_path()),
+        };
+        if let Some(missing) = missing {
+            if !log.allow_missing {
+                self.warning(&path, format!(
+                    "{} does not exist yet; it is watched once it appears, set allow_missing: true if that is expected",
+                    missing.display(),
+                ));
+            }
+        }
+    }
+
+    fn rule(&mut self, path: &str, log: &LogConfig, rule: &RuleConfig) {
+        self.thresholds(path, rule.count_threshold, rule.time_threshold);
+        self.timed_thresholds(&format!("{}.thresholds", path), &rule.thresholds);
+        self.matching(path, &rule.keywords, &rule.patterns, &rule.fields);
+        for (name, pattern) in &rule.terms {
+            self.regex(&format!("{}.terms.{}", path, name), pattern);
+        }
+        let valid = rule.terms.values().all(|pattern| Regex::new(pattern).is_ok());
+        if valid && (!rule.terms.is_empty() || rule.when.is_some()) {
+            if let Err(e) = Composite::new(&rule.terms, rule.when.as_deref()) {
+                self.error(&format!("{}.when", path), e);
+            }
+        }
+        if let Some(Err(e)) = rule.script.as_deref().map(Script::load) {
+            self.error(&format!("{}.script", path), e);
+        }
+        if let Some(Err(e)) = rule.value.as_ref().map(Measure::new) {
+            self.error(&format!("{}.value", path), last_line(&e).to_string());
+        }
+        self.regexes(&format!("{}.exclude", path), &rule.exclude);
+        self.resolution(path, rule.resolve_after, rule.clear_threshold);
+        self.dedup(&format!("{}.dedup", path), rule.dedup.as_ref());
+        self.escalation(&format!("{}.escalation", path), rule.escalation.as_ref());
+        self.burst(&format!("{}.burst", path), rule.burst.as_ref());
+        self.anomaly(&format!("{}.anomaly", path), rule.anomaly.as_ref());
+        let window = rule.time_threshold.unwrap_or(self.config.thresholds(log).1);
+        self.distinct(&format!("{}.distinct", path), rule.distinct.as_ref(), window);
+        if rule.distinct.is_none() && log.distinct.is_some() && window == 0 {
+            let message = "must be above 0 with the distinct error count of the log".to_string();
+            self.error(&format!("{}.time_threshold", path), message);
+        }
+        self.extract(&format!("{}.extract", path), rule.extract.as_ref());
+        self.targets(&format!("{}.notify", path), &rule.notify);
+        if let Some(after) = rule.absent_after {
+            if after <= 0 {
+                self.error(&format!("{}.absent_after", path), "must be more than 0 milliseconds".to_string());
+            }
+        }
+    }
+
+    fn thresholds(&mut self, path: &str, count: Option<i32>, time: Option<i64>) {
+        if let Some(count) = count {
+            if count < 1 {
+                self.warning(&format!("{}.count_threshold", path), format!("{} is raised to 1", count));
+            }
+        }
+        if let Some(time) = time {
+            if time < 0 {
+                self.error(&format!("{}.time_threshold", path), "must not be negative".to_string());
+            }
+        }
+    }
+
+    fn matching(&mut self, path: &str, keywords: &[String], patterns: &[String], fields: &[String]) {
+        for (index, keyword) in keywords.iter().enumerate() {
+            if keyword.is_empty() {
+                let message = "an empty keyword matches every line".to_string();
+                self.warning(&format!("{}.keywords[{}]", path, index), message);
+            }
+        }
+        self.regexes(&format!("{}.patterns", path), patterns);
+        for (index, field) in fields.iter().enumerate() {
+            if let Err(e) = Condition::parse(field) {
+                self.error(&format!("{}.fields[{}]", path, index), e);
+            }
+        }
+    }
+
+    fn regexes(&mut self, path: &str, patterns: &[String]) {
+        for (index, pattern) in patterns.iter().enumerate() {
+            self.regex(&format!("{}[{}]", path, index), pattern);
+        }
+    }
+
+    fn regex(&mut self, path: &str, pattern: &str) {
+        if let Err(e) = Regex::new(pattern) {
+            self.error(path, format!("invalid regex {}: {}", pattern, last_line(&e.to_string())));
+        }
+    }
+
+    fn resolution(&mut self, path: &str, resolve_after: Option<i64>, clear_threshold: Option<usize>) {
+        if let Some(after) = resolve_after {
+            if after < 0 {
+                self.error(&format!("{}.resolve_after", path), "must not be negative".to_string());
+            }
+        }
+        if clear_threshold == Some(0) {
+            self.error(&format!("{}.clear_threshold", path), "must be at least 1".to_string());
+        } else if clear_threshold.is_some() && resolve_after.is_none() {
+            self.warning(&format!("{}.clear_threshold", path), "has no effect without resolve_after".to_string());
+        }
+    }
+
+    fn dedup(&mut self, path: &str, dedup: Option<&DedupConfig>) {
+        let dedup = match dedup {
+            Some(dedup) => dedup,
+            None => return,
+        };
+        if dedup.window <= 0 {
+            self.warning(&format!("{}.window", path), "holds nothing back unless more than 0 milliseconds".to_string());
+        }
+        if let Some(pattern) = &dedup.pattern {
+            self.regex(&format!("{}.pattern", path), pattern);
+        }
+    }
+
+    fn escalation(&mut self, path: &str, escalation: Option<&EscalationConfig>) {
+        let escalation = match escalation {
+            Some(escalation) => escalation,
+            None => return,
+        };
+        if escalation.after_seconds == 0 {
+            self.error(&format!("{}.after_seconds", path), "must be more than 0".to_string());
+        }
+        self.targets(&format!("{}.notify", path), &escalation.notify);
+    }
+
+    fn timed_thresholds(&mut self, path: &str, timed: &[TimedThresholds]) {
+        for (index, timed) in timed.iter().enumerate() {
+            let path = format!("{}[{}]", path, index);
+            self.thresholds(&path, timed.count_threshold, timed.time_threshold);
+            if let Err(e) = Thresholds::new(timed, None) {
+                self.error(&path, e);
+            }
+        }
+    }
+
+    fn burst(&mut self, path: &str, burst: Option<&BurstConfig>) {
+        let burst = match burst {
+            Some(burst) => burst,
+            None => return,
+        };
+        if burst.count == 0 {
+            self.error(&format!("{}.count", path), "must be at least 1".to_string());
+        }
+        if burst.window <= 0 {
+            self.error(&format!("{}.window", path), "must be more than 0 milliseconds".to_string());
+        }
+    }
+
+    fn anomaly(&mut self, path: &str, anomaly: Option<&AnomalyConfig>) {
+        let anomaly = match anomaly {
+            Some(anomaly) => anomaly,
+            None => return,
+        };
+        if anomaly.factor <= 0.0 {
+            self.error(&format!("{}.factor", path), "must be more than 0".to_string());
+        }
+        if anomaly.learn_hours <= 0.0 {
+            self.error(&format!("{}.learn_hours", path), "must be more than 0".to_string());
+        }
+    }
+
+    fn distinct(&mut self, path: &str, distinct: Option<&DistinctConfig>, window: i64) {
+        let distinct = match distinct {
+            Some(distinct) => distinct,
+            None => return,
+        };
+        if window == 0 {
+            self.error(path, "needs a time_threshold above 0, or errors are never forgotten".to_string());
+        }
+        if distinct.count == 0 {
+            self.error(&format!("{}.count", path), "must be at least 1".to_string());
+        }
+        if let Some(pattern) = &distinct.pattern {
+            self.regex(&format!("{}.pattern", path), pattern);
+        }
+    }
+
+    fn extract(&mut self, path: &str, extract: Option<&ExtractConfig>) {
+        if let Some(Err(e)) = extract.map(Extractor::new) {
+            self.error(path, last_line(&e).to_string());
+        }
+    }
+
+    fn targets(&mut self, path: &str, targets: &[String]) {
+        for (index, target) in targets.iter().enumerate() {
+            if !self.names.contains(&target.as_str()) {
+                self.error(&format!("{}[{}]", path, index), format!(
+                    "unknown notifier {}, expected one of: {}", target, self.names.join(", "),
+                ));
+            }
+        }
+    }
+
+    fn email(&mut self) {
+        let email = match &self.config.email {
+            Some(email) => email,
+            None => return,
+        };
+        let smtp = email.transport == EmailTransport::Smtp;
+        if smtp && email.username.is_empty() {
+            self.error("email.username", "must not be empty".to_string());
+        }
+        match &email.stmp {
+            Some(_) if !email.smtp.is_empty() => {
+                self.warning("email.stmp", "deprecated and ignored, as smtp is set".to_string());
+            },
+            Some(_) => self.warning("email.stmp", "deprecated, rename it to smtp".to_string()),
+            None if smtp && email.smtp.is_empty() => self.error("email.smtp", "must name the SMTP server".to_string()),
+            None => (),
+        }
+        for (index, host) in email.smtp.iter().enumerate() {
+            if host.is_empty() {
+                self.error(&format!("email.smtp[{}]", index), "must not be empty".to_string());
+            }
+        }
+        if email.transport == EmailTransport::Sendmail
+            && email.sendmail_command.contains('/')
+            && !Path::new(&email.sendmail_command).exists()
+        {
+            self.warning("email.sendmail_command", format!("{} does not exist", email.sendmail_command));
+        }
+        if let Some(file) = &email.ca_file {
+            match std::fs::read(file) {
+                Ok(pem) if native_tls::Certificate::stack_from_pem(&pem).is_ok_and(|stack| !stack.is_empty()) => (),
+                Ok(_) => self.error("email.ca_file", format!("{} holds no PEM certificates", file)),
+                Err(e) => self.error("email.ca_file", format!("cannot read {}: {}", file, e)),
+            }
+        }
+        if let Some(fingerprint) = &email.certificate_fingerprint {
+            let digits = fingerprint.chars().filter(|c| *c != ':').collect::<String>();
+            if digits.len() != 64 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                let message = "must be a SHA-256 fingerprint, 64 hex digits optionally separated by colons";
+                self.error("email.certificate_fingerprint", message.to_string());
+            }
+        }
+        if (email.ca_file.is_some() || email.certificate_fingerprint.is_some()) && (!smtp || email.tls == TlsMode::None) {
+            self.warning("email", "ca_file and certificate_fingerprint are only used over TLS".to_string());
+        }
+        if email.recipients().next().is_none() {
+            self.error("email.to", "needs at least one recipient".to_string());
+        }
+        if let Some(target) = &email.target {
+            self.mailbox("email.target", target);
+        }
+        for (field, addresses) in [("to", &email.to), ("cc", &email.cc), ("bcc", &email.bcc)].iter() {
+            for (index, address) in addresses.iter().enumerate() {
+                self.mailbox(&format!("email.{}[{}]", field, index), address);
+            }
+        }
+        match &email.from {
+            Some(from) => self.mailbox("email.from", from),
+            None if !email.username.is_empty() && lettre::EmailAddress::new(email.username.clone()).is_err() => {
+                let message = format!("must be set, as username {} is not an email address", email.username);
+                self.error("email.from", message);
+            },
+            None if email.username.is_empty() => self.error("email.from", "must be set without a username".to_string()),
+            None => (),
+        }
+        if let Some(reply_to) = &email.reply_to {
+            self.mailbox("email.reply_to", reply_to);
+        }
+        if !smtp && (!email.password.is_empty() || email.oauth2.is_some()) {
+            self.warning("email", "password and oauth2 are only used by the smtp transport".to_string());
+        } else if smtp && email.password.is_empty() && email.oauth2.is_none() {
+            self.warning("email.password", "is empty and oauth2 is not set".to_string());
+        }
+        let templates = [("subject", &email.subject), ("body", &email.body), ("html_body", &email.html_body)];
+        for (field, template) in templates.iter() {
+            if let Some(template) = template {
+                if let Err(e) = Handlebars::new().register_template_string(field, template) {
+                    self.error(&format!("email.{}", field), format!("invalid template: {}", last_line(&e.to_string())));
+                }
+            }
+        }
+        if let Some(oauth2) = &email.oauth2 {
+            if oauth2.token_command.is_none() {
+                self.url("email.oauth2.token_url", &oauth2.token_url);
+                if oauth2.refresh_token.is_empty() {
+                    self.error("email.oauth2", "needs either token_command or refresh_token".to_string());
+                }
+            }
+        }
+        self.rate_limit("email.rate_limit", email.rate_limit.as_ref());
+        self.thresholds("email", email.count_threshold, email.time_threshold);
+    }
+
+    fn mailbox(&mut self, path: &str, address: &str) {
+        if lettre::EmailAddress::new(address.to_string()).is_err() {
+            self.error(path, format!("{} is not an email address", address));
+        }
+    }
+
+    fn notifiers(&mut self) {
+        if self.config.email.is_none() && self.config.notifiers.is_empty() {
+            self.warning("notifiers", "none configured and no email, so alerts are not sent anywhere".to_string());
+        }
+        let mut names = HashMap::new();
+        if self.config.email.is_some() {
+            names.insert("email", "email".to_string());
+        }
+        let fallbacks = self.config.fallbacks();
+        if let Some(email) = &self.config.email {
+            self.fallback("email.fallback", "email", email.fallback.as_deref(), &fallbacks);
+        }
+        for (index, entry) in self.config.notifiers.iter().enumerate() {
+            let path = format!("notifiers[{}]", index);
+            self.fallback(&format!("{}.fallback", path), entry.name(), entry.fallback.as_deref(), &fallbacks);
+            if let Some(first) = names.insert(entry.name(), path.clone()) {
+                self.error(&format!("{}.name", path), format!(
+                    "{} is already the name of {}; set a name to tell them apart", entry.name(), first,
+                ));
+            }
+            match &entry.notifier {
+                NotifierConfig::Slack(slack) => self.url(&format!("{}.webhook_url", path), &slack.webhook_url),
+                NotifierConfig::Telegram(telegram) => {
+                    if telegram.bot_token.is_empty() {
+                        self.error(&format!("{}.bot_token", path), "must not be empty".to_string());
+                    }
+                    self.url(&format!("{}.api_url", path), &telegram.api_url);
+                },
+                NotifierConfig::Webhook(webhook) => {
+                    self.url(&format!("{}.url", path), &webhook.url);
+                    if !WEBHOOK_METHODS.contains(&webhook.method.to_uppercase().as_str()) {
+                        self.error(&format!("{}.method", path), format!(
+                            "unknown method {}, expected one of {}", webhook.method, WEBHOOK_METHODS.join(", "),
+                        ));
+                    }
+                },
+                NotifierConfig::PagerDuty(pagerduty) => {
+                    if pagerduty.routing_key.is_empty() {
+                        self.error(&format!("{}.routing_key", path), "must not be empty".to_string());
+                    }
+                    if !PAGERDUTY_SEVERITIES.contains(&pagerduty.severity.as_str()) {
+                        self.error(&format!("{}.severity", path), format!(
+                            "unknown severity {}, expected one of {}", pagerduty.severity,
+                            PAGERDUTY_SEVERITIES.join(", "),
+                        ));
+                    }
+                    self.url(&format!("{}.url", path), &pagerduty.url);
+                },
+                NotifierConfig::Discord(discord) => self.url(&format!("{}.webhook_url", path), &discord.webhook_url),
+                NotifierConfig::Teams(teams) => self.url(&format!("{}.webhook_url", path), &teams.webhook_url),
+                NotifierConfig::DingTalk(dingtalk) => {
+                    self.url(&format!("{}.webhook_url", path), &dingtalk.webhook_url);
+                },
+                NotifierConfig::WeCom(wecom) => self.url(&format!("{}.webhook_url", path), &wecom.webhook_url),
+                NotifierConfig::Feishu(feishu) => self.url(&format!("{}.webhook_url", path), &feishu.webhook_url),
+                NotifierConfig::Twilio(twilio) => {
+                    let required = [
+                        ("account_sid", &twilio.account_sid), ("auth_token", &twilio.auth_token), ("from", &twilio.from),
+                    ];
+                    for (field, value) in required {
+                        if value.is_empty() {
+                            self.error(&format!("{}.{}", path, field), "must not be empty".to_string());
+                        }
+                    }
+                    if twilio.to.is_empty() {
+                        self.error(&format!("{}.to", path), "needs at least one number".to_string());
+                    }
+                    for (index, number) in twilio.to.iter().enumerate() {
+                        self.phone_number(&format!("{}.to[{}]", path, index), number);
+                    }
+                    self.url(&format!("{}.api_url", path), &twilio.api_url);
+                },
+                NotifierConfig::Ntfy(ntfy) => {
+                    self.url(&format!("{}.url", path), &ntfy.url);
+                    if Url::parse(&ntfy.url).is_ok_and(|url| url.path().trim_matches('/').is_empty()) {
+                        self.error(&format!("{}.url", path), "must name the topic, e.g. https://ntfy.sh/alerts".to_string());
+                    }
+                    self.priority(&format!("{}.priority", path), ntfy.priority, 1, 5);
+                },
+                NotifierConfig::Gotify(gotify) => {
+                    self.url(&format!("{}.url", path), &gotify.url);
+                    if gotify.token.is_empty() {
+                        self.error(&format!("{}.token", path), "must not be empty".to_string());
+                    }
+                    self.priority(&format!("{}.priority", path), gotify.priority, 0, 10);
+                },
+                NotifierConfig::Pushover(pushover) => self.pushover(&path, pushover),
+                NotifierConfig::Mqtt(mqtt) => {
+                    if mqtt.host.is_empty() {
+                        self.error(&format!("{}.host", path), "must name the broker".to_string());
+                    }
+                    if mqtt.topic.is_empty() || mqtt.topic.contains(['+', '#']) {
+                        self.error(&format!("{}.topic", path), "must be a topic name without wildcards".to_string());
+                    }
+                    if mqtt.qos > 2 {
+                        self.error(&format!("{}.qos", path), format!("{} is not a QoS level, 0, 1 or 2", mqtt.qos));
+                    }
+                    if mqtt.password.is_some() && mqtt.username.is_none() {
+                        self.error(&format!("{}.password", path), "is only sent with a username".to_string());
+                    }
+                },
+                NotifierConfig::Exec(exec) => {
+                    if exec.command.trim().is_empty() {
+                        self.error(&format!("{}.command", path), "must not be empty".to_string());
+                    }
+                    if exec.timeout_seconds == 0 {
+                        self.error(&format!("{}.timeout_seconds", path), "must be at least 1".to_string());
+                    }
+                    if let Some(dir) = &exec.working_dir {
+                        if !Path::new(dir).is_dir() {
+                            self.warning(&format!("{}.working_dir", path), format!("{} is not a directory", dir));
+                        }
+                    }
+                },
+                NotifierConfig::Desktop(_) => (),
+                NotifierConfig::Sns(sns) => {
+                    let parts: Vec<_> = sns.topic_arn.split(':').collect();
+                    if parts.len() != 6 || parts[0] != "arn" || parts[2] != "sns" {
+                        let message = format!("{} is not the ARN of an SNS topic", sns.topic_arn);
+                        self.error(&format!("{}.topic_arn", path), message);
+                    } else if sns.region().is_none_or(|region| region.is_empty()) {
+                        self.error(&format!("{}.region", path), "must be given, as the ARN names none".to_string());
+                    }
+                    if sns.access_key_id.is_some() != sns.secret_access_key.is_some() {
+                        self.error(
+                            &format!("{}.secret_access_key", path),
+                            "must be given together with access_key_id".to_string(),
+                        );
+                    }
+                    if let Some(endpoint) = &sns.endpoint {
+                        self.url(&format!("{}.endpoint", path), endpoint);
+                    }
+                },
+                NotifierConfig::Matrix(matrix) => {
+                    self.url(&format!("{}.homeserver", path), &matrix.homeserver);
+                    if matrix.access_token.is_empty() {
+                        self.error(&format!("{}.access_token", path), "must not be empty".to_string());
+                    }
+                    if !matrix.room_id.starts_with(['!', '#']) || !matrix.room_id.contains(':') {
+                        let message = format!("{} is neither a room id like !abc:example.org nor an alias", matrix.room_id);
+                        self.error(&format!("{}.room_id", path), message);
+                    }
+                },
+                NotifierConfig::Alertmanager(alertmanager) => {
+                    if alertmanager.url.is_empty() {
+                        self.error(&format!("{}.url", path), "must name at least one Alertmanager".to_string());
+                    }
+                    for (i, url) in alertmanager.url.iter().enumerate() {
+                        self.url(&format!("{}.url[{}]", path, i), url);
+                    }
+                    // Label names are of letters, digits and underscores, not led by a digit.
+                    let valid = |(i, c): (usize, char)| {
+                        c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit())
+                    };
+                    for name in alertmanager.labels.keys() {
+                        if name.is_empty() || !name.chars().enumerate().all(valid) {
+                            self.error(&format!("{}.labels.{}", path, name), "is not a valid label name".to_string());
+                        }
+                    }
+                },
+                NotifierConfig::Sentry(sentry) => {
+                    let dsn = Url::parse(&sentry.dsn).ok();
+                    let project = dsn.as_ref().and_then(|dsn| dsn.path().trim_end_matches('/').rsplit('/').next());
+                    if dsn.as_ref().is_none_or(|dsn| dsn.username().is_empty()) || project.is_none_or(str::is_empty) {
+                        let message = format!("{} is not a DSN like https://key@sentry.example.com/1", sentry.dsn);
+                        self.error(&format!("{}.dsn", path), message);
+                    }
+                },
+                NotifierConfig::Kafka(kafka) => self.kafka(&path, kafka),
+                NotifierConfig::Gelf(gelf) => self.gelf(&path, gelf),
+                NotifierConfig::Snmp(snmp) => self.snmp(&path, snmp),
+                NotifierConfig::Grafana(grafana) => {
+                    self.url(&format!("{}.url", path), &grafana.url);
+                    if grafana.panel_id.is_some() && grafana.dashboard_uid.is_none() {
+                        self.error(&format!("{}.panel_id", path), "needs dashboard_uid".to_string());
+                    }
+                },
+                NotifierConfig::Opsgenie(opsgenie) => {
+                    if opsgenie.api_key.is_empty() {
+                        self.error(&format!("{}.api_key", path), "must not be empty".to_string());
+                    }
+                    self.url(&format!("{}.url", path), &opsgenie.url);
+                    for (severity, priority) in &opsgenie.priorities {
+                        if !["P1", "P2", "P3", "P4", "P5"].contains(&priority.as_str()) {
+                            let message = format!("unknown priority {}, expected one of P1 to P5", priority);
+                            self.error(&format!("{}.priorities.{}", path, severity.as_str()), message);
+                        }
+                    }
+                },
+                NotifierConfig::Zabbix(zabbix) => {
+                    if zabbix.server.is_empty() {
+                        self.error(&format!("{}.server", path), "must name the Zabbix server or proxy".to_string());
+                    }
+                    if zabbix.key.is_empty() {
+                        self.error(&format!("{}.key", path), "must name an item".to_string());
+                    }
+                    if zabbix.timeout_seconds == 0 {
+                        self.error(&format!("{}.timeout_seconds", path), "must be at least 1".to_string());
+                    }
+                },
+            }
+            self.rate_limit(&format!("{}.rate_limit", path), entry.rate_limit.as_ref());
+        }
+    }
+
+    /// Checks that the notifier `name` falls back to one that is configured
+    /// and does not lead back to it.
+    fn fallback(&mut self, path: &str, name: &str, fallback: Option<&str>, fallbacks: &HashMap<String, String>) {
+        let fallback = match fallback {
+            Some(fallback) => fallback,
+            None => return,
+        };
+        if !self.names.contains(&fallback) {
+            return self.error(path, format!("unknown notifier {}, expected one of: {}", fallback, self.names.join(", ")));
+        }
+        let mut next = Some(fallback);
+        for _ in 0..fallbacks.len() {
+            if next == Some(name) {
+                return self.error(path, format!("the fallbacks of {} lead back to it", name));
+            }
+            next = next.and_then(|next| fallbacks.get(next)).map(String::as_str);
+        }
+    }
+
+    fn url(&mut self, path: &str, url: &str) {
+        match Url::parse(url) {
+            Ok(parsed) if ["http", "https"].contains(&parsed.scheme()) && parsed.has_host() => (),
+            Ok(_) => self.error(path, format!("{} is not an http or https URL", url)),
+            Err(e) => self.error(path, format!("invalid URL {}: {}", url, e)),
+        }
+    }
+
+    fn pushover(&mut self, path: &str, pushover: &PushoverConfig) {
+        for (field, value) in [("user_key", &pushover.user_key), ("app_token", &pushover.app_token)] {
+            if value.is_empty() {
+                self.error(&format!("{}.{}", path, field), "must not be empty".to_string());
+            }
+        }
+        for (severity, priority) in &pushover.priorities {
+            if !(-2..=2).contains(priority) {
+                self.error(&format!("{}.priorities.{}", path, severity.as_str()), format!(
+                    "{} is not a priority from -2 to 2", priority,
+                ));
+            }
+        }
+        // Limits of the Pushover API.
+        if pushover.retry_seconds < 30 {
+            self.error(&format!("{}.retry_seconds", path), "must be at least 30".to_string());
+        }
+        if pushover.expire_seconds > 10800 {
+            self.error(&format!("{}.expire_seconds", path), "must be at most 10800".to_string());
+        }
+        self.url(&format!("{}.api_url", path), &pushover.api_url);
+    }
+
+    fn priority(&mut self, path: &str, priority: Option<u8>, min: u8, max: u8) {
+        if let Some(priority) = priority.filter(|priority| !(min..=max).contains(priority)) {
+            self.error(path, format!("{} is not a priority from {} to {}", priority, min, max));
+        }
+    }
+
+    /// Checks that `number` is in E.164 format, e.g. `+4915112345678`.
+    fn phone_number(&mut self, path: &str, number: &str) {
+        let digits = number.strip_prefix('+').unwrap_or_default();
+        if digits.is_empty() || digits.len() > 15 || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            self.error(path, format!("{} is not a phone number in E.164 format, e.g. +4915112345678", number));
+        }
+    }
+
+    fn rate_limit(&mut self, path: &str, limit: Option<&RateLimitConfig>) {
+        let limit = match limit {
+            Some(limit) => limit,
+            None => return,
+        };
+        if limit.count == 0 {
+            self.error(&format!("{}.count", path), "must be at least 1".to_string());
+        }
+        if limit.per_seconds == 0 {
+            self.error(&format!("{}.per_seconds", path), "must be at least 1".to_string());
+        }
+    }
+
+    /// Checks the settings outside of logs and notifiers.
+    fn outputs(&mut self) {
+        let config = self.config;
+        let ids: Vec<&str> = config.log.as_slice().iter().map(|log| log.id.as_str()).collect();
+        for (index, entry) in config.outputs.iter().enumerate() {
+            let path = format!("outputs[{}]", index);
+            if config.outputs[..index].iter().any(|other| other.name() == entry.name()) {
+                self.error(&format!("{}.name", path), format!("{} is used by another output", entry.name()));
+            }
+            for (i, id) in entry.logs.iter().enumerate() {
+                if !ids.contains(&id.as_str()) {
+                    self.error(&format!("{}.logs[{}]", path, i), format!("{} is not the id of a log", id));
+                }
+            }
+            if entry.batch_size == 0 {
+                self.error(&format!("{}.batch_size", path), "must be at least 1".to_string());
+            }
+            if entry.max_buffer < entry.batch_size {
+                self.warning(&format!("{}.max_buffer", path), "is smaller than batch_size".to_string());
+            }
+            match &entry.output {
+                OutputConfig::Loki(loki) => self.url(&format!("{}.url", path), &loki.url),
+                OutputConfig::Elasticsearch(elasticsearch) => {
+                    self.url(&format!("{}.url", path), &elasticsearch.url);
+                    if StrftimeItems::new(&elasticsearch.index).any(|item| item == Item::Error) {
+                        self.error(&format!("{}.index", path), "has an unknown % escape".to_string());
+                    }
+                    if elasticsearch.password.is_some() && elasticsearch.username.is_none() {
+                        self.error(&format!("{}.password", path), "is only sent with a username".to_string());
+                    }
+                },
+                OutputConfig::Kafka(kafka) => self.kafka(&path, kafka),
+                OutputConfig::Gelf(gelf) => self.gelf(&path, gelf),
+            }
+        }
+    }
+
+    fn kafka(&mut self, path: &str, kafka: &KafkaConfig) {
+        if kafka.brokers.is_empty() {
+            self.error(&format!("{}.brokers", path), "must name at least one broker".to_string());
+        }
+        for (i, broker) in kafka.brokers.iter().enumerate() {
+            let port = broker.rsplit_once(':').map(|(_, port)| port);
+            if broker.is_empty() || port.is_some_and(|port| port.parse::<u16>().is_err()) {
+                let message = format!("{} is not an address like kafka:9092", broker);
+                self.error(&format!("{}.brokers[{}]", path, i), message);
+            }
+        }
+        // Topic names are of at most 249 letters, digits, dots, underscores and dashes.
+        let valid = |c: char| c.is_ascii_alphanumeric() || ['.', '_', '-'].contains(&c);
+        if kafka.topic.is_empty() || kafka.topic.len() > 249 || !kafka.topic.chars().all(valid) {
+            self.error(&format!("{}.topic", path), format!("{} is not a valid topic name", kafka.topic));
+        }
+        if ![-1, 0, 1].contains(&kafka.acks) {
+            self.error(&format!("{}.acks", path), "must be -1, 0 or 1".to_string());
+        }
+        if kafka.timeout_seconds == 0 {
+            self.error(&format!("{}.timeout_seconds", path), "must be at least 1".to_string());
+        }
+    }
+
+    fn gelf(&mut self, path: &str, gelf: &GelfConfig) {
+        self.address(&format!("{}.address", path), &gelf.address);
+        if gelf.tls && gelf.protocol == GelfProtocol::Udp {
+            self.error(&format!("{}.tls", path), "needs protocol tcp".to_string());
+        }
+        if gelf.timeout_seconds == 0 {
+            self.error(&format!("{}.timeout_seconds", path), "must be at least 1".to_string());
+        }
+    }
+
+    fn snmp(&mut self, path: &str, snmp: &SnmpConfig) {
+        if snmp.target.is_empty() {
+            self.error(&format!("{}.target", path), "must name the manager".to_string());
+        }
+        if snmp.version != "2c" && snmp.version != "3" {
+            self.error(&format!("{}.version", path), format!("{} is neither 2c nor 3", snmp.version));
+        }
+        if snmp.version == "3" && snmp.username.is_none() {
+            self.error(&format!("{}.username", path), "is required by version 3".to_string());
+        }
+        if snmp.auth_protocol.is_some() != snmp.auth_password.is_some() {
+            self.error(&format!("{}.auth_password", path), "goes along with auth_protocol".to_string());
+        }
+        if snmp.priv_password.is_some() && snmp.auth_protocol.is_none() {
+            self.error(&format!("{}.priv_password", path), "needs authentication as well".to_string());
+        }
+        // RFC 3414 keeps keys from passwords of fewer characters.
+        for (name, password) in [("auth_password", &snmp.auth_password), ("priv_password", &snmp.priv_password)] {
+            if password.as_ref().is_some_and(|password| password.len() < 8) {
+                self.error(&format!("{}.{}", path, name), "must have at least 8 characters".to_string());
+            }
+        }
+        if let Some(id) = &snmp.engine_id {
+            if snmp::from_hex(id).map_or(true, |id| id.len() < 5 || id.len() > 32) {
+                self.error(&format!("{}.engine_id", path), "must be 5 to 32 bytes in hex".to_string());
+            }
+        }
+        let oid = snmp.enterprise_oid.trim_start_matches('.');
+        if oid.split('.').count() < 2 || !oid.split('.').all(|arc| arc.parse::<u32>().is_ok()) {
+            self.error(&format!("{}.enterprise_oid", path), format!("{} is not an OID", snmp.enterprise_oid));
+        }
+    }
+
+    fn general(&mut self) {
+        let config = self.config;
+        if let Err(e) = Schedule::new(config.schedule.as_ref()) {
+            self.error("schedule", e);
+        }
+        if let Some(http) = &config.http {
+            self.address("http.bind", &http.bind);
+        }
+        if let Some(retry) = &config.retry {
+            if retry.max_attempts == 0 {
+                self.error("retry.max_attempts", "must be at least 1".to_string());
+            }
+            if retry.initial_delay_seconds > retry.max_delay_seconds {
+                self.warning("retry.initial_delay_seconds", format!(
+                    "is cut to max_delay_seconds, {}", retry.max_delay_seconds,
+                ));
+            }
+            if let Some(file) = &retry.queue_file {
+                self.directory("retry.queue_file", file);
+            }
+        }
+        let queue = &config.notify_queue;
+        if queue.capacity == 0 {
+            self.error("notify_queue.capacity", "must be at least 1".to_string());
+        }
+        if queue.senders == 0 {
+            self.error("notify_queue.senders", "must be at least 1".to_string());
+        }
+        if config.watch_buffer_size < MIN_WATCH_BUFFER {
+            self.error("watch_buffer_size", format!("must be at least {} bytes", MIN_WATCH_BUFFER));
+        }
+        if let Some(file) = &config.state_file {
+            self.directory("state_file", file);
+        }
+        if let Some(file) = &config.history_db {
+            self.directory("history_db", file);
+        }
+        for name in config.labels.keys().filter(|name| !is_name(name)) {
+            self.error(&format!("labels.{}", name), "is not a name of letters, digits and _".to_string());
+        }
+    }
+
+    /// Checks that `address` is `host:port`.
+    fn address(&mut self, path: &str, address: &str) {
+        let port = address.rsplit_once(':').map(|(_, port)| port);
+        if port.is_none_or(|port| port.parse::<u16>().is_err()) {
+            self.error(path, format!("{} is not an address like 127.0.0.1:9898", address));
+        }
+    }
+
+    /// Checks that the directory a file is created in exists.
+    fn directory(&mut self, path: &str, file: &str) {
+        let dir = match Path::new(file).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => return,
+        };
+        if !dir.is_dir() {
+            self.error(path, format!("directory {} does not exist", dir.display()));
+        }
+    }
+}
+
+/// The gist of a message spread over lines, such as those of regex errors
+/// pointing at the mistake.
+fn last_line(message: &str) -> &str {
+    let line = message.lines().map(str::trim).rfind(|line| !line.is_empty()).unwrap_or_default();
+    line.strip_prefix("error: ").unwrap_or(line)
+}
+
+/// Where the settings of a YAML document are, by their path.
+struct Positions {
+    lines: HashMap<String, usize>,
+    /// Paths of map keys, as segments, with their lines, in file order.
+    keys: Vec<(Vec<Segment>, usize)>,
+}
+
+#[derive(Clone)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl Positions {
+    fn of(text: &str, format: Format) -> Positions {
+        let mut positions = Positions { lines: HashMap::new(), keys: Vec::new() };
+        // Syntax errors were reported by serde already.
+        if format == Format::Toml {
+            if let Ok(table) = DeTable::parse(text) {
+                positions.record_table(text, &mut Vec::new(), table.get_ref());
+            }
+            return positions;
+        }
+        // JSON is read as the YAML it also is.
+        let mut recorder = Recorder { positions, stack: Vec::new() };
+        let _ = Parser::new(text.chars()).load(&mut recorder, false);
+        recorder.positions
+    }
+
+    fn record_table(&mut self, text: &str, path: &mut Vec<Segment>, table: &DeTable) {
+        for (key, value) in table.iter() {
+            path.push(Segment::Key(key.get_ref().to_string()));
+            let line = line_at(text, key.span().start);
+            self.keys.push((path.clone(), line));
+            self.lines.entry(join(path)).or_insert(line);
+            self.record_value(text, path, value);
+            path.pop();
+        }
+    }
+
+    fn record_value(&mut self, text: &str, path: &mut Vec<Segment>, value: &Spanned<DeValue>) {
+        match value.get_ref() {
+            DeValue::Table(table) => self.record_table(text, path, table),
+            DeValue::Array(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    path.push(Segment::Index(index));
+                    self.lines.entry(join(path)).or_insert(line_at(text, item.span().start));
+                    self.record_value(text, path, item);
+                    path.pop();
+                }
+            },
+            _ => (),
+        }
+    }
+
+    /// The line of `path`, or else of the closest enclosing setting.
+    fn line(&self, path: &str) -> Option<usize> {
+        let mut path = path;
+        loop {
+            if let Some(line) = self.lines.get(path) {
+                return Some(*line);
+            }
+            path = &path[..path.rfind(['.', '['])?];
+        }
+    }
+
+    /// Warns about the keys serde ignored, such as misspelt settings.
+    fn unknown_keys(&self, config: &Config) -> Vec<Problem> {
+        let known = match serde_yaml::to_value(config) {
+            Ok(known) => known,
+            Err(_) => return Vec::new(),
+        };
+        let mut problems: Vec<Problem> = Vec::new();
+        for (segments, _) in &self.keys {
+            let path = join(segments);
+            if problems.iter().any(|problem| path.starts_with(&format!("{}.", problem.path))) {
+                continue;
+            }
+            let mut value = Some(&known);
+            for segment in segments {
+                value = match (value, segment) {
+                    (Some(Value::Mapping(map)), Segment::Key(key)) => map.get(&Value::String(key.clone())),
+                    (Some(Value::Sequence(items)), Segment::Index(index)) => items.get(*index),
+                    _ => None,
+                };
+            }
+            if value.is_none() {
+                problems.push(Problem {
+                    level: Level::Warning,
+                    path,
+                    line: None,
+                    message: "unknown setting, ignored".to_string(),
+                });
+            }
+        }
+        problems
+    }
+}
+
+fn join(segments: &[Segment]) -> String {
+    let mut path = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Key(key) if path.is_empty() => path.push_str(key),
+            Segment::Key(key) => {
+                path.push('.');
+                path.push_str(key);
+            },
+            Segment::Index(index) => path.push_str(&format!("[{}]", index)),
+        }
+    }
+    path
+}
+
+/// Follows the YAML events, keeping track of the path of each node.
+struct Recorder {
+    positions: Positions,
+    stack: Vec<Frame>,
+}
+
+enum Frame {
+    /// A map, with the key of the value coming next, once read.
+    Map(Vec<Segment>, Option<String>),
+    /// A sequence, with the index of the item coming next.
+    Seq(Vec<Segment>, usize),
+}
+
+impl Recorder {
+    /// The path of the node coming next.
+    fn next_path(&mut self) -> Vec<Segment> {
+        let (mut path, segment) = match self.stack.last_mut() {
+            None => return Vec::new(),
+            Some(Frame::Map(path, key)) => (path.clone(), Segment::Key(key.take().unwrap_or_default())),
+            Some(Frame::Seq(path, index)) => {
+                *index += 1;
+                (path.clone(), Segment::Index(*index - 1))
+            },
+        };
+        path.push(segment);
+        path
+    }
+
+    fn record(&mut self, path: &[Segment], line: usize) {
+        self.positions.lines.entry(join(path)).or_insert(line);
+    }
+}
+
+impl MarkedEventReceiver for Recorder {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        let line = mark.line();
+        match event {
+            Event::Scalar(value, ..) => {
+                if let Some(Frame::Map(path, key @ None)) = self.stack.last_mut() {
+                    let mut path = path.clone();
+                    path.push(Segment::Key(value.clone()));
+                    *key = Some(value);
+                    self.record(&path, line);
+                    self.positions.keys.push((path, line));
+                    return;
+                }
+                let path = self.next_path();
+                self.record(&path, line);
+            },
+            Event::Alias(_) => {
+                self.next_path();
+            },
+            Event::MappingStart(_) => {
+                let path = self.next_path();
+                self.record(&path, line);
+                self.stack.push(Frame::Map(path, None));
+            },
+            Event::SequenceStart(_) => {
+                let path = self.next_path();
+                self.record(&path, line);
+                self.stack.push(Frame::Seq(path, 0));
+            },
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+            },
+            _ => (),
+        }
+    }
+}
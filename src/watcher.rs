@@ -0,0 +1,1300 @@
+use std::collections::{BTreeMap, HashMap};
+use std::future::{pending, Future};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{Local, Utc};
+use serde_json::{Map, Value};
+use tokio::sync::{mpsc, Notify};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::{interval, sleep, MissedTickBehavior};
+use tracing::{debug, debug_span, error, info, info_span, warn};
+
+use crate::config::{
+    default_watch_buffer_size, read_configuration, Config, LineFormat, LogConfig, NotifyQueueConfig, QueueOverflow,
+    QuietAction, RateLimitConfig, RetryConfig, Severity, WatchBackend, WatchMode,
+};
+use crate::dispatch::Dispatcher;
+use crate::error::Error;
+use crate::files::{Change, FileWatcher};
+use crate::forward::{Forwarder, Record};
+use crate::history::History;
+use crate::http::{self, Acknowledgements, Health};
+use crate::logfmt;
+use crate::logpath::LogPath;
+use crate::metrics;
+use crate::multiline::{Grouping, Pending};
+use crate::notify::digest::Digest;
+use crate::notify::{build_notifiers, dispatch, resolve, Alert, Callback, Delivery, DryRun, Notifier};
+use crate::queue::{Job, NotifyQueue};
+use crate::ratelimit::RateLimiter;
+use crate::retry::{self, Kind, RetryQueue};
+use crate::rule::{AlertRule, Outcome, RuleState};
+use crate::schedule::Schedule;
+use crate::source::{self, Lines};
+use crate::state::State;
+use crate::system::Snapshot;
+use crate::tail::Tailer;
+use crate::validate::{validate_configuration, Level};
+
+/// How often idle logs are checked when no file events arrive.
+const TICK: Duration = Duration::from_secs(1);
+
+/// Shortest accepted polling interval, in milliseconds.
+pub(crate) const MIN_POLL_INTERVAL: i64 = 100;
+
+/// How long the configuration file must be left alone before it is reloaded.
+const RELOAD_DELAY: i64 = 500;
+
+/// File event errors in a row after which watching is given up.
+const MAX_WATCH_FAILURES: u32 = 10;
+
+/// Pause after a file event error.
+const WATCH_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// One file of a log.
+struct LogFile {
+    path: String,
+    /// Index of the file in the `FileWatcher`.
+    watch: usize,
+    tailer: Option<Tailer>,
+    /// When the file is read after changes put off by `debounce`.
+    due: Option<i64>,
+}
+
+struct LogState {
+    log: LogConfig,
+    /// What `log.path` stands for, once running.
+    source: Option<LogPath>,
+    files: Vec<LogFile>,
+    /// Directories watched for new files, for globs, with their index.
+    dirs: Vec<(PathBuf, usize)>,
+    /// Task reading a source other than files.
+    task: Option<JoinHandle<()>>,
+    /// Id of the log whose source found this one, e.g. a container.
+    parent: Option<String>,
+    grouping: Option<Grouping>,
+    /// Entries still being read, by file or origin.
+    pending: HashMap<String, Pending>,
+    rules: Vec<RuleState>,
+    forwarder: Arc<Forwarder>,
+}
+
+impl LogState {
+    fn new(log: LogConfig, rules: Vec<AlertRule>, forwarder: Arc<Forwarder>) -> LogState {
+        LogState {
+            log,
+            source: None,
+            files: Vec::new(),
+            dirs: Vec::new(),
+            task: None,
+            parent: None,
+            grouping: None,
+            pending: HashMap::new(),
+            rules: rules.into_iter().map(RuleState::new).collect(),
+            forwarder,
+        }
+    }
+
+    /// A log of one instance of the source of this log, e.g. a container,
+    /// checked with the same rules.
+    fn child(&self, id: String) -> LogState {
+        let log = LogConfig { id, ..self.log.clone() };
+        let rules = self.rules.iter().map(|state| state.rule.clone()).collect();
+        let mut child = LogState::new(log, rules, self.forwarder.clone());
+        child.parent = Some(self.log.id.clone());
+        child.grouping = self.grouping.clone();
+        child
+    }
+
+    /// Starts watching the files of the log, or reading its other source,
+    /// taking only what is appended from now on.
+    fn start(&mut self, files: &mut FileWatcher, sources: &source::Sender) -> io::Result<()> {
+        self.grouping = grouping_of(&self.log).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if let Some(task) = source::spawn(&self.log, sources)? {
+            self.task = Some(task);
+            return Ok(());
+        }
+        if self.log.path.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("log {} has no path", self.log.id)));
+        }
+        let source = LogPath::parse(&self.log.path, self.log.recursive)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let dirs = match source.dirs() {
+            Ok(dirs) => dirs,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                info!("Waiting for {} to appear ({})", self.log.path, self.log.id);
+                self.source = Some(source);
+                return Ok(());
+            },
+            Err(e) => return Err(e),
+        };
+        for dir in dirs {
+            let index = self.watch_dir(files, &dir)?;
+            self.dirs.push((dir, index));
+        }
+        for path in source.files()? {
+            self.add_file(files, &path, false)?;
+        }
+        self.source = Some(source);
+        Ok(())
+    }
+
+    /// Looks for a glob's directory that was missing. Returns true if the
+    /// set of files changed.
+    fn poll(&mut self, files: &mut FileWatcher) -> bool {
+        if !self.dirs.is_empty() || !self.source.as_ref().is_some_and(LogPath::is_glob) {
+            return false;
+        }
+        let before = self.files.len();
+        self.rescan(files);
+        self.files.len() != before
+    }
+
+    fn stop(&mut self, files: &mut FileWatcher) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        for file in self.files.drain(..) {
+            files.remove(file.watch);
+        }
+        for (_, index) in self.dirs.drain(..) {
+            files.remove(index);
+        }
+    }
+
+    fn poll_interval(&self) -> Option<i64> {
+        self.log.poll_interval.map(|interval| interval.max(MIN_POLL_INTERVAL))
+    }
+
+    fn debounce(&self) -> Option<i64> {
+        self.log.debounce.filter(|debounce| *debounce > 0 && self.log.mode == WatchMode::Tail)
+    }
+
+    fn watch_dir(&self, files: &mut FileWatcher, dir: &Path) -> io::Result<usize> {
+        match self.poll_interval() {
+            Some(interval) => Ok(files.add_polled(dir, interval, true)),
+            None => files.add_dir(dir),
+        }
+    }
+
+    fn add_file(&mut self, files: &mut FileWatcher, path: &Path, from_start: bool) -> io::Result<()> {
+        let watch = match self.poll_interval() {
+            Some(interval) => files.add_polled(path, interval, false),
+            None => files.add(&path.to_string_lossy())?,
+        };
+        let path = path.to_string_lossy().into_owned();
+        let tailer = match self.log.mode {
+            WatchMode::Event => None,
+            WatchMode::Tail => {
+                let mut tailer = Tailer::open(&path);
+                tailer.set_encoding(self.log.text_encoding());
+                if from_start {
+                    tailer.reopen();
+                }
+                Some(tailer)
+            },
+        };
+        self.files.push(LogFile { path, watch, tailer, due: None });
+        Ok(())
+    }
+
+    fn owns(&self, index: usize) -> bool {
+        self.dirs.iter().any(|(_, dir)| *dir == index) || self.files.iter().any(|file| file.watch == index)
+    }
+
+    fn paths(&self) -> impl Iterator<Item = &String> {
+        self.files.iter().map(|file| &file.path)
+    }
+
+    /// Starts watching directories and matching files that appeared,
+    /// reading the files from the start.
+    fn rescan(&mut self, files: &mut FileWatcher) {
+        self.rescan_dirs(files);
+        let found = match self.source.as_ref().map(LogPath::files) {
+            Some(Ok(found)) => found,
+            Some(Err(ref e)) if e.kind() == io::ErrorKind::NotFound => return,
+            Some(Err(e)) => {
+                warn!("Failed to list {}: {}", self.log.path, e);
+                return;
+            },
+            None => return,
+        };
+        for path in found {
+            if self.paths().any(|known| Path::new(known) == path) {
+                continue;
+            }
+            match self.add_file(files, &path, true) {
+                Ok(()) => {
+                    info!("Watching new file {} ({})", path.display(), self.log.id);
+                    let lines = self.files.last_mut().and_then(|file| file.tailer.as_mut()).map(Tailer::read_lines);
+                    if let Some(lines) = lines {
+                        self.count_lines(&path.to_string_lossy(), lines);
+                    }
+                },
+                Err(e) => warn!("Failed to watch {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    fn rescan_dirs(&mut self, files: &mut FileWatcher) {
+        let found = match self.source.as_ref().map(LogPath::dirs) {
+            Some(Ok(found)) => found,
+            // The whole directory is gone; `poll` waits for it to come back.
+            Some(Err(ref e)) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            _ => return,
+        };
+        self.dirs.retain(|(dir, index)| {
+            let exists = found.contains(dir);
+            if !exists {
+                files.remove(*index);
+            }
+            exists
+        });
+        for dir in found {
+            if self.dirs.iter().any(|(known, _)| *known == dir) {
+                continue;
+            }
+            match self.watch_dir(files, &dir) {
+                Ok(index) => self.dirs.push((dir, index)),
+                Err(e) => warn!("Failed to watch {}: {}", dir.display(), e),
+            }
+        }
+    }
+
+    /// Replaces the rules, keeping the matches seen by rules whose name is
+    /// unchanged.
+    fn update_rules(&mut self, rules: Vec<AlertRule>) {
+        let mut old = std::mem::take(&mut self.rules);
+        for rule in rules {
+            match old.iter().position(|state| state.rule.name() == rule.name()) {
+                Some(position) => {
+                    let mut state = old.remove(position);
+                    state.update(rule);
+                    self.rules.push(state);
+                },
+                None => self.rules.push(RuleState::new(rule)),
+            }
+        }
+    }
+
+    fn count_lines(&mut self, path: &str, lines: io::Result<Vec<String>>) {
+        let lines = match lines {
+            Ok(lines) => lines,
+            Err(e) => {
+                warn!("Failed to read {}: {}", path, e);
+                return;
+            },
+        };
+        let entries = match &self.grouping {
+            Some(grouping) => {
+                let pending = self.pending.entry(path.to_string()).or_default();
+                grouping.push(pending, lines, Local::now().timestamp_millis())
+            },
+            None => lines,
+        };
+        for entry in entries {
+            self.count_entry(entry);
+        }
+    }
+
+    fn count_entry(&mut self, entry: String) {
+        let fields = match self.log.format {
+            LineFormat::Json => serde_json::from_str::<Map<String, Value>>(&entry).ok(),
+            LineFormat::Logfmt => logfmt::parse(&entry),
+            LineFormat::Plain => None,
+        };
+        let mut first = None;
+        for state in self.rules.iter_mut() {
+            if state.rule.matches(&entry, fields.as_ref()) && state.accepts(&entry, fields.as_ref()) {
+                metrics::matched(&self.log.id, &state.rule.name());
+                state.record(Some(entry.clone()), fields.as_ref());
+                first.get_or_insert_with(|| (state.rule.name(), state.rule.level()));
+            }
+        }
+        if let Some((rule, severity)) = first {
+            if !self.forwarder.is_empty() {
+                let (log_id, timestamp) = (self.log.id.clone(), Local::now());
+                self.forwarder.push(Record { log_id, rule, severity, line: entry, fields, timestamp });
+            }
+        }
+    }
+
+    /// Counts multiline entries no line was added to for a while, or all of
+    /// them with `now` at `i64::MAX`.
+    fn expire_entries(&mut self, now: i64) {
+        let grouping = match &self.grouping {
+            Some(grouping) => grouping,
+            None => return,
+        };
+        let entries: Vec<String> = self.pending.values_mut()
+            .filter_map(|pending| grouping.expire(pending, now))
+            .collect();
+        for entry in entries {
+            self.count_entry(entry);
+        }
+    }
+
+    /// Reads the files whose changes were put off until `now` or earlier.
+    fn read_due(&mut self, now: i64) {
+        for position in 0..self.files.len() {
+            let file = &mut self.files[position];
+            if file.due.is_none_or(|due| due > now) {
+                continue;
+            }
+            file.due = None;
+            if let Some(tailer) = file.tailer.as_mut() {
+                let _span = debug_span!("event", log = %self.log.id, change = Change::Modified.name()).entered();
+                debug!("File modified: {}", file.path);
+                let lines = tailer.read_lines();
+                let path = file.path.clone();
+                self.count_lines(&path, lines);
+            }
+        }
+    }
+
+    /// Takes the configuration of the log on a reload, without restarting
+    /// it.
+    fn reconfigure(&mut self, log: LogConfig) {
+        if log.multiline != self.log.multiline {
+            self.expire_entries(i64::MAX);
+            self.grouping = grouping_of(&log).unwrap_or_default();
+        }
+        for tailer in self.files.iter_mut().filter_map(|file| file.tailer.as_mut()) {
+            tailer.set_encoding(log.text_encoding());
+        }
+        self.log = log;
+    }
+
+    fn record_event(&mut self) {
+        for state in self.rules.iter_mut() {
+            metrics::matched(&self.log.id, &state.rule.name());
+            state.record(None, None);
+        }
+    }
+
+    /// Handles a change of the file or directory watched under `index`.
+    /// Returns true if the set of files changed.
+    fn on_change(&mut self, files: &mut FileWatcher, index: usize, change: Change) -> bool {
+        let _span = debug_span!("event", log = %self.log.id, change = change.name()).entered();
+        if self.dirs.iter().any(|(_, dir)| *dir == index) {
+            let before = self.files.len();
+            self.rescan(files);
+            return self.files.len() != before;
+        }
+        let position = match self.files.iter().position(|file| file.watch == index) {
+            Some(position) => position,
+            None => return false,
+        };
+        let path = self.files[position].path.clone();
+        metrics::event(&path, change.name());
+        match change {
+            Change::Modified => {
+                if let Some(debounce) = self.debounce() {
+                    let due = Local::now().timestamp_millis() + debounce;
+                    self.files[position].due.get_or_insert(due);
+                    return false;
+                }
+            },
+            // Both read what was put off.
+            Change::Removed | Change::Created => self.files[position].due = None,
+            Change::Attrib => (),
+        }
+        let tailer = self.files[position].tailer.as_mut();
+        match change {
+            Change::Modified => {
+                debug!("File modified: {}", path);
+                match tailer {
+                    Some(tailer) => {
+                        let lines = tailer.read_lines();
+                        self.count_lines(&path, lines);
+                    },
+                    None => self.record_event(),
+                }
+            },
+            Change::Attrib => {
+                debug!("File attribute modified: {}", path);
+                if tailer.is_none() {
+                    self.record_event();
+                }
+            },
+            Change::Removed => {
+                debug!("File moved or deleted: {}", path);
+                if let Some(tailer) = tailer {
+                    let lines = tailer.close();
+                    self.count_lines(&path, lines);
+                }
+                // Files of a glob are picked up again by the scan if they come back.
+                if self.source.as_ref().is_some_and(LogPath::is_glob) && !Path::new(&path).exists() {
+                    files.remove(index);
+                    self.files.remove(position);
+                    return true;
+                }
+            },
+            Change::Created => {
+                debug!("File created: {}", path);
+                if let Some(tailer) = tailer {
+                    tailer.reopen();
+                    let lines = tailer.read_lines();
+                    self.count_lines(&path, lines);
+                }
+            },
+        }
+        false
+    }
+}
+
+/// Asks a running [`Watcher`] to read its configuration file again.
+#[derive(Clone)]
+pub struct Reloader {
+    notify: Arc<Notify>,
+}
+
+impl Reloader {
+    pub fn reload(&self) {
+        self.notify.notify_one();
+    }
+}
+
+/// Watches log files and sends alerts through the registered notifiers.
+///
+/// Notifications wait in a bounded queue for the tasks sending them, so a
+/// slow channel never holds up reading the logs.
+///
+/// ```no_run
+/// use log_monitor::{read_configuration, Error, Watcher};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let config = read_configuration("./application.yml")?;
+///     let mut watcher = Watcher::new(config)?;
+///     watcher.on_alert(|alert| println!("{} errors on {}", alert.count, alert.log_id));
+///     watcher.run().await
+/// }
+/// ```
+pub struct Watcher {
+    logs: Vec<LogState>,
+    /// Notifiers created from the configuration, replaced on reload.
+    configured: Vec<Arc<dyn Notifier>>,
+    /// Notifiers registered by the embedding program.
+    custom: Vec<Arc<dyn Notifier>>,
+    /// Severities configured notifiers subscribed to, by name.
+    subscriptions: HashMap<String, Vec<Severity>>,
+    /// Alerts collected for notifiers sending digests, by name. Kept across
+    /// reloads, until sent.
+    digests: HashMap<String, Arc<Digest>>,
+    /// Rate limits of configured notifiers, by name. Kept across reloads
+    /// that leave them alone.
+    limits: HashMap<String, Arc<RateLimiter>>,
+    /// The notifier each configured notifier falls back to, by name.
+    fallbacks: HashMap<String, String>,
+    /// Labels added to every alert.
+    labels: BTreeMap<String, String>,
+    state_file: Option<PathBuf>,
+    config_path: Option<PathBuf>,
+    http_bind: Option<String>,
+    reload: Arc<Notify>,
+    health: Arc<Health>,
+    acks: Arc<Acknowledgements>,
+    history: Option<Arc<History>>,
+    retry: Arc<RetryQueue>,
+    /// Read when the watcher starts running.
+    notify_queue: NotifyQueueConfig,
+    forwarder: Arc<Forwarder>,
+    schedule: Schedule,
+    backend: WatchBackend,
+    watch_buffer_size: usize,
+    /// Dry run requested by the embedding program.
+    dry_run: bool,
+    /// Dry run requested by the configuration file.
+    dry_run_config: bool,
+}
+
+impl Watcher {
+    /// Creates a watcher for every log and notifier in `config`.
+    pub fn new(config: Config) -> Result<Watcher, Error> {
+        let mut watcher = Watcher::empty();
+        watcher.configured = build_notifiers(&config)
+            .map_err(|e| format!("invalid notifier configuration: {}", e))?;
+        watcher.subscriptions = config.subscriptions();
+        watcher.configure_digests(config.digests());
+        watcher.configure_limits(config.rate_limits());
+        watcher.fallbacks = fallbacks_of(&config)?;
+        watcher.labels = config.labels.clone();
+        watcher.state_file = config.state_file.as_ref().map(PathBuf::from);
+        watcher.http_bind = config.http.as_ref().map(|http| http.bind.clone());
+        watcher.acks = Arc::new(Acknowledgements::new(config.http.as_ref().and_then(|http| http.ack_token.clone())));
+        watcher.dry_run_config = config.dry_run;
+        watcher.backend = config.watch_backend;
+        watcher.watch_buffer_size = config.watch_buffer_size;
+        watcher.retry.configure(config.retry.clone());
+        watcher.notify_queue = config.notify_queue.clone();
+        watcher.forwarder.configure(&config.outputs);
+        watcher.schedule = Schedule::new(config.schedule.as_ref())
+            .map_err(|e| format!("invalid schedule: {}", e))?;
+        if let Some(path) = &config.history_db {
+            let history = History::open(path)
+                .map_err(|e| format!("failed to open history database {}: {}", path, e))?;
+            watcher.history = Some(Arc::new(history));
+        }
+        if config.log.as_slice().iter().filter(|log| log.stdin).count() > 1 {
+            return Err("only one log can read standard input".into());
+        }
+        for log in config.log.as_slice() {
+            watcher.add_log_rules(log.clone(), rules_of(&config, log)?);
+        }
+        Ok(watcher)
+    }
+
+    /// Creates a watcher without any logs or notifiers.
+    pub fn empty() -> Watcher {
+        Watcher {
+            logs: Vec::new(),
+            configured: Vec::new(),
+            subscriptions: HashMap::new(),
+            digests: HashMap::new(),
+            limits: HashMap::new(),
+            fallbacks: HashMap::new(),
+            labels: BTreeMap::new(),
+            custom: Vec::new(),
+            state_file: None,
+            config_path: None,
+            http_bind: None,
+            reload: Arc::new(Notify::new()),
+            health: Arc::new(Health::new()),
+            acks: Arc::new(Acknowledgements::default()),
+            history: None,
+            retry: Arc::new(RetryQueue::new(None)),
+            notify_queue: NotifyQueueConfig::default(),
+            forwarder: Arc::new(Forwarder::default()),
+            schedule: Schedule::default(),
+            backend: WatchBackend::default(),
+            watch_buffer_size: default_watch_buffer_size(),
+            dry_run: false,
+            dry_run_config: false,
+        }
+    }
+
+    /// Watches one more log with the given rule.
+    pub fn add_log(&mut self, log: LogConfig, rule: AlertRule) -> &mut Watcher {
+        self.add_log_rules(log, vec![rule])
+    }
+
+    /// Watches one more log, checking each line against every rule.
+    pub fn add_log_rules(&mut self, log: LogConfig, rules: Vec<AlertRule>) -> &mut Watcher {
+        self.logs.push(LogState::new(log, rules, self.forwarder.clone()));
+        self
+    }
+
+    pub fn add_notifier(&mut self, notifier: Box<dyn Notifier>) -> &mut Watcher {
+        self.custom.push(Arc::from(notifier));
+        self
+    }
+
+    /// Keeps alert states in `path` across restarts.
+    pub fn state_file<P: Into<PathBuf>>(&mut self, path: P) -> &mut Watcher {
+        self.state_file = Some(path.into());
+        self
+    }
+
+    /// Reloads the configuration from `path` whenever the file changes or
+    /// [`Reloader::reload`] is called.
+    pub fn config_path<P: Into<PathBuf>>(&mut self, path: P) -> &mut Watcher {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Serves the monitoring endpoints on `bind` while running.
+    pub fn http_bind<S: Into<String>>(&mut self, bind: S) -> &mut Watcher {
+        self.http_bind = Some(bind.into());
+        self
+    }
+
+    /// Runs the whole pipeline but prints alerts instead of sending them
+    /// through the configured channels. Callbacks are still called.
+    pub fn dry_run(&mut self, dry_run: bool) -> &mut Watcher {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Records every fired alert in `history`.
+    pub fn history(&mut self, history: History) -> &mut Watcher {
+        self.history = Some(Arc::new(history));
+        self
+    }
+
+    /// Retries failed notifications according to `config`.
+    pub fn retry(&mut self, config: RetryConfig) -> &mut Watcher {
+        self.retry.configure(Some(config));
+        self
+    }
+
+    /// Queues notifications according to `config`.
+    pub fn notify_queue(&mut self, config: NotifyQueueConfig) -> &mut Watcher {
+        self.notify_queue = config;
+        self
+    }
+
+    /// Detects file changes with `backend` instead of the platform default.
+    pub fn backend(&mut self, backend: WatchBackend) -> &mut Watcher {
+        self.backend = backend;
+        self
+    }
+
+    /// Reads inotify events into a buffer of `size` bytes.
+    pub fn watch_buffer_size(&mut self, size: usize) -> &mut Watcher {
+        self.watch_buffer_size = size;
+        self
+    }
+
+    /// Returns a handle that triggers a configuration reload.
+    pub fn reloader(&self) -> Reloader {
+        Reloader { notify: self.reload.clone() }
+    }
+
+    /// Calls `callback` for every alert, alongside the other notifiers.
+    pub fn on_alert<F>(&mut self, callback: F) -> &mut Watcher
+        where F: Fn(&Alert) + Send + Sync + 'static {
+        self.add_notifier(Box::new(Callback::new(callback)))
+    }
+
+    fn notifiers(&self) -> Arc<Dispatcher> {
+        let dry_run = self.dry_run || self.dry_run_config;
+        let mut configured: Vec<_> = self.configured.iter().map(|notifier| {
+            let name = notifier.name().to_string();
+            if dry_run {
+                (name, Arc::new(DryRun::new(notifier.clone())) as Arc<dyn Notifier>)
+            } else {
+                (name, notifier.clone())
+            }
+        }).collect();
+        // Dry runs show the alerts even when they have nowhere to go.
+        if dry_run && configured.is_empty() {
+            configured.push(("dry-run".to_string(), Arc::new(DryRun::unrouted())));
+        }
+        Arc::new(Dispatcher::new(
+            configured,
+            &self.subscriptions,
+            &self.digests,
+            &self.limits,
+            &self.fallbacks,
+            &self.labels,
+            self.custom.clone(),
+        ))
+    }
+
+    fn configure_limits(&mut self, limits: HashMap<String, RateLimitConfig>) {
+        let mut previous = std::mem::take(&mut self.limits);
+        for (name, config) in limits {
+            let limiter = previous.remove(&name)
+                .filter(|limiter| *limiter.config() == config)
+                .unwrap_or_else(|| Arc::new(RateLimiter::new(config)));
+            self.limits.insert(name, limiter);
+        }
+    }
+
+    /// Starts a digest for every notifier in `intervals` and retires those
+    /// of notifiers no longer sending them, which are sent at once.
+    fn configure_digests(&mut self, intervals: HashMap<String, i64>) {
+        for (name, digest) in &self.digests {
+            digest.set_interval(intervals.get(name).copied().unwrap_or(0));
+        }
+        for (name, interval) in intervals {
+            self.digests.entry(name.clone()).or_insert_with(|| Arc::new(Digest::new(&name, interval)));
+        }
+    }
+
+    /// Watches the logs until an unrecoverable error occurs.
+    pub async fn run(self) -> Result<(), Error> {
+        self.run_until(pending()).await
+    }
+
+    /// Watches the logs until `shutdown` completes, then sends alerts that
+    /// are still pending, waits for deliveries in progress and saves the
+    /// state file.
+    pub async fn run_until<F: Future<Output = ()>>(mut self, shutdown: F) -> Result<(), Error> {
+        let mut files = FileWatcher::new(self.backend, self.watch_buffer_size)?;
+        let (sources, mut lines) = mpsc::unbounded_channel::<Lines>();
+        for state in self.logs.iter_mut() {
+            state.start(&mut files, &sources)?;
+        }
+        let config_watch = match &self.config_path {
+            Some(path) => Some(files.add(&path.to_string_lossy())?),
+            None => None,
+        };
+        if let Err(e) = self.restore_state() {
+            warn!("Failed to restore the state file, starting afresh: {}", e);
+        }
+        if let Err(e) = self.retry.load() {
+            warn!("Failed to load the retry queue, starting with an empty one: {}", e);
+        }
+        self.update_health();
+        let server = match &self.http_bind {
+            Some(bind) => {
+                let listener = tokio::net::TcpListener::bind(bind).await
+                    .map_err(|source| Error::Http { bind: bind.clone(), source })?;
+                info!("Serving metrics and health on http://{}", listener.local_addr()?);
+                Some(tokio::spawn(http::serve(listener, self.health.clone(), self.acks.clone())))
+            },
+            None => None,
+        };
+        let mut dispatcher = self.notifiers();
+        let mut deliveries = JoinSet::new();
+        let outbox = Outbox {
+            queue: NotifyQueue::start(&self.notify_queue),
+            history: self.history.clone(),
+            retry: self.retry.clone(),
+            when_full: self.notify_queue.when_full,
+        };
+        let mut period = self.tick_period();
+        let mut tick = interval(period);
+        tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let reload = self.reload.clone();
+        let mut config_changed: Option<i64> = None;
+        let mut watch_failures = 0;
+        tokio::pin!(shutdown);
+        loop {
+            let mut reload_now = false;
+            let mut ended = false;
+            let mut changes = Vec::new();
+            tokio::select! {
+                result = files.next_changes() => match result {
+                    Ok(found) => {
+                        self.health.event();
+                        watch_failures = 0;
+                        changes = found;
+                    },
+                    Err(e) => {
+                        watch_failures += 1;
+                        if watch_failures >= MAX_WATCH_FAILURES {
+                            return Err(Error::Watch(e));
+                        }
+                        warn!("File watch error, retrying: {}", e);
+                        sleep(WATCH_RETRY_DELAY).await;
+                    },
+                },
+                _ = tick.tick() => {
+                    changes = files.retry_missing();
+                    let polled = files.poll(Local::now().timestamp_millis());
+                    if !polled.is_empty() {
+                        self.health.event();
+                    }
+                    changes.extend(polled);
+                    let mut found = false;
+                    let now = Local::now().timestamp_millis();
+                    for state in self.logs.iter_mut() {
+                        found |= state.poll(&mut files);
+                        state.expire_entries(now);
+                    }
+                    if found {
+                        self.update_health();
+                    }
+                    ended = self.sources_ended() && lines.is_empty();
+                },
+                Some(batch) = lines.recv() => {
+                    self.health.event();
+                    self.count_source_lines(batch);
+                },
+                _ = reload.notified() => reload_now = true,
+                _ = &mut shutdown => break,
+            }
+            for (index, change) in changes {
+                if Some(index) == config_watch {
+                    if change != Change::Removed {
+                        config_changed = Some(Local::now().timestamp_millis());
+                    }
+                } else if let Some(state) = self.logs.iter_mut().find(|s| s.owns(index)) {
+                    if state.on_change(&mut files, index, change) {
+                        self.update_health();
+                    }
+                }
+            }
+            let now = Local::now().timestamp_millis();
+            for state in self.logs.iter_mut() {
+                state.read_due(now);
+            }
+            if let Some(changed) = config_changed {
+                if now - changed >= RELOAD_DELAY {
+                    reload_now = true;
+                }
+            }
+            if reload_now {
+                config_changed = None;
+                self.reload_config(&mut files, &sources);
+                self.update_health();
+                dispatcher = self.notifiers();
+                if self.tick_period() != period {
+                    period = self.tick_period();
+                    tick = interval(period);
+                    tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                }
+            }
+            let at = Utc::now();
+            let acks = self.acks.take();
+            for log in self.logs.iter_mut() {
+                for state in log.rules.iter_mut() {
+                    let (log_id, name) = (&log.log.id, state.rule.name());
+                    if acks.iter().any(|ack| ack.matches(log_id, &name)) && state.acknowledge() {
+                        info!("Alert {} on {} acknowledged.", name, log.log.id);
+                    }
+                    let quiet = self.schedule.quiet(&log.log.id, &state.rule.name(), at);
+                    let mut outcomes = Vec::new();
+                    if quiet.is_none() {
+                        outcomes.extend(state.release().map(Outcome::Fire));
+                    }
+                    match (state.check(&log.log.id, now), quiet) {
+                        (Some(Outcome::Fire(alert)), Some(QuietAction::Suppress)) => {
+                            info!("Alert for {} suppressed by the schedule.", alert.log_id);
+                        },
+                        (Some(Outcome::Fire(alert)), Some(QuietAction::Digest)) => {
+                            info!("Alert for {} held back until the quiet period ends.", alert.log_id);
+                            state.hold(alert);
+                        },
+                        (Some(Outcome::Escalate(alert)), Some(_)) => {
+                            info!("Reminder for {} suppressed by the schedule.", alert.log_id);
+                        },
+                        (outcome, _) => outcomes.extend(outcome),
+                    }
+                    for mut outcome in outcomes {
+                        if log.log.system_snapshot {
+                            if let Outcome::Fire(alert) | Outcome::Escalate(alert) = &mut outcome {
+                                let path = Some(Path::new(&log.log.path)).filter(|path| !path.as_os_str().is_empty());
+                                alert.system = Some(Snapshot::take(path));
+                            }
+                        }
+                        match outcome {
+                            Outcome::Fire(alert) => {
+                                self.health.alert();
+                                let notifiers = dispatcher.route(state.rule.targets(), &alert);
+                                outbox.deliver(&dispatcher, notifiers, Kind::Fire, alert);
+                            },
+                            Outcome::Escalate(alert) => {
+                                let escalation = state.rule.escalation_targets();
+                                let notifiers = dispatcher.escalate(state.rule.targets(), escalation, &alert);
+                                outbox.deliver(&dispatcher, notifiers, Kind::Fire, alert);
+                            },
+                            Outcome::Resolve(alert) => {
+                                let notifiers = dispatcher.route(state.rule.targets(), &alert);
+                                outbox.deliver(&dispatcher, notifiers, Kind::Resolve, alert);
+                            },
+                        }
+                    }
+                }
+            }
+            for (name, digest) in &self.digests {
+                let alert = match digest.due(now) {
+                    Some(alert) => alert,
+                    None => continue,
+                };
+                match dispatcher.channel(name) {
+                    Some(notifier) => outbox.deliver(&dispatcher, vec![notifier], Kind::Fire, alert),
+                    None => warn!("Dropping the digest of {}, which is no longer configured.", name),
+                }
+            }
+            self.digests.retain(|_, digest| !digest.retired() || !digest.is_empty());
+            for pending in self.retry.due(now) {
+                outbox.retry(&dispatcher, pending, now);
+            }
+            for (output, batch) in self.forwarder.due(now) {
+                deliveries.spawn_blocking(move || {
+                    output.send(batch);
+                });
+            }
+            while deliveries.try_join_next().is_some() {}
+            if ended {
+                break;
+            }
+        }
+
+        info!("Shutting down.");
+        if let Some(server) = server {
+            server.abort();
+        }
+        for task in self.logs.iter_mut().filter_map(|log| log.task.take()) {
+            task.abort();
+        }
+        for log in self.logs.iter_mut() {
+            log.read_due(i64::MAX);
+            log.expire_entries(i64::MAX);
+            for state in log.rules.iter_mut() {
+                let held = state.release();
+                for alert in held.into_iter().chain(state.flush(&log.log.id)) {
+                    let notifiers = dispatcher.route(state.rule.targets(), &alert);
+                    outbox.queue.push(outbox.job(&dispatcher, notifiers, Kind::Fire, alert)).await;
+                }
+            }
+        }
+        let forwarder = self.forwarder.clone();
+        deliveries.spawn_blocking(move || forwarder.flush());
+        // The alerts above may still be on their way into digests.
+        outbox.queue.close().await;
+        while deliveries.join_next().await.is_some() {}
+        for (name, digest) in &self.digests {
+            if let (Some(alert), Some(notifier)) = (digest.take(), dispatcher.channel(name)) {
+                let dispatcher = dispatcher.clone();
+                let history = self.history.clone();
+                let retry = self.retry.clone();
+                deliveries.spawn_blocking(move || send(&dispatcher, &[notifier], history.as_deref(), &retry, &alert));
+            }
+        }
+        while deliveries.join_next().await.is_some() {}
+        Ok(self.save_state()?)
+    }
+
+    /// Reads the configuration file again and applies it. Logs reading the
+    /// same source the same way keep their position and counters.
+    fn reload_config(&mut self, files: &mut FileWatcher, sources: &source::Sender) {
+        let path = match &self.config_path {
+            Some(path) => path.to_string_lossy().into_owned(),
+            None => return,
+        };
+        let problems = validate_configuration(&path).unwrap_or_default();
+        if problems.iter().any(|problem| problem.level == Level::Error) {
+            for problem in problems.iter().filter(|problem| problem.level == Level::Error) {
+                warn!("{}: {}", path, problem);
+            }
+            warn!("Failed to reload configuration {}, keeping the current one.", path);
+            return;
+        }
+        let config = match read_configuration(&path).and_then(|config| {
+            let mut rules = Vec::new();
+            for log in config.log.as_slice() {
+                rules.push(rules_of(&config, log)?);
+            }
+            let notifiers = build_notifiers(&config)
+                .map_err(|e| format!("invalid notifier configuration: {}", e))?;
+            let schedule = Schedule::new(config.schedule.as_ref())
+                .map_err(|e| format!("invalid schedule: {}", e))?;
+            let fallbacks = fallbacks_of(&config)?;
+            Ok((config, rules, notifiers, schedule, fallbacks))
+        }) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Failed to reload configuration {}, keeping the current one: {}", path, e);
+                return;
+            }
+        };
+        let (config, rules, notifiers, schedule, fallbacks) = config;
+        let (children, mut old): (Vec<LogState>, Vec<LogState>) = std::mem::take(&mut self.logs)
+            .into_iter()
+            .partition(|state| state.parent.is_some());
+        for (log, rule) in config.log.as_slice().iter().zip(rules) {
+            let same = old.iter().position(|s| s.log.same_source(log));
+            match same {
+                Some(position) => {
+                    let mut state = old.remove(position);
+                    state.update_rules(rule);
+                    state.reconfigure(log.clone());
+                    self.logs.push(state);
+                },
+                None => {
+                    info!("Watching {} ({})", log.location(), log.id);
+                    let mut state = LogState::new(log.clone(), rule, self.forwarder.clone());
+                    if let Err(e) = state.start(files, sources) {
+                        warn!("Failed to watch {}: {}", log.location(), e);
+                    }
+                    self.logs.push(state);
+                },
+            }
+        }
+        for mut state in old {
+            info!("No longer watching {} ({})", state.log.location(), state.log.id);
+            state.stop(files);
+        }
+        for mut child in children {
+            let parent = self.logs.iter().find(|s| s.task.is_some() && child.parent.as_ref() == Some(&s.log.id));
+            match parent {
+                Some(parent) => {
+                    child.update_rules(parent.rules.iter().map(|state| state.rule.clone()).collect());
+                    let log = LogConfig { id: child.log.id.clone(), ..parent.log.clone() };
+                    child.reconfigure(log);
+                    self.logs.push(child);
+                },
+                None => info!("No longer watching {}", child.log.id),
+            }
+        }
+        self.configured = notifiers;
+        self.subscriptions = config.subscriptions();
+        self.configure_digests(config.digests());
+        self.configure_limits(config.rate_limits());
+        self.fallbacks = fallbacks;
+        self.labels = config.labels.clone();
+        self.schedule = schedule;
+        self.dry_run_config = config.dry_run;
+        self.retry.configure(config.retry.clone());
+        self.forwarder.configure(&config.outputs);
+        self.state_file = config.state_file.as_ref().map(PathBuf::from);
+        info!("Configuration reloaded from {}.", path);
+    }
+
+    /// Counts lines read by a source, adding a log for each new instance
+    /// of sources that have them.
+    fn count_source_lines(&mut self, batch: Lines) {
+        let _span = debug_span!("lines", log = %batch.log_id, origin = %batch.origin).entered();
+        let id = match &batch.instance {
+            Some(instance) => format!("{}/{}", batch.log_id, instance),
+            None => batch.log_id.clone(),
+        };
+        let position = self.logs.iter()
+            .position(|s| s.log.id == id && (s.task.is_some() || s.parent.is_some()));
+        let position = match position {
+            Some(position) => position,
+            None => {
+                let parent = match self.logs.iter().find(|s| s.log.id == batch.log_id && s.task.is_some()) {
+                    Some(parent) if batch.instance.is_some() => parent,
+                    _ => return,
+                };
+                let child = parent.child(id);
+                self.logs.push(child);
+                self.logs.len() - 1
+            },
+        };
+        self.logs[position].count_lines(&batch.origin, Ok(batch.lines));
+    }
+
+    /// Whether every log reads a source that ended, as standard input does,
+    /// so there is nothing left to watch.
+    fn sources_ended(&self) -> bool {
+        !self.logs.is_empty() && self.logs.iter().all(|log| log.task.as_ref().is_some_and(JoinHandle::is_finished))
+    }
+
+    /// The tick, shortened to the shortest polling interval.
+    fn tick_period(&self) -> Duration {
+        self.logs.iter()
+            .flat_map(|log| log.poll_interval().into_iter().chain(log.debounce()))
+            .map(|interval| Duration::from_millis(interval.max(MIN_POLL_INTERVAL) as u64))
+            .fold(TICK, Duration::min)
+    }
+
+    fn update_health(&self) {
+        self.health.set_files(self.logs.iter().flat_map(LogState::paths).cloned().collect());
+    }
+
+    fn restore_state(&mut self) -> io::Result<()> {
+        let path = match &self.state_file {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let saved = State::load(path)?;
+        for state in self.logs.iter_mut() {
+            let log = match saved.logs.get(&state.log.id) {
+                Some(log) => log,
+                None => continue,
+            };
+            for rule in state.rules.iter_mut() {
+                if let Some(record) = log.rules.get(&rule.rule.name()) {
+                    rule.restore(record);
+                }
+            }
+            // Lines written while stopped are counted now.
+            for position in 0..state.files.len() {
+                let file = &mut state.files[position];
+                let (tailer, record) = match (file.tailer.as_mut(), log.files.get(&file.path)) {
+                    (Some(tailer), Some(record)) => (tailer, record),
+                    _ => continue,
+                };
+                let path = file.path.clone();
+                let lines = tailer.resume(record).and_then(|()| tailer.read_lines());
+                state.count_lines(&path, lines);
+            }
+        }
+        Ok(())
+    }
+
+    fn save_state(&self) -> io::Result<()> {
+        let path = match &self.state_file {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        // The SNMP notifiers counted their starts in the file.
+        let engine_boots = State::load(path).map(|saved| saved.engine_boots).unwrap_or_default();
+        let mut saved = State { engine_boots, ..State::default() };
+        for state in &self.logs {
+            let log = saved.logs.entry(state.log.id.clone()).or_default();
+            for rule in &state.rules {
+                log.rules.insert(rule.rule.name(), rule.record_state());
+            }
+            for file in &state.files {
+                if let Some(position) = file.tailer.as_ref().and_then(Tailer::position) {
+                    log.files.insert(file.path.clone(), position);
+                }
+            }
+        }
+        saved.save(path)
+    }
+}
+
+fn rules_of(config: &Config, log: &LogConfig) -> Result<Vec<AlertRule>, Error> {
+    grouping_of(log).map_err(|e| format!("invalid multiline grouping for log {}: {}", log.id, e))?;
+    let rules = AlertRule::rules_of(config, log)
+        .map_err(|e| format!("invalid rule for log {}: {}", log.id, e))?;
+    let names = config.notifier_names();
+    for rule in &rules {
+        for target in rule.targets().iter().chain(rule.escalation_targets()) {
+            if !names.contains(&target.as_str()) {
+                return Err(format!("rule {} of log {} notifies unknown notifier {}",
+                    rule.name(), log.id, target).into());
+            }
+        }
+    }
+    Ok(rules)
+}
+
+/// The fallbacks of the configured notifiers, which have to be configured
+/// themselves and must not lead back to where they started.
+fn fallbacks_of(config: &Config) -> Result<HashMap<String, String>, String> {
+    let fallbacks = config.fallbacks();
+    let names = config.notifier_names();
+    for (name, fallback) in &fallbacks {
+        if !names.contains(&fallback.as_str()) {
+            return Err(format!("notifier {} falls back to unknown notifier {}", name, fallback));
+        }
+        let mut next = Some(fallback);
+        for _ in 0..fallbacks.len() {
+            next = next.and_then(|next| fallbacks.get(next));
+        }
+        if next.is_some() {
+            return Err(format!("the fallbacks of notifier {} go round in a circle", name));
+        }
+    }
+    Ok(fallbacks)
+}
+
+fn grouping_of(log: &LogConfig) -> Result<Option<Grouping>, String> {
+    log.multiline.as_ref().map(Grouping::new).transpose()
+}
+
+/// Hands notifications to the queue, and takes care of those it has no
+/// room for.
+struct Outbox {
+    queue: NotifyQueue,
+    history: Option<Arc<History>>,
+    retry: Arc<RetryQueue>,
+    when_full: QueueOverflow,
+}
+
+impl Outbox {
+    fn job(&self, dispatcher: &Arc<Dispatcher>, notifiers: Vec<Arc<dyn Notifier>>, kind: Kind, alert: Alert) -> Job {
+        let dispatcher = dispatcher.clone();
+        let history = self.history.clone();
+        let retry = self.retry.clone();
+        Box::new(move || match kind {
+            Kind::Fire => send(&dispatcher, &notifiers, history.as_deref(), &retry, &alert),
+            Kind::Resolve => {
+                let results = resolve(&notifiers, &alert);
+                queue_failed(&dispatcher, history.as_deref(), &retry, &notifiers, &results, Kind::Resolve, &alert);
+            },
+        })
+    }
+
+    /// Queues sending `alert` through `notifiers`. Without room in the
+    /// queue, it is retried later or dropped, as `when_full` says.
+    fn deliver(&self, dispatcher: &Arc<Dispatcher>, notifiers: Vec<Arc<dyn Notifier>>, kind: Kind, mut alert: Alert) {
+        dispatcher.label(&mut alert);
+        if let Some(slot) = self.queue.slot() {
+            slot.send(self.job(dispatcher, notifiers, kind, alert));
+            return;
+        }
+        let now = Local::now().timestamp_millis();
+        for notifier in &notifiers {
+            if self.when_full == QueueOverflow::Retry && self.retry.push(notifier, kind, &alert, now).is_none() {
+                continue;
+            }
+            warn!("Dropped the alert for {} to {}, as the notification queue is full.", alert.log_id, notifier.name());
+            metrics::notify_dropped(notifier.name());
+        }
+    }
+
+    /// Queues sending `pending` again, or puts it back into the retry queue
+    /// until there is room.
+    fn retry(&self, dispatcher: &Arc<Dispatcher>, pending: retry::Pending, now: i64) {
+        let slot = match self.queue.slot() {
+            Some(slot) => slot,
+            None => {
+                if let Some(failed) = self.retry.reschedule(pending, now) {
+                    warn!("Dropped the alert for {} to {}, as the notification queue is full.",
+                        failed.alert.log_id, failed.channel);
+                    metrics::notify_dropped(&failed.channel);
+                }
+                return;
+            },
+        };
+        let dispatcher = dispatcher.clone();
+        let history = self.history.clone();
+        let retry = self.retry.clone();
+        slot.send(Box::new(move || {
+            if let Some(failed) = retry.retry(pending, &dispatcher.all(), Local::now().timestamp_millis()) {
+                fall_back(&dispatcher, history.as_deref(), &retry, failed);
+            }
+        }));
+    }
+}
+
+/// Dispatches `alert`, records the outcome in the history, if any, and
+/// queues the channels that failed for a retry.
+fn send(dispatcher: &Dispatcher, notifiers: &[Arc<dyn Notifier>], history: Option<&History>, retry: &RetryQueue,
+        alert: &Alert) {
+    let deliveries = dispatch(notifiers, alert);
+    if let Some(history) = history {
+        if let Err(e) = history.record(alert, &deliveries) {
+            error!("Failed to record alert for {} in history: {}", alert.log_id, e);
+        }
+    }
+    queue_failed(dispatcher, history, retry, notifiers, &deliveries, Kind::Fire, alert);
+}
+
+/// Queues the channels that failed for a retry, falling back right away
+/// for those that are not retried.
+fn queue_failed(dispatcher: &Dispatcher, history: Option<&History>, retry: &RetryQueue,
+                notifiers: &[Arc<dyn Notifier>], deliveries: &[Delivery], kind: Kind, alert: &Alert) {
+    let now = Local::now().timestamp_millis();
+    for (notifier, delivery) in notifiers.iter().zip(deliveries) {
+        if delivery.error.is_some() {
+            if let Some(failed) = retry.push(notifier, kind, alert, now) {
+                fall_back(dispatcher, history, retry, failed);
+            }
+        }
+    }
+}
+
+/// Sends a notification its channel gave up on through the channel's
+/// fallback, if it has one, and records which channel took over.
+fn fall_back(dispatcher: &Dispatcher, history: Option<&History>, retry: &RetryQueue, failed: retry::Pending) {
+    let notifier = match dispatcher.fallback(&failed.channel) {
+        Some(notifier) => notifier,
+        None => return,
+    };
+    let alert = failed.alert;
+    let _span = info_span!("notify", channel = notifier.name(), log = %alert.log_id, rule = %alert.rule).entered();
+    let result = match failed.kind {
+        Kind::Fire => notifier.notify(&alert),
+        Kind::Resolve => notifier.resolve(&alert),
+    };
+    let error = match result {
+        Ok(()) => {
+            info!("Alert for {} sent via {}, falling back from {}.",
+                alert.log_id, notifier.name(), failed.channel);
+            metrics::alert_sent(notifier.name());
+            None
+        },
+        Err(e) => {
+            warn!("Alert for {} failed to send via {}, falling back from {}: {}",
+                alert.log_id, notifier.name(), failed.channel, e);
+            metrics::notify_failed(notifier.name());
+            Some(e.to_string())
+        },
+    };
+    if let (Some(history), Kind::Fire) = (history, failed.kind) {
+        let channel = format!("{} (fallback for {})", notifier.name(), failed.channel);
+        if let Err(e) = history.record(&alert, &[Delivery { channel, error: error.clone() }]) {
+            error!("Failed to record alert for {} in history: {}", alert.log_id, e);
+        }
+    }
+    if error.is_some() {
+        if let Some(failed) = retry.push(&notifier, failed.kind, &alert, Local::now().timestamp_millis()) {
+            fall_back(dispatcher, history, retry, failed);
+        }
+    }
+}
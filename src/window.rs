@@ -0,0 +1,38 @@
+use std::collections::VecDeque;
+
+/// Counts events that happened within the last `span` milliseconds.
+/// A span of zero or less keeps every event until the window is cleared.
+pub struct SlidingWindow {
+    span: i64,
+    events: VecDeque<i64>,
+}
+
+impl SlidingWindow {
+    pub fn new(span: i64) -> SlidingWindow {
+        SlidingWindow { span, events: VecDeque::new() }
+    }
+
+    pub fn set_span(&mut self, span: i64) {
+        self.span = span;
+    }
+
+    /// Records an event at `timestamp` (milliseconds).
+    pub fn push(&mut self, timestamp: i64) {
+        self.events.push_back(timestamp);
+    }
+
+    /// Number of events within the window ending at `now`.
+    pub fn count(&mut self, now: i64) -> usize {
+        while let Some(first) = self.events.front() {
+            if self.span <= 0 || now - *first < self.span {
+                break;
+            }
+            self.events.pop_front();
+        }
+        self.events.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}